@@ -1,3 +1,4 @@
+use super::types::ChatCompletionRequest;
 use crate::api_config::ApiProvider;
 use genai::adapter::AdapterKind;
 
@@ -7,5 +8,190 @@ pub(crate) fn adapter_kind(provider: ApiProvider) -> AdapterKind {
         ApiProvider::OpenAiResponses => AdapterKind::OpenAI,
         ApiProvider::Claude => AdapterKind::Anthropic,
         ApiProvider::GeminiV1Beta => AdapterKind::Gemini,
+        ApiProvider::Azure => AdapterKind::OpenAI,
+    }
+}
+
+/// 是否支持 OpenAI 风格的 JSON 模式（response_format = json_object）
+pub(crate) fn supports_json_mode(provider: ApiProvider) -> bool {
+    matches!(
+        provider,
+        ApiProvider::OpenAiCompatible | ApiProvider::OpenAiResponses | ApiProvider::Azure
+    )
+}
+
+/// 提供商/模型对采样参数的支持情况
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct SamplingSupport {
+    /// 不支持 temperature 时为 None，否则为允许的上限（下限为 0）
+    pub temperature_max: Option<f64>,
+    pub top_p: bool,
+    /// temperature 与 top_p 能否同时设置
+    pub temperature_with_top_p: bool,
+    pub penalties: bool,
+    pub seed: bool,
+}
+
+/// OpenAI 推理模型（o 系列、gpt-5）只接受默认的 temperature / top_p
+fn is_openai_reasoning_model(model: &str) -> bool {
+    let model = model.rsplit('/').next().unwrap_or(model).to_lowercase();
+    ["o1", "o3", "o4", "gpt-5"]
+        .iter()
+        .any(|prefix| model == *prefix || model.starts_with(&format!("{prefix}-")))
+}
+
+pub(crate) fn sampling_support(provider: ApiProvider, model: &str) -> SamplingSupport {
+    match provider {
+        ApiProvider::OpenAiCompatible | ApiProvider::OpenAiResponses | ApiProvider::Azure => {
+            let reasoning = is_openai_reasoning_model(model);
+            SamplingSupport {
+                temperature_max: (!reasoning).then_some(2.0),
+                top_p: !reasoning,
+                temperature_with_top_p: true,
+                penalties: !reasoning,
+                seed: true,
+            }
+        }
+        ApiProvider::Claude => SamplingSupport {
+            temperature_max: Some(1.0),
+            top_p: true,
+            temperature_with_top_p: false,
+            penalties: false,
+            seed: false,
+        },
+        ApiProvider::GeminiV1Beta => SamplingSupport {
+            temperature_max: Some(2.0),
+            top_p: true,
+            temperature_with_top_p: true,
+            penalties: false,
+            seed: true,
+        },
+    }
+}
+
+/// 按提供商能力清理请求中的采样参数：丢弃不支持的字段、把越界值收敛到允许范围，返回调整说明
+pub(crate) fn sanitize_sampling(
+    provider: ApiProvider,
+    request: &mut ChatCompletionRequest,
+) -> Vec<String> {
+    let support = sampling_support(provider, &request.model);
+    let mut changes = Vec::new();
+
+    if let Some(temperature) = request.temperature {
+        match support.temperature_max {
+            None => {
+                request.temperature = None;
+                changes.push("移除 temperature（模型不支持）".to_string());
+            }
+            Some(max) if !(0.0..=max).contains(&temperature) => {
+                let clamped = if temperature.is_nan() {
+                    0.0
+                } else {
+                    temperature.clamp(0.0, max)
+                };
+                request.temperature = Some(clamped);
+                changes.push(format!("temperature {} 调整为 {}", temperature, clamped));
+            }
+            Some(_) => {}
+        }
+    }
+
+    if let Some(top_p) = request.top_p {
+        if !support.top_p {
+            request.top_p = None;
+            changes.push("移除 top_p（模型不支持）".to_string());
+        } else if request.temperature.is_some() && !support.temperature_with_top_p {
+            request.top_p = None;
+            changes.push("移除 top_p（不能与 temperature 同时设置）".to_string());
+        } else if !(0.0..=1.0).contains(&top_p) {
+            let clamped = if top_p.is_nan() {
+                1.0
+            } else {
+                top_p.clamp(0.0, 1.0)
+            };
+            request.top_p = Some(clamped);
+            changes.push(format!("top_p {} 调整为 {}", top_p, clamped));
+        }
+    }
+
+    // genai 目前不转发 frequency/presence penalty，这里只保证字段本身合法，便于日后接入
+    for (name, penalty) in [
+        ("frequency_penalty", &mut request.frequency_penalty),
+        ("presence_penalty", &mut request.presence_penalty),
+    ] {
+        let Some(value) = *penalty else {
+            continue;
+        };
+        if !support.penalties {
+            *penalty = None;
+            changes.push(format!("移除 {}（提供商不支持）", name));
+        } else if !(-2.0..=2.0).contains(&value) {
+            let clamped = if value.is_nan() {
+                0.0
+            } else {
+                value.clamp(-2.0, 2.0)
+            };
+            *penalty = Some(clamped);
+            changes.push(format!("{} {} 调整为 {}", name, value, clamped));
+        }
+    }
+
+    if request.seed.is_some() && !support.seed {
+        request.seed = None;
+        changes.push("移除 seed（提供商不支持）".to_string());
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_request(model: &str) -> ChatCompletionRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": model,
+            "messages": [],
+            "temperature": 1.4,
+            "top_p": 0.9,
+            "frequency_penalty": 0.5,
+            "presence_penalty": 0.3,
+            "seed": 7
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn anthropic_requests_clamp_temperature_and_drop_top_p_and_seed() {
+        let mut request = sample_request("claude-sonnet-4-5");
+
+        let changes = sanitize_sampling(ApiProvider::Claude, &mut request);
+
+        assert_eq!(request.temperature, Some(1.0));
+        assert_eq!(request.top_p, None);
+        assert_eq!(request.seed, None);
+        assert_eq!(request.frequency_penalty, None);
+        assert_eq!(request.presence_penalty, None);
+        assert_eq!(changes.len(), 5);
+    }
+
+    #[test]
+    fn openai_requests_keep_supported_parameters() {
+        let mut request = sample_request("gpt-4.1");
+        request.temperature = Some(3.5);
+
+        let changes = sanitize_sampling(ApiProvider::OpenAiCompatible, &mut request);
+
+        assert_eq!(request.temperature, Some(2.0));
+        assert_eq!(request.top_p, Some(0.9));
+        assert_eq!(request.frequency_penalty, Some(0.5));
+        assert_eq!(request.seed, Some(7));
+        assert_eq!(changes, vec!["temperature 3.5 调整为 2".to_string()]);
+
+        let mut reasoning = sample_request("openai/o3-mini");
+        sanitize_sampling(ApiProvider::OpenAiCompatible, &mut reasoning);
+        assert_eq!(reasoning.temperature, None);
+        assert_eq!(reasoning.top_p, None);
+        assert_eq!(reasoning.presence_penalty, None);
     }
 }
@@ -1,7 +1,10 @@
 mod adapter;
 mod formatting;
+mod provider_error;
 mod service;
+mod stream_test;
 mod types;
 
 pub use service::*;
+pub use stream_test::{StreamTestChunk, StreamTestSummary};
 pub use types::*;
@@ -0,0 +1,167 @@
+use regex::Regex;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+const RETRY_BASE_DELAY_MS: u64 = 500;
+const RETRY_MAX_DELAY_MS: u64 = 30_000;
+
+/// 提供商返回的原始错误信息
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ProviderErrorDetail {
+    pub(crate) status: Option<u16>,
+    pub(crate) body: String,
+}
+
+/// 从 genai 错误中取出 HTTP 状态码与响应体
+pub(crate) fn extract_provider_error(error: &genai::Error) -> Option<ProviderErrorDetail> {
+    let webc_error = match error {
+        genai::Error::WebModelCall { webc_error, .. }
+        | genai::Error::WebAdapterCall { webc_error, .. } => webc_error,
+        genai::Error::HttpError { status, body, .. } => {
+            return Some(ProviderErrorDetail {
+                status: Some(status.as_u16()),
+                body: body.clone(),
+            });
+        }
+        _ => return None,
+    };
+
+    match webc_error {
+        genai::webc::Error::ResponseFailedStatus { status, body, .. } => {
+            Some(ProviderErrorDetail {
+                status: Some(status.as_u16()),
+                body: body.clone(),
+            })
+        }
+        genai::webc::Error::ResponseFailedNotJson { body, .. }
+        | genai::webc::Error::ResponseFailedInvalidJson { body, .. } => Some(ProviderErrorDetail {
+            status: None,
+            body: body.clone(),
+        }),
+        _ => None,
+    }
+}
+
+/// 是否值得重试：网络错误与 5xx 重试，4xx 等请求本身的问题不重试
+pub(crate) fn is_transient_error(error: &genai::Error) -> bool {
+    if let Some(status) = extract_provider_error(error).and_then(|detail| detail.status) {
+        return status >= 500;
+    }
+
+    matches!(
+        error,
+        genai::Error::WebModelCall {
+            webc_error: genai::webc::Error::Reqwest(_),
+            ..
+        } | genai::Error::WebAdapterCall {
+            webc_error: genai::webc::Error::Reqwest(_),
+            ..
+        }
+    )
+}
+
+/// 第 attempt 次重试前的等待时间（指数退避，封顶 30 秒）
+pub(crate) fn retry_delay(attempt: u32) -> Duration {
+    let factor = 1u64 << attempt.min(16);
+    Duration::from_millis(
+        RETRY_BASE_DELAY_MS
+            .saturating_mul(factor)
+            .min(RETRY_MAX_DELAY_MS),
+    )
+}
+
+fn secret_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(r"(sk-[A-Za-z0-9_\-]{8,}|AIza[0-9A-Za-z_\-]{20,}|Bearer\s+[A-Za-z0-9._\-]+)")
+            .expect("secret pattern should compile")
+    })
+}
+
+/// 抹去错误内容中的 API Key
+pub(crate) fn redact_secrets(text: &str, api_key: &str) -> String {
+    let api_key = api_key.trim();
+    let text = if api_key.is_empty() {
+        text.to_string()
+    } else {
+        text.replace(api_key, "[REDACTED]")
+    };
+
+    secret_pattern()
+        .replace_all(&text, "[REDACTED]")
+        .into_owned()
+}
+
+/// 生成面向用户的错误描述，尽量保留提供商返回的原始错误体
+pub(crate) fn describe_provider_error(prefix: &str, error: &genai::Error, api_key: &str) -> String {
+    let Some(detail) = extract_provider_error(error) else {
+        return redact_secrets(&format!("{prefix}: {error}"), api_key);
+    };
+
+    let body = redact_secrets(detail.body.trim(), api_key);
+    match detail.status {
+        Some(status) => format!("{prefix} (HTTP {status}): {body}"),
+        None => format!("{prefix}: {body}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use genai::adapter::AdapterKind;
+    use genai::ModelIden;
+    use reqwest::header::HeaderMap;
+    use reqwest::StatusCode;
+
+    fn failed_status(status: StatusCode, body: &str) -> genai::Error {
+        genai::Error::WebModelCall {
+            model_iden: ModelIden::new(AdapterKind::OpenAI, "gpt-missing"),
+            webc_error: genai::webc::Error::ResponseFailedStatus {
+                status,
+                body: body.to_string(),
+                headers: Box::new(HeaderMap::new()),
+            },
+        }
+    }
+
+    fn bad_request(body: &str) -> genai::Error {
+        failed_status(StatusCode::BAD_REQUEST, body)
+    }
+
+    #[test]
+    fn surfaces_redacted_body_of_failed_request() {
+        let body = r#"{"error":{"message":"The model `gpt-missing` does not exist","code":"model_not_found","key":"sk-live-1234567890abcdef"}}"#;
+        let message = describe_provider_error(
+            "AI API调用失败",
+            &bad_request(body),
+            "sk-live-1234567890abcdef",
+        );
+
+        assert!(message.starts_with("AI API调用失败 (HTTP 400): "));
+        assert!(message.contains("model_not_found"));
+        assert!(message.contains("does not exist"));
+        assert!(!message.contains("sk-live-1234567890abcdef"));
+        assert!(message.contains("[REDACTED]"));
+    }
+
+    #[test]
+    fn redacts_keys_not_matching_configured_key() {
+        let redacted = redact_secrets("Authorization: Bearer abc.def-123 and sk-otherkey12345", "");
+
+        assert_eq!(redacted, "Authorization: [REDACTED] and [REDACTED]");
+    }
+
+    #[test]
+    fn retries_server_errors_with_increasing_backoff() {
+        assert!(is_transient_error(&failed_status(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "overloaded"
+        )));
+        assert!(!is_transient_error(&bad_request("invalid model")));
+
+        let delays: Vec<_> = (0..4).map(retry_delay).collect();
+        assert_eq!(delays[0], Duration::from_millis(500));
+        assert!(delays.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(retry_delay(20), Duration::from_secs(30));
+    }
+}
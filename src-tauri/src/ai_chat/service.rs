@@ -1,21 +1,29 @@
+use super::stream_test::{measure_stream, StreamTestChunk, StreamTestDelta, StreamTestSummary};
 use super::types::*;
-use super::{adapter, formatting};
+use super::{adapter, formatting, provider_error};
 use crate::ai_cancellation::ActiveCancellationRequest;
 use crate::ai_tools::{ToolCallRequest, ToolDefinition};
-use crate::api_config::ApiConfig;
+use crate::api_config::{azure_chat_completions_url, validate_proxy_url, ApiConfig, ApiProvider};
 use crate::backend::domain::{ReasoningDeltaKind, ToolExecutionPhase};
 use crate::events::EventEmitter;
+use crate::request_limiter::{RequestPermit, REQUEST_LIMITER};
 use crate::tools::ToolRegistry;
 use futures_util::StreamExt;
 use genai::chat::{
     ChatMessage as GenAiChatMessage, ChatOptions as GenAiChatOptions,
-    ChatRequest as GenAiChatRequest, ChatResponse as GenAiChatResponse,
-    ChatStreamEvent as GenAiChatStreamEvent, StreamEnd as GenAiStreamEnd, Tool as GenAiTool,
-    ToolCall as GenAiToolCall, ToolResponse as GenAiToolResponse,
+    ChatRequest as GenAiChatRequest, ChatResponse as GenAiChatResponse, ChatResponseFormat,
+    ChatStreamEvent as GenAiChatStreamEvent, ContentPart, MessageContent,
+    StreamEnd as GenAiStreamEnd, Tool as GenAiTool, ToolCall as GenAiToolCall,
+    ToolResponse as GenAiToolResponse,
 };
 use genai::resolver::{AuthData, Endpoint, ServiceTargetResolver};
 use genai::{Client, ModelIden, ServiceTarget};
 use std::collections::HashMap;
+use std::ops::ControlFlow;
+
+/// 视觉能力探测使用的 1x1 透明 PNG
+const PROBE_IMAGE_BASE64: &str =
+    "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mP8z8BQDwAEhQGAhKmMIQAAAABJRU5ErkJggg==";
 
 impl AIChatError {
     fn failed(message: impl Into<String>) -> Self {
@@ -40,16 +48,41 @@ impl AIChatService {
         }
     }
 
-    fn create_client_with_config(api_config: &ApiConfig) -> Client {
+    /// Azure OpenAI 的地址包含部署名和 api-version，且使用 api-key 请求头鉴权，
+    /// 因此整体覆盖请求地址与请求头（自定义请求头一并带上）
+    fn azure_request_override(api_config: &ApiConfig) -> AuthData {
+        let url = azure_chat_completions_url(
+            &api_config.base_url,
+            api_config.azure_deployment(),
+            api_config.azure_api_version(),
+        );
+        let mut headers: Vec<(String, String)> = api_config
+            .headers
+            .iter()
+            .flatten()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        headers.push(("api-key".to_string(), api_config.api_key.clone()));
+        AuthData::RequestOverride {
+            url,
+            headers: headers.into(),
+        }
+    }
+
+    fn create_client_with_config(api_config: &ApiConfig) -> Result<Client, String> {
         let provider = api_config.provider;
         let base_url = Self::normalize_endpoint_for_genai(&api_config.base_url);
         let api_key = api_config.api_key.clone();
+        let azure_override =
+            (provider == ApiProvider::Azure).then(|| Self::azure_request_override(api_config));
 
         let target_resolver = ServiceTargetResolver::from_resolver_fn(
             move |service_target: ServiceTarget| -> Result<ServiceTarget, genai::resolver::Error> {
                 let model_name = service_target.model.model_name;
                 let endpoint = Endpoint::from_owned(base_url.clone());
-                let auth = AuthData::from_single(api_key.clone());
+                let auth = azure_override
+                    .clone()
+                    .unwrap_or_else(|| AuthData::from_single(api_key.clone()));
                 let model = ModelIden::new(adapter::adapter_kind(provider), model_name);
                 Ok(ServiceTarget {
                     endpoint,
@@ -59,12 +92,67 @@ impl AIChatService {
             },
         );
 
-        Client::builder()
+        let timeout = std::time::Duration::from_secs(api_config.timeout_secs);
+        // 流式响应可能持续很久，因此只限制连接与两次读取之间的等待
+        let mut web_config = genai::WebConfig {
+            connect_timeout: Some(timeout),
+            read_timeout: Some(timeout),
+            ..Default::default()
+        };
+        if let Some(proxy_url) = api_config.proxy_url.as_deref() {
+            // genai 依赖的 reqwest 与本 crate 主版本不同，代理需用其对应版本构造
+            let proxy = genai_reqwest::Proxy::all(validate_proxy_url(proxy_url)?.as_str())
+                .map_err(|error| format!("代理地址无效: {}", error))?;
+            web_config.proxy = Some(proxy);
+        }
+
+        Ok(Client::builder()
             .with_service_target_resolver(target_resolver)
-            .build()
+            .with_web_config(web_config)
+            .build())
+    }
+
+    /// 按配置的次数重试临时性错误（网络错误、5xx），两次尝试之间指数退避。
+    /// 每次尝试前获取请求名额，退避等待期间不占用名额；成功时名额随结果返回，由调用方释放。
+    /// 外层错误为获取名额失败
+    async fn with_retries<T, F, Fut>(
+        api_config: &ApiConfig,
+        mut call: F,
+    ) -> Result<Result<(T, RequestPermit), genai::Error>, String>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<T, genai::Error>>,
+    {
+        let mut attempt = 0;
+        loop {
+            let permit = REQUEST_LIMITER.acquire().await?;
+            match call().await {
+                Err(error)
+                    if attempt < api_config.max_retries
+                        && provider_error::is_transient_error(&error) =>
+                {
+                    drop(permit);
+                    tokio::time::sleep(provider_error::retry_delay(attempt)).await;
+                    attempt += 1;
+                }
+                result => return Ok(result.map(|value| (value, permit))),
+            }
+        }
     }
 
-    fn build_options(request: &ChatCompletionRequest) -> GenAiChatOptions {
+    /// 发送前按提供商能力清理采样参数，并记录被移除或调整的字段
+    fn sanitize_request(
+        api_config: &ApiConfig,
+        request: &ChatCompletionRequest,
+    ) -> ChatCompletionRequest {
+        let mut request = request.clone();
+        for change in adapter::sanitize_sampling(api_config.provider, &mut request) {
+            crate::debug_log!("采样参数调整: {}", change);
+        }
+        request
+    }
+
+    fn build_options(api_config: &ApiConfig, request: &ChatCompletionRequest) -> GenAiChatOptions {
         let mut options = GenAiChatOptions::default()
             .with_capture_raw_body(true)
             .with_capture_usage(true)
@@ -89,6 +177,27 @@ impl AIChatService {
             };
             options = options.with_stop_sequences(sequences);
         }
+        // genai 的 ChatOptions 没有 frequency/presence penalty，这两个字段不会发送
+        // 负数种子无法映射到 genai 的 u64，直接忽略
+        if let Some(seed) = request.seed.and_then(|seed| u64::try_from(seed).ok()) {
+            options = options.with_seed(seed);
+        }
+        if request.response_format == Some(ResponseFormat::JsonObject)
+            && adapter::supports_json_mode(api_config.provider)
+        {
+            options = options.with_response_format(ChatResponseFormat::JsonMode);
+        }
+        if let Some(headers) = api_config
+            .headers
+            .as_ref()
+            .filter(|headers| !headers.is_empty())
+        {
+            let headers: Vec<(String, String)> = headers
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect();
+            options = options.with_extra_headers(headers);
+        }
 
         options
     }
@@ -110,12 +219,71 @@ impl AIChatService {
         }
     }
 
+    /// 把 data URL（data:image/png;base64,...）或 http(s) URL 转为图片内容块，无法识别时返回 None
+    fn image_content_part(image: &str) -> Option<ContentPart> {
+        let image = image.trim();
+        if let Some(data) = image.strip_prefix("data:") {
+            let (content_type, base64) = data.split_once(";base64,")?;
+            return (content_type.starts_with("image/") && !base64.is_empty())
+                .then(|| ContentPart::from_binary_base64(content_type, base64, None));
+        }
+
+        if !(image.starts_with("https://") || image.starts_with("http://")) {
+            return None;
+        }
+        let path = image
+            .split(['?', '#'])
+            .next()
+            .unwrap_or(image)
+            .to_lowercase();
+        let content_type = match path.rsplit('.').next() {
+            Some("png") => "image/png",
+            Some("gif") => "image/gif",
+            Some("webp") => "image/webp",
+            _ => "image/jpeg",
+        };
+        Some(ContentPart::from_binary_url(content_type, image, None))
+    }
+
+    /// 带图片的用户消息组装为文本 + 图片的多段内容，否则保持纯文本
+    fn convert_user_message(message: &ChatMessage) -> GenAiChatMessage {
+        let image_parts: Vec<ContentPart> = message
+            .images
+            .iter()
+            .flatten()
+            .filter_map(|image| Self::image_content_part(image))
+            .collect();
+        if image_parts.is_empty() {
+            return GenAiChatMessage::user(message.content.clone());
+        }
+
+        let mut parts = Vec::with_capacity(image_parts.len() + 1);
+        if !message.content.is_empty() {
+            parts.push(ContentPart::from_text(message.content.clone()));
+        }
+        parts.extend(image_parts);
+        GenAiChatMessage::user(MessageContent::from_parts(parts))
+    }
+
+    /// 提供商已探测为不支持视觉输入时移除图片，只发送文本
+    fn strip_unsupported_images(api_config: &ApiConfig, messages: &mut [ChatMessage]) {
+        let supports_vision = api_config
+            .capabilities
+            .is_none_or(|capabilities| capabilities.supports_vision);
+        if supports_vision {
+            return;
+        }
+        for message in messages.iter_mut() {
+            message.images = None;
+        }
+    }
+
     fn convert_messages_to_genai(messages: &[ChatMessage]) -> Vec<GenAiChatMessage> {
         messages
             .iter()
             .filter_map(|message| match message.role {
                 MessageRole::System => None,
-                MessageRole::User => Some(GenAiChatMessage::user(message.content.clone())),
+                MessageRole::User => Some(Self::convert_user_message(message)),
                 MessageRole::Assistant => {
                     let mut assistant_message =
                         GenAiChatMessage::assistant(message.content.clone())
@@ -217,6 +385,7 @@ impl AIChatService {
             reasoning_content: response.reasoning_content.clone(),
             tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
             tool_call_id: None,
+            images: None,
         };
 
         ChatCompletionResponse {
@@ -224,7 +393,12 @@ impl AIChatService {
             object: "chat.completion".to_string(),
             created: chrono::Utc::now().timestamp() as u64,
             model: response.model_iden.model_name.to_string(),
-            system_fingerprint: None,
+            system_fingerprint: response
+                .captured_raw_body
+                .as_ref()
+                .and_then(|body| body.get("system_fingerprint"))
+                .and_then(|value| value.as_str())
+                .map(str::to_string),
             choices: vec![ChatCompletionChoice {
                 index: 0,
                 finish_reason: if message.tool_calls.is_some() {
@@ -274,6 +448,7 @@ impl AIChatService {
                 reasoning_content: None,
                 tool_calls: None,
                 tool_call_id: Some(tool_call.id.clone()),
+                images: None,
             },
             success: result.success,
             data,
@@ -282,7 +457,7 @@ impl AIChatService {
         }
     }
 
-    fn build_chat_request(
+    pub(crate) fn build_chat_request(
         messages: &[ChatMessage],
         request: &ChatCompletionRequest,
     ) -> GenAiChatRequest {
@@ -337,6 +512,7 @@ impl AIChatService {
             reasoning_content: stream_end.captured_reasoning_content.clone(),
             tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
             tool_call_id: None,
+            images: None,
         };
 
         ChatCompletionResponse {
@@ -367,7 +543,31 @@ impl AIChatService {
             reasoning_content: None,
             tool_calls: None,
             tool_call_id: None,
+            images: None,
+        }
+    }
+
+    /// 整理提供商错误并在可用时发送 chat-error 事件
+    fn report_provider_error(
+        app_handle: Option<&tauri::AppHandle>,
+        api_config: &ApiConfig,
+        prefix: &str,
+        error: &genai::Error,
+    ) -> String {
+        let message = provider_error::describe_provider_error(prefix, error, &api_config.api_key);
+
+        if let Some(app_handle) = app_handle {
+            let status =
+                provider_error::extract_provider_error(error).and_then(|detail| detail.status);
+            let _ = EventEmitter::send_chat_error(
+                app_handle,
+                &Self::character_uuid_for_events(),
+                status,
+                &message,
+            );
         }
+
+        message
     }
 
     fn character_uuid_for_events() -> String {
@@ -418,6 +618,80 @@ impl AIChatService {
         }
     }
 
+    /// 工具调用轮数耗尽时丢弃未执行的工具调用，直接返回当前响应
+    fn stop_at_tool_iteration_limit(
+        mut response: ChatCompletionResponse,
+        max_iterations: u32,
+    ) -> ChatCompletionResponse {
+        crate::debug_log!("⚠️ 工具调用已达到上限 {} 轮，停止继续执行", max_iterations);
+        for choice in &mut response.choices {
+            choice.message.tool_calls = None;
+            choice.finish_reason = TOOL_ITERATIONS_EXCEEDED_FINISH_REASON.to_string();
+        }
+        response
+    }
+
+    /// 一轮响应之后的去向：没有工具调用或轮数耗尽时结束并返回响应（耗尽时丢弃工具调用），
+    /// 否则继续执行本轮的工具调用
+    fn next_tool_round(
+        response: ChatCompletionResponse,
+        tool_budget: &mut ToolIterationBudget,
+    ) -> ControlFlow<ChatCompletionResponse, Vec<ToolCallData>> {
+        let tool_calls = response
+            .choices
+            .first()
+            .and_then(|choice| choice.message.tool_calls.clone())
+            .unwrap_or_default();
+        if tool_calls.is_empty() {
+            return ControlFlow::Break(response);
+        }
+
+        if !tool_budget.try_consume() {
+            return ControlFlow::Break(Self::stop_at_tool_iteration_limit(
+                response,
+                tool_budget.max_iterations(),
+            ));
+        }
+
+        ControlFlow::Continue(tool_calls)
+    }
+
+    /// 携带工具调用的 assistant 消息（内容为空）
+    fn tool_call_assistant_message(
+        tool_calls: Vec<ToolCallData>,
+        reasoning_content: Option<String>,
+    ) -> ChatMessage {
+        ChatMessage {
+            role: MessageRole::Assistant,
+            content: String::new(),
+            name: None,
+            reasoning_content,
+            tool_calls: Some(tool_calls),
+            tool_call_id: None,
+            images: None,
+        }
+    }
+
+    /// 工具循环中产生的消息既要发回模型，也要作为中间消息保存到聊天记录
+    fn record_tool_message(
+        message: ChatMessage,
+        messages: &mut Vec<ChatMessage>,
+        intermediate_messages: &mut Vec<ChatMessage>,
+    ) {
+        intermediate_messages.push(message.clone());
+        messages.push(message);
+    }
+
+    /// 有工具运行过时把中间消息附加到响应上，否则保持 None
+    fn attach_intermediate_messages(
+        response: &mut ChatCompletionResponse,
+        intermediate_messages: Vec<ChatMessage>,
+    ) {
+        if !intermediate_messages.is_empty() {
+            response.intermediate_messages = Some(intermediate_messages);
+        }
+    }
+
     async fn execute_tool_calls(
         app_handle: &tauri::AppHandle,
         character_uuid: &str,
@@ -432,17 +706,11 @@ impl AIChatService {
             return Ok(());
         }
 
-        let assistant_message = ChatMessage {
-            role: MessageRole::Assistant,
-            content: String::new(),
-            name: None,
-            reasoning_content,
-            tool_calls: Some(tool_calls.clone()),
-            tool_call_id: None,
-        };
-
-        intermediate_messages.push(assistant_message.clone());
-        messages.push(assistant_message);
+        Self::record_tool_message(
+            Self::tool_call_assistant_message(tool_calls.clone(), reasoning_content),
+            messages,
+            intermediate_messages,
+        );
 
         for tool_call in tool_calls {
             if cancellation
@@ -504,8 +772,7 @@ impl AIChatService {
                 eprintln!("发送工具执行事件失败: {error_message}");
             }
 
-            intermediate_messages.push(tool_result.tool_message.clone());
-            messages.push(tool_result.tool_message);
+            Self::record_tool_message(tool_result.tool_message, messages, intermediate_messages);
 
             if cancellation
                 .as_ref()
@@ -531,11 +798,69 @@ impl AIChatService {
         target_message_id: &str,
         cancellation: &mut ActiveCancellationRequest,
     ) -> Result<ChatCompletionResponse, AIChatError> {
-        let client = Self::create_client_with_config(api_config);
-        let options = Self::build_options(request);
+        let result = Self::stream_chat_completion(
+            api_config,
+            request,
+            app_handle,
+            target_message_id,
+            cancellation,
+        )
+        .await;
+        let character_uuid = Self::character_uuid_for_events();
+
+        match &result {
+            Ok(response) => {
+                let (finish_reason, content) = response
+                    .choices
+                    .first()
+                    .map(|choice| {
+                        (
+                            choice.finish_reason.as_str(),
+                            choice.message.content.as_str(),
+                        )
+                    })
+                    .unwrap_or(("stop", ""));
+                if let Err(error) = EventEmitter::send_message_stream_done(
+                    app_handle,
+                    &character_uuid,
+                    target_message_id,
+                    finish_reason,
+                    content,
+                ) {
+                    eprintln!("{error}");
+                }
+            }
+            Err(AIChatError::Failed(message)) => {
+                if let Err(error) = EventEmitter::send_message_stream_error(
+                    app_handle,
+                    &character_uuid,
+                    target_message_id,
+                    message,
+                ) {
+                    eprintln!("{error}");
+                }
+            }
+            Err(AIChatError::Aborted(_)) => {}
+        }
+
+        result
+    }
+
+    async fn stream_chat_completion(
+        api_config: &ApiConfig,
+        request: &ChatCompletionRequest,
+        app_handle: &tauri::AppHandle,
+        target_message_id: &str,
+        cancellation: &mut ActiveCancellationRequest,
+    ) -> Result<ChatCompletionResponse, AIChatError> {
+        let request = &Self::sanitize_request(api_config, request);
+        let client = Self::create_client_with_config(api_config)?;
+        let options = Self::build_options(api_config, request);
         let mut messages = request.messages.clone();
+        Self::strip_unsupported_images(api_config, &mut messages);
         let mut intermediate_messages: Vec<ChatMessage> = Vec::new();
         let character_uuid = Self::character_uuid_for_events();
+        let mut tool_budget = ToolIterationBudget::new(request.max_tool_iterations);
 
         loop {
             if cancellation.is_cancelled() {
@@ -548,10 +873,19 @@ impl AIChatService {
             }
 
             let chat_request = Self::build_chat_request(&messages, request);
-            let stream_response = client
-                .exec_chat_stream(&request.model, chat_request, Some(&options))
-                .await
-                .map_err(|error| AIChatError::failed(format!("AI 流式调用失败: {error}")))?;
+            // 名额在本轮流式读取结束前一直占用，执行工具调用前释放
+            let (stream_response, permit) = Self::with_retries(api_config, || {
+                client.exec_chat_stream(&request.model, chat_request.clone(), Some(&options))
+            })
+            .await?
+            .map_err(|error| {
+                AIChatError::failed(Self::report_provider_error(
+                    Some(app_handle),
+                    api_config,
+                    "AI 流式调用失败",
+                    &error,
+                ))
+            })?;
 
             let mut stream = stream_response.stream;
             let mut emitted_delta = false;
@@ -643,6 +977,8 @@ impl AIChatService {
                 }
             }
 
+            drop(permit);
+
             let Some(stream_end) = stream_end else {
                 Self::maybe_emit_stream_abort(app_handle, &character_uuid, target_message_id);
                 return Err(AIChatError::failed("AI 流式响应在结束前中断"));
@@ -698,10 +1034,10 @@ impl AIChatService {
                 )?;
             }
 
-            let tool_calls = assistant_message.tool_calls.clone().unwrap_or_default();
-            if tool_calls.is_empty() {
-                return Ok(response);
-            }
+            let tool_calls = match Self::next_tool_round(response, &mut tool_budget) {
+                ControlFlow::Break(response) => return Ok(response),
+                ControlFlow::Continue(tool_calls) => tool_calls,
+            };
 
             Self::execute_tool_calls(
                 app_handle,
@@ -717,40 +1053,198 @@ impl AIChatService {
         }
     }
 
+    /// 探测用的最小请求，已按提供商能力清理采样参数（推理模型不接受 temperature，否则会被误判为不支持）
+    fn probe_request(
+        api_config: &ApiConfig,
+        tools: Option<Vec<ToolDefinition>>,
+    ) -> ChatCompletionRequest {
+        let request = ChatCompletionRequest {
+            model: api_config.model.clone(),
+            messages: vec![ChatMessage {
+                role: MessageRole::User,
+                content: "Reply with exactly one short word: PONG".to_string(),
+                name: None,
+                reasoning_content: None,
+                tool_calls: None,
+                tool_call_id: None,
+                images: None,
+            }],
+            temperature: Some(0.0),
+            max_tokens: Some(16),
+            top_p: None,
+            frequency_penalty: None,
+            presence_penalty: None,
+            stop: None,
+            stream: None,
+            tools,
+            tool_choice: None,
+            max_tool_iterations: None,
+            response_format: None,
+            seed: None,
+        };
+        Self::sanitize_request(api_config, &request)
+    }
+
+    /// 探测是否支持流式响应：收到首个流事件即视为支持
+    pub async fn probe_streaming(api_config: &ApiConfig) -> Result<(), String> {
+        let client = Self::create_client_with_config(api_config)?;
+        let request = Self::probe_request(api_config, None);
+        let options = Self::build_options(api_config, &request);
+        let _permit = REQUEST_LIMITER.acquire().await?;
+        let stream_response = client
+            .exec_chat_stream(
+                &request.model,
+                Self::build_chat_request(&request.messages, &request),
+                Some(&options),
+            )
+            .await
+            .map_err(|error| format!("流式请求失败: {error}"))?;
+
+        let mut stream = stream_response.stream;
+        match stream.next().await {
+            Some(Ok(_)) => Ok(()),
+            Some(Err(error)) => Err(format!("流式事件处理失败: {error}")),
+            None => Err("流式响应为空".to_string()),
+        }
+    }
+
+    /// 发起一次独立于会话的流式请求用于诊断，逐块回调并返回计时汇总（计时从获得请求名额后开始）
+    pub async fn stream_test(
+        api_config: &ApiConfig,
+        prompt: &str,
+        on_chunk: impl FnMut(&StreamTestChunk),
+    ) -> Result<StreamTestSummary, String> {
+        let client = Self::create_client_with_config(api_config)?;
+        let mut request = Self::probe_request(api_config, None);
+        request.messages[0].content = prompt.to_string();
+        request.temperature = None;
+        request.max_tokens = None;
+        let options = Self::build_options(api_config, &request);
+        let _permit = REQUEST_LIMITER.acquire().await?;
+        let started = std::time::Instant::now();
+
+        let stream_response = client
+            .exec_chat_stream(
+                &request.model,
+                Self::build_chat_request(&request.messages, &request),
+                Some(&options),
+            )
+            .await
+            .map_err(|error| format!("流式请求失败: {error}"))?;
+
+        let deltas = stream_response.stream.filter_map(|event| async move {
+            match event {
+                Ok(GenAiChatStreamEvent::Chunk(chunk)) => Some(Ok(StreamTestDelta {
+                    content: chunk.content,
+                    reasoning: false,
+                })),
+                Ok(GenAiChatStreamEvent::ReasoningChunk(chunk)) => Some(Ok(StreamTestDelta {
+                    content: chunk.content,
+                    reasoning: true,
+                })),
+                Ok(_) => None,
+                Err(error) => Some(Err(format!("流式事件处理失败: {error}"))),
+            }
+        });
+
+        measure_stream(started, deltas, on_chunk).await
+    }
+
+    /// 探测是否支持视觉输入：携带一张 1x1 图片的请求被接受即视为支持
+    pub async fn probe_vision_support(api_config: &ApiConfig) -> Result<(), String> {
+        let client = Self::create_client_with_config(api_config)?;
+        let mut request = Self::probe_request(api_config, None);
+        request.messages[0].images =
+            Some(vec![format!("data:image/png;base64,{PROBE_IMAGE_BASE64}")]);
+        let options = Self::build_options(api_config, &request);
+        let _permit = REQUEST_LIMITER.acquire().await?;
+
+        client
+            .exec_chat(
+                &request.model,
+                Self::build_chat_request(&request.messages, &request),
+                Some(&options),
+            )
+            .await
+            .map(|_| ())
+            .map_err(|error| format!("图片输入请求失败: {error}"))
+    }
+
+    /// 探测是否支持工具调用：携带工具定义的请求被接受即视为支持
+    pub async fn probe_tool_support(api_config: &ApiConfig) -> Result<(), String> {
+        let client = Self::create_client_with_config(api_config)?;
+        let probe_tool = ToolDefinition {
+            tool_type: "function".to_string(),
+            function: crate::ai_tools::ToolFunction {
+                name: "ping".to_string(),
+                description: Some("Connectivity probe, never needs to be called".to_string()),
+                parameters: Some(crate::ai_tools::ToolParameters {
+                    param_type: "object".to_string(),
+                    properties: HashMap::new(),
+                    required: None,
+                }),
+            },
+        };
+        let request = Self::probe_request(api_config, Some(vec![probe_tool]));
+        let options = Self::build_options(api_config, &request);
+        let _permit = REQUEST_LIMITER.acquire().await?;
+
+        client
+            .exec_chat(
+                &request.model,
+                Self::build_chat_request(&request.messages, &request),
+                Some(&options),
+            )
+            .await
+            .map(|_| ())
+            .map_err(|error| format!("工具调用请求失败: {error}"))
+    }
+
     pub async fn create_chat_completion(
         api_config: &ApiConfig,
         request: &ChatCompletionRequest,
         app_handle: Option<&tauri::AppHandle>,
         target_message_id: Option<&str>,
     ) -> Result<ChatCompletionResponse, String> {
-        let client = Self::create_client_with_config(api_config);
-        let options = Self::build_options(request);
+        let request = &Self::sanitize_request(api_config, request);
+        let client = Self::create_client_with_config(api_config)?;
+        let options = Self::build_options(api_config, request);
         let mut messages = request.messages.clone();
+        Self::strip_unsupported_images(api_config, &mut messages);
         let mut intermediate_messages: Vec<ChatMessage> = Vec::new();
         let character_uuid = app_handle.map(|_| Self::character_uuid_for_events());
+        let mut tool_budget = ToolIterationBudget::new(request.max_tool_iterations);
 
         loop {
             let chat_request = Self::build_chat_request(&messages, request);
 
-            let response = client
-                .exec_chat(&request.model, chat_request, Some(&options))
-                .await
-                .map_err(|error| format!("AI API调用失败: {error}"))?;
+            let (response, permit) = Self::with_retries(api_config, || {
+                client.exec_chat(&request.model, chat_request.clone(), Some(&options))
+            })
+            .await?
+            .map_err(|error| {
+                Self::report_provider_error(app_handle, api_config, "AI API调用失败", &error)
+            })?;
+            drop(permit);
 
-            let mut converted_response = Self::convert_response_from_genai(&response);
+            let converted_response = Self::convert_response_from_genai(&response);
             let assistant_message = converted_response
                 .choices
                 .first()
                 .map(|choice| choice.message.clone())
                 .unwrap_or_else(Self::empty_assistant_message);
 
-            let tool_calls = assistant_message.tool_calls.clone().unwrap_or_default();
-            if tool_calls.is_empty() || app_handle.is_none() {
-                if !intermediate_messages.is_empty() {
-                    converted_response.intermediate_messages = Some(intermediate_messages);
+            let step = match app_handle {
+                Some(_) => Self::next_tool_round(converted_response, &mut tool_budget),
+                None => ControlFlow::Break(converted_response),
+            };
+            let tool_calls = match step {
+                ControlFlow::Break(mut response) => {
+                    Self::attach_intermediate_messages(&mut response, intermediate_messages);
+                    return Ok(response);
                 }
-                return Ok(converted_response);
-            }
+                ControlFlow::Continue(tool_calls) => tool_calls,
+            };
 
             let app_handle = app_handle.expect("checked above");
             let character_uuid = character_uuid.as_deref().unwrap_or("unknown");
@@ -774,3 +1268,364 @@ impl AIChatService {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_message(role: MessageRole, content: &str) -> ChatMessage {
+        ChatMessage {
+            role,
+            content: content.to_string(),
+            name: None,
+            reasoning_content: None,
+            tool_calls: None,
+            tool_call_id: None,
+            images: None,
+        }
+    }
+
+    #[test]
+    fn claude_request_lifts_system_prompt_and_keeps_tools() {
+        let messages = vec![
+            text_message(MessageRole::System, "You help write character cards."),
+            text_message(MessageRole::User, "Describe Alice."),
+        ];
+        let request = ChatCompletionRequest {
+            model: "claude-3-5-sonnet-latest".to_string(),
+            messages: messages.clone(),
+            temperature: None,
+            max_tokens: Some(1024),
+            top_p: None,
+            frequency_penalty: None,
+            presence_penalty: None,
+            stop: None,
+            stream: Some(true),
+            tools: Some(vec![ToolDefinition {
+                tool_type: "function".to_string(),
+                function: crate::ai_tools::ToolFunction {
+                    name: "edit_character".to_string(),
+                    description: Some("Edit a character field".to_string()),
+                    parameters: None,
+                },
+            }]),
+            tool_choice: None,
+            max_tool_iterations: None,
+            response_format: None,
+            seed: None,
+        };
+
+        let chat_request = AIChatService::build_chat_request(&messages, &request);
+
+        assert_eq!(
+            adapter::adapter_kind(crate::api_config::ApiProvider::Claude),
+            genai::adapter::AdapterKind::Anthropic
+        );
+        assert_eq!(
+            chat_request.system.as_deref(),
+            Some("You help write character cards.")
+        );
+        assert_eq!(chat_request.messages.len(), 1);
+        assert_eq!(chat_request.messages[0].role, genai::chat::ChatRole::User);
+        let tools = chat_request.tools.expect("tools should be forwarded");
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].name.to_string(), "edit_character");
+    }
+
+    #[test]
+    fn user_message_with_image_becomes_multipart_content() {
+        let mut message = text_message(MessageRole::User, "Describe this character.");
+        message.images = Some(vec![format!("data:image/png;base64,{PROBE_IMAGE_BASE64}")]);
+
+        let converted = AIChatService::convert_messages_to_genai(&[message.clone()]);
+        let parts = converted[0].content.parts();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(
+            converted[0].content.texts(),
+            vec!["Describe this character."]
+        );
+        let images = converted[0].content.binaries();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].content_type, "image/png");
+
+        let mut api_config: ApiConfig = serde_json::from_value(serde_json::json!({
+            "profile": "Text only",
+            "base_url": "https://api.openai.com/v1",
+            "api_key": "sk-test",
+            "model": "gpt-3.5-turbo",
+            "default": false,
+            "enabled": true,
+            "capabilities": {
+                "supports_streaming": true,
+                "supports_tools": true,
+                "supports_models_endpoint": true,
+                "supports_vision": false
+            }
+        }))
+        .unwrap();
+        let mut messages = vec![message];
+        AIChatService::strip_unsupported_images(&api_config, &mut messages);
+        let converted = AIChatService::convert_messages_to_genai(&messages);
+        assert_eq!(converted[0].content.parts().len(), 1);
+        assert!(converted[0].content.binaries().is_empty());
+
+        api_config.capabilities = None;
+        messages[0].images = Some(vec!["https://example.com/portrait.webp?size=2".to_string()]);
+        AIChatService::strip_unsupported_images(&api_config, &mut messages);
+        let converted = AIChatService::convert_messages_to_genai(&messages);
+        assert_eq!(
+            converted[0].content.binaries()[0].content_type,
+            "image/webp"
+        );
+        assert!(AIChatService::image_content_part("not-an-image").is_none());
+    }
+
+    #[test]
+    fn probe_request_for_reasoning_model_carries_no_temperature() {
+        let mut api_config: ApiConfig = serde_json::from_value(serde_json::json!({
+            "profile": "Reasoning",
+            "base_url": "https://api.openai.com/v1",
+            "api_key": "sk-test",
+            "model": "o3-mini",
+            "default": false,
+            "enabled": true
+        }))
+        .unwrap();
+
+        let request = AIChatService::probe_request(&api_config, None);
+        assert_eq!(request.temperature, None);
+        assert_eq!(request.max_tokens, Some(16));
+
+        api_config.model = "gpt-4o".to_string();
+        let request = AIChatService::probe_request(&api_config, None);
+        assert_eq!(request.temperature, Some(0.0));
+    }
+
+    /// 每一轮都请求调用工具的模型响应
+    fn tool_call_response(round: u32) -> ChatCompletionResponse {
+        let tool_call = ToolCallData {
+            id: "call-1".to_string(),
+            call_type: "function".to_string(),
+            function: ToolCallFunctionData {
+                name: "edit_character".to_string(),
+                arguments: "{}".to_string(),
+            },
+            thought_signatures: None,
+        };
+        ChatCompletionResponse {
+            id: format!("resp-{round}"),
+            object: "chat.completion".to_string(),
+            created: 0,
+            model: "mock".to_string(),
+            system_fingerprint: None,
+            choices: vec![ChatCompletionChoice {
+                index: 0,
+                message: ChatMessage {
+                    tool_calls: Some(vec![tool_call]),
+                    ..text_message(MessageRole::Assistant, &format!("第 {round} 轮"))
+                },
+                finish_reason: "tool_calls".to_string(),
+            }],
+            usage: Usage {
+                prompt_tokens: 0,
+                completion_tokens: 0,
+                total_tokens: 0,
+            },
+            intermediate_messages: None,
+        }
+    }
+
+    /// 按 create_chat_completion 的方式驱动工具循环，返回执行的工具轮数与最终响应
+    fn run_tool_loop(max_tool_iterations: Option<u32>) -> (u32, ChatCompletionResponse) {
+        let mut budget = ToolIterationBudget::new(max_tool_iterations);
+        let mut executed = 0;
+        for round in 1.. {
+            match AIChatService::next_tool_round(tool_call_response(round), &mut budget) {
+                ControlFlow::Continue(_) => executed += 1,
+                ControlFlow::Break(response) => return (executed, response),
+            }
+        }
+        unreachable!("工具循环应当在轮数耗尽时结束")
+    }
+
+    #[test]
+    fn tool_loop_stops_at_configured_count() {
+        let (executed, response) = run_tool_loop(Some(3));
+        assert_eq!(executed, 3);
+        let choice = &response.choices[0];
+        assert_eq!(choice.message.content, "第 4 轮");
+        assert_eq!(choice.finish_reason, TOOL_ITERATIONS_EXCEEDED_FINISH_REASON);
+        assert!(choice.message.tool_calls.is_none());
+
+        let (executed, _) = run_tool_loop(None);
+        assert_eq!(executed, 5);
+    }
+
+    #[test]
+    fn tool_loop_hitting_limit_returns_last_assistant_message() {
+        let mut budget = ToolIterationBudget::new(Some(2));
+        for round in 1..=2 {
+            let ControlFlow::Continue(tool_calls) =
+                AIChatService::next_tool_round(tool_call_response(round), &mut budget)
+            else {
+                panic!("第 {round} 轮应当继续执行工具调用");
+            };
+            assert_eq!(tool_calls.len(), 1);
+            assert_eq!(tool_calls[0].id, "call-1");
+        }
+
+        let ControlFlow::Break(response) =
+            AIChatService::next_tool_round(tool_call_response(3), &mut budget)
+        else {
+            panic!("第 3 轮应当因达到上限而结束");
+        };
+        let choice = &response.choices[0];
+        assert_eq!(choice.finish_reason, TOOL_ITERATIONS_EXCEEDED_FINISH_REASON);
+        assert_eq!(choice.message.content, "第 3 轮");
+        assert!(choice.message.tool_calls.is_none());
+    }
+
+    #[test]
+    fn single_tool_round_trip_yields_two_intermediate_messages() {
+        let tool_call = ToolCallData {
+            id: "call-1".to_string(),
+            call_type: "function".to_string(),
+            function: ToolCallFunctionData {
+                name: "edit_character".to_string(),
+                arguments: "{}".to_string(),
+            },
+            thought_signatures: None,
+        };
+        let mut messages = vec![text_message(MessageRole::User, "改名为 Bob")];
+        let mut intermediate_messages = Vec::new();
+
+        AIChatService::record_tool_message(
+            AIChatService::tool_call_assistant_message(vec![tool_call], None),
+            &mut messages,
+            &mut intermediate_messages,
+        );
+        AIChatService::record_tool_message(
+            ChatMessage {
+                tool_call_id: Some("call-1".to_string()),
+                ..text_message(MessageRole::Tool, "ok")
+            },
+            &mut messages,
+            &mut intermediate_messages,
+        );
+
+        let mut response = ChatCompletionResponse {
+            id: "resp".to_string(),
+            object: "chat.completion".to_string(),
+            created: 0,
+            model: "mock".to_string(),
+            system_fingerprint: None,
+            choices: Vec::new(),
+            usage: Usage {
+                prompt_tokens: 0,
+                completion_tokens: 0,
+                total_tokens: 0,
+            },
+            intermediate_messages: None,
+        };
+        AIChatService::attach_intermediate_messages(&mut response, Vec::new());
+        assert!(response.intermediate_messages.is_none());
+
+        AIChatService::attach_intermediate_messages(&mut response, intermediate_messages);
+        let intermediate = response.intermediate_messages.unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(intermediate.len(), 2);
+        assert_eq!(intermediate[0].role, MessageRole::Assistant);
+        assert!(intermediate[0].tool_calls.is_some());
+        assert_eq!(intermediate[1].role, MessageRole::Tool);
+        assert_eq!(intermediate[1].tool_call_id.as_deref(), Some("call-1"));
+    }
+
+    #[test]
+    fn tool_budget_clamps_out_of_range_values() {
+        assert_eq!(ToolIterationBudget::new(Some(0)).max_iterations(), 1);
+        assert_eq!(ToolIterationBudget::new(Some(100)).max_iterations(), 20);
+    }
+
+    #[test]
+    fn json_mode_is_requested_only_for_openai_style_providers() {
+        let mut api_config: ApiConfig = serde_json::from_value(serde_json::json!({
+            "profile": "OpenAI",
+            "provider": "open_ai_compatible",
+            "base_url": "https://api.openai.com/v1",
+            "api_key": "sk-test",
+            "model": "gpt-4.1",
+            "default": true,
+            "enabled": true
+        }))
+        .unwrap();
+        let request = ChatCompletionRequest {
+            model: "gpt-4.1".to_string(),
+            messages: vec![text_message(MessageRole::User, "Reply in JSON.")],
+            temperature: None,
+            max_tokens: None,
+            top_p: None,
+            frequency_penalty: None,
+            presence_penalty: None,
+            stop: None,
+            stream: None,
+            tools: None,
+            tool_choice: None,
+            max_tool_iterations: None,
+            response_format: Some(ResponseFormat::JsonObject),
+            seed: None,
+        };
+
+        let options = AIChatService::build_options(&api_config, &request);
+        assert!(matches!(
+            options.response_format,
+            Some(ChatResponseFormat::JsonMode)
+        ));
+
+        api_config.provider = crate::api_config::ApiProvider::Claude;
+        let options = AIChatService::build_options(&api_config, &request);
+        assert!(options.response_format.is_none());
+
+        let format: ResponseFormat =
+            serde_json::from_value(serde_json::json!({"type": "json_object"})).unwrap();
+        assert_eq!(format, ResponseFormat::JsonObject);
+    }
+
+    #[test]
+    fn seed_round_trips_and_reaches_chat_options() {
+        let request: ChatCompletionRequest = serde_json::from_value(serde_json::json!({
+            "model": "gpt-4.1",
+            "messages": [],
+            "temperature": null,
+            "max_tokens": null,
+            "top_p": null,
+            "frequency_penalty": null,
+            "presence_penalty": null,
+            "stop": null,
+            "stream": null,
+            "tools": null,
+            "tool_choice": null,
+            "seed": 42
+        }))
+        .unwrap();
+        assert_eq!(request.seed, Some(42));
+
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["seed"], 42);
+        let restored: ChatCompletionRequest = serde_json::from_value(json).unwrap();
+        assert_eq!(restored.seed, Some(42));
+
+        let api_config: ApiConfig = serde_json::from_value(serde_json::json!({
+            "profile": "OpenAI",
+            "base_url": "https://api.openai.com/v1",
+            "api_key": "sk-test",
+            "model": "gpt-4.1",
+            "default": true,
+            "enabled": true
+        }))
+        .unwrap();
+        assert_eq!(
+            AIChatService::build_options(&api_config, &restored).seed,
+            Some(42)
+        );
+    }
+}
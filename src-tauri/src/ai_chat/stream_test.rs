@@ -0,0 +1,122 @@
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+/// 测试流中的一段增量内容
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamTestDelta {
+    pub content: String,
+    pub reasoning: bool,
+}
+
+/// stream-test-chunk 事件载荷：elapsed_ms 从发起请求算起，delta_ms 为距上一块的间隔
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StreamTestChunk {
+    pub index: usize,
+    pub content: String,
+    pub reasoning: bool,
+    pub elapsed_ms: u64,
+    pub delta_ms: u64,
+}
+
+/// 流式测试汇总；没有收到任何内容块时 first_chunk_ms 为 None
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct StreamTestSummary {
+    pub total_chunks: usize,
+    pub total_time_ms: u64,
+    pub first_chunk_ms: Option<u64>,
+}
+
+/// 逐块读取测试流并计时，每收到一块调用一次 on_chunk；空内容块不计数
+pub async fn measure_stream<S>(
+    started: Instant,
+    stream: S,
+    mut on_chunk: impl FnMut(&StreamTestChunk),
+) -> Result<StreamTestSummary, String>
+where
+    S: Stream<Item = Result<StreamTestDelta, String>>,
+{
+    let mut stream = std::pin::pin!(stream);
+    let mut total_chunks = 0;
+    let mut first_chunk_ms = None;
+    let mut last_elapsed_ms = 0;
+
+    while let Some(delta) = stream.next().await {
+        let delta = delta?;
+        if delta.content.is_empty() {
+            continue;
+        }
+
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        first_chunk_ms.get_or_insert(elapsed_ms);
+        on_chunk(&StreamTestChunk {
+            index: total_chunks,
+            content: delta.content,
+            reasoning: delta.reasoning,
+            elapsed_ms,
+            delta_ms: elapsed_ms.saturating_sub(last_elapsed_ms),
+        });
+        total_chunks += 1;
+        last_elapsed_ms = elapsed_ms;
+    }
+
+    Ok(StreamTestSummary {
+        total_chunks,
+        total_time_ms: started.elapsed().as_millis() as u64,
+        first_chunk_ms,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{measure_stream, StreamTestDelta};
+    use std::time::Instant;
+
+    fn delta(content: &str, reasoning: bool) -> Result<StreamTestDelta, String> {
+        Ok(StreamTestDelta {
+            content: content.to_string(),
+            reasoning,
+        })
+    }
+
+    #[tokio::test]
+    async fn mocked_stream_reports_each_chunk_and_summary() {
+        let stream = futures_util::stream::iter(vec![
+            delta("thinking", true),
+            delta("", false),
+            delta("PO", false),
+            delta("NG", false),
+        ]);
+        let mut chunks = Vec::new();
+
+        let summary = measure_stream(Instant::now(), stream, |chunk| chunks.push(chunk.clone()))
+            .await
+            .unwrap();
+
+        assert_eq!(summary.total_chunks, 3);
+        assert_eq!(
+            chunks.iter().map(|chunk| chunk.index).collect::<Vec<_>>(),
+            vec![0, 1, 2]
+        );
+        assert!(chunks[0].reasoning);
+        assert_eq!(chunks[2].content, "NG");
+        assert_eq!(summary.first_chunk_ms, Some(chunks[0].elapsed_ms));
+        assert!(summary.total_time_ms >= chunks[2].elapsed_ms);
+    }
+
+    #[tokio::test]
+    async fn stream_error_is_returned_after_earlier_chunks() {
+        let stream = futures_util::stream::iter(vec![
+            delta("partial", false),
+            Err("connection reset".to_string()),
+        ]);
+        let mut chunk_count = 0;
+
+        let error = measure_stream(Instant::now(), stream, |_| chunk_count += 1)
+            .await
+            .unwrap_err();
+
+        assert_eq!(error, "connection reset");
+        assert_eq!(chunk_count, 1);
+    }
+}
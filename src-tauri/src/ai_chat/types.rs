@@ -20,6 +20,9 @@ pub struct ChatMessage {
     pub reasoning_content: Option<String>,
     pub tool_calls: Option<Vec<ToolCallData>>,
     pub tool_call_id: Option<String>,
+    /// 用户消息附带的图片（data URL 或 http(s) URL），模型不支持视觉输入时忽略
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub images: Option<Vec<String>>,
 }
 
 /// 工具调用数据
@@ -64,6 +67,14 @@ pub enum StopSequence {
     Multiple(Vec<String>),
 }
 
+/// 响应格式，序列化为 {"type":"json_object"} / {"type":"text"}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+    Text,
+    JsonObject,
+}
+
 /// 使用统计
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Usage {
@@ -110,6 +121,15 @@ pub struct ChatCompletionRequest {
     pub stream: Option<bool>,
     pub tools: Option<Vec<ToolDefinition>>,
     pub tool_choice: Option<ToolChoice>,
+    /// 最多执行多少轮工具调用，未指定时使用默认值
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tool_iterations: Option<u32>,
+    /// 期望的响应格式，不支持 JSON 模式的提供商会忽略该设置
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+    /// 随机种子，支持的模型据此尽量给出可复现的结果
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
 }
 
 #[derive(Debug, Clone)]
@@ -121,8 +141,46 @@ pub(super) struct ToolExecutionOutput {
     pub(super) execution_time_ms: u64,
 }
 
+/// 单轮对话内工具调用轮数的预算
+#[derive(Debug, Clone)]
+pub(super) struct ToolIterationBudget {
+    max_iterations: u32,
+    used_iterations: u32,
+}
+
+impl ToolIterationBudget {
+    pub(super) fn new(max_iterations: Option<u32>) -> Self {
+        Self {
+            max_iterations: max_iterations
+                .unwrap_or_else(crate::ai_config::default_max_tool_iterations)
+                .clamp(
+                    crate::ai_config::MIN_MAX_TOOL_ITERATIONS,
+                    crate::ai_config::MAX_MAX_TOOL_ITERATIONS,
+                ),
+            used_iterations: 0,
+        }
+    }
+
+    /// 尝试消耗一轮工具调用，预算耗尽时返回 false
+    pub(super) fn try_consume(&mut self) -> bool {
+        if self.used_iterations >= self.max_iterations {
+            return false;
+        }
+
+        self.used_iterations += 1;
+        true
+    }
+
+    pub(super) fn max_iterations(&self) -> u32 {
+        self.max_iterations
+    }
+}
+
 pub const AI_RESPONSE_INTERRUPTED_ERROR: &str = "AI 响应已中断";
 
+/// 工具调用轮数耗尽时返回响应的 finish_reason，界面据此展示部分进度
+pub const TOOL_ITERATIONS_EXCEEDED_FINISH_REASON: &str = "tool_iterations_exceeded";
+
 #[derive(Debug, Clone)]
 pub struct AbortedGeneration {
     pub content: String,
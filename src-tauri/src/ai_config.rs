@@ -1,4 +1,7 @@
 use super::file_utils::FileUtils;
+use crate::ai_tools::ToolDefinition;
+use crate::backend::domain::ContextBuilderOptions;
+use crate::persona::PersonaService;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
@@ -16,6 +19,13 @@ fn default_tools_enabled() -> bool {
     true
 }
 
+pub const MIN_MAX_TOOL_ITERATIONS: u32 = 1;
+pub const MAX_MAX_TOOL_ITERATIONS: u32 = 20;
+
+pub fn default_max_tool_iterations() -> u32 {
+    5
+}
+
 fn default_context_role_template() -> String {
     "角色卡编写助手".to_string()
 }
@@ -49,6 +59,35 @@ pub struct AIRole {
     pub context_task_template: String,
     #[serde(default = "default_context_instructions_template")]
     pub context_instructions_template: String,
+    /// 允许该角色使用的工具名称，未设置时提供全部可用工具
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_tools: Option<Vec<String>>,
+    /// 要求模型以 JSON 对象回复（仅对支持 JSON 模式的提供商生效）
+    #[serde(default)]
+    pub json_mode: bool,
+    /// 随机种子，设置后用于可复现的生成
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    /// 该角色单轮对话的最大工具调用轮数，未设置时使用全局设置
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tool_iterations: Option<u32>,
+}
+
+impl AIRole {
+    /// 按工具开关与允许列表筛选实际提供给模型的工具
+    pub fn offered_tools(&self, tools: Vec<ToolDefinition>) -> Vec<ToolDefinition> {
+        if !self.tools_enabled {
+            return Vec::new();
+        }
+
+        match &self.allowed_tools {
+            Some(allowed_tools) => tools
+                .into_iter()
+                .filter(|tool| allowed_tools.contains(&tool.function.name))
+                .collect(),
+            None => tools,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -62,6 +101,18 @@ pub struct AIRoleRecord {
 pub struct AIConfig {
     pub default_role: String,
     pub roles: HashMap<String, AIRole>,
+    /// 单轮对话中最多执行多少轮工具调用
+    #[serde(default = "default_max_tool_iterations")]
+    pub max_tool_iterations: u32,
+    /// 自定义上下文指令，设置后覆盖角色自带的指令模板
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_instructions: Option<String>,
+    /// 世界书条目最低重要性，低于该值的条目不进入上下文
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_importance: Option<f64>,
+    /// 保存的助手回复最大字符数，超出部分截断（None 表示不限制）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_reply_chars: Option<usize>,
 }
 
 /// AI配置服务
@@ -85,6 +136,10 @@ impl AIConfigService {
             context_role_template: "角色卡编写助手".to_string(),
                   context_task_template: "帮助用户创作和完善角色设定, 需要从多个角度(角色动机，角色心理，角色性格，角色背景)等分析，完成角色卡。当需要局部修改某个字段中的一句话、某个 trait 或某段内容时，优先先读后写：不确定当前文本时使用 read_character_field 或 patch_character_field(dry_run=true) 预览，确认唯一命中后再执行 patch_character_field；只有当用户明确要求重写整个字段时，才使用 edit_character。当处理世界书时，先使用 list_world_book_entries 查看候选，必要时用 read_world_book_entry 读取完整条目；创建使用 create_world_book_entry，更新使用 update_world_book_entry，删除使用 delete_world_book_entry，并尽量传 entry_id 以避免误操作。".to_string(),
                   context_instructions_template: "基于用户需求分析现有角色设定，提供建议并调用相应工具。\n始终保持角色设定的一致性和逻辑性，遵循用户的具体要求。\n如果需要局部修改角色信息，优先先用 read_character_field 或 patch_character_field(dry_run=true) 确认当前文本，再使用 patch_character_field；search 必须唯一命中，0 个或超过 1 个匹配都应视为失败。\n只有在用户明确要求重写整个字段时，才使用 edit_character 工具。\n如果需要处理世界书，先使用 list_world_book_entries，必要时再用 read_world_book_entry / update_world_book_entry / delete_world_book_entry；如果需要添加世界书条目，请使用 create_world_book_entry 工具。".to_string(),
+            allowed_tools: None,
+            json_mode: false,
+            seed: None,
+            max_tool_iterations: None,
         }
     }
 
@@ -99,6 +154,10 @@ impl AIConfigService {
             context_role_template: "创意写作助手".to_string(),
             context_task_template: "围绕角色卡和世界观帮助用户进行剧情构思、桥段展开、对白润色与创作延展。必要时可以调用工具同步角色卡与世界书。".to_string(),
                  context_instructions_template: "优先保持创意、多样性与角色一致性。\n如果用户要求你直接修改角色设定中的局部内容，先使用 read_character_field 或 patch_character_field(dry_run=true) 确认上下文，再使用 patch_character_field；只有明确要求整段重写时才使用 edit_character。\n如果用户要求补充或调整世界观知识，先使用 list_world_book_entries / read_world_book_entry 了解现状；新增请使用 create_world_book_entry，更新请使用 update_world_book_entry。".to_string(),
+            allowed_tools: None,
+            json_mode: false,
+            seed: None,
+            max_tool_iterations: None,
         }
     }
 
@@ -113,6 +172,10 @@ impl AIConfigService {
             context_role_template: "角色分析师".to_string(),
             context_task_template: "分析角色设定的合理性、层次感、一致性与可写性，并给出结构化建议。".to_string(),
             context_instructions_template: "优先给出分析、诊断和建议，不主动调用工具。\n保持批判性但语气友好。\n当用户要求具体修改方案时，先解释原因，再给出可执行建议。".to_string(),
+            allowed_tools: None,
+            json_mode: false,
+            seed: None,
+            max_tool_iterations: None,
         }
     }
 
@@ -136,6 +199,10 @@ impl AIConfigService {
         AIConfig {
             default_role: "character_assistant".to_string(),
             roles,
+            max_tool_iterations: default_max_tool_iterations(),
+            context_instructions: None,
+            min_importance: None,
+            max_reply_chars: None,
         }
     }
 
@@ -150,6 +217,10 @@ impl AIConfigService {
             }
         }
 
+        config.max_tool_iterations = config
+            .max_tool_iterations
+            .clamp(MIN_MAX_TOOL_ITERATIONS, MAX_MAX_TOOL_ITERATIONS);
+
         if !config.roles.contains_key(&config.default_role) {
             if let Some(first_role_id) = config.roles.keys().next().cloned() {
                 config.default_role = first_role_id;
@@ -328,6 +399,101 @@ impl AIConfigService {
         Ok(())
     }
 
+    /// 获取单轮对话的最大工具调用轮数
+    pub fn get_max_tool_iterations(app_handle: &tauri::AppHandle) -> Result<u32, String> {
+        Ok(Self::load_config(app_handle)?.max_tool_iterations)
+    }
+
+    /// 设置单轮对话的最大工具调用轮数
+    pub fn set_max_tool_iterations(
+        app_handle: &tauri::AppHandle,
+        max_tool_iterations: u32,
+    ) -> Result<(), String> {
+        if !(MIN_MAX_TOOL_ITERATIONS..=MAX_MAX_TOOL_ITERATIONS).contains(&max_tool_iterations) {
+            return Err(format!(
+                "max_tool_iterations must be between {} and {}",
+                MIN_MAX_TOOL_ITERATIONS, MAX_MAX_TOOL_ITERATIONS
+            ));
+        }
+
+        let mut config = Self::load_config(app_handle)?;
+        config.max_tool_iterations = max_tool_iterations;
+        Self::save_config(app_handle, &config)
+    }
+
+    /// 获取自定义上下文指令（None 表示使用角色自带的指令模板）
+    pub fn get_context_instructions(
+        app_handle: &tauri::AppHandle,
+    ) -> Result<Option<String>, String> {
+        Ok(Self::load_config(app_handle)?.context_instructions)
+    }
+
+    /// 设置自定义上下文指令，传入 None 或空字符串时恢复默认
+    pub fn set_context_instructions(
+        app_handle: &tauri::AppHandle,
+        instructions: Option<String>,
+    ) -> Result<(), String> {
+        let mut config = Self::load_config(app_handle)?;
+        config.context_instructions = instructions.filter(|text| !text.trim().is_empty());
+        Self::save_config(app_handle, &config)
+    }
+
+    /// 获取世界书条目最低重要性（None 表示全部纳入）
+    pub fn get_min_importance(app_handle: &tauri::AppHandle) -> Result<Option<f64>, String> {
+        Ok(Self::load_config(app_handle)?.min_importance)
+    }
+
+    /// 设置世界书条目最低重要性，传入 None 时恢复全部纳入
+    pub fn set_min_importance(
+        app_handle: &tauri::AppHandle,
+        min_importance: Option<f64>,
+    ) -> Result<(), String> {
+        if min_importance.is_some_and(|value| !value.is_finite()) {
+            return Err("min_importance must be a finite number".to_string());
+        }
+
+        let mut config = Self::load_config(app_handle)?;
+        config.min_importance = min_importance;
+        Self::save_config(app_handle, &config)
+    }
+
+    /// 获取助手回复最大字符数（None 表示不限制）
+    pub fn get_max_reply_chars(app_handle: &tauri::AppHandle) -> Result<Option<usize>, String> {
+        Ok(Self::load_config(app_handle)?.max_reply_chars)
+    }
+
+    /// 设置助手回复最大字符数，传入 None 时恢复不限制
+    pub fn set_max_reply_chars(
+        app_handle: &tauri::AppHandle,
+        max_reply_chars: Option<usize>,
+    ) -> Result<(), String> {
+        if max_reply_chars == Some(0) {
+            return Err("max_reply_chars must be greater than 0".to_string());
+        }
+
+        let mut config = Self::load_config(app_handle)?;
+        config.max_reply_chars = max_reply_chars;
+        Self::save_config(app_handle, &config)
+    }
+
+    /// 将全局上下文设置（自定义指令、最低重要性）应用到构建选项
+    pub fn apply_context_settings(
+        app_handle: &tauri::AppHandle,
+        options: &mut ContextBuilderOptions,
+    ) -> Result<(), String> {
+        let config = Self::load_config(app_handle)?;
+        if let Some(instructions) = config.context_instructions {
+            options.instructions = instructions;
+        }
+        options.min_importance = config.min_importance;
+
+        if let Some(persona) = PersonaService::load_persona(app_handle)? {
+            options.user_name = persona.name.clone();
+            options.persona = Some(persona);
+        }
+        Ok(())
+    }
+
     /// 获取所有角色列表
     pub fn get_all_roles(app_handle: &tauri::AppHandle) -> Result<Vec<AIRoleRecord>, String> {
         let config = Self::load_config(app_handle)?;
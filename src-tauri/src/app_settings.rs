@@ -0,0 +1,298 @@
+use crate::file_utils::FileUtils;
+use crate::model_cache::DEFAULT_MODEL_CACHE_TTL_SECS;
+use crate::request_limiter::DEFAULT_MAX_CONCURRENT_REQUESTS;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+pub const DEFAULT_MAX_SESSIONS: usize = 10;
+
+fn default_max_sessions() -> usize {
+    DEFAULT_MAX_SESSIONS
+}
+
+fn default_max_concurrent_requests() -> usize {
+    DEFAULT_MAX_CONCURRENT_REQUESTS
+}
+
+pub const DEFAULT_SUMMARIZE_KEEP_RECENT: usize = 10;
+
+fn default_summarize_keep_recent() -> usize {
+    DEFAULT_SUMMARIZE_KEEP_RECENT
+}
+
+fn default_model_cache_ttl_secs() -> u64 {
+    DEFAULT_MODEL_CACHE_TTL_SECS
+}
+
+/// 过期会话自动清理配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoCleanupConfig {
+    pub enabled: bool,
+    /// 清理间隔（分钟）
+    pub interval_minutes: u64,
+    /// 超过该时长未活跃的会话会被卸载（小时）
+    pub max_age_hours: u64,
+}
+
+impl Default for AutoCleanupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_minutes: 60,
+            max_age_hours: 24,
+        }
+    }
+}
+
+/// 应用级设置（app_data/settings.json）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppSettings {
+    /// 最大并发会话数
+    #[serde(default = "default_max_sessions")]
+    pub max_sessions: usize,
+    #[serde(default)]
+    pub auto_cleanup: AutoCleanupConfig,
+    /// 同时进行的 API 请求上限
+    #[serde(default = "default_max_concurrent_requests")]
+    pub max_concurrent_requests: usize,
+    /// /summarize 时保留的最近消息数，更早的消息会被压缩为一条记忆摘要
+    #[serde(default = "default_summarize_keep_recent")]
+    pub summarize_keep_recent: usize,
+    /// 各 API 端点最近一次成功生成所用的模型（endpoint → model）
+    #[serde(default)]
+    pub provider_default_models: HashMap<String, String>,
+    /// 模型列表缓存有效期（秒），0 表示不缓存
+    #[serde(default = "default_model_cache_ttl_secs")]
+    pub model_cache_ttl_secs: u64,
+}
+
+/// 统一端点写法，忽略首尾空白和结尾的斜杠
+fn normalize_endpoint(endpoint: &str) -> String {
+    endpoint.trim().trim_end_matches('/').to_string()
+}
+
+impl AppSettings {
+    /// 记录端点最近使用的模型，返回是否有变化
+    pub fn record_provider_model(&mut self, endpoint: &str, model: &str) -> bool {
+        let endpoint = normalize_endpoint(endpoint);
+        let model = model.trim();
+        if endpoint.is_empty() || model.is_empty() {
+            return false;
+        }
+
+        if self
+            .provider_default_models
+            .get(&endpoint)
+            .map(String::as_str)
+            == Some(model)
+        {
+            return false;
+        }
+
+        self.provider_default_models
+            .insert(endpoint, model.to_string());
+        true
+    }
+
+    pub fn provider_model(&self, endpoint: &str) -> Option<String> {
+        self.provider_default_models
+            .get(&normalize_endpoint(endpoint))
+            .cloned()
+    }
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        Self {
+            max_sessions: DEFAULT_MAX_SESSIONS,
+            auto_cleanup: AutoCleanupConfig::default(),
+            max_concurrent_requests: DEFAULT_MAX_CONCURRENT_REQUESTS,
+            summarize_keep_recent: DEFAULT_SUMMARIZE_KEEP_RECENT,
+            provider_default_models: HashMap::new(),
+            model_cache_ttl_secs: DEFAULT_MODEL_CACHE_TTL_SECS,
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    /// 串行化 settings.json 的读-改-写，避免并发修改互相覆盖
+    static ref SETTINGS_WRITE_LOCK: Mutex<()> = Mutex::new(());
+}
+
+/// 应用设置服务
+pub struct AppSettingsService;
+
+impl AppSettingsService {
+    fn get_settings_path(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
+        let app_data_dir = FileUtils::get_app_data_dir(app_handle)?;
+        Ok(app_data_dir.join("settings.json"))
+    }
+
+    /// 读取设置，文件不存在时返回默认值
+    pub fn load_settings(app_handle: &tauri::AppHandle) -> Result<AppSettings, String> {
+        let settings_path = Self::get_settings_path(app_handle)?;
+        if !settings_path.exists() {
+            return Ok(AppSettings::default());
+        }
+
+        FileUtils::read_json_file(&settings_path)
+    }
+
+    fn save_settings(app_handle: &tauri::AppHandle, settings: &AppSettings) -> Result<(), String> {
+        let settings_path = Self::get_settings_path(app_handle)?;
+        FileUtils::write_json_file(&settings_path, settings)
+    }
+
+    /// 在写锁内读取、修改并保存设置；update 返回 false 时不写回
+    fn update_settings(
+        app_handle: &tauri::AppHandle,
+        update: impl FnOnce(&mut AppSettings) -> bool,
+    ) -> Result<(), String> {
+        let _guard = SETTINGS_WRITE_LOCK
+            .lock()
+            .map_err(|e| format!("锁定失败: {}", e))?;
+        let mut settings = Self::load_settings(app_handle)?;
+        if update(&mut settings) {
+            Self::save_settings(app_handle, &settings)?;
+        }
+        Ok(())
+    }
+
+    pub fn get_max_sessions(app_handle: &tauri::AppHandle) -> Result<usize, String> {
+        Ok(Self::load_settings(app_handle)?.max_sessions.max(1))
+    }
+
+    pub fn set_max_sessions(
+        app_handle: &tauri::AppHandle,
+        max_sessions: usize,
+    ) -> Result<(), String> {
+        if max_sessions == 0 {
+            return Err("最大会话数必须大于 0".to_string());
+        }
+
+        Self::update_settings(app_handle, |settings| {
+            settings.max_sessions = max_sessions;
+            true
+        })
+    }
+
+    pub fn get_max_concurrent_requests(app_handle: &tauri::AppHandle) -> Result<usize, String> {
+        Ok(Self::load_settings(app_handle)?
+            .max_concurrent_requests
+            .max(1))
+    }
+
+    pub fn set_max_concurrent_requests(
+        app_handle: &tauri::AppHandle,
+        max_concurrent_requests: usize,
+    ) -> Result<(), String> {
+        if max_concurrent_requests == 0 {
+            return Err("最大并发请求数必须大于 0".to_string());
+        }
+
+        Self::update_settings(app_handle, |settings| {
+            settings.max_concurrent_requests = max_concurrent_requests;
+            true
+        })
+    }
+
+    pub fn get_model_cache_ttl_secs(app_handle: &tauri::AppHandle) -> Result<u64, String> {
+        Ok(Self::load_settings(app_handle)?.model_cache_ttl_secs)
+    }
+
+    pub fn set_model_cache_ttl_secs(
+        app_handle: &tauri::AppHandle,
+        model_cache_ttl_secs: u64,
+    ) -> Result<(), String> {
+        Self::update_settings(app_handle, |settings| {
+            settings.model_cache_ttl_secs = model_cache_ttl_secs;
+            true
+        })
+    }
+
+    pub fn get_summarize_keep_recent(app_handle: &tauri::AppHandle) -> Result<usize, String> {
+        Ok(Self::load_settings(app_handle)?.summarize_keep_recent)
+    }
+
+    pub fn set_summarize_keep_recent(
+        app_handle: &tauri::AppHandle,
+        summarize_keep_recent: usize,
+    ) -> Result<(), String> {
+        if summarize_keep_recent == 0 {
+            return Err("摘要保留的消息数必须大于 0".to_string());
+        }
+
+        Self::update_settings(app_handle, |settings| {
+            settings.summarize_keep_recent = summarize_keep_recent;
+            true
+        })
+    }
+
+    pub fn get_auto_cleanup_config(
+        app_handle: &tauri::AppHandle,
+    ) -> Result<AutoCleanupConfig, String> {
+        Ok(Self::load_settings(app_handle)?.auto_cleanup)
+    }
+
+    pub fn set_auto_cleanup_config(
+        app_handle: &tauri::AppHandle,
+        config: AutoCleanupConfig,
+    ) -> Result<(), String> {
+        if config.interval_minutes == 0 {
+            return Err("清理间隔必须大于 0 分钟".to_string());
+        }
+        if config.max_age_hours == 0 {
+            return Err("会话过期时间必须大于 0 小时".to_string());
+        }
+
+        Self::update_settings(app_handle, |settings| {
+            settings.auto_cleanup = config;
+            true
+        })
+    }
+
+    /// 获取端点最近一次成功生成所用的模型
+    pub fn get_provider_default_model(
+        app_handle: &tauri::AppHandle,
+        endpoint: &str,
+    ) -> Result<Option<String>, String> {
+        Ok(Self::load_settings(app_handle)?.provider_model(endpoint))
+    }
+
+    /// 生成成功后记录端点使用的模型（不修改 ApiConfig.model）
+    pub fn record_provider_default_model(
+        app_handle: &tauri::AppHandle,
+        endpoint: &str,
+        model: &str,
+    ) -> Result<(), String> {
+        Self::update_settings(app_handle, |settings| {
+            settings.record_provider_model(endpoint, model)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AppSettings;
+
+    #[test]
+    fn record_provider_model_normalizes_endpoint_and_skips_repeats() {
+        let mut settings = AppSettings::default();
+
+        assert!(settings.record_provider_model("https://api.openai.com/v1/", "gpt-4.1"));
+        assert!(!settings.record_provider_model("https://api.openai.com/v1", "gpt-4.1"));
+        assert!(!settings.record_provider_model("https://api.anthropic.com", ""));
+
+        assert_eq!(
+            settings
+                .provider_model("https://api.openai.com/v1")
+                .as_deref(),
+            Some("gpt-4.1")
+        );
+        assert!(settings
+            .provider_model("https://api.anthropic.com")
+            .is_none());
+    }
+}
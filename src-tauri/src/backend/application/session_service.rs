@@ -1,17 +1,49 @@
 use crate::ai_cancellation::AI_CANCELLATION_MANAGER;
-use crate::ai_config::{AIConfigService, AIRole};
-use crate::backend::domain::sessions::config::ContextBuilderOptions;
-use crate::backend::domain::{SessionInfo, SessionUnloadReason, TokenUsageStats};
+use crate::ai_config::{AIConfig, AIConfigService, AIRole};
+use crate::api_config::{
+    estimate_generation_cost, ApiConfig, ApiConfigService, GenerationCostEstimate,
+};
+use crate::app_settings::{AppSettingsService, AutoCleanupConfig};
+use crate::backend::domain::sessions::config::{ContextBuilderOptions, TokenBudget};
+use crate::backend::domain::{
+    ActiveState, PromptPreview, SessionInfo, SessionUnloadReason, TokenUsageStats,
+};
 use crate::character_session::{CharacterSession, SESSION_MANAGER};
+use crate::character_storage::{CharacterData, CharacterStorage};
+use crate::chat_history::{
+    diff_histories, merge_swipe, ChatHistoryDiff, ChatHistoryManager, ChatMessage,
+};
+use crate::context_builder::BuiltContextResult;
 use crate::events::EventEmitter;
 use crate::tools::ToolRegistry;
+use crate::usage_stats::UsageStatsService;
 use tauri::AppHandle;
 
+/// 回复按 max_reply_chars 截断后追加的标记
+const REPLY_TRUNCATION_MARKER: &str = "…";
+
+/// 活跃状态快照中返回的最近消息条数
+const ACTIVE_STATE_RECENT_MESSAGES: usize = 20;
+
 pub struct SessionService;
 
 impl SessionService {
     pub async fn load_session(app_handle: &AppHandle, uuid: String) -> Result<SessionInfo, String> {
-        let session = SESSION_MANAGER.get_or_create_session(app_handle, uuid)?;
+        let mut session = SESSION_MANAGER.get_or_create_session(app_handle, uuid)?;
+
+        // 没有聊天记录时以 first_mes 作为第一条 AI 消息
+        if session.chat_history.is_empty() {
+            let user_name = Self::greeting_user_name(app_handle)?;
+            session =
+                SESSION_MANAGER.with_session(app_handle, session.uuid.clone(), |session| {
+                    if session.chat_history.is_empty()
+                        && session.seed_greeting(-1, &user_name)?.is_some()
+                    {
+                        session.rewrite_all_history_now(app_handle)?;
+                    }
+                    Ok(session.clone())
+                })?;
+        }
 
         let character_data = session.character_data.clone();
         let chat_history = session.chat_history.clone();
@@ -22,21 +54,50 @@ impl SessionService {
         Ok(session.get_session_info())
     }
 
+    /// 开场白中 {{user}} 的替换名称，与构建上下文时使用的用户名一致
+    fn greeting_user_name(app_handle: &AppHandle) -> Result<String, String> {
+        let mut options = ContextBuilderOptions::default();
+        AIConfigService::apply_context_settings(app_handle, &mut options)?;
+        Ok(options.user_name)
+    }
+
+    /// 选择开场白（-1 为 first_mes，0 起为 alternate_greetings）作为第一条 AI 消息，
+    /// 仅在没有对话记录或只有开场白时可用
+    pub fn select_greeting(
+        app_handle: &AppHandle,
+        uuid: String,
+        index: i32,
+    ) -> Result<Option<ChatMessage>, String> {
+        let user_name = Self::greeting_user_name(app_handle)?;
+        let (greeting, chat_history) =
+            SESSION_MANAGER.with_session(app_handle, uuid.clone(), |session| {
+                let greeting = session.seed_greeting(index, &user_name)?;
+                session.rewrite_all_history_now(app_handle)?;
+                Ok((greeting, session.chat_history.clone()))
+            })?;
+
+        EventEmitter::send_chat_history_loaded(app_handle, &uuid, &chat_history)?;
+
+        Ok(greeting)
+    }
+
     pub async fn send_chat_message(
         app_handle: &AppHandle,
         message: String,
+        images: Option<Vec<String>>,
         role_id: Option<String>,
     ) -> Result<(), String> {
         let uuid = crate::character_state::get_active_character().ok_or("没有活跃的角色会话")?;
 
-        let (mut session, user_message) =
+        let (mut session, user_message, role_id) =
             SESSION_MANAGER.with_session(app_handle, uuid.clone(), |session| {
+                let role_id = role_id.or_else(|| session.selected_ai_role_id.clone());
                 session.set_selected_ai_role_id(role_id.clone());
-                let user_message = session.add_user_message(message);
+                let user_message = session.add_user_message(message, images);
                 session
                     .save_history_now(app_handle)
                     .map_err(|e| format!("保存用户消息失败: {}", e))?;
-                Ok((session.clone(), user_message))
+                Ok((session.clone(), user_message, role_id))
             })?;
         EventEmitter::send_message_sent(app_handle, &session.uuid, &user_message)?;
 
@@ -71,6 +132,31 @@ impl SessionService {
         Ok(())
     }
 
+    /// 保存并卸载全部会话，同时清除当前活跃角色，返回卸载的数量
+    pub async fn unload_all_sessions(app_handle: &AppHandle) -> Result<usize, String> {
+        let sessions = SESSION_MANAGER.remove_all_sessions()?;
+
+        for mut session in sessions.iter().cloned() {
+            if let Err(e) = session.save_history_now(app_handle) {
+                eprintln!("保存会话 {} 历史记录失败: {}", session.uuid, e);
+            }
+
+            if let Err(e) = EventEmitter::send_session_unloaded(
+                app_handle,
+                &session.uuid,
+                &session.get_session_info(),
+                SessionUnloadReason::MemoryCleanup,
+            ) {
+                eprintln!("发送会话卸载事件失败: {}", e);
+            }
+        }
+
+        crate::character_state::CHARACTER_STATE.clear_current_character()?;
+        SESSION_MANAGER.persist_state(app_handle)?;
+
+        Ok(sessions.len())
+    }
+
     pub fn get_session_info(uuid: String) -> Result<SessionInfo, String> {
         let session = SESSION_MANAGER
             .get_session(&uuid)
@@ -79,6 +165,91 @@ impl SessionService {
         Ok(session.get_session_info())
     }
 
+    pub fn diff_session_history(
+        app_handle: &AppHandle,
+        uuid: String,
+    ) -> Result<ChatHistoryDiff, String> {
+        let session = SESSION_MANAGER
+            .get_session(&uuid)
+            .ok_or_else(|| format!("会话 {} 不存在", uuid))?;
+        let on_disk_history = ChatHistoryManager::new(app_handle, &uuid).load_history()?;
+
+        Ok(diff_histories(&session.chat_history, &on_disk_history))
+    }
+
+    /// 预估发送草稿消息的费用（只读）
+    pub fn estimate_generation_cost(
+        app_handle: &AppHandle,
+        uuid: String,
+        draft: String,
+    ) -> Result<GenerationCostEstimate, String> {
+        let session = SESSION_MANAGER
+            .get_session(&uuid)
+            .ok_or_else(|| format!("会话 {} 不存在", uuid))?;
+        let (_, ai_role) =
+            AIConfigService::resolve_role(app_handle, session.selected_ai_role_id.as_deref())?;
+        let api_config =
+            ApiConfigService::get_default_api_config(app_handle)?.ok_or("没有可用的API配置")?;
+
+        let context_builder =
+            crate::context_builder::create_context_builder(Self::build_context_options(
+                app_handle,
+                &ai_role,
+                &api_config,
+                &session.character_data,
+            )?);
+        let context_result = context_builder
+            .build_full_context(&session.character_data, &session.chat_history, Some(&draft))
+            .map_err(|e| format!("构建上下文失败: {}", e))?;
+        let system_prompt_tokens = crate::token_counter::TokenCounter::for_model(&api_config.model)
+            .count_tokens(&ai_role.system_prompt)
+            .token_count;
+
+        Ok(estimate_generation_cost(
+            api_config.pricing.as_ref(),
+            context_result.total_tokens + system_prompt_tokens,
+            ai_role.max_tokens,
+        ))
+    }
+
+    /// 预览发送草稿消息时实际提交给模型的消息（只读，不发起网络请求）
+    pub fn preview_prompt(
+        app_handle: &AppHandle,
+        uuid: String,
+        user_message: String,
+    ) -> Result<PromptPreview, String> {
+        let session = SESSION_MANAGER
+            .get_session(&uuid)
+            .ok_or_else(|| format!("会话 {} 不存在", uuid))?;
+        let (_, ai_role) =
+            AIConfigService::resolve_role(app_handle, session.selected_ai_role_id.as_deref())?;
+        let api_config =
+            ApiConfigService::get_default_api_config(app_handle)?.ok_or("没有可用的API配置")?;
+
+        let context_builder =
+            crate::context_builder::create_context_builder(Self::build_context_options(
+                app_handle,
+                &ai_role,
+                &api_config,
+                &session.character_data,
+            )?);
+        let user_message = Some(user_message).filter(|message| !message.trim().is_empty());
+        let context = context_builder
+            .build_full_context(
+                &session.character_data,
+                &session.chat_history,
+                user_message.as_deref(),
+            )
+            .map_err(|e| format!("构建上下文失败: {}", e))?;
+        let messages = Self::build_chat_messages(
+            &ai_role.system_prompt,
+            &context,
+            session.next_reply_prefix.as_deref(),
+        );
+
+        Ok(PromptPreview { messages, context })
+    }
+
     pub fn get_all_sessions() -> Result<Vec<SessionInfo>, String> {
         SESSION_MANAGER.get_all_sessions_info()
     }
@@ -98,29 +269,74 @@ impl SessionService {
             }
         }
 
+        SESSION_MANAGER.persist_state(app_handle)?;
+
         Ok(saved_count)
     }
 
-    pub fn cleanup_expired_sessions(max_age_hours: u64) -> Result<usize, String> {
-        let mut sessions = SESSION_MANAGER.get_sessions_map()?;
-
-        let now = chrono::Utc::now();
+    pub fn cleanup_expired_sessions(
+        app_handle: &AppHandle,
+        max_age_hours: u64,
+    ) -> Result<usize, String> {
         let max_duration = chrono::Duration::hours(max_age_hours as i64);
-        let mut removed_count = 0;
+        let expired_sessions =
+            SESSION_MANAGER.remove_expired_sessions(chrono::Utc::now(), max_duration)?;
 
-        let expired_sessions: Vec<String> = sessions
-            .iter()
-            .filter(|(_, session)| now.signed_duration_since(session.last_active) > max_duration)
-            .map(|(uuid, _)| uuid.clone())
-            .collect();
+        for mut session in expired_sessions.iter().cloned() {
+            crate::debug_log!("清理过期会话: {}", session.uuid);
 
-        for uuid in expired_sessions {
-            sessions.remove(&uuid);
-            removed_count += 1;
-            crate::debug_log!("清理过期会话: {}", uuid);
+            if let Err(e) = session.save_history_now(app_handle) {
+                eprintln!("保存过期会话 {} 历史记录失败: {}", session.uuid, e);
+            }
+
+            if let Err(e) = EventEmitter::send_session_unloaded(
+                app_handle,
+                &session.uuid,
+                &session.get_session_info(),
+                SessionUnloadReason::Expired,
+            ) {
+                eprintln!("发送会话卸载事件失败: {}", e);
+            }
         }
 
-        Ok(removed_count)
+        Ok(expired_sessions.len())
+    }
+
+    /// 按 settings.json 中的配置周期性清理过期会话，配置变更在下一轮生效
+    pub async fn run_auto_cleanup(app_handle: AppHandle) {
+        loop {
+            let config =
+                AppSettingsService::get_auto_cleanup_config(&app_handle).unwrap_or_else(|e| {
+                    eprintln!("读取自动清理配置失败: {}", e);
+                    AutoCleanupConfig::default()
+                });
+
+            tokio::time::sleep(std::time::Duration::from_secs(
+                config.interval_minutes.max(1) * 60,
+            ))
+            .await;
+
+            if !config.enabled {
+                continue;
+            }
+
+            match Self::cleanup_expired_sessions(&app_handle, config.max_age_hours) {
+                Ok(0) => {}
+                Ok(count) => crate::debug_log!("自动清理了 {} 个过期会话", count),
+                Err(e) => eprintln!("自动清理过期会话失败: {}", e),
+            }
+        }
+    }
+
+    pub fn get_auto_cleanup_config(app_handle: &AppHandle) -> Result<AutoCleanupConfig, String> {
+        AppSettingsService::get_auto_cleanup_config(app_handle)
+    }
+
+    pub fn set_auto_cleanup_config(
+        app_handle: &AppHandle,
+        config: AutoCleanupConfig,
+    ) -> Result<(), String> {
+        AppSettingsService::set_auto_cleanup_config(app_handle, config)
     }
 
     pub async fn delete_chat_message(app_handle: &AppHandle, index: usize) -> Result<(), String> {
@@ -199,6 +415,118 @@ impl SessionService {
         Self::generate_ai_response(app_handle, &mut session, "regenerate", effective_role_id).await
     }
 
+    /// 从 index 处的 AI 回复重新生成：丢弃该回复及之后的全部消息，基于其前的用户消息重新请求
+    pub async fn regenerate_from(
+        app_handle: &AppHandle,
+        index: usize,
+        role_id: Option<String>,
+    ) -> Result<(), String> {
+        let uuid = crate::character_state::get_active_character().ok_or("没有活跃的角色会话")?;
+
+        let (mut session, effective_role_id, chat_history) =
+            SESSION_MANAGER.with_session(app_handle, uuid.clone(), |session| {
+                let effective_role_id = role_id.or_else(|| session.selected_ai_role_id.clone());
+                session.truncate_before_reply(index)?;
+                session.rewrite_all_history_now(app_handle)?;
+                Ok((
+                    session.clone(),
+                    effective_role_id,
+                    session.chat_history.clone(),
+                ))
+            })?;
+
+        crate::debug_log!(
+            "从消息 [{}] 重新生成，保留 {} 条消息",
+            index,
+            chat_history.len()
+        );
+        EventEmitter::send_chat_history_loaded(app_handle, &uuid, &chat_history)?;
+
+        Self::generate_ai_response(app_handle, &mut session, "regenerate", effective_role_id).await
+    }
+
+    /// 为最后一条 AI 回复生成新的备选回复（swipe），原回复保留在备选列表中
+    ///
+    /// 与 regenerate_from 一样回退到最后一条用户消息后重新请求；
+    /// 回复过程中的工具调用消息不随备选保留，合并后只留下最终的 AI 回复
+    pub async fn generate_swipe(
+        app_handle: &AppHandle,
+        role_id: Option<String>,
+    ) -> Result<(), String> {
+        let uuid = crate::character_state::get_active_character().ok_or("没有活跃的角色会话")?;
+
+        let (mut session, effective_role_id, discarded, kept_len) =
+            SESSION_MANAGER.with_session(app_handle, uuid.clone(), |session| {
+                let effective_role_id = role_id.or_else(|| session.selected_ai_role_id.clone());
+
+                let last_index = session
+                    .chat_history
+                    .len()
+                    .checked_sub(1)
+                    .ok_or("聊天历史为空")?;
+                if session.chat_history[last_index].role != "assistant" {
+                    return Err("最后一条消息不是AI回复，无法生成备选回复".to_string());
+                }
+
+                let discarded = session.truncate_before_reply(last_index)?;
+                session.rewrite_all_history_now(app_handle)?;
+                let kept_len = session.chat_history.len();
+
+                Ok((session.clone(), effective_role_id, discarded, kept_len))
+            })?;
+
+        if let Err(error) =
+            Self::generate_ai_response(app_handle, &mut session, "swipe", effective_role_id).await
+        {
+            // 生成失败时恢复原回复
+            SESSION_MANAGER.with_session(app_handle, uuid.clone(), |session| {
+                session.chat_history.truncate(kept_len);
+                session.chat_history.extend(discarded);
+                session.rewrite_all_history_now(app_handle)
+            })?;
+            return Err(error);
+        }
+
+        let chat_history = SESSION_MANAGER.with_session(app_handle, uuid.clone(), |session| {
+            let new_turn = session
+                .chat_history
+                .split_off(kept_len.min(session.chat_history.len()));
+            let previous_reply = discarded.last().cloned().ok_or("原回复不存在")?;
+            match new_turn.into_iter().last() {
+                Some(new_reply) if new_reply.role == "assistant" => {
+                    session
+                        .chat_history
+                        .push(merge_swipe(previous_reply, new_reply));
+                }
+                // 没有生成新的回复（例如被中断）时恢复原来的整轮消息
+                _ => session.chat_history.extend(discarded),
+            }
+            session.rewrite_all_history_now(app_handle)?;
+            Ok(session.chat_history.clone())
+        })?;
+
+        EventEmitter::send_chat_history_loaded(app_handle, &uuid, &chat_history)?;
+
+        Ok(())
+    }
+
+    /// 切换最后一条 AI 回复的当前备选回复，返回更新后的消息
+    pub fn set_active_swipe(app_handle: &AppHandle, index: usize) -> Result<ChatMessage, String> {
+        let uuid = crate::character_state::get_active_character().ok_or("没有活跃的角色会话")?;
+
+        SESSION_MANAGER.with_session(app_handle, uuid, |session| {
+            let last_message = session
+                .chat_history
+                .last_mut()
+                .filter(|message| message.role == "assistant")
+                .ok_or("最后一条消息不是AI回复，无法切换备选回复")?;
+            last_message.set_active_swipe(index)?;
+            let updated = last_message.clone();
+            session.rewrite_all_history_now(app_handle)?;
+            Ok(updated)
+        })
+    }
+
     pub async fn continue_chat(
         app_handle: &AppHandle,
         role_id: Option<String>,
@@ -238,6 +566,25 @@ impl SessionService {
         AI_CANCELLATION_MANAGER.cancel_request(&session_uuid)
     }
 
+    /// 取消指定会话正在进行的 AI 生成
+    pub fn cancel_generation(uuid: String) -> Result<bool, String> {
+        Self::interrupt_ai_response(Some(uuid))
+    }
+
+    pub fn get_max_sessions(app_handle: &AppHandle) -> Result<usize, String> {
+        AppSettingsService::get_max_sessions(app_handle)
+    }
+
+    /// 保存新的会话上限并立即生效，超出上限的最旧会话会先保存再卸载
+    pub async fn set_max_sessions(
+        app_handle: &AppHandle,
+        max_sessions: usize,
+    ) -> Result<usize, String> {
+        AppSettingsService::set_max_sessions(app_handle, max_sessions)?;
+        Self::save_all_sessions(app_handle).await?;
+        SESSION_MANAGER.set_max_sessions(max_sessions)
+    }
+
     fn append_intermediate_messages(
         session: &mut CharacterSession,
         intermediate_messages: &[crate::ai_chat::ChatMessage],
@@ -304,74 +651,198 @@ impl SessionService {
         Some(session.add_assistant_message(content, reasoning_content, tool_calls))
     }
 
+    /// 把回复前缀拼到模型输出前（模型已自行输出前缀时不重复添加）
+    fn apply_reply_prefix(reply_prefix: Option<&str>, content: String) -> String {
+        match reply_prefix {
+            Some(prefix) if !content.starts_with(prefix) => format!("{}{}", prefix, content),
+            _ => content,
+        }
+    }
+
+    /// 按字符数截断回复：优先在单词边界处截断，并追加省略标记
+    fn truncate_reply(content: String, max_chars: Option<usize>) -> String {
+        let Some(max_chars) = max_chars else {
+            return content;
+        };
+        let Some((cut, _)) = content.char_indices().nth(max_chars) else {
+            return content;
+        };
+
+        let kept = &content[..cut];
+        let kept = match kept.rfind(char::is_whitespace) {
+            Some(boundary) if boundary > 0 && !content[cut..].starts_with(char::is_whitespace) => {
+                &kept[..boundary]
+            }
+            _ => kept,
+        };
+        format!("{}{}", kept.trim_end(), REPLY_TRUNCATION_MARKER)
+    }
+
+    /// 为会话选择 AI 角色，之后的生成默认使用该角色的参数与工具开关；
+    /// 传入 None 时清除选择，恢复使用默认角色
+    pub fn set_session_role(
+        app_handle: &AppHandle,
+        uuid: String,
+        role_name: Option<String>,
+    ) -> Result<(), String> {
+        if let Some(role_name) = &role_name {
+            Self::validate_session_role(&AIConfigService::load_config(app_handle)?, role_name)?;
+        }
+
+        SESSION_MANAGER.with_session(app_handle, uuid, |session| {
+            session.set_selected_ai_role_id(role_name);
+            Ok(())
+        })?;
+        SESSION_MANAGER.persist_state(app_handle)?;
+        Ok(())
+    }
+
+    fn validate_session_role(config: &AIConfig, role_name: &str) -> Result<(), String> {
+        if config.roles.contains_key(role_name) {
+            Ok(())
+        } else {
+            Err(format!("AI角色 '{}' 不存在", role_name))
+        }
+    }
+
+    /// 获取会话选择的 AI 角色，未选择时为 None（生成时使用默认角色）
+    pub fn get_session_role(uuid: String) -> Result<Option<String>, String> {
+        let session = SESSION_MANAGER
+            .get_session(&uuid)
+            .ok_or_else(|| format!("会话 {} 不存在", uuid))?;
+
+        Ok(session.selected_ai_role_id)
+    }
+
+    /// 设置下一次生成的回复前缀，传入 None 或空字符串时清除
+    pub fn set_next_reply_prefix(
+        app_handle: &AppHandle,
+        uuid: String,
+        prefix: Option<String>,
+    ) -> Result<(), String> {
+        SESSION_MANAGER.with_session(app_handle, uuid, |session| {
+            session.next_reply_prefix = prefix.filter(|prefix| !prefix.is_empty());
+            Ok(())
+        })
+    }
+
+    pub fn get_next_reply_prefix(uuid: String) -> Result<Option<String>, String> {
+        let session = SESSION_MANAGER
+            .get_session(&uuid)
+            .ok_or_else(|| format!("会话 {} 不存在", uuid))?;
+
+        Ok(session.next_reply_prefix)
+    }
+
+    fn offered_tool_names(request: &crate::ai_chat::ChatCompletionRequest) -> Vec<String> {
+        request
+            .tools
+            .iter()
+            .flatten()
+            .map(|tool| tool.function.name.clone())
+            .collect()
+    }
+
+    /// 获取最近一次请求实际提供给模型的工具名称
+    pub fn get_last_offered_tools(uuid: String) -> Result<Vec<String>, String> {
+        let session = SESSION_MANAGER
+            .get_session(&uuid)
+            .ok_or_else(|| format!("会话 {} 不存在", uuid))?;
+
+        Ok(session.last_offered_tools)
+    }
+
+    /// 一次性获取当前活跃角色的完整状态（只读），没有活跃角色时返回 None
+    pub fn get_active_state(app_handle: &AppHandle) -> Result<Option<ActiveState>, String> {
+        let Some(uuid) = crate::character_state::get_active_character() else {
+            return Ok(None);
+        };
+
+        if let Some(session) = SESSION_MANAGER.get_session(&uuid) {
+            return Ok(Some(session.active_state(ACTIVE_STATE_RECENT_MESSAGES)));
+        }
+
+        // 会话尚未加载时直接从磁盘读取，不创建会话
+        let character_data =
+            crate::character_storage::CharacterStorage::get_character_by_uuid(app_handle, &uuid)?
+                .ok_or_else(|| format!("角色 {} 不存在", uuid))?;
+        let recent_messages = ChatHistoryManager::new(app_handle, &uuid)
+            .get_recent_messages(ACTIVE_STATE_RECENT_MESSAGES)?;
+
+        Ok(Some(ActiveState {
+            active_uuid: uuid,
+            character_data,
+            session_info: None,
+            recent_messages,
+            last_token_stats: None,
+        }))
+    }
+
     fn context_token_limit(context_window: u32) -> usize {
         ((context_window as f64) * 0.8).round() as usize
     }
 
-    fn build_context_options(ai_role: &AIRole, context_window: u32) -> ContextBuilderOptions {
+    fn build_context_options(
+        app_handle: &AppHandle,
+        ai_role: &AIRole,
+        api_config: &ApiConfig,
+        character_data: &CharacterData,
+    ) -> Result<ContextBuilderOptions, String> {
         let mut options = ContextBuilderOptions::default();
-        options.token_limit = Self::context_token_limit(context_window);
+        options.token_limit = Self::context_token_limit(api_config.context_window);
+        options.model = Some(api_config.model.clone());
+        options.linked_characters =
+            CharacterStorage::resolve_linked_characters(app_handle, character_data);
         options.ai_role = ai_role.context_role_template.clone();
         options.ai_task = ai_role.context_task_template.clone();
-        options.ai_instructions = ai_role.context_instructions_template.clone();
+        options.instructions = ai_role.context_instructions_template.clone();
         options.tools_enabled = ai_role.tools_enabled;
-        options
+        AIConfigService::apply_context_settings(app_handle, &mut options)?;
+        Ok(options)
     }
 
-    async fn generate_ai_response(
-        app_handle: &AppHandle,
-        session: &mut CharacterSession,
-        operation_type: &str,
-        requested_role_id: Option<String>,
-    ) -> Result<(), String> {
-        let (resolved_role_id, ai_role) =
-            AIConfigService::resolve_role(app_handle, requested_role_id.as_deref())?;
-        session.set_selected_ai_role_id(Some(resolved_role_id.clone()));
-
-        let api_config = crate::api_config::ApiConfigService::get_default_api_config(app_handle)?
-            .ok_or("没有可用的API配置")?;
-        let context_token_limit = Self::context_token_limit(api_config.context_window);
-        let context_builder = crate::context_builder::create_context_builder(
-            Self::build_context_options(&ai_role, api_config.context_window),
-        );
-        let context_result = context_builder
-            .build_full_context(&session.character_data, &session.chat_history, None)
-            .map_err(|e| format!("构建上下文失败: {}", e))?;
-
-        EventEmitter::send_context_built(app_handle, &session.uuid, &context_result)?;
-
+    /// 按发送顺序组装消息：AI 角色系统提示词、上下文 system 块、角色信息块、
+    /// 聊天历史、当前用户消息，最后是可选的回复前缀
+    fn build_chat_messages(
+        system_prompt: &str,
+        context_result: &BuiltContextResult,
+        reply_prefix: Option<&str>,
+    ) -> Vec<crate::ai_chat::ChatMessage> {
         let mut ai_chat_messages = Vec::new();
 
-        if !ai_role.system_prompt.trim().is_empty() {
+        if !system_prompt.trim().is_empty() {
             ai_chat_messages.push(crate::ai_chat::ChatMessage {
                 role: crate::ai_chat::MessageRole::System,
-                content: ai_role.system_prompt.clone(),
+                content: system_prompt.to_string(),
                 name: None,
                 reasoning_content: None,
                 tool_calls: None,
                 tool_call_id: None,
+                images: None,
             });
         }
 
-        for msg in context_result.system_messages {
+        for msg in &context_result.system_messages {
             ai_chat_messages.push(crate::ai_chat::ChatMessage {
                 role: crate::ai_chat::MessageRole::System,
-                content: msg.content,
-                name: msg.name,
+                content: msg.content.clone(),
+                name: msg.name.clone(),
                 reasoning_content: None,
                 tool_calls: None,
                 tool_call_id: None,
+                images: None,
             });
         }
 
-        for msg in context_result.assistant_messages {
+        for msg in &context_result.assistant_messages {
             ai_chat_messages.push(crate::ai_chat::ChatMessage {
                 role: crate::ai_chat::MessageRole::System,
-                content: msg.content,
-                name: msg.name,
+                content: msg.content.clone(),
+                name: msg.name.clone(),
                 reasoning_content: None,
                 tool_calls: None,
                 tool_call_id: None,
+                images: None,
             });
         }
 
@@ -406,27 +877,110 @@ impl SessionService {
                 reasoning_content: msg.reasoning_content.clone(),
                 tool_calls: converted_tool_calls,
                 tool_call_id: msg.tool_call_id.clone(),
+                images: msg.images.clone(),
             }
         }));
 
-        if let Some(current_msg) = context_result.current_user_message {
+        if let Some(current_msg) = &context_result.current_user_message {
             ai_chat_messages.push(crate::ai_chat::ChatMessage {
                 role: crate::ai_chat::MessageRole::User,
-                content: current_msg.content,
-                name: current_msg.name,
+                content: current_msg.content.clone(),
+                name: current_msg.name.clone(),
                 reasoning_content: None,
                 tool_calls: None,
-                tool_call_id: current_msg.tool_call_id,
+                tool_call_id: current_msg.tool_call_id.clone(),
+                images: current_msg.images.clone(),
             });
         }
 
-        let chat_tools = if ai_role.tools_enabled {
-            ToolRegistry::get_available_tools_global()
-        } else {
-            Vec::new()
-        };
+        // 回复前缀以一条未完成的 assistant 消息发送，由模型接着续写
+        if let Some(prefix) = reply_prefix {
+            ai_chat_messages.push(crate::ai_chat::ChatMessage {
+                role: crate::ai_chat::MessageRole::Assistant,
+                content: prefix.to_string(),
+                name: None,
+                reasoning_content: None,
+                tool_calls: None,
+                tool_call_id: None,
+                images: None,
+            });
+        }
+
+        ai_chat_messages
+    }
+
+    /// 按 AI 角色的采样参数与工具开关构建请求；角色关闭工具或没有可用工具时不携带工具
+    fn build_completion_request(
+        model: &str,
+        ai_role: &AIRole,
+        messages: Vec<crate::ai_chat::ChatMessage>,
+        chat_tools: Vec<crate::ai_tools::ToolDefinition>,
+        max_tool_iterations: u32,
+    ) -> crate::ai_chat::ChatCompletionRequest {
+        let offer_tools = ai_role.tools_enabled && !chat_tools.is_empty();
+
+        crate::ai_chat::ChatCompletionRequest {
+            model: model.to_string(),
+            messages,
+            temperature: Some(ai_role.temperature as f64),
+            max_tokens: Some(ai_role.max_tokens),
+            top_p: None,
+            frequency_penalty: None,
+            presence_penalty: None,
+            stop: None,
+            stream: Some(true),
+            tools: offer_tools.then_some(chat_tools),
+            tool_choice: offer_tools
+                .then(|| crate::ai_chat::ToolChoice::String("auto".to_string())),
+            max_tool_iterations: Some(max_tool_iterations),
+            response_format: ai_role
+                .json_mode
+                .then_some(crate::ai_chat::ResponseFormat::JsonObject),
+            seed: ai_role.seed,
+        }
+    }
+
+    async fn generate_ai_response(
+        app_handle: &AppHandle,
+        session: &mut CharacterSession,
+        operation_type: &str,
+        requested_role_id: Option<String>,
+    ) -> Result<(), String> {
+        // 优先使用本次指定的角色，其次是会话选择的角色，最后回退到默认角色；
+        // 回退得到的默认角色不写回会话，默认角色变化后会话仍跟随默认角色
+        let requested_role_id = requested_role_id.or_else(|| session.selected_ai_role_id.clone());
+        let (resolved_role_id, ai_role) =
+            AIConfigService::resolve_role(app_handle, requested_role_id.as_deref())?;
+
+        let api_config = crate::api_config::ApiConfigService::get_default_api_config(app_handle)?
+            .ok_or("没有可用的API配置")?;
+        let context_options = Self::build_context_options(
+            app_handle,
+            &ai_role,
+            &api_config,
+            &session.character_data,
+        )?;
+        let token_budget = TokenBudget::from_total_limit(context_options.token_limit);
+        let context_builder = crate::context_builder::create_context_builder(context_options);
+        let context_result = context_builder
+            .build_full_context(&session.character_data, &session.chat_history, None)
+            .map_err(|e| format!("构建上下文失败: {}", e))?;
+
+        EventEmitter::send_context_built(app_handle, &session.uuid, &context_result)?;
+
+        let reply_prefix = session.next_reply_prefix.take();
+        let ai_chat_messages = Self::build_chat_messages(
+            &ai_role.system_prompt,
+            &context_result,
+            reply_prefix.as_deref(),
+        );
 
         let disable_tools_for_debug = false;
+        let chat_tools = if disable_tools_for_debug {
+            Vec::new()
+        } else {
+            ai_role.offered_tools(ToolRegistry::get_available_tools_global())
+        };
 
         crate::debug_log!("=== AI 请求调试信息 ===");
         crate::debug_log!("AI角色ID: {}", resolved_role_id);
@@ -461,27 +1015,18 @@ impl SessionService {
         }
         crate::debug_log!("=====================");
 
-        let request = crate::ai_chat::ChatCompletionRequest {
-            model: api_config.model.clone(),
-            messages: ai_chat_messages,
-            temperature: Some(ai_role.temperature as f64),
-            max_tokens: Some(ai_role.max_tokens),
-            top_p: None,
-            frequency_penalty: None,
-            presence_penalty: None,
-            stop: None,
-            stream: Some(true),
-            tools: if disable_tools_for_debug || !ai_role.tools_enabled || chat_tools.is_empty() {
-                None
-            } else {
-                Some(chat_tools)
-            },
-            tool_choice: if disable_tools_for_debug || !ai_role.tools_enabled {
-                None
-            } else {
-                Some(crate::ai_chat::ToolChoice::String("auto".to_string()))
-            },
+        let max_tool_iterations = match ai_role.max_tool_iterations {
+            Some(max_tool_iterations) => max_tool_iterations,
+            None => AIConfigService::get_max_tool_iterations(app_handle)?,
         };
+        let request = Self::build_completion_request(
+            &api_config.model,
+            &ai_role,
+            ai_chat_messages,
+            chat_tools,
+            max_tool_iterations,
+        );
+        session.last_offered_tools = Self::offered_tool_names(&request);
 
         let start_time = std::time::Instant::now();
         let target_message_id = crate::file_utils::FileUtils::generate_uuid();
@@ -502,7 +1047,7 @@ impl SessionService {
                     Self::append_intermediate_messages(session, &aborted.intermediate_messages);
                     Self::append_final_assistant_message(
                         session,
-                        aborted.content,
+                        Self::apply_reply_prefix(reply_prefix.as_deref(), aborted.content),
                         aborted.reasoning_content,
                         None,
                     );
@@ -548,6 +1093,18 @@ impl SessionService {
             .first()
             .map(|choice| choice.message.content.clone())
             .unwrap_or_else(|| "AI未返回响应".to_string());
+        let ai_content = Self::apply_reply_prefix(reply_prefix.as_deref(), ai_content);
+        let max_reply_chars = AIConfigService::get_max_reply_chars(app_handle)?;
+        let full_content_len = ai_content.chars().count();
+        if max_reply_chars.is_some_and(|max_chars| full_content_len > max_chars) {
+            crate::debug_log!(
+                "回复超出 {:?} 字符上限（{} 字符），已截断保存，完整内容: {:?}",
+                max_reply_chars,
+                full_content_len,
+                ai_content
+            );
+        }
+        let ai_content = Self::truncate_reply(ai_content, max_reply_chars);
 
         let tool_calls_data = ai_response_result
             .choices
@@ -598,6 +1155,7 @@ impl SessionService {
                                 crate::ai_chat::MessageRole::Tool => "tool".to_string(),
                             },
                             content: msg.content.clone(),
+                            images: None,
                             timestamp: Some(chrono::Utc::now().timestamp_millis()),
                             tool_calls: msg.tool_calls.as_ref().map(|calls| {
                                 calls
@@ -616,6 +1174,8 @@ impl SessionService {
                             reasoning_content: msg.reasoning_content.clone(),
                             tool_call_id: msg.tool_call_id.clone(),
                             name: msg.name.clone(),
+                            swipes: Vec::new(),
+                            active_swipe: 0,
                         })
                         .collect()
                 });
@@ -626,6 +1186,7 @@ impl SessionService {
             &ai_response,
             Some(&target_message_id),
             converted_intermediate_msgs,
+            ai_response_result.system_fingerprint.clone(),
         )?;
 
         let token_stats = TokenUsageStats {
@@ -633,11 +1194,28 @@ impl SessionService {
             completion_tokens: ai_response_result.usage.completion_tokens as usize,
             total_tokens: ai_response_result.usage.total_tokens as usize,
             context_tokens: context_result.total_tokens,
-            budget_utilization: (ai_response_result.usage.total_tokens as f64
-                / context_token_limit.max(1) as f64
-                * 100.0),
+            budget_utilization: token_budget
+                .utilization_percent(ai_response_result.usage.total_tokens as usize),
         };
 
+        if let Err(error) = AppSettingsService::record_provider_default_model(
+            app_handle,
+            &api_config.base_url,
+            &api_config.model,
+        ) {
+            eprintln!("记录端点默认模型失败: {}", error);
+        }
+
+        if let Err(error) = UsageStatsService::record_usage(
+            app_handle,
+            &session.uuid,
+            ai_response_result.usage.prompt_tokens as u64,
+            ai_response_result.usage.completion_tokens as u64,
+        ) {
+            eprintln!("记录角色用量统计失败: {}", error);
+        }
+
+        session.last_token_stats = Some(token_stats.clone());
         EventEmitter::send_token_stats(app_handle, &session.uuid, token_stats)?;
 
         EventEmitter::send_progress(
@@ -658,3 +1236,237 @@ impl SessionService {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::character_storage::test_support::sample_character;
+    use serde_json::json;
+
+    fn sample_session() -> CharacterSession {
+        CharacterSession::new("session-test".to_string(), sample_character("session-test"))
+    }
+
+    #[test]
+    fn reply_prefix_starts_saved_reply_and_is_used_once() {
+        let mut session = sample_session();
+        session.add_user_message("hello".to_string(), None);
+        session.next_reply_prefix = Some("*smiles* ".to_string());
+
+        let reply_prefix = session.next_reply_prefix.take();
+        let content = SessionService::apply_reply_prefix(
+            reply_prefix.as_deref(),
+            "Nice to meet you.".to_string(),
+        );
+        let saved =
+            SessionService::append_final_assistant_message(&mut session, content, None, None)
+                .expect("reply should be saved");
+
+        assert_eq!(saved.content, "*smiles* Nice to meet you.");
+        assert!(session.next_reply_prefix.is_none());
+        assert_eq!(
+            SessionService::apply_reply_prefix(Some("*smiles* "), "*smiles* Hi".to_string()),
+            "*smiles* Hi"
+        );
+    }
+
+    #[test]
+    fn chat_messages_put_system_blocks_before_character_block() {
+        let session = sample_session();
+        let context =
+            crate::context_builder::create_context_builder(ContextBuilderOptions::default())
+                .build_full_context(&session.character_data, &session.chat_history, Some("hi"))
+                .unwrap();
+        assert!(!context.system_messages.is_empty());
+        assert!(!context.assistant_messages.is_empty());
+
+        let messages =
+            SessionService::build_chat_messages("You are a card editor.", &context, Some("Sure"));
+        let position_of = |content: &str| {
+            messages
+                .iter()
+                .position(|message| message.content == content)
+                .unwrap()
+        };
+
+        assert_eq!(messages[0].content, "You are a card editor.");
+        assert!(
+            position_of(&context.system_messages[0].content)
+                < position_of(&context.assistant_messages[0].content)
+        );
+        let tail: Vec<_> = messages[messages.len() - 2..]
+            .iter()
+            .map(|message| (message.role.clone(), message.content.as_str()))
+            .collect();
+        assert_eq!(
+            tail,
+            vec![
+                (crate::ai_chat::MessageRole::User, "hi"),
+                (crate::ai_chat::MessageRole::Assistant, "Sure"),
+            ]
+        );
+    }
+
+    #[test]
+    fn role_without_tools_builds_request_without_tools() {
+        let all_tools = ToolRegistry::get_available_tools_global();
+        let mut role: AIRole = serde_json::from_value(json!({ "name": "analyst" })).unwrap();
+        role.temperature = 0.2;
+        role.max_tokens = 512;
+
+        let request = SessionService::build_completion_request(
+            "test-model",
+            &role,
+            Vec::new(),
+            role.offered_tools(all_tools.clone()),
+            4,
+        );
+        assert!(request.tools.is_some());
+        assert!(request.tool_choice.is_some());
+
+        role.tools_enabled = false;
+        let request = SessionService::build_completion_request(
+            "test-model",
+            &role,
+            Vec::new(),
+            role.offered_tools(all_tools),
+            4,
+        );
+        assert!(request.tools.is_none());
+        assert!(request.tool_choice.is_none());
+        assert_eq!(request.temperature, Some(0.2f32 as f64));
+        assert_eq!(request.max_tokens, Some(512));
+    }
+
+    #[test]
+    fn setting_unknown_session_role_fails() {
+        let config: AIConfig = serde_json::from_value(json!({
+            "default_role": "analyst",
+            "roles": { "analyst": { "name": "Analyst" } }
+        }))
+        .unwrap();
+
+        assert!(SessionService::validate_session_role(&config, "analyst").is_ok());
+        assert_eq!(
+            SessionService::validate_session_role(&config, "creative_writer").unwrap_err(),
+            "AI角色 'creative_writer' 不存在"
+        );
+    }
+
+    #[test]
+    fn role_allow_list_reduces_recorded_tools() {
+        let all_tools = ToolRegistry::get_available_tools_global();
+        let mut role: AIRole = serde_json::from_value(json!({ "name": "tester" })).unwrap();
+        let recorded_for = |role: &AIRole| {
+            let request: crate::ai_chat::ChatCompletionRequest = serde_json::from_value(json!({
+                "model": "test-model",
+                "messages": [],
+                "tools": role.offered_tools(all_tools.clone()),
+            }))
+            .unwrap();
+            SessionService::offered_tool_names(&request)
+        };
+
+        let unrestricted = recorded_for(&role);
+        role.allowed_tools = Some(vec!["read_character_field".to_string()]);
+        let restricted = recorded_for(&role);
+
+        assert!(unrestricted.len() > 1);
+        assert_eq!(restricted, vec!["read_character_field".to_string()]);
+    }
+
+    #[test]
+    fn at_depth_world_book_entry_keeps_its_position_in_chat_request() {
+        let mut session = sample_session();
+        session.character_data.card.data.character_book = Some(
+            serde_json::from_value(json!({
+                "entries": [{
+                    "keys": ["Aster"],
+                    "content": "lore about Aster",
+                    "extensions": { "depth": 1 },
+                    "enabled": true,
+                    "insertion_order": 0,
+                    "position": "at_depth"
+                }]
+            }))
+            .unwrap(),
+        );
+        session.add_user_message("Hello".to_string(), None);
+        session.add_assistant_message("Hi.".to_string(), None, None);
+        session.add_user_message("Tell me about Aster.".to_string(), None);
+
+        let context = crate::context_builder::ContextBuilder::new(ContextBuilderOptions::default())
+            .build_full_context(&session.character_data, &session.chat_history, None)
+            .unwrap();
+        let messages = SessionService::build_chat_messages("", &context, None);
+        let request: crate::ai_chat::ChatCompletionRequest =
+            serde_json::from_value(json!({ "model": "test-model", "messages": [] })).unwrap();
+        let chat_request = crate::ai_chat::AIChatService::build_chat_request(&messages, &request);
+
+        let texts: Vec<&str> = chat_request
+            .messages
+            .iter()
+            .map(|message| message.content.first_text().unwrap_or_default())
+            .collect();
+        let entry_index = texts
+            .iter()
+            .position(|text| text.contains("lore about Aster"))
+            .expect("depth entry should be sent as a chat message");
+        assert_eq!(
+            chat_request.messages[entry_index].role,
+            genai::chat::ChatRole::User
+        );
+        assert_eq!(texts[entry_index - 1], "Hi.");
+        assert_eq!(texts[entry_index + 1], "Tell me about Aster.");
+        assert!(!chat_request
+            .system
+            .unwrap_or_default()
+            .contains("lore about Aster"));
+    }
+
+    #[test]
+    fn user_images_reach_the_chat_request() {
+        let mut session = sample_session();
+        let portrait = "data:image/png;base64,iVBORw0KGgo=".to_string();
+        session.add_user_message(
+            "Describe this portrait.".to_string(),
+            Some(vec![portrait.clone()]),
+        );
+
+        let context = crate::context_builder::ContextBuilder::new(ContextBuilderOptions::default())
+            .build_full_context(&session.character_data, &session.chat_history, None)
+            .unwrap();
+        let messages = SessionService::build_chat_messages("", &context, None);
+
+        let user_message = messages
+            .iter()
+            .find(|message| message.role == crate::ai_chat::MessageRole::User)
+            .unwrap();
+        assert_eq!(user_message.images, Some(vec![portrait]));
+        assert_eq!(
+            session
+                .add_user_message("text only".to_string(), Some(Vec::new()))
+                .images,
+            None
+        );
+    }
+
+    #[test]
+    fn truncate_reply_cuts_on_word_boundary_and_marks_ellipsis() {
+        let reply = "The quick brown fox jumps over the lazy dog".to_string();
+
+        assert_eq!(
+            SessionService::truncate_reply(reply.clone(), Some(12)),
+            "The quick…"
+        );
+        assert_eq!(
+            SessionService::truncate_reply("角色设定助手".to_string(), Some(4)),
+            "角色设定…"
+        );
+        assert_eq!(SessionService::truncate_reply(reply.clone(), None), reply);
+        assert_eq!(
+            SessionService::truncate_reply(reply.clone(), Some(100)),
+            reply
+        );
+    }
+}
@@ -4,11 +4,12 @@ pub mod sessions;
 
 pub use commands::models::{CommandCategory, CommandMetadata, CommandResult};
 pub use events::payloads::{
-    CharacterLoadedPayload, CharacterUpdateType, CharacterUpdatedPayload, ChatHistoryLoadedPayload,
-    ContextBuiltPayload, MessageReasoningDeltaPayload, MessageReceivedPayload, MessageSentPayload,
-    MessageStreamDeltaPayload, ReasoningDeltaKind, SessionUnloadReason, SessionUnloadedPayload,
-    TokenStatsPayload, TokenUsageStats, ToolExecutedPayload, ToolExecutionPhase,
-    ToolExecutionStatusPayload,
+    CharacterLoadedPayload, CharacterUpdateType, CharacterUpdatedPayload, ChatErrorPayload,
+    ChatHistoryLoadedPayload, ContextBuiltPayload, MessageReasoningDeltaPayload,
+    MessageReceivedPayload, MessageSentPayload, MessageStreamDeltaPayload,
+    MessageStreamDonePayload, MessageStreamErrorPayload, ReasoningDeltaKind, SessionUnloadReason,
+    SessionUnloadedPayload, TokenStatsPayload, TokenUsageStats, ToolExecutedPayload,
+    ToolExecutionPhase, ToolExecutionStatusPayload,
 };
-pub use sessions::config::{ContextBuilderOptions, TokenBudget};
-pub use sessions::session::{SessionInfo, SessionStatus};
+pub use sessions::config::{ContextBuilderOptions, LinkedCharacterSummary, TokenBudget};
+pub use sessions::session::{ActiveState, PromptPreview, SessionInfo, SessionStatus};
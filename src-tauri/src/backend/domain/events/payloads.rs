@@ -41,6 +41,9 @@ pub struct MessageReceivedPayload {
     /// 中间消息（包括 assistant with tool_calls 和 tool results）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub intermediate_messages: Option<Vec<ChatMessage>>,
+    /// 提供商返回的 system_fingerprint，用于核对可复现性
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_fingerprint: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -63,6 +66,34 @@ pub struct MessageStreamDeltaPayload {
     pub timestamp: i64,
 }
 
+/// 流式生成结束事件载荷
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageStreamDonePayload {
+    pub uuid: String,
+    pub target_message_id: String,
+    pub finish_reason: String,
+    pub content: String,
+    pub timestamp: i64,
+}
+
+/// 流式生成失败事件载荷
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageStreamErrorPayload {
+    pub uuid: String,
+    pub target_message_id: String,
+    pub error: String,
+    pub timestamp: i64,
+}
+
+/// AI 请求失败事件载荷（error 中的密钥已抹去）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatErrorPayload {
+    pub uuid: String,
+    pub status: Option<u16>,
+    pub error: String,
+    pub timestamp: i64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ReasoningDeltaKind {
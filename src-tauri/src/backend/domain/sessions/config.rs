@@ -1,8 +1,12 @@
+use crate::persona::Persona;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 const DEFAULT_TOKEN_LIMIT: usize = 102400;
 
+/// 默认的上下文指令
+pub const DEFAULT_CONTEXT_INSTRUCTIONS: &str = "基于用户需求分析现有角色设定，提供建议并调用相应工具。始终保持角色设定的一致性和逻辑性，遵循用户的具体要求。";
+
 /// Token 预算分配策略
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TokenBudget {
@@ -34,6 +38,18 @@ impl TokenBudget {
             history_reserved: (total as f64 * 0.30) as usize,
         }
     }
+
+    /// 已用 token 占总预算的百分比
+    pub fn utilization_percent(&self, used_tokens: usize) -> f64 {
+        used_tokens as f64 / self.total_limit.max(1) as f64 * 100.0
+    }
+}
+
+/// 关联角色摘要：在群像场景中作为额外上下文注入
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LinkedCharacterSummary {
+    pub name: String,
+    pub description: String,
 }
 
 /// 上下文构建配置选项
@@ -43,18 +59,46 @@ pub struct ContextBuilderOptions {
     pub token_limit: usize,
     /// 是否启用智能裁剪
     pub enable_smart_truncation: bool,
+    /// 裁剪历史时始终保留开头的开场白（第一条 assistant 消息）
+    #[serde(default = "default_preserve_first_message")]
+    pub preserve_first_message: bool,
     /// AI 角色定义（支持占位符）
     pub ai_role: String,
     /// AI 任务定义（支持占位符）
     pub ai_task: String,
-    /// AI 指令定义（支持占位符）
-    pub ai_instructions: String,
+    /// AI 指令内容（支持 {{char}}/{{user}} 等占位符）
+    pub instructions: String,
     /// 是否在上下文中声明工具
     pub tools_enabled: bool,
     /// 是否优先保留聊天历史
     pub prioritize_chat_history: bool,
     /// 占位符替换映射
     pub placeholders: HashMap<String, String>,
+    /// 角色卡与聊天历史中 {{user}} 替换成的用户/人设名称
+    #[serde(default = "default_user_name")]
+    pub user_name: String,
+    /// 用户人设（配置后会追加到 System 消息中）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub persona: Option<Persona>,
+    /// 世界书条目最低重要性（None 表示全部纳入）
+    #[serde(default)]
+    pub min_importance: Option<f64>,
+    /// 用于选择分词编码的模型名（None 时使用 cl100k_base）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// 关联角色摘要（为空时不注入）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub linked_characters: Vec<LinkedCharacterSummary>,
+}
+
+pub const DEFAULT_USER_NAME: &str = "User";
+
+fn default_user_name() -> String {
+    DEFAULT_USER_NAME.to_string()
+}
+
+fn default_preserve_first_message() -> bool {
+    true
 }
 
 impl Default for ContextBuilderOptions {
@@ -65,20 +109,36 @@ impl Default for ContextBuilderOptions {
                   "{{TASK}}".to_string(),
                   "帮助用户创作和完善角色设定, 需要从多个角度(角色动机，角色心理，角色性格，角色背景)等分析，完成角色卡。当需要局部修改某个字段中的一句话、某个 trait 或某段内容时，优先先读后写：不确定当前文本时使用 read_character_field 或 patch_character_field(dry_run=true) 预览，确认唯一命中后再执行 patch_character_field；只有当用户明确要求重写整个字段时，才使用 edit_character。当处理世界书时，先使用 list_world_book_entries 查看候选，必要时用 read_world_book_entry 读取完整条目；创建使用 create_world_book_entry，更新使用 update_world_book_entry，删除使用 delete_world_book_entry，并尽量传 entry_id 以避免误操作。".to_string(),
               );
-        placeholders.insert(
-            "{{INSTRUCTIONS}}".to_string(),
-            "基于用户需求分析现有角色设定，提供建议并调用相应工具。始终保持角色设定的一致性和逻辑性，遵循用户的具体要求。".to_string(),
-        );
 
         Self {
             token_limit: DEFAULT_TOKEN_LIMIT,
             enable_smart_truncation: true,
+            preserve_first_message: default_preserve_first_message(),
             ai_role: "{{ROLE}}".to_string(),
             ai_task: "{{TASK}}".to_string(),
-            ai_instructions: "{{INSTRUCTIONS}}".to_string(),
+            instructions: DEFAULT_CONTEXT_INSTRUCTIONS.to_string(),
             tools_enabled: true,
             prioritize_chat_history: true,
             placeholders,
+            user_name: default_user_name(),
+            persona: None,
+            min_importance: None,
+            model: None,
+            linked_characters: Vec::new(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::TokenBudget;
+
+    #[test]
+    fn utilization_is_measured_against_active_limit() {
+        assert_eq!(
+            TokenBudget::from_total_limit(10000).utilization_percent(5000),
+            50.0
+        );
+        assert_eq!(TokenBudget::from_total_limit(0).utilization_percent(0), 0.0);
+    }
+}
@@ -1,3 +1,7 @@
+use crate::backend::domain::events::payloads::TokenUsageStats;
+use crate::character_storage::CharacterData;
+use crate::chat_history::ChatMessage;
+use crate::context_builder::BuiltContextResult;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
@@ -24,3 +28,21 @@ pub struct SessionInfo {
     pub status: SessionStatus,
     pub last_context_tokens: usize,
 }
+
+/// 当前活跃角色的完整状态（前端启动时一次性获取）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActiveState {
+    pub active_uuid: String,
+    pub character_data: CharacterData,
+    /// 会话尚未加载时为空
+    pub session_info: Option<SessionInfo>,
+    pub recent_messages: Vec<ChatMessage>,
+    pub last_token_stats: Option<TokenUsageStats>,
+}
+
+/// 提示词预览：将要发送给模型的完整消息数组及上下文构建详情（不发起请求）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptPreview {
+    pub messages: Vec<crate::ai_chat::ChatMessage>,
+    pub context: BuiltContextResult,
+}
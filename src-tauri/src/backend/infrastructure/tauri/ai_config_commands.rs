@@ -1,4 +1,5 @@
 use crate::ai_config::{AIConfigService, AIRole, AIRoleRecord};
+use crate::persona::{Persona, PersonaService};
 
 #[tauri::command]
 pub async fn get_ai_config(app_handle: tauri::AppHandle) -> Result<serde_json::Value, String> {
@@ -44,3 +45,74 @@ pub async fn set_default_ai_role(
 pub async fn get_all_ai_roles(app_handle: tauri::AppHandle) -> Result<Vec<AIRoleRecord>, String> {
     AIConfigService::get_all_roles(&app_handle)
 }
+
+#[tauri::command]
+pub async fn get_max_tool_iterations(app_handle: tauri::AppHandle) -> Result<u32, String> {
+    AIConfigService::get_max_tool_iterations(&app_handle)
+}
+
+#[tauri::command]
+pub async fn set_max_tool_iterations(
+    app_handle: tauri::AppHandle,
+    max_tool_iterations: u32,
+) -> Result<(), String> {
+    AIConfigService::set_max_tool_iterations(&app_handle, max_tool_iterations)
+}
+
+#[tauri::command]
+pub async fn get_context_instructions(
+    app_handle: tauri::AppHandle,
+) -> Result<Option<String>, String> {
+    AIConfigService::get_context_instructions(&app_handle)
+}
+
+#[tauri::command]
+pub async fn set_context_instructions(
+    app_handle: tauri::AppHandle,
+    instructions: Option<String>,
+) -> Result<(), String> {
+    AIConfigService::set_context_instructions(&app_handle, instructions)
+}
+
+#[tauri::command]
+pub async fn get_min_importance(app_handle: tauri::AppHandle) -> Result<Option<f64>, String> {
+    AIConfigService::get_min_importance(&app_handle)
+}
+
+#[tauri::command]
+pub async fn set_min_importance(
+    app_handle: tauri::AppHandle,
+    min_importance: Option<f64>,
+) -> Result<(), String> {
+    AIConfigService::set_min_importance(&app_handle, min_importance)
+}
+
+/// 获取助手回复最大字符数，null 表示不限制
+#[tauri::command]
+pub async fn get_max_reply_chars(app_handle: tauri::AppHandle) -> Result<Option<usize>, String> {
+    AIConfigService::get_max_reply_chars(&app_handle)
+}
+
+/// 设置助手回复最大字符数，传入 null 时恢复不限制
+#[tauri::command]
+pub async fn set_max_reply_chars(
+    app_handle: tauri::AppHandle,
+    max_reply_chars: Option<usize>,
+) -> Result<(), String> {
+    AIConfigService::set_max_reply_chars(&app_handle, max_reply_chars)
+}
+
+/// 获取用户人设，未配置时返回 null
+#[tauri::command]
+pub async fn get_persona(app_handle: tauri::AppHandle) -> Result<Option<Persona>, String> {
+    PersonaService::load_persona(&app_handle)
+}
+
+/// 保存用户人设，传入 null 或空名称时清除
+#[tauri::command]
+pub async fn set_persona(
+    app_handle: tauri::AppHandle,
+    persona: Option<Persona>,
+) -> Result<(), String> {
+    PersonaService::save_persona(&app_handle, persona)
+}
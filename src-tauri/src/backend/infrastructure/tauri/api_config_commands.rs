@@ -1,6 +1,14 @@
+use crate::ai_chat::{AIChatService, StreamTestSummary};
 use crate::api_config::{
     ApiConfig, ApiConfigService, ApiTestResult, CreateApiRequest, ModelInfo, UpdateApiRequest,
 };
+use crate::app_settings::AppSettingsService;
+use crate::events::EventEmitter;
+use crate::model_cache::MODEL_LIST_CACHE;
+use crate::provider_probe::{
+    probe_capabilities, LiveProviderProbe, ProviderCapabilities, PROBE_TIMEOUT_SECS,
+};
+use crate::request_limiter::REQUEST_LIMITER;
 
 #[tauri::command]
 pub async fn get_all_api_configs(app_handle: tauri::AppHandle) -> Result<Vec<ApiConfig>, String> {
@@ -63,6 +71,12 @@ pub async fn toggle_api_config(
     ApiConfigService::toggle_api_config(&app_handle, &profile, enabled)
 }
 
+/// 轮换 API 密钥的本机加密密钥，返回重新加密的密钥数
+#[tauri::command]
+pub async fn rotate_encryption_key(app_handle: tauri::AppHandle) -> Result<usize, String> {
+    ApiConfigService::rotate_encryption_key(&app_handle)
+}
+
 #[tauri::command]
 pub async fn test_api_connection(
     app_handle: tauri::AppHandle,
@@ -75,6 +89,98 @@ pub async fn test_api_connection(
 pub async fn fetch_models(
     app_handle: tauri::AppHandle,
     config: ApiConfig,
+    force: Option<bool>,
 ) -> Result<Vec<ModelInfo>, String> {
-    ApiConfigService::fetch_models(&app_handle, &config).await
+    ApiConfigService::fetch_models_cached(&app_handle, &config, force.unwrap_or(false)).await
+}
+
+/// 获取端点最近一次成功生成所用的模型，用于切换配置时预选
+#[tauri::command]
+pub async fn get_provider_default_model(
+    app_handle: tauri::AppHandle,
+    endpoint: String,
+) -> Result<Option<String>, String> {
+    AppSettingsService::get_provider_default_model(&app_handle, &endpoint)
+}
+
+#[tauri::command]
+pub async fn repair_default_api_config(
+    app_handle: tauri::AppHandle,
+) -> Result<Option<String>, String> {
+    ApiConfigService::repair_default_api_config(&app_handle)
+}
+
+/// 探测提供商能力；probe_vision 为 true 时才额外发送一次带图片的请求
+#[tauri::command]
+pub async fn probe_provider(
+    app_handle: tauri::AppHandle,
+    config: ApiConfig,
+    probe_vision: Option<bool>,
+) -> Result<ProviderCapabilities, String> {
+    let probe = LiveProviderProbe {
+        app_handle: &app_handle,
+        config: &config,
+    };
+    let capabilities = probe_capabilities(
+        &probe,
+        std::time::Duration::from_secs(PROBE_TIMEOUT_SECS),
+        probe_vision.unwrap_or(false),
+        config
+            .capabilities
+            .map(|capabilities| capabilities.supports_vision),
+    )
+    .await;
+
+    ApiConfigService::save_provider_capabilities(&app_handle, &config.profile, capabilities)?;
+    Ok(capabilities)
+}
+
+/// 独立于会话发起一次流式测试，逐块发送 stream-test-chunk 事件，失败时发送 stream-test-error
+#[tauri::command]
+pub async fn stream_test(
+    app_handle: tauri::AppHandle,
+    config: ApiConfig,
+    prompt: String,
+) -> Result<StreamTestSummary, String> {
+    let result = AIChatService::stream_test(&config, &prompt, |chunk| {
+        if let Err(error) = EventEmitter::send_stream_test_chunk(&app_handle, chunk) {
+            eprintln!("{}", error);
+        }
+    })
+    .await;
+
+    if let Err(error) = &result {
+        EventEmitter::send_stream_test_error(&app_handle, error)?;
+    }
+    result
+}
+
+#[tauri::command]
+pub async fn get_max_concurrent_requests() -> Result<usize, String> {
+    Ok(REQUEST_LIMITER.max_permits())
+}
+
+#[tauri::command]
+pub async fn get_model_cache_ttl() -> Result<u64, String> {
+    Ok(MODEL_LIST_CACHE.ttl().as_secs())
+}
+
+/// 保存并立即应用模型列表缓存有效期（秒）
+#[tauri::command]
+pub async fn set_model_cache_ttl(
+    app_handle: tauri::AppHandle,
+    ttl_secs: u64,
+) -> Result<(), String> {
+    AppSettingsService::set_model_cache_ttl_secs(&app_handle, ttl_secs)?;
+    MODEL_LIST_CACHE.set_ttl(std::time::Duration::from_secs(ttl_secs))
+}
+
+/// 保存并立即应用 API 请求并发上限
+#[tauri::command]
+pub async fn set_max_concurrent_requests(
+    app_handle: tauri::AppHandle,
+    max_concurrent_requests: usize,
+) -> Result<(), String> {
+    AppSettingsService::set_max_concurrent_requests(&app_handle, max_concurrent_requests)?;
+    REQUEST_LIMITER.set_max_permits(max_concurrent_requests)
 }
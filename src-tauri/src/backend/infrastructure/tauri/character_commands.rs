@@ -1,7 +1,23 @@
+use crate::backend::application::session_service::SessionService;
+use crate::backend::domain::sessions::config::DEFAULT_USER_NAME;
 use crate::backend::domain::CharacterUpdateType;
-use crate::character_storage::{CharacterData, CharacterStorage, TavernCardV2};
+use crate::card_validation::ValidationIssue;
+use crate::character_storage::{
+    CharacterData, CharacterStorage, LibraryImportMode, LibraryImportSummary, TavernCardV2,
+    WorldBookEntry,
+};
 use crate::events::EventEmitter;
-use crate::tools::character_fields::{parse_alternate_greetings, parse_tags};
+use crate::persona::{extract_persona_hint, Persona, PersonaService};
+use crate::png_utils::PngMetadataUtils;
+use crate::tools::character_fields::{
+    bake_card_placeholders, collect_used_macros, greeting_count, lint_greeting_macros,
+    parse_alternate_greetings, parse_tags, score_card_completeness, CompletenessReport,
+    GreetingMacroIssue, MacroUsage,
+};
+use crate::tools::world_book_shared::{
+    clean_entry_keys, find_dead_entries, reorder_entries, WorldBookMergeStrategy,
+};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
 
 #[tauri::command]
 pub async fn get_all_characters(
@@ -18,6 +34,21 @@ pub async fn get_character_by_uuid(
     CharacterStorage::get_character_by_uuid(&app_handle, &uuid)
 }
 
+/// 获取所有标签及使用该标签的角色卡数量
+#[tauri::command]
+pub async fn get_all_tags(app_handle: tauri::AppHandle) -> Result<Vec<(String, usize)>, String> {
+    CharacterStorage::get_all_tags(&app_handle)
+}
+
+/// 按标签筛选角色卡（忽略大小写）
+#[tauri::command]
+pub async fn get_characters_by_tag(
+    app_handle: tauri::AppHandle,
+    tag: String,
+) -> Result<Vec<CharacterData>, String> {
+    CharacterStorage::get_characters_by_tag(&app_handle, &tag)
+}
+
 #[tauri::command]
 pub async fn create_character(
     app_handle: tauri::AppHandle,
@@ -26,6 +57,25 @@ pub async fn create_character(
     CharacterStorage::create_character(&app_handle, &name)
 }
 
+/// 复制角色卡（不复制聊天记录）
+#[tauri::command]
+pub async fn duplicate_character(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+) -> Result<CharacterData, String> {
+    CharacterStorage::duplicate_character(&app_handle, &uuid)
+}
+
+/// 设置关联角色，返回去重后实际保存的 UUID 列表
+#[tauri::command]
+pub async fn set_linked_characters(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+    linked_uuids: Vec<String>,
+) -> Result<Vec<String>, String> {
+    CharacterStorage::set_linked_characters(&app_handle, &uuid, &linked_uuids)
+}
+
 #[tauri::command]
 pub async fn update_character(
     app_handle: tauri::AppHandle,
@@ -99,6 +149,31 @@ pub async fn upload_background_image(
     CharacterStorage::upload_background_image(&app_handle, &uuid, &image_data, &extension)
 }
 
+/// 压缩角色背景图片，返回节省的字节数
+#[tauri::command]
+pub async fn optimize_background(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+    max_dimension: u32,
+    quality: u8,
+) -> Result<u64, String> {
+    let saved_bytes =
+        CharacterStorage::optimize_background(&app_handle, &uuid, max_dimension, quality)?;
+
+    if saved_bytes > 0 {
+        if let Some(character_data) = CharacterStorage::get_character_by_uuid(&app_handle, &uuid)? {
+            EventEmitter::send_character_updated(
+                &app_handle,
+                &uuid,
+                &character_data,
+                CharacterUpdateType::FullData,
+            )?;
+        }
+    }
+
+    Ok(saved_bytes)
+}
+
 #[tauri::command]
 pub async fn update_character_background_path(
     app_handle: tauri::AppHandle,
@@ -108,6 +183,18 @@ pub async fn update_character_background_path(
     CharacterStorage::update_character_background_path(&app_handle, &uuid, &background_path)
 }
 
+/// 提取 PNG 角色卡中的图片，返回 base64 data URL（用于导入前预览头像）
+#[tauri::command]
+pub async fn extract_card_avatar(file_data: Vec<u8>) -> Result<String, String> {
+    let image_bytes = PngMetadataUtils::extract_image_bytes(&file_data)
+        .map_err(|e| format!("提取角色卡图片失败: {}", e))?;
+
+    Ok(format!(
+        "data:image/png;base64,{}",
+        STANDARD.encode(image_bytes)
+    ))
+}
+
 #[tauri::command]
 pub async fn export_character_card(
     app_handle: tauri::AppHandle,
@@ -117,6 +204,97 @@ pub async fn export_character_card(
     CharacterStorage::export_character_card(&app_handle, &uuid, &output_path)
 }
 
+/// 用当前角色卡数据原地更新已导出的 PNG 角色卡（只替换元数据块）
+#[tauri::command]
+pub async fn update_png_character_data(
+    app_handle: tauri::AppHandle,
+    png_path: String,
+    uuid: String,
+) -> Result<String, String> {
+    CharacterStorage::update_png_character_data(&app_handle, &png_path, &uuid)
+}
+
+#[tauri::command]
+pub async fn export_character_with_history(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+    output_path: String,
+) -> Result<String, String> {
+    CharacterStorage::export_character_with_history(&app_handle, &uuid, &output_path)
+}
+
+#[tauri::command]
+pub async fn import_character_with_history(
+    app_handle: tauri::AppHandle,
+    file_path: String,
+) -> Result<CharacterData, String> {
+    CharacterStorage::import_character_with_history(&app_handle, &file_path)
+}
+
+/// 备份整个角色库与配置为 zip，include_api_keys 默认为 false（不导出 API 密钥）
+#[tauri::command]
+pub async fn export_all_characters(
+    app_handle: tauri::AppHandle,
+    output_path: String,
+    include_api_keys: Option<bool>,
+) -> Result<String, String> {
+    CharacterStorage::export_all_characters(
+        &app_handle,
+        &output_path,
+        include_api_keys.unwrap_or(false),
+    )
+}
+
+/// 从整库备份恢复角色与配置
+#[tauri::command]
+pub async fn import_all_characters(
+    app_handle: tauri::AppHandle,
+    file_path: String,
+    mode: LibraryImportMode,
+) -> Result<LibraryImportSummary, String> {
+    // 覆盖会替换角色目录，已加载的会话需先保存并卸载，否则之后会把旧历史写进恢复的文件
+    if mode == LibraryImportMode::Replace {
+        SessionService::unload_all_sessions(&app_handle).await?;
+    }
+    CharacterStorage::import_all_characters(&app_handle, &file_path, mode)
+}
+
+/// 把角色目录打包为 zip 备份，返回备份文件路径
+#[tauri::command]
+pub async fn export_character_archive(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+    output_path: String,
+) -> Result<String, String> {
+    CharacterStorage::export_archive(&app_handle, &uuid, &output_path)
+}
+
+/// 从 zip 备份恢复角色，uuid 冲突时分配新的 uuid
+#[tauri::command]
+pub async fn import_character_archive(
+    app_handle: tauri::AppHandle,
+    file_path: String,
+) -> Result<CharacterData, String> {
+    CharacterStorage::import_archive(&app_handle, &file_path)
+}
+
+/// 按 Tavern V2 / V3 规范检查角色卡（只读）
+#[tauri::command]
+pub async fn validate_card_spec(card: TavernCardV2) -> Result<Vec<ValidationIssue>, String> {
+    crate::card_validation::validate_card_spec(&card)
+}
+
+/// 检查已保存角色卡的内容问题（只读）
+#[tauri::command]
+pub async fn validate_character(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+) -> Result<Vec<ValidationIssue>, String> {
+    let character = CharacterStorage::get_character_by_uuid(&app_handle, &uuid)?
+        .ok_or_else(|| format!("角色 {} 不存在", uuid))?;
+    Ok(CharacterStorage::validate_card(&character.card))
+}
+
 #[tauri::command]
 pub async fn import_character_card(
     app_handle: tauri::AppHandle,
@@ -133,3 +311,246 @@ pub async fn import_character_card_from_bytes(
 ) -> Result<CharacterData, String> {
     CharacterStorage::import_character_card_from_bytes(&app_handle, &file_data, &file_name)
 }
+
+/// 用样本消息检查从未被激活的世界书条目（只读）
+#[tauri::command]
+pub async fn find_dead_world_book_entries(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+    sample_messages: Vec<String>,
+) -> Result<Vec<WorldBookEntry>, String> {
+    let character_data = CharacterStorage::get_character_by_uuid(&app_handle, &uuid)?
+        .ok_or_else(|| format!("角色 {} 不存在", uuid))?;
+
+    Ok(character_data
+        .card
+        .data
+        .character_book
+        .map(|book| find_dead_entries(&book.entries, &sample_messages))
+        .unwrap_or_default())
+}
+
+/// 把角色卡和世界书中的 {{char}} / {{user}} 固化为当前名称（破坏性操作，需 confirm=true），返回替换次数
+#[tauri::command]
+pub async fn bake_placeholders(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+    confirm: bool,
+) -> Result<usize, String> {
+    if !confirm {
+        return Err("固化占位符会直接改写角色卡内容，请确认后再执行".to_string());
+    }
+
+    let mut character_data = CharacterStorage::get_character_by_uuid(&app_handle, &uuid)?
+        .ok_or_else(|| format!("角色 {} 不存在", uuid))?;
+    let user_name = PersonaService::load_persona(&app_handle)?
+        .map(|persona| persona.name)
+        .unwrap_or_else(|| DEFAULT_USER_NAME.to_string());
+
+    let substitutions = bake_card_placeholders(&mut character_data.card, &user_name);
+    if substitutions == 0 {
+        return Ok(0);
+    }
+
+    CharacterStorage::update_character(&app_handle, &uuid, &character_data.card)?;
+    EventEmitter::send_character_updated(
+        &app_handle,
+        &uuid,
+        &character_data,
+        CharacterUpdateType::FullData,
+    )?;
+
+    Ok(substitutions)
+}
+
+/// 清理世界书关键词（去空白、去空、去重），返回删除的关键词数量
+#[tauri::command]
+pub async fn clean_world_book_keys(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+    case_sensitive: Option<bool>,
+) -> Result<usize, String> {
+    let mut character_data = CharacterStorage::get_character_by_uuid(&app_handle, &uuid)?
+        .ok_or_else(|| format!("角色 {} 不存在", uuid))?;
+
+    let Some(book) = character_data.card.data.character_book.as_mut() else {
+        return Ok(0);
+    };
+
+    // 只去除了首尾空白时没有删除关键词，但同样需要保存
+    let cleanup = clean_entry_keys(&mut book.entries, case_sensitive);
+    if !cleanup.changed {
+        return Ok(0);
+    }
+
+    CharacterStorage::update_character(&app_handle, &uuid, &character_data.card)?;
+    EventEmitter::send_character_updated(
+        &app_handle,
+        &uuid,
+        &character_data,
+        CharacterUpdateType::Worldbook,
+    )?;
+
+    Ok(cleanup.removed)
+}
+
+/// 按给定的条目 id 顺序重排世界书，并依次重写 insertion_order
+#[tauri::command]
+pub async fn reorder_world_book(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+    ordered_ids: Vec<i32>,
+) -> Result<(), String> {
+    let mut character_data = CharacterStorage::get_character_by_uuid(&app_handle, &uuid)?
+        .ok_or_else(|| format!("角色 {} 不存在", uuid))?;
+    let book = character_data
+        .card
+        .data
+        .character_book
+        .as_mut()
+        .ok_or_else(|| "当前角色没有世界书".to_string())?;
+
+    reorder_entries(&mut book.entries, &ordered_ids)?;
+
+    CharacterStorage::update_character(&app_handle, &uuid, &character_data.card)?;
+    EventEmitter::send_character_updated(
+        &app_handle,
+        &uuid,
+        &character_data,
+        CharacterUpdateType::Worldbook,
+    )?;
+
+    Ok(())
+}
+
+/// 将 source 角色的世界书合并到 target 角色，返回合并的条目数
+#[tauri::command]
+pub async fn merge_world_books(
+    app_handle: tauri::AppHandle,
+    target_uuid: String,
+    source_uuid: String,
+    strategy: WorldBookMergeStrategy,
+) -> Result<usize, String> {
+    let (character_data, merged_count) =
+        CharacterStorage::merge_world_books(&app_handle, &target_uuid, &source_uuid, strategy)?;
+    if merged_count == 0 {
+        return Ok(0);
+    }
+
+    EventEmitter::send_character_updated(
+        &app_handle,
+        &target_uuid,
+        &character_data,
+        CharacterUpdateType::Worldbook,
+    )?;
+
+    Ok(merged_count)
+}
+
+/// 导入 SillyTavern lorebook JSON；strategy 为空时替换整个世界书，返回导入的条目数
+#[tauri::command]
+pub async fn import_lorebook(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+    file_data: Vec<u8>,
+    strategy: Option<WorldBookMergeStrategy>,
+) -> Result<usize, String> {
+    let (character_data, imported_count) =
+        CharacterStorage::import_lorebook(&app_handle, &uuid, &file_data, strategy)?;
+
+    EventEmitter::send_character_updated(
+        &app_handle,
+        &uuid,
+        &character_data,
+        CharacterUpdateType::Worldbook,
+    )?;
+
+    Ok(imported_count)
+}
+
+/// 导出角色世界书为 SillyTavern lorebook JSON 文件
+#[tauri::command]
+pub async fn export_lorebook(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+    output_path: String,
+) -> Result<(), String> {
+    let lorebook = CharacterStorage::export_lorebook(&app_handle, &uuid)?;
+    std::fs::write(&output_path, lorebook).map_err(|e| format!("写入世界书文件失败: {}", e))
+}
+
+/// 在指定消息处分支对话，返回新建的角色（复制角色卡，聊天记录截至该消息）
+#[tauri::command]
+pub async fn branch_conversation(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+    index: usize,
+) -> Result<CharacterData, String> {
+    CharacterStorage::branch_conversation(&app_handle, &uuid, index)
+}
+
+/// 切换角色收藏状态，返回切换后的值
+#[tauri::command]
+pub async fn toggle_favorite(app_handle: tauri::AppHandle, uuid: String) -> Result<bool, String> {
+    CharacterStorage::toggle_favorite(&app_handle, &uuid)
+}
+
+/// 获取开场白数量（first_mes + alternate_greetings），有效索引为 0..count（只读）
+#[tauri::command]
+pub async fn get_greeting_count(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+) -> Result<usize, String> {
+    let character_data = CharacterStorage::get_character_by_uuid(&app_handle, &uuid)?
+        .ok_or_else(|| format!("角色 {} 不存在", uuid))?;
+
+    Ok(greeting_count(&character_data.card))
+}
+
+/// 检查开场白中无法识别的占位符，返回每条开场白的问题列表（只读）
+#[tauri::command]
+pub async fn lint_greetings(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+) -> Result<Vec<GreetingMacroIssue>, String> {
+    let character_data = CharacterStorage::get_character_by_uuid(&app_handle, &uuid)?
+        .ok_or_else(|| format!("角色 {} 不存在", uuid))?;
+
+    Ok(lint_greeting_macros(&character_data.card))
+}
+
+/// 统计角色卡和世界书中使用的 {{...}} 占位符及次数，便于发现拼写错误（只读）
+#[tauri::command]
+pub async fn get_used_macros(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+) -> Result<Vec<MacroUsage>, String> {
+    let character_data = CharacterStorage::get_character_by_uuid(&app_handle, &uuid)?
+        .ok_or_else(|| format!("角色 {} 不存在", uuid))?;
+
+    Ok(collect_used_macros(&character_data.card))
+}
+
+/// 计算角色卡完整度（0–100）及各关键字段是否达标（只读）
+#[tauri::command]
+pub async fn character_completeness(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+) -> Result<CompletenessReport, String> {
+    let character_data = CharacterStorage::get_character_by_uuid(&app_handle, &uuid)?
+        .ok_or_else(|| format!("角色 {} 不存在", uuid))?;
+
+    Ok(score_card_completeness(&character_data.card))
+}
+
+/// 从角色卡中读取作者预设的用户人设供用户选择采用，不会自动应用；没有提示时返回 null
+#[tauri::command]
+pub async fn extract_persona_from_card(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+) -> Result<Option<Persona>, String> {
+    let character_data = CharacterStorage::get_character_by_uuid(&app_handle, &uuid)?
+        .ok_or_else(|| format!("角色 {} 不存在", uuid))?;
+
+    Ok(extract_persona_hint(&character_data.card))
+}
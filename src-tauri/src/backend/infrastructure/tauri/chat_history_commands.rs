@@ -1,5 +1,13 @@
+use crate::ai_config::AIConfigService;
+use crate::backend::domain::ContextBuilderOptions;
 use crate::character_session::SESSION_MANAGER;
-use crate::chat_history::{ChatHistoryManager, ChatMessage};
+use crate::character_storage::CharacterStorage;
+use crate::chat_history::{
+    build_finetune_lines, merge_consecutive_messages, ChatHistoryManager, ChatHistoryPage,
+    ChatMessage, FinetuneExportMode, HistoryIntegrityReport,
+};
+use crate::context_builder::ContextBuilder;
+use tauri::AppHandle;
 
 #[tauri::command]
 pub async fn save_chat_message(
@@ -20,6 +28,101 @@ pub async fn load_chat_history(
     manager.load_history()
 }
 
+/// 分页加载聊天历史，offset 从最早的消息开始计数，用于按需加载较早的消息
+#[tauri::command]
+pub async fn load_chat_history_page(
+    app_handle: tauri::AppHandle,
+    character_id: String,
+    offset: usize,
+    limit: usize,
+) -> Result<ChatHistoryPage, String> {
+    let manager = ChatHistoryManager::new(&app_handle, &character_id);
+    let (messages, total) = manager.load_history_page(offset, limit)?;
+    Ok(ChatHistoryPage { messages, total })
+}
+
+/// 获取合并连续同角色消息后的历史（只读，不修改存储文件）
+#[tauri::command]
+pub async fn get_merged_history(
+    app_handle: tauri::AppHandle,
+    character_id: String,
+) -> Result<Vec<ChatMessage>, String> {
+    let manager = ChatHistoryManager::new(&app_handle, &character_id);
+    Ok(merge_consecutive_messages(&manager.load_history()?))
+}
+
+/// 角色的 system 提示词，取自上下文构建结果（系统指令 + 角色信息 + 世界书）
+fn character_system_prompt(app_handle: &AppHandle, character_id: &str) -> Result<String, String> {
+    let character_data = CharacterStorage::get_character_by_uuid(app_handle, character_id)?
+        .ok_or_else(|| format!("角色 {} 不存在", character_id))?;
+
+    let mut options = ContextBuilderOptions::default();
+    AIConfigService::apply_context_settings(app_handle, &mut options)?;
+    let context = ContextBuilder::new(options).build_full_context(&character_data, &[], None)?;
+    Ok(context
+        .system_messages
+        .iter()
+        .chain(context.assistant_messages.iter())
+        .map(|message| message.content.as_str())
+        .filter(|content| !content.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n"))
+}
+
+/// 将聊天记录导出为 OpenAI 微调格式的 JSONL，返回写入的行数
+#[tauri::command]
+pub async fn export_finetune_jsonl(
+    app_handle: tauri::AppHandle,
+    character_id: String,
+    output_path: String,
+    mode: Option<FinetuneExportMode>,
+) -> Result<usize, String> {
+    let system_prompt = character_system_prompt(&app_handle, &character_id)?;
+    let history = ChatHistoryManager::new(&app_handle, &character_id).load_history()?;
+
+    let lines = build_finetune_lines(&system_prompt, &history, mode.unwrap_or_default(), false);
+    let mut content = String::new();
+    for line in &lines {
+        content.push_str(
+            &serde_json::to_string(line).map_err(|e| format!("序列化微调数据失败: {}", e))?,
+        );
+        content.push('\n');
+    }
+
+    std::fs::write(&output_path, content).map_err(|e| format!("写入微调数据失败: {}", e))?;
+
+    Ok(lines.len())
+}
+
+/// 按轮次导出包含工具调用的 OpenAI 微调 JSONL 并写入指定路径
+#[tauri::command]
+pub async fn export_training_jsonl(
+    app_handle: tauri::AppHandle,
+    character_id: String,
+    output_path: String,
+    include_system: bool,
+) -> Result<(), String> {
+    let system_prompt = if include_system {
+        Some(character_system_prompt(&app_handle, &character_id)?)
+    } else {
+        None
+    };
+    let content = ChatHistoryManager::new(&app_handle, &character_id)
+        .export_training_jsonl(system_prompt.as_deref())?;
+    std::fs::write(&output_path, content).map_err(|e| format!("写入训练数据失败: {}", e))
+}
+
+/// 将聊天记录导出为 Markdown 文档并写入指定路径
+#[tauri::command]
+pub async fn export_chat_markdown(
+    app_handle: tauri::AppHandle,
+    character_id: String,
+    output_path: String,
+) -> Result<(), String> {
+    let markdown = ChatHistoryManager::new(&app_handle, &character_id).export_markdown()?;
+    std::fs::write(&output_path, markdown).map_err(|e| format!("写入 Markdown 文件失败: {}", e))
+}
+
 #[tauri::command]
 pub async fn clear_chat_history(
     app_handle: tauri::AppHandle,
@@ -57,3 +160,12 @@ pub async fn get_recent_chat_messages(
     let manager = ChatHistoryManager::new(&app_handle, &character_id);
     manager.get_recent_messages(count)
 }
+
+/// 检查聊天历史文件完整性（解析、工具调用配对、时间戳顺序、校验和），只读
+#[tauri::command]
+pub async fn verify_history_integrity(
+    app_handle: tauri::AppHandle,
+    character_id: String,
+) -> Result<HistoryIntegrityReport, String> {
+    ChatHistoryManager::new(&app_handle, &character_id).verify_integrity()
+}
@@ -1,5 +1,8 @@
+use crate::api_config::GenerationCostEstimate;
+use crate::app_settings::{AppSettingsService, AutoCleanupConfig};
 use crate::backend::application::session_service::SessionService;
-use crate::backend::domain::sessions::session::SessionInfo;
+use crate::backend::domain::sessions::session::{ActiveState, PromptPreview, SessionInfo};
+use crate::chat_history::{ChatHistoryDiff, ChatMessage};
 
 /// 加载角色会话
 #[tauri::command]
@@ -10,14 +13,25 @@ pub async fn load_character_session(
     SessionService::load_session(&app_handle, uuid).await
 }
 
+/// 选择开场白作为第一条 AI 消息，index 为 -1 时使用 first_mes，0 起为备选开场白
+#[tauri::command]
+pub async fn select_greeting(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+    index: i32,
+) -> Result<Option<ChatMessage>, String> {
+    SessionService::select_greeting(&app_handle, uuid, index)
+}
+
 /// 发送聊天消息
 #[tauri::command]
 pub async fn send_chat_message(
     app_handle: tauri::AppHandle,
     message: String,
+    images: Option<Vec<String>>,
     role_id: Option<String>,
 ) -> Result<(), String> {
-    SessionService::send_chat_message(&app_handle, message, role_id).await
+    SessionService::send_chat_message(&app_handle, message, images, role_id).await
 }
 
 /// 卸载角色会话
@@ -29,12 +43,91 @@ pub async fn unload_character_session(
     SessionService::unload_session(&app_handle, uuid).await
 }
 
+/// 保存并卸载全部会话
+#[tauri::command]
+pub async fn unload_all_sessions(app_handle: tauri::AppHandle) -> Result<usize, String> {
+    SessionService::unload_all_sessions(&app_handle).await
+}
+
 /// 获取会话信息
 #[tauri::command]
 pub async fn get_session_info(uuid: String) -> Result<SessionInfo, String> {
     SessionService::get_session_info(uuid)
 }
 
+/// 一次性获取当前活跃角色的完整状态，没有活跃角色时返回 null
+#[tauri::command]
+pub async fn get_active_state(app_handle: tauri::AppHandle) -> Result<Option<ActiveState>, String> {
+    SessionService::get_active_state(&app_handle)
+}
+
+/// 为会话选择 AI 角色，传入 null 恢复使用默认角色
+#[tauri::command]
+pub async fn set_session_role(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+    role_name: Option<String>,
+) -> Result<(), String> {
+    SessionService::set_session_role(&app_handle, uuid, role_name)
+}
+
+/// 获取会话选择的 AI 角色
+#[tauri::command]
+pub async fn get_session_role(uuid: String) -> Result<Option<String>, String> {
+    SessionService::get_session_role(uuid)
+}
+
+/// 设置下一次生成的回复前缀（使用一次后清除），传入 null 清除
+#[tauri::command]
+pub async fn set_next_reply_prefix(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+    prefix: Option<String>,
+) -> Result<(), String> {
+    SessionService::set_next_reply_prefix(&app_handle, uuid, prefix)
+}
+
+/// 获取尚未使用的回复前缀
+#[tauri::command]
+pub async fn get_next_reply_prefix(uuid: String) -> Result<Option<String>, String> {
+    SessionService::get_next_reply_prefix(uuid)
+}
+
+/// 获取最近一次请求实际提供给模型的工具名称
+#[tauri::command]
+pub async fn get_last_offered_tools(uuid: String) -> Result<Vec<String>, String> {
+    SessionService::get_last_offered_tools(uuid)
+}
+
+/// 对比内存会话历史与磁盘历史（只读，用于排查同步问题）
+#[tauri::command]
+pub async fn diff_session_history(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+) -> Result<ChatHistoryDiff, String> {
+    SessionService::diff_session_history(&app_handle, uuid)
+}
+
+/// 预估发送草稿消息的费用（只读）
+#[tauri::command]
+pub async fn estimate_generation_cost(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+    draft: String,
+) -> Result<GenerationCostEstimate, String> {
+    SessionService::estimate_generation_cost(&app_handle, uuid, draft)
+}
+
+/// 预览发送草稿消息时将提交给模型的完整消息（只读，不发起请求）
+#[tauri::command]
+pub async fn preview_prompt(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+    user_message: String,
+) -> Result<PromptPreview, String> {
+    SessionService::preview_prompt(&app_handle, uuid, user_message)
+}
+
 /// 获取所有活跃会话信息
 #[tauri::command]
 pub async fn get_all_sessions() -> Result<Vec<SessionInfo>, String> {
@@ -49,8 +142,28 @@ pub async fn save_all_sessions(app_handle: tauri::AppHandle) -> Result<usize, St
 
 /// 清理过期会话（基于最后活跃时间）
 #[tauri::command]
-pub async fn cleanup_expired_sessions(max_age_hours: u64) -> Result<usize, String> {
-    SessionService::cleanup_expired_sessions(max_age_hours)
+pub async fn cleanup_expired_sessions(
+    app_handle: tauri::AppHandle,
+    max_age_hours: u64,
+) -> Result<usize, String> {
+    SessionService::cleanup_expired_sessions(&app_handle, max_age_hours)
+}
+
+/// 获取自动清理过期会话的配置
+#[tauri::command]
+pub async fn get_auto_cleanup_config(
+    app_handle: tauri::AppHandle,
+) -> Result<AutoCleanupConfig, String> {
+    SessionService::get_auto_cleanup_config(&app_handle)
+}
+
+/// 设置自动清理过期会话的配置（interval_minutes / max_age_hours / enabled）
+#[tauri::command]
+pub async fn set_auto_cleanup_config(
+    app_handle: tauri::AppHandle,
+    config: AutoCleanupConfig,
+) -> Result<(), String> {
+    SessionService::set_auto_cleanup_config(&app_handle, config)
 }
 
 /// 删除指定索引的消息
@@ -78,6 +191,34 @@ pub async fn regenerate_last_message(
     SessionService::regenerate_last_message(&app_handle, role_id).await
 }
 
+/// 从指定的AI回复开始重新生成，之后的消息全部丢弃
+#[tauri::command]
+pub async fn regenerate_from_index(
+    app_handle: tauri::AppHandle,
+    index: usize,
+    role_id: Option<String>,
+) -> Result<(), String> {
+    SessionService::regenerate_from(&app_handle, index, role_id).await
+}
+
+/// 为最后一条AI回复生成新的备选回复（保留原回复）
+#[tauri::command]
+pub async fn generate_swipe(
+    app_handle: tauri::AppHandle,
+    role_id: Option<String>,
+) -> Result<(), String> {
+    SessionService::generate_swipe(&app_handle, role_id).await
+}
+
+/// 切换最后一条AI回复的当前备选回复
+#[tauri::command]
+pub async fn set_active_swipe(
+    app_handle: tauri::AppHandle,
+    index: usize,
+) -> Result<ChatMessage, String> {
+    SessionService::set_active_swipe(&app_handle, index)
+}
+
 /// 继续对话（当最后一条是用户消息时生成AI回复）
 #[tauri::command]
 pub async fn continue_chat(
@@ -87,6 +228,42 @@ pub async fn continue_chat(
     SessionService::continue_chat(&app_handle, role_id).await
 }
 
+/// 取消指定会话正在进行的 AI 生成
+#[tauri::command]
+pub async fn cancel_generation(uuid: String) -> Result<bool, String> {
+    SessionService::cancel_generation(uuid)
+}
+
+/// 获取最大并发会话数
+#[tauri::command]
+pub async fn get_max_sessions(app_handle: tauri::AppHandle) -> Result<usize, String> {
+    SessionService::get_max_sessions(&app_handle)
+}
+
+/// 设置最大并发会话数，返回因超出上限被卸载的会话数量
+#[tauri::command]
+pub async fn set_max_sessions(
+    app_handle: tauri::AppHandle,
+    max_sessions: usize,
+) -> Result<usize, String> {
+    SessionService::set_max_sessions(&app_handle, max_sessions).await
+}
+
+/// 获取 /summarize 保留的最近消息数
+#[tauri::command]
+pub async fn get_summarize_keep_recent(app_handle: tauri::AppHandle) -> Result<usize, String> {
+    AppSettingsService::get_summarize_keep_recent(&app_handle)
+}
+
+/// 设置 /summarize 保留的最近消息数
+#[tauri::command]
+pub async fn set_summarize_keep_recent(
+    app_handle: tauri::AppHandle,
+    keep_recent: usize,
+) -> Result<(), String> {
+    AppSettingsService::set_summarize_keep_recent(&app_handle, keep_recent)
+}
+
 /// 中断当前 AI 响应
 #[tauri::command]
 pub async fn interrupt_ai_response(uuid: Option<String>) -> Result<bool, String> {
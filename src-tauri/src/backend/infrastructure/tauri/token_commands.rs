@@ -1,14 +1,32 @@
-use crate::token_counter::{get_token_counter, TokenCountResult};
+use crate::token_counter::{
+    get_token_counter, get_token_counter_for_encoding, get_token_counter_for_model,
+    TokenCountResult, TokenEncoding,
+};
+use crate::usage_stats::{CharacterUsageStats, UsageStatsService};
 
+/// 计算 Token 数量；传入 model 时按模型选择分词编码（如 gpt-4o 使用 o200k_base）
 #[tauri::command]
-pub async fn count_tokens(text: String) -> Result<TokenCountResult, String> {
-    let counter = get_token_counter();
+pub async fn count_tokens(text: String, model: Option<String>) -> Result<TokenCountResult, String> {
+    let counter = get_token_counter_for_model(model.as_deref());
     Ok(counter.count_tokens(&text))
 }
 
+/// 使用指定的分词编码计算 Token 数量（不经过模型映射），如 cl100k_base / o200k_base
 #[tauri::command]
-pub async fn count_tokens_batch(texts: Vec<String>) -> Result<Vec<TokenCountResult>, String> {
-    let counter = get_token_counter();
+pub async fn count_tokens_with_encoding(
+    text: String,
+    encoding: String,
+) -> Result<TokenCountResult, String> {
+    let counter = get_token_counter_for_encoding(TokenEncoding::from_name(&encoding)?);
+    Ok(counter.count_tokens(&text))
+}
+
+#[tauri::command]
+pub async fn count_tokens_batch(
+    texts: Vec<String>,
+    model: Option<String>,
+) -> Result<Vec<TokenCountResult>, String> {
+    let counter = get_token_counter_for_model(model.as_deref());
     Ok(counter.count_tokens_batch(&texts))
 }
 
@@ -23,3 +41,21 @@ pub async fn truncate_to_token_limit(text: String, limit: usize) -> Result<Strin
     let counter = get_token_counter();
     Ok(counter.truncate_to_limit(&text, limit))
 }
+
+/// 获取角色跨会话累计的 token 用量
+#[tauri::command]
+pub async fn get_character_usage_stats(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+) -> Result<CharacterUsageStats, String> {
+    UsageStatsService::load_stats(&app_handle, &uuid)
+}
+
+/// 清零角色累计的 token 用量
+#[tauri::command]
+pub async fn reset_character_usage_stats(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+) -> Result<CharacterUsageStats, String> {
+    UsageStatsService::reset_stats(&app_handle, &uuid)
+}
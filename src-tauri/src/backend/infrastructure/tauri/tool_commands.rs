@@ -1,4 +1,5 @@
 use crate::ai_tools::{ToolCallRequest, ToolDefinition, ToolResult};
+use crate::tooling_config::{ToolingConfig, ToolingConfigService, ToolingImportReport};
 use crate::tools::ToolRegistry;
 
 #[tauri::command]
@@ -11,6 +12,12 @@ pub async fn get_tools_by_category(category: String) -> Result<Vec<ToolDefinitio
     Ok(ToolRegistry::get_tools_by_category_global(&category))
 }
 
+/// 按关键字搜索工具（只读）
+#[tauri::command]
+pub async fn search_tools(query: String) -> Result<Vec<ToolDefinition>, String> {
+    Ok(ToolRegistry::search_tools_global(&query))
+}
+
 #[tauri::command]
 pub async fn execute_tool_call(
     app_handle: tauri::AppHandle,
@@ -23,3 +30,21 @@ pub async fn execute_tool_call(
 pub async fn get_tool_categories() -> Result<Vec<&'static str>, String> {
     Ok(ToolRegistry::get_tool_categories_global())
 }
+
+/// 导出工具配置（各 AI 角色的工具开关、允许列表与调用轮数上限）
+#[tauri::command]
+pub async fn export_tooling_config(
+    app_handle: tauri::AppHandle,
+    output_path: String,
+) -> Result<ToolingConfig, String> {
+    ToolingConfigService::export_tooling_config(&app_handle, &output_path)
+}
+
+/// 导入工具配置，本机不存在的角色与未知工具会被跳过
+#[tauri::command]
+pub async fn import_tooling_config(
+    app_handle: tauri::AppHandle,
+    file_path: String,
+) -> Result<ToolingImportReport, String> {
+    ToolingConfigService::import_tooling_config(&app_handle, &file_path)
+}
@@ -0,0 +1,449 @@
+use crate::character_storage::{serialize_tavern_card, TavernCardV2, SPEC_V2, SPEC_V3};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// 规范检查问题的严重程度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationSeverity {
+    Error,
+    Warning,
+}
+
+/// 单个规范检查问题，path 为 JSON 路径（如 data.character_book.entries[0].keys）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValidationIssue {
+    pub path: String,
+    pub severity: ValidationSeverity,
+    pub message: String,
+}
+
+/// Tavern 规范要求 data 中必须存在的字符串字段
+const REQUIRED_STRING_FIELDS: [&str; 11] = [
+    "name",
+    "description",
+    "personality",
+    "scenario",
+    "first_mes",
+    "mes_example",
+    "creator_notes",
+    "system_prompt",
+    "post_history_instructions",
+    "creator",
+    "character_version",
+];
+
+/// 必须为字符串数组的字段
+const REQUIRED_STRING_ARRAY_FIELDS: [&str; 2] = ["alternate_greetings", "tags"];
+
+#[derive(Default)]
+struct IssueCollector {
+    issues: Vec<ValidationIssue>,
+}
+
+impl IssueCollector {
+    fn error(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.push(path, ValidationSeverity::Error, message);
+    }
+
+    fn warning(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.push(path, ValidationSeverity::Warning, message);
+    }
+
+    fn push(
+        &mut self,
+        path: impl Into<String>,
+        severity: ValidationSeverity,
+        message: impl Into<String>,
+    ) {
+        self.issues.push(ValidationIssue {
+            path: path.into(),
+            severity,
+            message: message.into(),
+        });
+    }
+}
+
+/// 按 Tavern V2 / V3 规范检查角色卡 JSON（只读，不修改卡片）
+pub fn validate_card_spec_value(card: &Value) -> Vec<ValidationIssue> {
+    let mut collector = IssueCollector::default();
+    let Some(root) = card.as_object() else {
+        collector.error("", "角色卡必须是 JSON 对象");
+        return collector.issues;
+    };
+
+    let spec = match root.get("spec") {
+        None => {
+            collector.error("spec", "缺少 spec 字段");
+            None
+        }
+        Some(Value::String(spec)) if spec == SPEC_V2 || spec == SPEC_V3 => Some(spec.as_str()),
+        Some(Value::String(spec)) => {
+            collector.error("spec", format!("不支持的 spec: {}", spec));
+            None
+        }
+        Some(_) => {
+            collector.error("spec", "spec 必须是字符串");
+            None
+        }
+    };
+
+    match root.get("spec_version") {
+        None => collector.error("spec_version", "缺少 spec_version 字段"),
+        Some(Value::String(version)) => {
+            let expected = match spec {
+                Some(SPEC_V3) => version.starts_with("3."),
+                Some(_) => version == "2.0",
+                None => true,
+            };
+            if !expected {
+                collector.warning(
+                    "spec_version",
+                    format!("spec_version {} 与 spec 不匹配", version),
+                );
+            }
+        }
+        Some(_) => collector.error("spec_version", "spec_version 必须是字符串"),
+    }
+
+    match root.get("data") {
+        None => collector.error("data", "缺少 data 字段"),
+        Some(Value::Object(data)) => validate_card_data(data, &mut collector),
+        Some(_) => collector.error("data", "data 必须是对象"),
+    }
+
+    collector.issues
+}
+
+/// 检查已解析的角色卡（按原始规范序列化后检查）
+pub fn validate_card_spec(card: &TavernCardV2) -> Result<Vec<ValidationIssue>, String> {
+    let card_json = serialize_tavern_card(card)?;
+    let value =
+        serde_json::from_str(&card_json).map_err(|e| format!("解析角色卡数据失败: {}", e))?;
+    Ok(validate_card_spec_value(&value))
+}
+
+/// 检查角色卡内容是否可用：空名称、空开场白、spec 与 spec_version 不一致、
+/// 无关键词且非常驻的世界书条目、重复的条目 id
+pub fn validate_card_content(card: &TavernCardV2) -> Vec<ValidationIssue> {
+    let mut collector = IssueCollector::default();
+
+    if card.data.name.trim().is_empty() {
+        collector.error("data.name", "角色名称不能为空");
+    }
+    if card.data.first_mes.trim().is_empty() {
+        collector.warning("data.first_mes", "开场白为空");
+    }
+
+    let version_matches = match card.spec.as_str() {
+        SPEC_V2 => card.spec_version == "2.0",
+        SPEC_V3 => card.spec_version.starts_with("3."),
+        _ => false,
+    };
+    if !version_matches {
+        collector.error(
+            "spec_version",
+            format!(
+                "spec {} 与 spec_version {} 不匹配",
+                card.spec, card.spec_version
+            ),
+        );
+    }
+
+    if let Some(book) = &card.data.character_book {
+        let mut seen_ids = std::collections::HashSet::new();
+        for (index, entry) in book.entries.iter().enumerate() {
+            let path = format!("data.character_book.entries[{}]", index);
+            let has_keys = entry.keys.iter().any(|key| !key.trim().is_empty());
+            if !has_keys && !entry.constant.unwrap_or(false) {
+                collector.warning(
+                    format!("{}.keys", path),
+                    "条目没有关键词且不是常驻条目，永远不会被触发",
+                );
+            }
+            if let Some(id) = entry.id {
+                if !seen_ids.insert(id) {
+                    collector.error(format!("{}.id", path), format!("条目 id {} 重复", id));
+                }
+            }
+        }
+    }
+
+    collector.issues
+}
+
+fn validate_card_data(data: &serde_json::Map<String, Value>, collector: &mut IssueCollector) {
+    for field in REQUIRED_STRING_FIELDS {
+        let path = format!("data.{}", field);
+        match data.get(field) {
+            None => collector.error(path, format!("缺少 {} 字段", field)),
+            Some(Value::String(_)) => {}
+            Some(_) => collector.error(path, format!("{} 必须是字符串", field)),
+        }
+    }
+
+    for field in REQUIRED_STRING_ARRAY_FIELDS {
+        let path = format!("data.{}", field);
+        match data.get(field) {
+            None => collector.error(path, format!("缺少 {} 字段", field)),
+            Some(Value::Array(items)) => {
+                for (index, item) in items.iter().enumerate() {
+                    if !item.is_string() {
+                        collector.error(
+                            format!("{}[{}]", path, index),
+                            format!("{} 的元素必须是字符串", field),
+                        );
+                    }
+                }
+            }
+            Some(_) => collector.error(path, format!("{} 必须是数组", field)),
+        }
+    }
+
+    validate_extensions(data.get("extensions"), "data.extensions", collector);
+
+    match data.get("character_book") {
+        None | Some(Value::Null) => {}
+        Some(Value::Object(book)) => validate_character_book(book, collector),
+        Some(_) => collector.error("data.character_book", "character_book 必须是对象"),
+    }
+}
+
+fn validate_character_book(book: &serde_json::Map<String, Value>, collector: &mut IssueCollector) {
+    validate_extensions(
+        book.get("extensions"),
+        "data.character_book.extensions",
+        collector,
+    );
+
+    let entries = match book.get("entries") {
+        None => {
+            collector.error("data.character_book.entries", "缺少 entries 字段");
+            return;
+        }
+        Some(Value::Array(entries)) => entries,
+        Some(_) => {
+            collector.error("data.character_book.entries", "entries 必须是数组");
+            return;
+        }
+    };
+
+    for (index, entry) in entries.iter().enumerate() {
+        let path = format!("data.character_book.entries[{}]", index);
+        let Some(entry) = entry.as_object() else {
+            collector.error(path, "世界书条目必须是对象");
+            continue;
+        };
+
+        match entry.get("keys") {
+            None => collector.error(format!("{}.keys", path), "缺少 keys 字段"),
+            Some(Value::Array(keys)) if keys.iter().all(Value::is_string) => {}
+            Some(_) => collector.error(format!("{}.keys", path), "keys 必须是字符串数组"),
+        }
+        match entry.get("content") {
+            None => collector.error(format!("{}.content", path), "缺少 content 字段"),
+            Some(Value::String(_)) => {}
+            Some(_) => collector.error(format!("{}.content", path), "content 必须是字符串"),
+        }
+        match entry.get("enabled") {
+            None => collector.error(format!("{}.enabled", path), "缺少 enabled 字段"),
+            Some(Value::Bool(_)) => {}
+            Some(_) => collector.error(format!("{}.enabled", path), "enabled 必须是布尔值"),
+        }
+        match entry.get("insertion_order") {
+            None => collector.error(
+                format!("{}.insertion_order", path),
+                "缺少 insertion_order 字段",
+            ),
+            Some(Value::Number(_)) => {}
+            Some(_) => collector.error(
+                format!("{}.insertion_order", path),
+                "insertion_order 必须是数字",
+            ),
+        }
+        validate_extensions(
+            entry.get("extensions"),
+            &format!("{}.extensions", path),
+            collector,
+        );
+    }
+}
+
+/// extensions 为规范必填字段，缺失时导入会补为空对象，因此只作为警告
+fn validate_extensions(extensions: Option<&Value>, path: &str, collector: &mut IssueCollector) {
+    match extensions {
+        None => collector.warning(path, "缺少 extensions 字段，将使用空对象"),
+        Some(Value::Object(_)) => {}
+        Some(_) => collector.error(path, "extensions 必须是对象"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate_card_content, validate_card_spec_value, ValidationSeverity};
+    use crate::character_storage::TavernCardV2;
+
+    fn valid_card() -> serde_json::Value {
+        serde_json::json!({
+            "spec": "chara_card_v2",
+            "spec_version": "2.0",
+            "data": {
+                "name": "Alice",
+                "description": "",
+                "personality": "",
+                "scenario": "",
+                "first_mes": "",
+                "mes_example": "",
+                "creator_notes": "",
+                "system_prompt": "",
+                "post_history_instructions": "",
+                "alternate_greetings": [],
+                "tags": ["fantasy"],
+                "creator": "",
+                "character_version": "",
+                "extensions": {},
+                "character_book": {
+                    "extensions": {},
+                    "entries": [{
+                        "keys": ["castle"],
+                        "content": "An old castle.",
+                        "extensions": {},
+                        "enabled": true,
+                        "insertion_order": 0
+                    }]
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn valid_card_has_no_issues() {
+        assert!(validate_card_spec_value(&valid_card()).is_empty());
+    }
+
+    #[test]
+    fn missing_spec_version_is_reported_as_error() {
+        let mut card = valid_card();
+        card.as_object_mut().unwrap().remove("spec_version");
+
+        let issues = validate_card_spec_value(&card);
+
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].path, "spec_version");
+        assert_eq!(issues[0].severity, ValidationSeverity::Error);
+    }
+
+    #[test]
+    fn field_types_and_world_book_keys_are_checked() {
+        let mut card = valid_card();
+        card["data"]["tags"] = "fantasy".into();
+        card["data"]["character_book"]["entries"][0]
+            .as_object_mut()
+            .unwrap()
+            .remove("keys");
+        card["data"].as_object_mut().unwrap().remove("extensions");
+
+        let issues = validate_card_spec_value(&card);
+        let paths: Vec<(&str, ValidationSeverity)> = issues
+            .iter()
+            .map(|issue| (issue.path.as_str(), issue.severity))
+            .collect();
+
+        assert_eq!(
+            paths,
+            vec![
+                ("data.tags", ValidationSeverity::Error),
+                ("data.extensions", ValidationSeverity::Warning),
+                (
+                    "data.character_book.entries[0].keys",
+                    ValidationSeverity::Error
+                ),
+            ]
+        );
+    }
+
+    fn parsed_card() -> TavernCardV2 {
+        let mut card = valid_card();
+        card["data"]["first_mes"] = "Hello".into();
+        serde_json::from_value(card).unwrap()
+    }
+
+    fn content_issues(card: &TavernCardV2) -> Vec<(String, ValidationSeverity)> {
+        validate_card_content(card)
+            .into_iter()
+            .map(|issue| (issue.path, issue.severity))
+            .collect()
+    }
+
+    #[test]
+    fn well_formed_card_has_no_content_issues() {
+        assert!(content_issues(&parsed_card()).is_empty());
+    }
+
+    #[test]
+    fn empty_name_is_an_error() {
+        let mut card = parsed_card();
+        card.data.name = "  ".to_string();
+
+        assert_eq!(
+            content_issues(&card),
+            vec![("data.name".to_string(), ValidationSeverity::Error)]
+        );
+    }
+
+    #[test]
+    fn empty_first_message_is_a_warning() {
+        let mut card = parsed_card();
+        card.data.first_mes.clear();
+
+        assert_eq!(
+            content_issues(&card),
+            vec![("data.first_mes".to_string(), ValidationSeverity::Warning)]
+        );
+    }
+
+    #[test]
+    fn spec_version_mismatch_is_an_error() {
+        let mut card = parsed_card();
+        card.spec_version = "3.0".to_string();
+
+        assert_eq!(
+            content_issues(&card),
+            vec![("spec_version".to_string(), ValidationSeverity::Error)]
+        );
+    }
+
+    #[test]
+    fn keyless_entry_warns_unless_constant() {
+        let mut card = parsed_card();
+        let book = card.data.character_book.as_mut().unwrap();
+        book.entries[0].keys = vec![String::new()];
+        let mut constant_entry = book.entries[0].clone();
+        constant_entry.constant = Some(true);
+        book.entries.push(constant_entry);
+
+        assert_eq!(
+            content_issues(&card),
+            vec![(
+                "data.character_book.entries[0].keys".to_string(),
+                ValidationSeverity::Warning
+            )]
+        );
+    }
+
+    #[test]
+    fn duplicate_entry_ids_are_errors() {
+        let mut card = parsed_card();
+        let book = card.data.character_book.as_mut().unwrap();
+        book.entries[0].id = Some(7);
+        book.entries.push(book.entries[0].clone());
+
+        assert_eq!(
+            content_issues(&card),
+            vec![(
+                "data.character_book.entries[1].id".to_string(),
+                ValidationSeverity::Error
+            )]
+        );
+    }
+}
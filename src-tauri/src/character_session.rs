@@ -1,8 +1,13 @@
-use crate::backend::domain::{SessionInfo, SessionStatus};
+use crate::backend::domain::{ActiveState, SessionInfo, SessionStatus, TokenUsageStats};
 use crate::character_storage::CharacterData;
 use crate::chat_history::{ChatHistoryManager, ChatMessage};
+use crate::context_builder::substitute_character_macros;
+use crate::file_utils::FileUtils;
+use crate::tools::character_fields::select_greeting;
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, UNIX_EPOCH};
 use tauri::AppHandle;
@@ -26,6 +31,12 @@ pub struct CharacterSession {
     pub status: SessionStatus,
     /// 已保存到磁盘的消息数量（用于增量保存）
     pub last_saved_index: usize,
+    /// 最近一次请求实际提供给模型的工具名称
+    pub last_offered_tools: Vec<String>,
+    /// 最近一次请求的 Token 使用统计
+    pub last_token_stats: Option<TokenUsageStats>,
+    /// 下一次生成的回复前缀（使用一次后清除）
+    pub next_reply_prefix: Option<String>,
 }
 
 impl CharacterSession {
@@ -41,6 +52,9 @@ impl CharacterSession {
             last_active: now,
             status: SessionStatus::Loading,
             last_saved_index: 0,
+            last_offered_tools: Vec::new(),
+            last_token_stats: None,
+            next_reply_prefix: None,
         }
     }
 
@@ -77,8 +91,12 @@ impl CharacterSession {
         Ok(())
     }
 
-    /// 添加用户消息到历史记录
-    pub fn add_user_message(&mut self, content: String) -> ChatMessage {
+    /// 添加用户消息到历史记录，空图片列表视为没有图片
+    pub fn add_user_message(
+        &mut self,
+        content: String,
+        images: Option<Vec<String>>,
+    ) -> ChatMessage {
         let message = ChatMessage {
             role: "user".to_string(),
             content,
@@ -86,12 +104,15 @@ impl CharacterSession {
             reasoning_content: None,
             tool_calls: None,
             tool_call_id: None,
+            images: images.filter(|images| !images.is_empty()),
             timestamp: Some(
                 SystemTime::now()
                     .duration_since(UNIX_EPOCH)
                     .unwrap()
                     .as_secs() as i64,
             ),
+            swipes: Vec::new(),
+            active_swipe: 0,
         };
 
         self.chat_history.push(message.clone());
@@ -118,12 +139,15 @@ impl CharacterSession {
             reasoning_content,
             tool_calls,
             tool_call_id: None,
+            images: None,
             timestamp: Some(
                 SystemTime::now()
                     .duration_since(UNIX_EPOCH)
                     .unwrap()
                     .as_secs() as i64,
             ),
+            swipes: Vec::new(),
+            active_swipe: 0,
         };
 
         self.chat_history.push(message.clone());
@@ -145,12 +169,15 @@ impl CharacterSession {
             reasoning_content: None,
             tool_calls: None,
             tool_call_id: Some(tool_call_id),
+            images: None,
             timestamp: Some(
                 SystemTime::now()
                     .duration_since(UNIX_EPOCH)
                     .unwrap()
                     .as_secs() as i64,
             ),
+            swipes: Vec::new(),
+            active_swipe: 0,
         };
 
         self.chat_history.push(message.clone());
@@ -158,6 +185,30 @@ impl CharacterSession {
         message
     }
 
+    /// 历史为空或只有一条开场白时，用指定开场白（-1 为 first_mes，0 起为备选开场白）作为第一条 AI 消息，
+    /// 其中的 {{char}} / {{user}} 会被替换；开场白为空时只清除原开场白，返回 None
+    pub fn seed_greeting(
+        &mut self,
+        index: i32,
+        user_name: &str,
+    ) -> Result<Option<ChatMessage>, String> {
+        let only_greeting =
+            self.chat_history.len() == 1 && self.chat_history[0].role == "assistant";
+        if !self.chat_history.is_empty() && !only_greeting {
+            return Err("已有对话记录，无法更换开场白".to_string());
+        }
+
+        let card = &self.character_data.card;
+        let greeting =
+            substitute_character_macros(select_greeting(card, index)?, &card.data.name, user_name);
+        self.chat_history.clear();
+        if greeting.trim().is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(self.add_assistant_message(greeting, None, None)))
+    }
+
     /// 保存聊天历史到文件（增量保存）
     pub fn save_history_now(&mut self, app_handle: &AppHandle) -> Result<(), String> {
         let history_manager = ChatHistoryManager::new(app_handle, &self.uuid);
@@ -229,7 +280,7 @@ impl CharacterSession {
             ));
         }
 
-        self.chat_history[index].content = new_content;
+        self.chat_history[index].set_content(new_content);
         self.last_active = Utc::now();
         Ok(self.chat_history[index].clone())
     }
@@ -245,6 +296,30 @@ impl CharacterSession {
         Ok(removed)
     }
 
+    /// 回退到 index 处 AI 回复之前的用户消息：index 必须指向 AI 回复，
+    /// 保留到（含）其前最近一条用户消息为止，返回被丢弃的消息
+    pub fn truncate_before_reply(&mut self, index: usize) -> Result<Vec<ChatMessage>, String> {
+        let message = self.chat_history.get(index).ok_or_else(|| {
+            format!(
+                "消息索引 {} 超出范围（共 {} 条消息）",
+                index,
+                self.chat_history.len()
+            )
+        })?;
+        if message.role != "assistant" {
+            return Err(format!("消息 {} 不是AI回复，无法重新生成", index));
+        }
+
+        let user_index = self.chat_history[..index]
+            .iter()
+            .rposition(|message| message.role == "user")
+            .ok_or_else(|| format!("消息 {} 之前没有用户消息，无法重新生成", index))?;
+
+        let discarded = self.chat_history.split_off(user_index + 1);
+        self.last_active = Utc::now();
+        Ok(discarded)
+    }
+
     /// 获取会话信息摘要
     pub fn get_session_info(&self) -> SessionInfo {
         SessionInfo {
@@ -256,6 +331,50 @@ impl CharacterSession {
             last_context_tokens: self.last_context_tokens,
         }
     }
+
+    /// 组装活跃状态快照，recent_count 为返回的最近消息条数
+    pub fn active_state(&self, recent_count: usize) -> ActiveState {
+        let start = self.chat_history.len().saturating_sub(recent_count);
+        ActiveState {
+            active_uuid: self.uuid.clone(),
+            character_data: self.character_data.clone(),
+            session_info: Some(self.get_session_info()),
+            recent_messages: self.chat_history[start..].to_vec(),
+            last_token_stats: self.last_token_stats.clone(),
+        }
+    }
+}
+
+/// 持久化的会话状态快照（聊天历史已单独保存在磁盘上）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersistedSessionState {
+    pub uuid: String,
+    pub status: SessionStatus,
+    pub last_active: DateTime<Utc>,
+    #[serde(default)]
+    pub last_context_tokens: usize,
+    #[serde(default)]
+    pub selected_ai_role_id: Option<String>,
+}
+
+impl PersistedSessionState {
+    fn from_session(session: &CharacterSession) -> Self {
+        Self {
+            uuid: session.uuid.clone(),
+            status: session.status.clone(),
+            last_active: session.last_active,
+            last_context_tokens: session.last_context_tokens,
+            selected_ai_role_id: session.selected_ai_role_id.clone(),
+        }
+    }
+
+    fn apply_to(self, session: &mut CharacterSession) {
+        session.status = self.status;
+        session.last_active = self.last_active;
+        // 保存进度沿用 load_history 得到的值：历史以磁盘为准，快照中的旧进度会导致重复追加
+        session.last_context_tokens = self.last_context_tokens;
+        session.selected_ai_role_id = self.selected_ai_role_id;
+    }
 }
 
 /// 全局会话管理器
@@ -263,7 +382,7 @@ pub struct SessionManager {
     /// 活跃的会话映射
     sessions: Arc<Mutex<HashMap<String, CharacterSession>>>,
     /// 最大活跃会话数
-    max_sessions: usize,
+    max_sessions: AtomicUsize,
 }
 
 impl SessionManager {
@@ -271,10 +390,27 @@ impl SessionManager {
     pub fn new(max_sessions: usize) -> Self {
         Self {
             sessions: Arc::new(Mutex::new(HashMap::new())),
-            max_sessions,
+            max_sessions: AtomicUsize::new(max_sessions.max(1)),
         }
     }
 
+    /// 获取最大活跃会话数
+    pub fn max_sessions(&self) -> usize {
+        self.max_sessions.load(Ordering::Relaxed)
+    }
+
+    /// 调整最大活跃会话数，超出新上限的最旧会话会被移除，返回移除的数量
+    pub fn set_max_sessions(&self, max_sessions: usize) -> Result<usize, String> {
+        let max_sessions = max_sessions.max(1);
+        let mut sessions = self
+            .sessions
+            .lock()
+            .map_err(|e| format!("锁定会话失败: {}", e))?;
+
+        self.max_sessions.store(max_sessions, Ordering::Relaxed);
+        Ok(Self::cleanup_old_sessions(&mut sessions, max_sessions))
+    }
+
     /// 获取或创建角色会话
     pub fn get_or_create_session(
         &self,
@@ -293,8 +429,8 @@ impl SessionManager {
         }
 
         // 检查会话数量限制
-        if sessions.len() >= self.max_sessions {
-            self.cleanup_old_sessions(&mut sessions)?;
+        if sessions.len() >= self.max_sessions() {
+            Self::cleanup_old_sessions(&mut sessions, self.max_sessions() - 1);
         }
 
         // 创建新会话
@@ -316,8 +452,8 @@ impl SessionManager {
             .lock()
             .map_err(|e| format!("锁定会话失败: {}", e))?;
 
-        if sessions.len() >= self.max_sessions && !sessions.contains_key(&uuid) {
-            self.cleanup_old_sessions(&mut sessions)?;
+        if sessions.len() >= self.max_sessions() && !sessions.contains_key(&uuid) {
+            Self::cleanup_old_sessions(&mut sessions, self.max_sessions() - 1);
         }
 
         if !sessions.contains_key(&uuid) {
@@ -369,6 +505,16 @@ impl SessionManager {
         Ok(sessions.remove(uuid))
     }
 
+    /// 移除全部会话，返回被移除的会话
+    pub fn remove_all_sessions(&self) -> Result<Vec<CharacterSession>, String> {
+        let mut sessions = self
+            .sessions
+            .lock()
+            .map_err(|e| format!("锁定会话失败: {}", e))?;
+
+        Ok(sessions.drain().map(|(_, session)| session).collect())
+    }
+
     /// 获取所有活跃会话信息
     pub fn get_all_sessions_info(&self) -> Result<Vec<SessionInfo>, String> {
         let sessions = self
@@ -382,22 +528,130 @@ impl SessionManager {
             .collect())
     }
 
-    /// 清理旧的会话
+    /// 清理旧的会话，按最后活跃时间移除最旧的会话直到数量不超过 limit，返回移除的数量
     fn cleanup_old_sessions(
-        &self,
         sessions: &mut HashMap<String, CharacterSession>,
-    ) -> Result<(), String> {
-        // 按最后活跃时间排序，移除最旧的会话
-        if let Some((oldest_uuid, _)) = sessions
-            .iter()
-            .min_by_key(|(_, session)| session.last_active)
-            .map(|(uuid, _)| (uuid.clone(), ()))
-        {
+        limit: usize,
+    ) -> usize {
+        let mut removed_count = 0;
+
+        while sessions.len() > limit {
+            let Some(oldest_uuid) = sessions
+                .iter()
+                .min_by_key(|(_, session)| session.last_active)
+                .map(|(uuid, _)| uuid.clone())
+            else {
+                break;
+            };
+
             crate::debug_warn!("清理旧会话: {}", oldest_uuid);
             sessions.remove(&oldest_uuid);
+            removed_count += 1;
         }
 
-        Ok(())
+        removed_count
+    }
+
+    fn get_state_file_path(app_handle: &AppHandle) -> Result<std::path::PathBuf, String> {
+        let app_data_dir = FileUtils::get_app_data_dir(app_handle)?;
+        Ok(app_data_dir.join("sessions").join("state.json"))
+    }
+
+    /// 将活跃会话的状态快照写入 sessions/state.json
+    pub fn persist_state(&self, app_handle: &AppHandle) -> Result<usize, String> {
+        let states = {
+            let sessions = self
+                .sessions
+                .lock()
+                .map_err(|e| format!("锁定会话失败: {}", e))?;
+            sessions
+                .values()
+                .map(PersistedSessionState::from_session)
+                .collect::<Vec<_>>()
+        };
+
+        let file_path = Self::get_state_file_path(app_handle)?;
+        if let Some(parent) = file_path.parent() {
+            FileUtils::ensure_dir_exists(parent)?;
+        }
+        FileUtils::write_json_file(&file_path, &states)?;
+
+        Ok(states.len())
+    }
+
+    /// 从 sessions/state.json 恢复会话，已不存在的角色会被跳过
+    pub fn restore_state(&self, app_handle: &AppHandle) -> Result<usize, String> {
+        let file_path = Self::get_state_file_path(app_handle)?;
+        if !file_path.exists() {
+            return Ok(0);
+        }
+
+        let mut states = FileUtils::read_json_file::<Vec<PersistedSessionState>>(&file_path)?;
+        // 优先恢复最近活跃的会话
+        states.sort_by_key(|state| std::cmp::Reverse(state.last_active));
+
+        let mut sessions = self
+            .sessions
+            .lock()
+            .map_err(|e| format!("锁定会话失败: {}", e))?;
+        let mut restored_count = 0;
+
+        for state in states {
+            if sessions.len() >= self.max_sessions() {
+                break;
+            }
+            if sessions.contains_key(&state.uuid) {
+                continue;
+            }
+
+            match crate::character_storage::CharacterStorage::get_character_by_uuid(
+                app_handle,
+                &state.uuid,
+            ) {
+                Ok(Some(_)) => {}
+                Ok(None) => continue,
+                Err(error) => {
+                    crate::debug_warn!("跳过无法恢复的会话 {}: {}", state.uuid, error);
+                    continue;
+                }
+            }
+
+            let mut session = match CharacterSession::load(app_handle, state.uuid.clone()) {
+                Ok(session) => session,
+                Err(error) => {
+                    crate::debug_warn!("跳过无法恢复的会话 {}: {}", state.uuid, error);
+                    continue;
+                }
+            };
+            state.apply_to(&mut session);
+            sessions.insert(session.uuid.clone(), session);
+            restored_count += 1;
+        }
+
+        Ok(restored_count)
+    }
+
+    /// 移除在 now 时刻已超过 max_age 未活跃的会话，返回被移除的会话
+    pub fn remove_expired_sessions(
+        &self,
+        now: DateTime<Utc>,
+        max_age: chrono::Duration,
+    ) -> Result<Vec<CharacterSession>, String> {
+        let mut sessions = self
+            .sessions
+            .lock()
+            .map_err(|e| format!("锁定会话失败: {}", e))?;
+
+        let expired_uuids: Vec<String> = sessions
+            .iter()
+            .filter(|(_, session)| now.signed_duration_since(session.last_active) > max_age)
+            .map(|(uuid, _)| uuid.clone())
+            .collect();
+
+        Ok(expired_uuids
+            .iter()
+            .filter_map(|uuid| sessions.remove(uuid))
+            .collect())
     }
 
     /// 获取会话（如果存在）
@@ -409,16 +663,183 @@ impl SessionManager {
 
 // 全局会话管理器实例
 lazy_static::lazy_static! {
-    pub static ref SESSION_MANAGER: SessionManager = SessionManager::new(10); // 最多支持10个并发会话
+    pub static ref SESSION_MANAGER: SessionManager = SessionManager::new(
+        crate::app_settings::DEFAULT_MAX_SESSIONS,
+    ); // 启动时按 settings.json 调整
 }
 
-impl SessionManager {
-    /// 获取会话映射的内部引用（用于清理过期会话）
-    pub fn get_sessions_map(
-        &self,
-    ) -> Result<std::sync::MutexGuard<'_, HashMap<String, CharacterSession>>, String> {
-        self.sessions
-            .lock()
-            .map_err(|e| format!("锁定会话失败: {}", e))
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::character_storage::test_support::sample_character;
+    use chrono::Duration;
+    use serde_json::json;
+
+    fn sample_session(uuid: &str, minutes_ago: i64) -> CharacterSession {
+        let mut character_data = sample_character(uuid);
+        character_data.card.data.name = uuid.to_string();
+
+        let mut session = CharacterSession::new(uuid.to_string(), character_data);
+        session.last_active = Utc::now() - Duration::minutes(minutes_ago);
+        session
+    }
+
+    #[test]
+    fn empty_history_is_seeded_with_selected_alternate_greeting() {
+        let mut session = sample_session("greeter", 0);
+        session.character_data.card.data.first_mes = "Hello.".to_string();
+        session.character_data.card.data.alternate_greetings = vec![
+            "Good morning, {{user}}.".to_string(),
+            "Good evening.".to_string(),
+        ];
+
+        let seeded = session.seed_greeting(0, "Bob").unwrap().unwrap();
+
+        assert_eq!(seeded.role, "assistant");
+        assert_eq!(seeded.content, "Good morning, Bob.");
+        assert_eq!(session.chat_history, vec![seeded]);
+
+        session.seed_greeting(-1, "Bob").unwrap();
+        assert_eq!(session.chat_history.len(), 1);
+        assert_eq!(session.chat_history[0].content, "Hello.");
+        assert!(session.seed_greeting(2, "Bob").is_err());
+        assert!(session.seed_greeting(-2, "Bob").is_err());
+        assert_eq!(session.chat_history[0].content, "Hello.");
+
+        session.add_user_message("hi".to_string(), None);
+        assert!(session.seed_greeting(0, "Bob").is_err());
+    }
+
+    #[test]
+    fn truncating_from_middle_reply_discards_later_messages() {
+        let mut session = sample_session("rewind", 0);
+        session.add_user_message("first".to_string(), None);
+        session.add_assistant_message("reply one".to_string(), None, None);
+        session.add_user_message("second".to_string(), None);
+        session.add_tool_message("{}".to_string(), "call_1".to_string(), None);
+        session.add_assistant_message("reply two".to_string(), None, None);
+        session.add_user_message("third".to_string(), None);
+        session.add_assistant_message("reply three".to_string(), None, None);
+
+        assert!(session.truncate_before_reply(2).is_err());
+        let discarded = session.truncate_before_reply(4).unwrap();
+
+        assert_eq!(discarded.len(), 4);
+        let remaining: Vec<&str> = session
+            .chat_history
+            .iter()
+            .map(|message| message.content.as_str())
+            .collect();
+        assert_eq!(remaining, vec!["first", "reply one", "second"]);
+    }
+
+    #[test]
+    fn restored_state_keeps_save_progress_from_loaded_history() {
+        let mut session = sample_session("restored", 0);
+        session.add_user_message("first".to_string(), None);
+        session.add_assistant_message("reply".to_string(), None, None);
+        session.last_saved_index = session.chat_history.len();
+
+        // 旧版本写入的快照中仍带有过期的 last_saved_index
+        let state: PersistedSessionState = serde_json::from_value(json!({
+            "uuid": "restored",
+            "status": "Active",
+            "last_active": Utc::now(),
+            "last_saved_index": 0
+        }))
+        .unwrap();
+        state.apply_to(&mut session);
+
+        assert_eq!(session.last_saved_index, 2);
+    }
+
+    #[test]
+    fn lowering_max_sessions_evicts_oldest_down_to_limit() {
+        let manager = SessionManager::new(4);
+        for (uuid, minutes_ago) in [("a", 30), ("b", 5), ("c", 20), ("d", 1)] {
+            manager
+                .update_session(sample_session(uuid, minutes_ago))
+                .unwrap();
+        }
+
+        let removed = manager.set_max_sessions(2).unwrap();
+
+        assert_eq!(removed, 2);
+        assert_eq!(manager.max_sessions(), 2);
+        assert!(manager.get_session("a").is_none());
+        assert!(manager.get_session("c").is_none());
+        assert!(manager.get_session("b").is_some());
+        assert!(manager.get_session("d").is_some());
+    }
+
+    #[test]
+    fn expired_sessions_are_removed_after_cleanup_interval() {
+        let manager = SessionManager::new(4);
+        manager.update_session(sample_session("fresh", 30)).unwrap();
+        manager.update_session(sample_session("stale", 90)).unwrap();
+
+        let max_age = Duration::hours(2);
+        assert!(manager
+            .remove_expired_sessions(Utc::now(), max_age)
+            .unwrap()
+            .is_empty());
+
+        // 模拟一个小时的清理间隔之后再次检查
+        let removed = manager
+            .remove_expired_sessions(Utc::now() + Duration::hours(1), max_age)
+            .unwrap();
+
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].uuid, "stale");
+        assert!(manager.get_session("stale").is_none());
+        assert!(manager.get_session("fresh").is_some());
+    }
+
+    #[test]
+    fn remove_all_sessions_empties_manager() {
+        let manager = SessionManager::new(4);
+        manager.update_session(sample_session("first", 10)).unwrap();
+        manager.update_session(sample_session("second", 5)).unwrap();
+
+        let mut removed: Vec<_> = manager
+            .remove_all_sessions()
+            .unwrap()
+            .into_iter()
+            .map(|session| session.uuid)
+            .collect();
+        removed.sort();
+
+        assert_eq!(removed, ["first", "second"]);
+        assert!(manager.get_all_sessions_info().unwrap().is_empty());
+        assert!(manager.remove_all_sessions().unwrap().is_empty());
+    }
+
+    #[test]
+    fn active_state_bundles_session_snapshot() {
+        let mut session = sample_session("active", 0);
+        session.status = SessionStatus::Active;
+        for index in 0..5 {
+            session.add_user_message(format!("message {}", index), None);
+        }
+        session.last_token_stats = Some(TokenUsageStats {
+            prompt_tokens: 120,
+            completion_tokens: 30,
+            total_tokens: 150,
+            context_tokens: 100,
+            budget_utilization: 1.5,
+        });
+
+        let state = session.active_state(2);
+
+        assert_eq!(state.active_uuid, "active");
+        assert_eq!(state.character_data.card.data.name, "active");
+        assert_eq!(state.session_info.unwrap().message_count, 5);
+        let contents: Vec<_> = state
+            .recent_messages
+            .iter()
+            .map(|message| message.content.as_str())
+            .collect();
+        assert_eq!(contents, ["message 3", "message 4"]);
+        assert_eq!(state.last_token_stats.unwrap().total_tokens, 150);
     }
 }
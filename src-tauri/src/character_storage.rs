@@ -1,10 +1,25 @@
 use super::file_utils::FileUtils;
-use super::png_utils::PngMetadataUtils;
+use super::png_utils::{PngMetadataUtils, PNG_SIGNATURE};
+use crate::api_config::{ApiConfig, ApiConfigService};
+use crate::backend::domain::LinkedCharacterSummary;
+use crate::card_validation::{
+    validate_card_content, validate_card_spec_value, ValidationIssue, ValidationSeverity,
+};
 use crate::character_session::SESSION_MANAGER;
+use crate::chat_history::{
+    branch_history, history_to_jsonl, parse_history_jsonl, ChatHistoryManager,
+};
+use crate::lorebook::{lorebook_to_json, parse_lorebook};
+use crate::token_counter::get_token_counter;
+use crate::tools::world_book_shared::{merge_world_book_entries, WorldBookMergeStrategy};
 use base64::{engine::general_purpose::STANDARD, Engine as _};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{CompressionType, FilterType as PngFilterType, PngEncoder};
+use image::codecs::webp::WebPEncoder;
 use image::{imageops::FilterType, DynamicImage, ImageFormat};
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::io::Read;
 use std::path::{Path, PathBuf};
 
 /// 角色卡元数据
@@ -14,6 +29,15 @@ pub struct CharacterMeta {
     pub version: String,
     pub created_at: String,
     pub updated_at: String,
+    /// 保存时缓存的角色卡 token 数（见 card_token_count），旧数据在读取列表时补算
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_count: Option<usize>,
+    /// 收藏的角色在列表中置顶
+    #[serde(default)]
+    pub favorite: bool,
+    /// 手动排序序号，越小越靠前；未设置的排在设置了的之后
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort_order: Option<i32>,
 }
 
 // 默认 extensions 值
@@ -88,6 +112,9 @@ pub struct TavernCardV2Data {
     pub extensions: serde_json::Value,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub character_book: Option<CharacterBook>,
+    /// 从 V3 卡导入时保留的 V3 独有字段（group_only_greetings、nickname、assets 等）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub v3_fields: Option<serde_json::Map<String, serde_json::Value>>,
 }
 
 /// Tavern Card V2 结构
@@ -98,6 +125,239 @@ pub struct TavernCardV2 {
     pub data: TavernCardV2Data,
 }
 
+pub const SPEC_V2: &str = "chara_card_v2";
+pub const SPEC_V3: &str = "chara_card_v3";
+
+/// Tavern Card V3 数据结构：V2 字段之外的内容原样保留在 v3_fields 中
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TavernCardV3Data {
+    #[serde(flatten)]
+    pub base: TavernCardV2Data,
+    #[serde(flatten)]
+    pub v3_fields: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Tavern Card V3 结构
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TavernCardV3 {
+    pub spec: String,
+    pub spec_version: String,
+    pub data: TavernCardV3Data,
+}
+
+impl From<TavernCardV3> for TavernCardV2 {
+    fn from(card: TavernCardV3) -> Self {
+        let mut data = card.data.base;
+        data.v3_fields = (!card.data.v3_fields.is_empty()).then_some(card.data.v3_fields);
+
+        Self {
+            spec: card.spec,
+            spec_version: card.spec_version,
+            data,
+        }
+    }
+}
+
+impl From<&TavernCardV2> for TavernCardV3 {
+    fn from(card: &TavernCardV2) -> Self {
+        let mut base = card.data.clone();
+        let v3_fields = base.v3_fields.take().unwrap_or_default();
+
+        Self {
+            spec: card.spec.clone(),
+            spec_version: card.spec_version.clone(),
+            data: TavernCardV3Data { base, v3_fields },
+        }
+    }
+}
+
+/// 按 spec 字段解析角色卡 JSON，V3 卡的独有字段保存在 data.v3_fields 中
+pub fn parse_tavern_card(card_json: &str) -> Result<TavernCardV2, String> {
+    let value: serde_json::Value =
+        serde_json::from_str(card_json).map_err(|e| format!("解析角色卡数据失败: {}", e))?;
+    let spec = value
+        .get("spec")
+        .and_then(|spec| spec.as_str())
+        .unwrap_or(SPEC_V2);
+
+    match spec {
+        SPEC_V3 => serde_json::from_value::<TavernCardV3>(value)
+            .map(TavernCardV2::from)
+            .map_err(|e| format!("解析 V3 角色卡数据失败: {}", e)),
+        SPEC_V2 => serde_json::from_value::<TavernCardV2>(value)
+            .map_err(|e| format!("解析角色卡数据失败: {}", e)),
+        other => Err(format!("不支持的角色卡规范: {}", other)),
+    }
+}
+
+/// 按角色卡原始规范序列化，V3 卡会把 v3_fields 展开回 data
+pub fn serialize_tavern_card(card: &TavernCardV2) -> Result<String, String> {
+    let result = if card.spec == SPEC_V3 {
+        serde_json::to_string_pretty(&TavernCardV3::from(card))
+    } else {
+        serde_json::to_string_pretty(card)
+    };
+
+    result.map_err(|e| format!("序列化角色卡失败: {}", e))
+}
+
+/// 生成 V2 兼容的角色卡 JSON（用于 PNG 的 chara 块）
+fn serialize_tavern_card_as_v2(card: &TavernCardV2) -> Result<String, String> {
+    let mut card = card.clone();
+    card.spec = SPEC_V2.to_string();
+    card.spec_version = "2.0".to_string();
+    card.data.v3_fields = None;
+
+    serde_json::to_string_pretty(&card).map_err(|e| format!("序列化角色卡失败: {}", e))
+}
+
+/// 压缩背景图片：超过 max_dimension 时等比缩小，并按原格式重新编码：
+/// JPEG 按 quality 有损编码；WEBP 为无损编码（image 库只支持无损 WebP 编码）；
+/// 其它格式以最高压缩率编码为 PNG，quality 低于 100 时允许把 16 位色深降为 8 位。
+pub fn compact_image_bytes(
+    image_bytes: &[u8],
+    max_dimension: u32,
+    quality: u8,
+) -> Result<Vec<u8>, String> {
+    if max_dimension == 0 {
+        return Err("最大尺寸必须大于 0".to_string());
+    }
+    if !(1..=100).contains(&quality) {
+        return Err("压缩质量必须在 1 到 100 之间".to_string());
+    }
+
+    let format = image::guess_format(image_bytes).map_err(|e| format!("解析图片失败: {}", e))?;
+    let mut image =
+        image::load_from_memory(image_bytes).map_err(|e| format!("解析图片失败: {}", e))?;
+
+    if image.width() > max_dimension || image.height() > max_dimension {
+        image = image.resize(max_dimension, max_dimension, FilterType::Lanczos3);
+    }
+
+    let color = image.color();
+    let is_high_bit_depth = color.bytes_per_pixel() > color.channel_count();
+    let to_eight_bit = |image: &DynamicImage| {
+        if color.has_alpha() {
+            DynamicImage::ImageRgba8(image.to_rgba8())
+        } else {
+            DynamicImage::ImageRgb8(image.to_rgb8())
+        }
+    };
+
+    let mut output = Vec::new();
+    let result = match format {
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8())
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut output, quality)),
+        ImageFormat::WebP => {
+            to_eight_bit(&image).write_with_encoder(WebPEncoder::new_lossless(&mut output))
+        }
+        _ => {
+            if quality < 100 && is_high_bit_depth {
+                image = to_eight_bit(&image);
+            }
+            image.write_with_encoder(PngEncoder::new_with_quality(
+                &mut output,
+                CompressionType::Best,
+                PngFilterType::Adaptive,
+            ))
+        }
+    };
+    result.map_err(|e| format!("编码图片失败: {}", e))?;
+
+    Ok(output)
+}
+
+/// 角色卡 extensions 中保存关联角色 UUID 列表的键
+pub const LINKED_CHARACTERS_EXTENSION_KEY: &str = "ccc_linked_characters";
+/// 最多注入的关联角色数量（含递归关联）
+pub const MAX_LINKED_CHARACTERS: usize = 5;
+
+/// 读取角色卡关联的角色 UUID
+pub fn linked_character_ids(card: &TavernCardV2) -> Vec<String> {
+    card.data
+        .extensions
+        .get(LINKED_CHARACTERS_EXTENSION_KEY)
+        .and_then(|value| value.as_array())
+        .map(|ids| {
+            ids.iter()
+                .filter_map(|id| id.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// 从根角色出发按广度优先收集关联角色（跳过自身与已访问的角色以避免循环，数量受上限约束）
+pub fn collect_linked_characters(
+    root: &CharacterData,
+    lookup: impl Fn(&str) -> Option<CharacterData>,
+) -> Vec<LinkedCharacterSummary> {
+    let mut visited = vec![root.uuid.clone()];
+    let mut queue: std::collections::VecDeque<String> =
+        linked_character_ids(&root.card).into_iter().collect();
+    let mut linked = Vec::new();
+
+    while let Some(uuid) = queue.pop_front() {
+        if linked.len() >= MAX_LINKED_CHARACTERS {
+            break;
+        }
+        if visited.contains(&uuid) {
+            continue;
+        }
+        visited.push(uuid.clone());
+
+        let Some(character) = lookup(&uuid) else {
+            continue;
+        };
+        queue.extend(linked_character_ids(&character.card));
+        linked.push(LinkedCharacterSummary {
+            name: character.card.data.name,
+            description: character.card.data.description,
+        });
+    }
+
+    linked
+}
+
+/// 把角色卡写入 PNG 的 chara / ccv3 块（chara 写 V2 兼容数据，ccv3 写原始规范数据），
+/// 只替换元数据块，图像数据与已有的聊天记录块原样保留
+fn embed_card_into_png(png_bytes: &[u8], card: &TavernCardV2) -> Result<Vec<u8>, String> {
+    let card_json = serialize_tavern_card(card)?;
+    let chara_json = if card.spec == SPEC_V3 {
+        serialize_tavern_card_as_v2(card)?
+    } else {
+        card_json.clone()
+    };
+    let history_jsonl = PngMetadataUtils::read_history_from_bytes(png_bytes)
+        .map_err(|e| format!("读取 PNG 失败: {}", e))?;
+
+    let output_bytes =
+        PngMetadataUtils::write_character_data_to_bytes(png_bytes, &chara_json, &card_json)
+            .map_err(|e| format!("写入 PNG 元数据失败: {}", e))?;
+    match history_jsonl {
+        Some(history_jsonl) => {
+            PngMetadataUtils::write_history_to_bytes(&output_bytes, &history_jsonl)
+                .map_err(|e| format!("写入聊天记录失败: {}", e))
+        }
+        None => Ok(output_bytes),
+    }
+}
+
+/// 小写的文件扩展名
+fn file_extension(path: &Path) -> Option<String> {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+}
+
+/// 没有角色卡图片时用于导出 PNG 的默认模板图
+fn default_card_template() -> Result<Vec<u8>, String> {
+    let template = image::RgbaImage::from_pixel(400, 600, image::Rgba([38, 38, 46, 255]));
+    let mut bytes = Vec::new();
+    DynamicImage::ImageRgba8(template)
+        .write_to(&mut std::io::Cursor::new(&mut bytes), ImageFormat::Png)
+        .map_err(|e| format!("生成默认角色卡图片失败: {}", e))?;
+    Ok(bytes)
+}
+
 /// 角色数据
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CharacterData {
@@ -108,10 +368,409 @@ pub struct CharacterData {
     pub background_path: String,
     #[serde(rename = "thumbnailPath", default)]
     pub thumbnail_path: String,
+    /// 导入时发现的规范问题，仅随导入结果返回，不写入磁盘
+    #[serde(
+        rename = "importWarnings",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub import_warnings: Vec<ValidationIssue>,
 }
 
 const CARD_FILE_NAME: &str = "card.png";
 const THUMBNAIL_FILE_NAME: &str = "thumbnail.png";
+const CHARACTER_FILE_NAME: &str = "character.json";
+const LEGACY_CHARACTER_FILE_NAME: &str = "card.json";
+
+const CHARACTERS_DIR_NAME: &str = "character-cards";
+const AI_CONFIG_FILE_NAME: &str = "ai_config.yml";
+/// 整库备份中 API 配置的文件名（密钥为明文或已清空，导入时按本机密钥重新加密）
+const LIBRARY_API_CONFIGS_FILE_NAME: &str = "api_configs.json";
+/// 解压备份时允许写出的总字节数上限，防止压缩炸弹占满磁盘
+const MAX_EXTRACTED_ARCHIVE_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+
+/// 列出目录下的所有文件（含子目录），返回 (zip 内路径, 文件路径)，zip 内路径以 prefix 开头
+fn collect_archive_files(dir: &Path, prefix: &str) -> Result<Vec<(String, PathBuf)>, String> {
+    fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+        let entries = fs::read_dir(dir).map_err(|e| format!("读取目录失败: {}", e))?;
+        for entry in entries {
+            let path = entry.map_err(|e| format!("读取目录失败: {}", e))?.path();
+            if path.is_dir() {
+                walk(&path, files)?;
+            } else {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    walk(dir, &mut files)?;
+    files.sort();
+
+    files
+        .into_iter()
+        .map(|file| {
+            let relative = file
+                .strip_prefix(dir)
+                .map_err(|e| format!("计算备份路径失败: {}", e))?
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            Ok((format!("{}{}", prefix, relative), file))
+        })
+        .collect()
+}
+
+/// 把文件与内存中的数据写入 zip，返回写入的条目数
+fn write_zip(
+    output_path: &Path,
+    files: &[(String, PathBuf)],
+    extra: &[(&str, Vec<u8>)],
+) -> Result<usize, String> {
+    let output = fs::File::create(output_path).map_err(|e| format!("创建备份文件失败: {}", e))?;
+    let mut writer = zip::ZipWriter::new(output);
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+
+    let mut write_entry = |name: &str, bytes: &[u8]| -> Result<(), String> {
+        writer
+            .start_file(name, options)
+            .map_err(|e| format!("写入备份失败: {}", e))?;
+        std::io::Write::write_all(&mut writer, bytes).map_err(|e| format!("写入备份失败: {}", e))
+    };
+
+    for (name, file) in files {
+        let bytes = fs::read(file).map_err(|e| format!("读取文件 {} 失败: {}", name, e))?;
+        write_entry(name, &bytes)?;
+    }
+    for (name, bytes) in extra {
+        write_entry(name, bytes)?;
+    }
+
+    writer
+        .finish()
+        .map_err(|e| format!("写入备份失败: {}", e))?;
+    Ok(files.len() + extra.len())
+}
+
+/// 把目录下的所有文件（含子目录）打包为 zip，返回写入的文件数
+pub fn write_directory_archive(dir: &Path, output_path: &Path) -> Result<usize, String> {
+    write_zip(output_path, &collect_archive_files(dir, "")?, &[])
+}
+
+/// 整库导入时如何处理已存在的角色与配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LibraryImportMode {
+    /// 跳过 uuid 已存在的角色与同名 API 配置，本机已有的 AI 配置保持不变
+    Merge,
+    /// 用备份覆盖 uuid 相同的角色、同名 API 配置与 AI 配置
+    Replace,
+}
+
+/// 整库导入结果
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LibraryImportSummary {
+    pub imported_characters: usize,
+    pub skipped_characters: usize,
+    pub imported_api_configs: usize,
+    pub ai_config_restored: bool,
+}
+
+/// 把 app_data 下的 character-cards 目录、ai_config.yml 与给定的 API 配置打包为 zip，返回写入的条目数
+pub fn write_library_archive(
+    app_data_dir: &Path,
+    api_configs: &[ApiConfig],
+    output_path: &Path,
+) -> Result<usize, String> {
+    let characters_dir = app_data_dir.join(CHARACTERS_DIR_NAME);
+    let mut files = if characters_dir.is_dir() {
+        collect_archive_files(&characters_dir, &format!("{}/", CHARACTERS_DIR_NAME))?
+    } else {
+        Vec::new()
+    };
+    // 跳过导入过程中残留的临时目录
+    files.retain(|(name, _)| !name[CHARACTERS_DIR_NAME.len() + 1..].starts_with('.'));
+
+    let ai_config_path = app_data_dir.join(AI_CONFIG_FILE_NAME);
+    if ai_config_path.is_file() {
+        files.push((AI_CONFIG_FILE_NAME.to_string(), ai_config_path));
+    }
+
+    let api_configs_json = serde_json::to_vec_pretty(api_configs)
+        .map_err(|e| format!("序列化 API 配置失败: {}", e))?;
+    write_zip(
+        output_path,
+        &files,
+        &[(LIBRARY_API_CONFIGS_FILE_NAME, api_configs_json)],
+    )
+}
+
+/// 把整库备份恢复到 app_data 目录，返回导入结果与备份中的 API 配置（由调用方合并保存）
+pub fn restore_library_archive(
+    app_data_dir: &Path,
+    archive_path: &Path,
+    mode: LibraryImportMode,
+) -> Result<(LibraryImportSummary, Vec<ApiConfig>), String> {
+    let staging_dir = app_data_dir.join(format!(".library-import-{}", FileUtils::generate_uuid()));
+    let restored = restore_library_from_staging(app_data_dir, &staging_dir, archive_path, mode);
+    if staging_dir.exists() {
+        if let Err(error) = FileUtils::delete_path(&staging_dir) {
+            eprintln!("清理整库导入临时目录失败: {}", error);
+        }
+    }
+    restored
+}
+
+fn restore_library_from_staging(
+    app_data_dir: &Path,
+    staging_dir: &Path,
+    archive_path: &Path,
+    mode: LibraryImportMode,
+) -> Result<(LibraryImportSummary, Vec<ApiConfig>), String> {
+    extract_archive(archive_path, staging_dir)?;
+    let mut summary = LibraryImportSummary::default();
+
+    let characters_dir = app_data_dir.join(CHARACTERS_DIR_NAME);
+    FileUtils::ensure_dir_exists(&characters_dir)?;
+    let staged_characters = staging_dir.join(CHARACTERS_DIR_NAME);
+    if staged_characters.is_dir() {
+        let entries =
+            fs::read_dir(&staged_characters).map_err(|e| format!("读取备份目录失败: {}", e))?;
+        for entry in entries {
+            let source = entry
+                .map_err(|e| format!("读取备份目录失败: {}", e))?
+                .path();
+            let Some(uuid) = source.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if !source.is_dir() || uuid::Uuid::parse_str(uuid).is_err() {
+                continue;
+            }
+
+            let target = characters_dir.join(uuid);
+            if target.exists() {
+                if mode == LibraryImportMode::Merge {
+                    summary.skipped_characters += 1;
+                    continue;
+                }
+                FileUtils::delete_path(&target)?;
+            }
+            fs::rename(&source, &target).map_err(|e| format!("恢复角色 {} 失败: {}", uuid, e))?;
+            summary.imported_characters += 1;
+        }
+    }
+
+    let staged_ai_config = staging_dir.join(AI_CONFIG_FILE_NAME);
+    let ai_config_path = app_data_dir.join(AI_CONFIG_FILE_NAME);
+    if staged_ai_config.is_file()
+        && (mode == LibraryImportMode::Replace || !ai_config_path.exists())
+    {
+        fs::copy(&staged_ai_config, &ai_config_path)
+            .map_err(|e| format!("恢复 AI 配置失败: {}", e))?;
+        summary.ai_config_restored = true;
+    }
+
+    let staged_api_configs = staging_dir.join(LIBRARY_API_CONFIGS_FILE_NAME);
+    let api_configs = if staged_api_configs.is_file() {
+        FileUtils::read_json_file(&staged_api_configs)?
+    } else {
+        Vec::new()
+    };
+
+    Ok((summary, api_configs))
+}
+
+/// 把 zip 解压到目标目录，拒绝指向目录外的条目，返回解压的文件数
+pub fn extract_archive(archive_path: &Path, dest: &Path) -> Result<usize, String> {
+    extract_archive_with_limit(archive_path, dest, MAX_EXTRACTED_ARCHIVE_BYTES)
+}
+
+/// 同 extract_archive，解压总量超过 max_bytes 时中止
+fn extract_archive_with_limit(
+    archive_path: &Path,
+    dest: &Path,
+    max_bytes: u64,
+) -> Result<usize, String> {
+    let file = fs::File::open(archive_path).map_err(|e| format!("打开备份文件失败: {}", e))?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|e| format!("备份文件无效: {}", e))?;
+    FileUtils::ensure_dir_exists(dest)?;
+
+    let mut extracted = 0;
+    let mut remaining = max_bytes;
+    for index in 0..archive.len() {
+        let mut entry = archive
+            .by_index(index)
+            .map_err(|e| format!("读取备份条目失败: {}", e))?;
+        let relative_path = entry
+            .enclosed_name()
+            .ok_or_else(|| format!("备份中包含非法路径: {}", entry.name()))?;
+        let target = dest.join(relative_path);
+
+        if entry.is_dir() {
+            FileUtils::ensure_dir_exists(&target)?;
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            FileUtils::ensure_dir_exists(parent)?;
+        }
+        let mut output = fs::File::create(&target).map_err(|e| format!("写入文件失败: {}", e))?;
+        let written = std::io::copy(&mut (&mut entry).take(remaining + 1), &mut output)
+            .map_err(|e| format!("写入文件失败: {}", e))?;
+        if written > remaining {
+            return Err(format!("备份解压后超过 {} 字节上限", max_bytes));
+        }
+        remaining -= written;
+        extracted += 1;
+    }
+
+    Ok(extracted)
+}
+
+/// 角色卡会进入上下文的文本的 token 数（角色字段、备选开场白与启用的世界书条目）
+pub fn card_token_count(card: &TavernCardV2) -> usize {
+    let data = &card.data;
+    let counter = get_token_counter();
+    let fields = [
+        &data.name,
+        &data.description,
+        &data.personality,
+        &data.scenario,
+        &data.first_mes,
+        &data.mes_example,
+        &data.system_prompt,
+        &data.post_history_instructions,
+    ];
+    let world_book_entries = data
+        .character_book
+        .iter()
+        .flat_map(|book| book.entries.iter())
+        .filter(|entry| entry.enabled)
+        .map(|entry| &entry.content);
+
+    fields
+        .into_iter()
+        .chain(data.alternate_greetings.iter())
+        .chain(world_book_entries)
+        .filter(|text| !text.is_empty())
+        .map(|text| counter.count_tokens(text).token_count)
+        .sum()
+}
+
+/// 新建世界书时使用的默认设置
+fn empty_character_book() -> CharacterBook {
+    CharacterBook {
+        name: None,
+        description: None,
+        scan_depth: Some(2),
+        token_budget: Some(500),
+        recursive_scanning: Some(false),
+        extensions: serde_json::json!({}),
+        entries: Vec::new(),
+    }
+}
+
+/// 写入新的卡数据并刷新修改时间与缓存的 token 数
+pub fn apply_card_update(character: &mut CharacterData, card: &TavernCardV2, now: &str) {
+    character.card = card.clone();
+    character.meta.updated_at = now.to_string();
+    character.meta.token_count = Some(card_token_count(card));
+}
+
+/// 基于已有角色生成副本数据：新 UUID、名称追加 " (Copy)"、重置时间戳
+pub fn duplicate_character_data(source: &CharacterData, uuid: &str, now: &str) -> CharacterData {
+    let mut copy = source.clone();
+    copy.uuid = uuid.to_string();
+    copy.meta.uuid = uuid.to_string();
+    copy.meta.created_at = now.to_string();
+    copy.meta.updated_at = now.to_string();
+    copy.meta.favorite = false;
+    copy.card.data.name = format!("{} (Copy)", source.card.data.name);
+    copy.meta.token_count = Some(card_token_count(&copy.card));
+    copy
+}
+
+/// 只有名称、其余字段为空的 V2 角色卡
+fn new_card(name: &str) -> TavernCardV2 {
+    TavernCardV2 {
+        spec: SPEC_V2.to_string(),
+        spec_version: "2.0".to_string(),
+        data: TavernCardV2Data {
+            name: name.to_string(),
+            description: String::new(),
+            personality: String::new(),
+            scenario: String::new(),
+            first_mes: String::new(),
+            mes_example: String::new(),
+            creator_notes: String::new(),
+            system_prompt: String::new(),
+            post_history_instructions: String::new(),
+            alternate_greetings: Vec::new(),
+            tags: Vec::new(),
+            creator: String::new(),
+            character_version: "1.0".to_string(),
+            extensions: serde_json::json!({}),
+            character_book: None,
+            v3_fields: None,
+        },
+    }
+}
+
+/// 角色列表排序：收藏优先，其次按 sort_order 升序，最后按更新时间倒序（稳定排序）
+pub fn sort_characters(characters: &mut [CharacterData]) {
+    characters.sort_by(|left, right| {
+        right
+            .meta
+            .favorite
+            .cmp(&left.meta.favorite)
+            .then_with(|| match (left.meta.sort_order, right.meta.sort_order) {
+                (Some(left), Some(right)) => left.cmp(&right),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (None, None) => std::cmp::Ordering::Equal,
+            })
+            .then_with(|| right.meta.updated_at.cmp(&left.meta.updated_at))
+    });
+}
+
+/// 统计各标签被多少张角色卡使用（忽略大小写，显示首次出现的写法），按数量倒序、名称升序排列
+pub fn tag_counts(characters: &[CharacterData]) -> Vec<(String, usize)> {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    let mut index_by_tag = std::collections::HashMap::new();
+
+    for character in characters {
+        let mut seen = std::collections::HashSet::new();
+        for tag in &character.card.data.tags {
+            let tag = tag.trim();
+            let normalized = tag.to_lowercase();
+            if tag.is_empty() || !seen.insert(normalized.clone()) {
+                continue;
+            }
+
+            let index = *index_by_tag.entry(normalized).or_insert_with(|| {
+                counts.push((tag.to_string(), 0));
+                counts.len() - 1
+            });
+            counts[index].1 += 1;
+        }
+    }
+
+    counts.sort_by(|left, right| right.1.cmp(&left.1).then_with(|| left.0.cmp(&right.0)));
+    counts
+}
+
+/// 角色卡是否带有指定标签（忽略大小写与首尾空白）
+pub fn has_tag(character: &CharacterData, tag: &str) -> bool {
+    let tag = tag.trim().to_lowercase();
+    character
+        .card
+        .data
+        .tags
+        .iter()
+        .any(|candidate| candidate.trim().to_lowercase() == tag)
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ImagePaths {
@@ -144,7 +803,7 @@ impl CharacterStorage {
     /// 获取角色卡目录
     fn get_characters_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
         let app_data_dir = FileUtils::get_app_data_dir(app_handle)?;
-        let characters_dir = app_data_dir.join("character-cards");
+        let characters_dir = app_data_dir.join(CHARACTERS_DIR_NAME);
         FileUtils::ensure_dir_exists(&characters_dir)?;
         Ok(characters_dir)
     }
@@ -163,7 +822,7 @@ impl CharacterStorage {
         uuid: &str,
     ) -> Result<PathBuf, String> {
         let character_dir = Self::get_character_dir(app_handle, uuid)?;
-        Ok(character_dir.join("character.json"))
+        Ok(character_dir.join(CHARACTER_FILE_NAME))
     }
 
     /// 兼容旧文件名 card.json（仅用于迁移）
@@ -172,7 +831,7 @@ impl CharacterStorage {
         uuid: &str,
     ) -> Result<PathBuf, String> {
         let character_dir = Self::get_character_dir(app_handle, uuid)?;
-        Ok(character_dir.join("card.json"))
+        Ok(character_dir.join(LEGACY_CHARACTER_FILE_NAME))
     }
 
     /// 获取角色原始图片路径（固定文件名）
@@ -393,6 +1052,12 @@ impl CharacterStorage {
                         // 迁移旧数据并生成缩略图（写入新文件名）
                         Self::migrate_character_assets(app_handle, &card_file, &mut character)?;
 
+                        // 旧数据没有缓存 token 数时补算并写回
+                        if character.meta.token_count.is_none() {
+                            character.meta.token_count = Some(card_token_count(&character.card));
+                            FileUtils::write_json_file(&card_file, &character)?;
+                        }
+
                         // 返回给前端时使用绝对路径
                         let mut response_character = character.clone();
                         Self::apply_absolute_paths(app_handle, &mut response_character)?;
@@ -407,9 +1072,26 @@ impl CharacterStorage {
             }
         }
 
+        sort_characters(&mut characters);
         Ok(characters)
     }
 
+    /// 获取所有标签及其使用数量
+    pub fn get_all_tags(app_handle: &tauri::AppHandle) -> Result<Vec<(String, usize)>, String> {
+        Ok(tag_counts(&Self::get_all_characters(app_handle)?))
+    }
+
+    /// 获取带有指定标签的角色卡（忽略大小写），顺序与 get_all_characters 一致
+    pub fn get_characters_by_tag(
+        app_handle: &tauri::AppHandle,
+        tag: &str,
+    ) -> Result<Vec<CharacterData>, String> {
+        Ok(Self::get_all_characters(app_handle)?
+            .into_iter()
+            .filter(|character| has_tag(character, tag))
+            .collect())
+    }
+
     /// 根据UUID获取角色卡
     pub fn get_character_by_uuid(
         app_handle: &tauri::AppHandle,
@@ -440,6 +1122,11 @@ impl CharacterStorage {
         Ok(Some(response))
     }
 
+    /// 检查角色卡内容（名称、开场白、spec 版本、世界书条目），只读
+    pub fn validate_card(card: &TavernCardV2) -> Vec<ValidationIssue> {
+        validate_card_content(card)
+    }
+
     /// 创建新的角色卡
     pub fn create_character(
         app_handle: &tauri::AppHandle,
@@ -448,33 +1135,16 @@ impl CharacterStorage {
         let uuid = FileUtils::generate_uuid();
         let now = chrono::Utc::now().to_rfc3339();
 
+        let card = new_card(name);
+
         let meta = CharacterMeta {
             uuid: uuid.clone(),
             version: "1.0".to_string(),
             created_at: now.clone(),
             updated_at: now,
-        };
-
-        let card = TavernCardV2 {
-            spec: "chara_card_v2".to_string(),
-            spec_version: "2.0".to_string(),
-            data: TavernCardV2Data {
-                name: name.to_string(),
-                description: String::new(),
-                personality: String::new(),
-                scenario: String::new(),
-                first_mes: String::new(),
-                mes_example: String::new(),
-                creator_notes: String::new(),
-                system_prompt: String::new(),
-                post_history_instructions: String::new(),
-                alternate_greetings: Vec::new(),
-                tags: Vec::new(),
-                creator: String::new(),
-                character_version: "1.0".to_string(),
-                extensions: serde_json::json!({}),
-                character_book: None,
-            },
+            token_count: Some(card_token_count(&card)),
+            favorite: false,
+            sort_order: None,
         };
 
         let character_data = CharacterData {
@@ -483,6 +1153,7 @@ impl CharacterStorage {
             card,
             background_path: String::new(),
             thumbnail_path: String::new(),
+            import_warnings: Vec::new(),
         };
 
         // 保存角色卡文件
@@ -506,15 +1177,236 @@ impl CharacterStorage {
 
         let mut character_data: CharacterData = FileUtils::read_json_file(&card_file)?;
 
-        // 更新卡数据和修改时间
-        character_data.card = card.clone();
-        character_data.meta.updated_at = chrono::Utc::now().to_rfc3339();
+        // 更新卡数据、修改时间和 token 数
+        apply_card_update(&mut character_data, card, &chrono::Utc::now().to_rfc3339());
 
         FileUtils::write_json_file(&card_file, &character_data)?;
         Self::sync_session_character_data(app_handle, uuid)?;
         Ok(())
     }
 
+    /// 切换角色的收藏状态，返回切换后的值
+    pub fn toggle_favorite(app_handle: &tauri::AppHandle, uuid: &str) -> Result<bool, String> {
+        let card_file = Self::get_character_file_path(app_handle, uuid)?;
+        if !card_file.exists() {
+            return Err(format!("角色 {} 不存在", uuid));
+        }
+
+        let mut character_data: CharacterData = FileUtils::read_json_file(&card_file)?;
+        character_data.meta.favorite = !character_data.meta.favorite;
+        FileUtils::write_json_file(&card_file, &character_data)?;
+        Self::sync_session_character_data(app_handle, uuid)?;
+
+        Ok(character_data.meta.favorite)
+    }
+
+    /// 设置关联角色（保存在角色卡 extensions 中），传入空列表时取消关联
+    pub fn set_linked_characters(
+        app_handle: &tauri::AppHandle,
+        uuid: &str,
+        linked_uuids: &[String],
+    ) -> Result<Vec<String>, String> {
+        let mut character = Self::get_character_by_uuid(app_handle, uuid)?
+            .ok_or_else(|| format!("角色 {} 不存在", uuid))?;
+
+        let mut linked_ids: Vec<String> = Vec::new();
+        for linked_uuid in linked_uuids.iter().map(|id| id.trim()) {
+            if linked_uuid.is_empty() || linked_uuid == uuid {
+                continue;
+            }
+            if linked_ids.iter().any(|id| id == linked_uuid) {
+                continue;
+            }
+            if Self::get_character_by_uuid(app_handle, linked_uuid)?.is_none() {
+                return Err(format!("关联角色 {} 不存在", linked_uuid));
+            }
+            linked_ids.push(linked_uuid.to_string());
+        }
+        if linked_ids.len() > MAX_LINKED_CHARACTERS {
+            return Err(format!("最多只能关联 {} 个角色", MAX_LINKED_CHARACTERS));
+        }
+
+        let extensions = &mut character.card.data.extensions;
+        if !extensions.is_object() {
+            *extensions = serde_json::json!({});
+        }
+        let extensions = extensions.as_object_mut().expect("checked above");
+        if linked_ids.is_empty() {
+            extensions.remove(LINKED_CHARACTERS_EXTENSION_KEY);
+        } else {
+            extensions.insert(
+                LINKED_CHARACTERS_EXTENSION_KEY.to_string(),
+                serde_json::json!(linked_ids),
+            );
+        }
+
+        Self::update_character(app_handle, uuid, &character.card)?;
+        Ok(linked_ids)
+    }
+
+    /// 解析角色的关联角色摘要，读取失败的角色会被跳过
+    pub fn resolve_linked_characters(
+        app_handle: &tauri::AppHandle,
+        character: &CharacterData,
+    ) -> Vec<LinkedCharacterSummary> {
+        collect_linked_characters(character, |uuid| {
+            Self::get_character_by_uuid(app_handle, uuid).ok().flatten()
+        })
+    }
+
+    /// 复制角色卡（包含世界书和背景图片，不复制聊天记录）
+    pub fn duplicate_character(
+        app_handle: &tauri::AppHandle,
+        uuid: &str,
+    ) -> Result<CharacterData, String> {
+        let source_file = Self::get_character_file_path(app_handle, uuid)?;
+        if !source_file.exists() {
+            return Err(format!("角色 {} 不存在", uuid));
+        }
+
+        let source: CharacterData = FileUtils::read_json_file(&source_file)?;
+        let new_uuid = FileUtils::generate_uuid();
+        let now = chrono::Utc::now().to_rfc3339();
+        let mut character_data = duplicate_character_data(&source, &new_uuid, &now);
+
+        let source_card = Self::get_card_image_path(app_handle, uuid)?;
+        if source_card.exists() {
+            let target_card = Self::get_card_image_path(app_handle, &new_uuid)?;
+            fs::copy(&source_card, &target_card).map_err(|e| format!("复制背景图片失败: {}", e))?;
+            character_data.background_path = CARD_FILE_NAME.to_string();
+
+            let source_thumbnail = Self::get_thumbnail_image_path(app_handle, uuid)?;
+            let target_thumbnail = Self::get_thumbnail_image_path(app_handle, &new_uuid)?;
+            if source_thumbnail.exists() {
+                fs::copy(&source_thumbnail, &target_thumbnail)
+                    .map_err(|e| format!("复制缩略图失败: {}", e))?;
+            } else {
+                let card_bytes =
+                    fs::read(&target_card).map_err(|e| format!("读取背景图片失败: {}", e))?;
+                Self::write_card_and_thumbnail(&target_card, &target_thumbnail, &card_bytes)?;
+            }
+            character_data.thumbnail_path = THUMBNAIL_FILE_NAME.to_string();
+        } else {
+            character_data.background_path = String::new();
+            character_data.thumbnail_path = String::new();
+        }
+
+        let card_file = Self::get_character_file_path(app_handle, &new_uuid)?;
+        FileUtils::write_json_file(&card_file, &character_data)?;
+
+        Self::apply_absolute_paths(app_handle, &mut character_data)?;
+        Ok(character_data)
+    }
+
+    /// 把 source 角色的世界书合并进 target 角色并保存，返回更新后的 target 与合并的条目数
+    pub fn merge_world_books(
+        app_handle: &tauri::AppHandle,
+        target_uuid: &str,
+        source_uuid: &str,
+        strategy: WorldBookMergeStrategy,
+    ) -> Result<(CharacterData, usize), String> {
+        if target_uuid == source_uuid {
+            return Err("不能将角色的世界书合并到自身".to_string());
+        }
+
+        let mut target = Self::get_character_by_uuid(app_handle, target_uuid)?
+            .ok_or_else(|| format!("角色 {} 不存在", target_uuid))?;
+        let source = Self::get_character_by_uuid(app_handle, source_uuid)?
+            .ok_or_else(|| format!("角色 {} 不存在", source_uuid))?;
+
+        let Some(source_book) = source
+            .card
+            .data
+            .character_book
+            .filter(|book| !book.entries.is_empty())
+        else {
+            return Ok((target, 0));
+        };
+
+        let target_book = target
+            .card
+            .data
+            .character_book
+            .get_or_insert_with(empty_character_book);
+        let merged_count =
+            merge_world_book_entries(&mut target_book.entries, &source_book.entries, strategy);
+
+        Self::update_character(app_handle, target_uuid, &target.card)?;
+        Ok((target, merged_count))
+    }
+
+    /// 导入 SillyTavern lorebook JSON 到角色世界书。
+    /// strategy 为 None 时替换全部条目，否则按策略合并；返回更新后的角色与导入的条目数
+    pub fn import_lorebook(
+        app_handle: &tauri::AppHandle,
+        uuid: &str,
+        file_data: &[u8],
+        strategy: Option<WorldBookMergeStrategy>,
+    ) -> Result<(CharacterData, usize), String> {
+        let lorebook_json =
+            std::str::from_utf8(file_data).map_err(|e| format!("读取世界书文件失败: {}", e))?;
+        let entries = parse_lorebook(lorebook_json)?;
+
+        let mut character = Self::get_character_by_uuid(app_handle, uuid)?
+            .ok_or_else(|| format!("角色 {} 不存在", uuid))?;
+        let book = character
+            .card
+            .data
+            .character_book
+            .get_or_insert_with(empty_character_book);
+        let imported_count = match strategy {
+            Some(strategy) => merge_world_book_entries(&mut book.entries, &entries, strategy),
+            None => {
+                book.entries = entries;
+                book.entries.len()
+            }
+        };
+
+        Self::update_character(app_handle, uuid, &character.card)?;
+        Ok((character, imported_count))
+    }
+
+    /// 导出角色世界书为 SillyTavern lorebook JSON
+    pub fn export_lorebook(app_handle: &tauri::AppHandle, uuid: &str) -> Result<String, String> {
+        let character = Self::get_character_by_uuid(app_handle, uuid)?
+            .ok_or_else(|| format!("角色 {} 不存在", uuid))?;
+        let entries = character
+            .card
+            .data
+            .character_book
+            .map(|book| book.entries)
+            .unwrap_or_default();
+
+        serde_json::to_string_pretty(&lorebook_to_json(&entries))
+            .map_err(|e| format!("序列化世界书失败: {}", e))
+    }
+
+    /// 在指定消息处分支对话：复制角色卡，新角色的聊天记录为源记录截至（含）index 的部分。
+    /// 源会话已加载时使用内存中的记录（可能包含尚未保存的消息）
+    pub fn branch_conversation(
+        app_handle: &tauri::AppHandle,
+        uuid: &str,
+        index: usize,
+    ) -> Result<CharacterData, String> {
+        let source_history = match SESSION_MANAGER.get_session(uuid) {
+            Some(session) => session.chat_history,
+            None => ChatHistoryManager::new(app_handle, uuid).load_history()?,
+        };
+        let history = branch_history(&source_history, index)?;
+
+        let branch = Self::duplicate_character(app_handle, uuid)?;
+        if let Err(error) = ChatHistoryManager::new(app_handle, &branch.uuid).save_history(&history)
+        {
+            // 写入分支历史失败时删除刚复制的角色，避免留下没有对话记录的分支
+            if let Err(cleanup_error) = Self::delete_character(app_handle, &branch.uuid) {
+                eprintln!("删除未完成的分支角色失败: {}", cleanup_error);
+            }
+            return Err(error);
+        }
+
+        Ok(branch)
+    }
+
     /// 删除角色卡
     pub fn delete_character(app_handle: &tauri::AppHandle, uuid: &str) -> Result<(), String> {
         let characters_dir = Self::get_characters_dir(app_handle)?;
@@ -583,6 +1475,40 @@ impl CharacterStorage {
         })
     }
 
+    /// 压缩并重写角色背景图片，返回节省的字节数（压缩后没有变小时保留原图）
+    pub fn optimize_background(
+        app_handle: &tauri::AppHandle,
+        uuid: &str,
+        max_dimension: u32,
+        quality: u8,
+    ) -> Result<u64, String> {
+        let card_file = Self::get_character_file_path(app_handle, uuid)?;
+        if !card_file.exists() {
+            return Err(format!("角色 {} 不存在", uuid));
+        }
+
+        let card_path = Self::get_card_image_path(app_handle, uuid)?;
+        if !card_path.exists() {
+            return Err(format!("角色 {} 没有背景图片", uuid));
+        }
+
+        let original = fs::read(&card_path).map_err(|e| format!("读取背景图片失败: {}", e))?;
+        let compacted = compact_image_bytes(&original, max_dimension, quality)?;
+        if compacted.len() >= original.len() {
+            return Ok(0);
+        }
+
+        fs::write(&card_path, &compacted).map_err(|e| format!("写入背景图片失败: {}", e))?;
+
+        let mut character_data: CharacterData = FileUtils::read_json_file(&card_file)?;
+        character_data.background_path = CARD_FILE_NAME.to_string();
+        character_data.meta.updated_at = chrono::Utc::now().to_rfc3339();
+        FileUtils::write_json_file(&card_file, &character_data)?;
+        Self::sync_session_character_data(app_handle, uuid)?;
+
+        Ok((original.len() - compacted.len()) as u64)
+    }
+
     /// 更新角色背景图片路径
     pub fn update_character_background_path(
         app_handle: &tauri::AppHandle,
@@ -623,13 +1549,16 @@ impl CharacterStorage {
 
     /// 导出角色卡
     ///
+    /// 输出路径以 `.png` 结尾时嵌入角色卡图片（没有图片时使用默认模板图），
+    /// 以 `.json` 结尾时导出 JSON；未指定扩展名时按是否有图片自动选择并补全扩展名。
+    ///
     /// # 参数
     /// * `app_handle` - Tauri 应用句柄
     /// * `uuid` - 角色 UUID
     /// * `output_path` - 输出文件路径
     ///
     /// # 返回
-    /// * `Ok(String)` - 导出的文件类型（"json" 或 "png"）
+    /// * `Ok(String)` - 实际写入的文件路径
     pub fn export_character_card(
         app_handle: &tauri::AppHandle,
         uuid: &str,
@@ -639,103 +1568,246 @@ impl CharacterStorage {
         let character = Self::get_character_by_uuid(app_handle, uuid)?
             .ok_or_else(|| format!("角色 {} 不存在", uuid))?;
 
-        // 序列化 TavernCardV2 为 JSON
-        let card_json = serde_json::to_string_pretty(&character.card)
-            .map_err(|e| format!("序列化角色卡失败: {}", e))?;
+        // 按原始规范序列化角色卡
+        let card_json = serialize_tavern_card(&character.card)?;
 
         let card_image_path = Self::get_card_image_path(app_handle, uuid)?;
+        let mut output_path = PathBuf::from(output_path);
+        let export_png = match file_extension(&output_path).as_deref() {
+            Some("png") => true,
+            Some("json") => false,
+            Some(_) => card_image_path.exists(),
+            None => {
+                let export_png = card_image_path.exists();
+                output_path.set_extension(if export_png { "png" } else { "json" });
+                export_png
+            }
+        };
 
-        if card_image_path.exists() {
-            let image_data =
-                fs::read(&card_image_path).map_err(|e| format!("读取背景图片失败: {}", e))?;
+        if export_png {
+            let image_data = if card_image_path.exists() {
+                fs::read(&card_image_path).map_err(|e| format!("读取背景图片失败: {}", e))?
+            } else {
+                default_card_template()?
+            };
 
-            // 将角色卡数据写入 PNG
-            let output_bytes =
-                PngMetadataUtils::write_character_data_to_bytes(&image_data, &card_json)
-                    .map_err(|e| format!("写入 PNG 元数据失败: {}", e))?;
+            let output_bytes = embed_card_into_png(&image_data, &character.card)?;
 
             // 保存到文件
-            fs::write(output_path, output_bytes)
+            fs::write(&output_path, output_bytes)
                 .map_err(|e| format!("保存 PNG 文件失败: {}", e))?;
-
-            Ok("png".to_string())
         } else {
-            // 没有图片，直接导出 JSON
-            fs::write(output_path, card_json).map_err(|e| format!("保存 JSON 文件失败: {}", e))?;
+            fs::write(&output_path, card_json).map_err(|e| format!("保存 JSON 文件失败: {}", e))?;
+        }
+
+        Ok(output_path.to_string_lossy().to_string())
+    }
+
+    /// 用当前角色卡数据原地更新已导出 PNG 中的 chara / ccv3 块，不重新编码图像
+    pub fn update_png_character_data(
+        app_handle: &tauri::AppHandle,
+        png_path: &str,
+        uuid: &str,
+    ) -> Result<String, String> {
+        let character = Self::get_character_by_uuid(app_handle, uuid)?
+            .ok_or_else(|| format!("角色 {} 不存在", uuid))?;
 
-            Ok("json".to_string())
+        let png_bytes = fs::read(png_path).map_err(|e| format!("读取 PNG 文件失败: {}", e))?;
+        if !png_bytes.starts_with(&PNG_SIGNATURE) {
+            return Err(format!("{} 不是 PNG 图片", png_path));
         }
+
+        let output_bytes = embed_card_into_png(&png_bytes, &character.card)?;
+        fs::write(png_path, output_bytes).map_err(|e| format!("保存 PNG 文件失败: {}", e))?;
+
+        Ok(png_path.to_string())
     }
 
-    /// 从 PNG 或 JSON 导入角色卡
-    ///
-    /// # 参数
-    /// * `app_handle` - Tauri 应用句柄
-    /// * `file_path` - 导入文件路径
-    ///
-    /// # 返回
-    /// * `Ok(CharacterData)` - 导入的角色数据
-    pub fn import_character_card(
+    /// 导出携带聊天记录的 PNG 角色卡，聊天记录以 JSONL 写入 ccc_history 块
+    pub fn export_character_with_history(
+        app_handle: &tauri::AppHandle,
+        uuid: &str,
+        output_path: &str,
+    ) -> Result<String, String> {
+        let mut output_path = PathBuf::from(output_path);
+        output_path.set_extension("png");
+        let exported_path =
+            Self::export_character_card(app_handle, uuid, &output_path.to_string_lossy())?;
+
+        let history = ChatHistoryManager::new(app_handle, uuid).load_history()?;
+        let png_bytes = fs::read(&exported_path).map_err(|e| format!("读取导出文件失败: {}", e))?;
+        let output_bytes =
+            PngMetadataUtils::write_history_to_bytes(&png_bytes, &history_to_jsonl(&history))
+                .map_err(|e| format!("写入聊天记录失败: {}", e))?;
+        fs::write(&exported_path, output_bytes).map_err(|e| format!("保存 PNG 文件失败: {}", e))?;
+
+        Ok(exported_path)
+    }
+
+    /// 导入角色卡并恢复其中携带的聊天记录；没有聊天记录块时等同于普通导入
+    pub fn import_character_with_history(
         app_handle: &tauri::AppHandle,
         file_path: &str,
     ) -> Result<CharacterData, String> {
-        // 读取文件
         let file_data = fs::read(file_path).map_err(|e| format!("读取文件失败: {}", e))?;
-
-        // 判断文件类型
-        let is_png = file_path.ends_with(".png");
-
-        // 尝试解析为 PNG
-        let card_json = if is_png {
-            // 从 PNG 中提取角色卡数据
-            PngMetadataUtils::read_character_data_from_bytes(&file_data)
-                .map_err(|e| format!("从 PNG 读取角色卡数据失败: {}", e))?
+        let is_png = file_extension(Path::new(file_path)).as_deref() == Some("png");
+        let history_jsonl = if is_png {
+            PngMetadataUtils::read_history_from_bytes(&file_data)
+                .map_err(|e| format!("读取聊天记录失败: {}", e))?
         } else {
-            // 作为 JSON 解析
-            String::from_utf8(file_data.clone())
-                .map_err(|e| format!("读取 JSON 文件失败: {}", e))?
+            None
         };
 
-        // 解析 TavernCardV2
-        let card: TavernCardV2 =
-            serde_json::from_str(&card_json).map_err(|e| format!("解析角色卡数据失败: {}", e))?;
+        let Some(history_jsonl) = history_jsonl else {
+            return Self::import_character_card_from_bytes(app_handle, &file_data, file_path);
+        };
 
-        // 生成新的 UUID 和元数据
-        let uuid = FileUtils::generate_uuid();
-        let now = chrono::Utc::now().to_rfc3339();
+        let history = parse_history_jsonl(&history_jsonl)?;
+        let card_bytes = PngMetadataUtils::strip_history_from_bytes(&file_data)
+            .map_err(|e| format!("读取 PNG 失败: {}", e))?;
+        let character = Self::import_character_card_from_bytes(app_handle, &card_bytes, file_path)?;
+        ChatHistoryManager::new(app_handle, &character.uuid).save_history(&history)?;
 
-        let meta = CharacterMeta {
-            uuid: uuid.clone(),
-            version: "1.0".to_string(),
-            created_at: now.clone(),
-            updated_at: now,
-        };
+        Ok(character)
+    }
 
-        let mut character_data = CharacterData {
-            uuid: uuid.clone(),
-            meta,
-            card,
-            background_path: String::new(),
-            thumbnail_path: String::new(),
-        };
+    /// 把角色目录（角色数据、背景图、聊天记录、使用统计等）打包为 zip 备份，返回备份文件路径
+    pub fn export_archive(
+        app_handle: &tauri::AppHandle,
+        uuid: &str,
+        output_path: &str,
+    ) -> Result<String, String> {
+        let character_dir = Self::get_characters_dir(app_handle)?.join(uuid);
+        if !character_dir.is_dir() {
+            return Err(format!("角色 {} 不存在", uuid));
+        }
 
-        // 保存角色卡及图片
-        let card_file = Self::get_character_file_path(app_handle, &uuid)?;
+        let mut output_path = PathBuf::from(output_path);
+        output_path.set_extension("zip");
+        if let Some(parent) = output_path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            FileUtils::ensure_dir_exists(parent)?;
+        }
+        write_directory_archive(&character_dir, &output_path)?;
 
-        if is_png {
-            let card_path = Self::get_card_image_path(app_handle, &uuid)?;
-            let thumbnail_path = Self::get_thumbnail_image_path(app_handle, &uuid)?;
-            Self::write_card_and_thumbnail(&card_path, &thumbnail_path, &file_data)?;
-            character_data.background_path = CARD_FILE_NAME.to_string();
-            character_data.thumbnail_path = THUMBNAIL_FILE_NAME.to_string();
+        Ok(output_path.to_string_lossy().to_string())
+    }
+
+    /// 从 zip 备份恢复角色；备份中的 uuid 已被占用或无效时分配新的 uuid，避免覆盖现有角色
+    pub fn import_archive(
+        app_handle: &tauri::AppHandle,
+        file_path: &str,
+    ) -> Result<CharacterData, String> {
+        let characters_dir = Self::get_characters_dir(app_handle)?;
+        // 暂存在角色目录之外，避免解压期间被当作角色列出；与角色目录同盘以便直接 rename
+        let staging_dir = FileUtils::get_app_data_dir(app_handle)?
+            .join(format!(".character-import-{}", FileUtils::generate_uuid()));
+
+        let restored = Self::restore_archive(&characters_dir, &staging_dir, Path::new(file_path));
+        if staging_dir.exists() {
+            if let Err(error) = FileUtils::delete_path(&staging_dir) {
+                eprintln!("清理备份解压目录失败: {}", error);
+            }
         }
+        let uuid = restored?;
 
-        FileUtils::write_json_file(&card_file, &character_data)?;
+        Self::get_character_by_uuid(app_handle, &uuid)?
+            .ok_or_else(|| format!("恢复后的角色 {} 读取失败", uuid))
+    }
 
-        let mut response = character_data.clone();
-        Self::apply_absolute_paths(app_handle, &mut response)?;
+    /// 备份整个角色库（全部角色目录、ai_config.yml 与 API 配置）为 zip，返回备份文件路径。
+    /// include_api_keys 为 false 时清空 API 密钥；为 true 时密钥以明文写入备份
+    pub fn export_all_characters(
+        app_handle: &tauri::AppHandle,
+        output_path: &str,
+        include_api_keys: bool,
+    ) -> Result<String, String> {
+        let app_data_dir = FileUtils::get_app_data_dir(app_handle)?;
+        let mut api_configs = ApiConfigService::get_all_api_configs(app_handle)?;
+        if !include_api_keys {
+            for config in &mut api_configs {
+                config.api_key.clear();
+            }
+        }
 
-        Ok(response)
+        let mut output_path = PathBuf::from(output_path);
+        output_path.set_extension("zip");
+        if let Some(parent) = output_path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            FileUtils::ensure_dir_exists(parent)?;
+        }
+        write_library_archive(&app_data_dir, &api_configs, &output_path)?;
+
+        Ok(output_path.to_string_lossy().to_string())
+    }
+
+    /// 从整库备份恢复角色与配置；Merge 模式跳过已存在的 uuid，Replace 模式覆盖
+    pub fn import_all_characters(
+        app_handle: &tauri::AppHandle,
+        file_path: &str,
+        mode: LibraryImportMode,
+    ) -> Result<LibraryImportSummary, String> {
+        let app_data_dir = FileUtils::get_app_data_dir(app_handle)?;
+        let (mut summary, api_configs) =
+            restore_library_archive(&app_data_dir, Path::new(file_path), mode)?;
+        summary.imported_api_configs = ApiConfigService::import_api_configs(
+            app_handle,
+            api_configs,
+            mode == LibraryImportMode::Replace,
+        )?;
+
+        Ok(summary)
+    }
+
+    /// 解压到临时目录、必要时重映射 uuid，再移动到角色目录，返回最终 uuid
+    fn restore_archive(
+        characters_dir: &Path,
+        staging_dir: &Path,
+        archive_path: &Path,
+    ) -> Result<String, String> {
+        extract_archive(archive_path, staging_dir)?;
+
+        let character_file = [CHARACTER_FILE_NAME, LEGACY_CHARACTER_FILE_NAME]
+            .iter()
+            .map(|name| staging_dir.join(name))
+            .find(|path| path.is_file())
+            .ok_or_else(|| "备份中缺少角色数据文件".to_string())?;
+        let mut character: CharacterData = FileUtils::read_json_file(&character_file)?;
+
+        let uuid = Some(character.uuid.as_str())
+            .filter(|uuid| uuid::Uuid::parse_str(uuid).is_ok())
+            .filter(|uuid| !characters_dir.join(uuid).exists())
+            .map(ToString::to_string)
+            .unwrap_or_else(FileUtils::generate_uuid);
+        character.uuid = uuid.clone();
+        character.meta.uuid = uuid.clone();
+
+        fs::remove_file(&character_file).map_err(|e| format!("写入角色数据失败: {}", e))?;
+        FileUtils::write_json_file(&staging_dir.join(CHARACTER_FILE_NAME), &character)?;
+        fs::rename(staging_dir, characters_dir.join(&uuid))
+            .map_err(|e| format!("恢复角色目录失败: {}", e))?;
+
+        Ok(uuid)
+    }
+
+    /// 从 PNG 或 JSON 导入角色卡
+    ///
+    /// # 参数
+    /// * `app_handle` - Tauri 应用句柄
+    /// * `file_path` - 导入文件路径
+    ///
+    /// # 返回
+    /// * `Ok(CharacterData)` - 导入的角色数据
+    pub fn import_character_card(
+        app_handle: &tauri::AppHandle,
+        file_path: &str,
+    ) -> Result<CharacterData, String> {
+        let file_data = fs::read(file_path).map_err(|e| format!("读取文件失败: {}", e))?;
+        Self::import_character_card_from_bytes(app_handle, &file_data, file_path)
     }
 
     /// 从字节数据导入角色卡
@@ -752,8 +1824,10 @@ impl CharacterStorage {
         file_data: &[u8],
         file_name: &str,
     ) -> Result<CharacterData, String> {
+        let is_png = file_extension(Path::new(file_name)).as_deref() == Some("png");
+
         // 尝试解析为 PNG
-        let card_json = if file_name.ends_with(".png") {
+        let card_json = if is_png {
             // 从 PNG 中提取角色卡数据
             PngMetadataUtils::read_character_data_from_bytes(file_data)
                 .map_err(|e| format!("从 PNG 读取角色卡数据失败: {}", e))?
@@ -763,9 +1837,22 @@ impl CharacterStorage {
                 .map_err(|e| format!("读取 JSON 文件失败: {}", e))?
         };
 
-        // 解析 TavernCardV2
-        let card: TavernCardV2 =
-            serde_json::from_str(&card_json).map_err(|e| format!("解析角色卡数据失败: {}", e))?;
+        // 按 Tavern 规范检查原始 JSON，再按 spec 解析 V2 / V3 角色卡
+        let import_warnings = serde_json::from_str(&card_json)
+            .map(|value| validate_card_spec_value(&value))
+            .unwrap_or_default();
+        let card = parse_tavern_card(&card_json).map_err(|error| {
+            let spec_errors: Vec<String> = import_warnings
+                .iter()
+                .filter(|issue| issue.severity == ValidationSeverity::Error)
+                .map(|issue| format!("{}: {}", issue.path, issue.message))
+                .collect();
+            if spec_errors.is_empty() {
+                error
+            } else {
+                format!("{}（{}）", error, spec_errors.join("；"))
+            }
+        })?;
 
         // 生成新的 UUID 和元数据
         let uuid = FileUtils::generate_uuid();
@@ -776,6 +1863,9 @@ impl CharacterStorage {
             version: "1.0".to_string(),
             created_at: now.clone(),
             updated_at: now,
+            token_count: Some(card_token_count(&card)),
+            favorite: false,
+            sort_order: None,
         };
 
         let mut character_data = CharacterData {
@@ -784,12 +1874,13 @@ impl CharacterStorage {
             card,
             background_path: String::new(),
             thumbnail_path: String::new(),
+            import_warnings: Vec::new(),
         };
 
         // 保存角色卡
         let card_file = Self::get_character_file_path(app_handle, &uuid)?;
 
-        if file_name.ends_with(".png") {
+        if is_png {
             let card_path = Self::get_card_image_path(app_handle, &uuid)?;
             let thumbnail_path = Self::get_thumbnail_image_path(app_handle, &uuid)?;
             Self::write_card_and_thumbnail(&card_path, &thumbnail_path, file_data)?;
@@ -801,7 +1892,537 @@ impl CharacterStorage {
 
         let mut response = character_data.clone();
         Self::apply_absolute_paths(app_handle, &mut response)?;
+        response.import_warnings = import_warnings;
 
         Ok(response)
     }
 }
+
+/// 测试共用的角色卡与角色数据
+#[cfg(test)]
+pub(crate) mod test_support {
+    use super::*;
+
+    /// 名为 Alice、其余字段为空的角色卡
+    pub(crate) fn sample_card() -> TavernCardV2 {
+        new_card("Alice")
+    }
+
+    /// 使用 sample_card 的角色，时间字段为空
+    pub(crate) fn sample_character(uuid: &str) -> CharacterData {
+        CharacterData {
+            uuid: uuid.to_string(),
+            meta: CharacterMeta {
+                uuid: uuid.to_string(),
+                version: "1.0".to_string(),
+                created_at: String::new(),
+                updated_at: String::new(),
+                token_count: None,
+                favorite: false,
+                sort_order: None,
+            },
+            card: sample_card(),
+            background_path: String::new(),
+            thumbnail_path: String::new(),
+            import_warnings: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_support::sample_character;
+    use super::*;
+
+    const V3_CARD: &str = r#"{
+        "spec": "chara_card_v3",
+        "spec_version": "3.0",
+        "data": {
+            "name": "Alice",
+            "description": "A traveler",
+            "personality": "",
+            "scenario": "",
+            "first_mes": "Hello",
+            "mes_example": "",
+            "creator_notes": "",
+            "system_prompt": "",
+            "post_history_instructions": "",
+            "alternate_greetings": [],
+            "tags": ["v3"],
+            "creator": "tester",
+            "character_version": "1.0",
+            "extensions": {},
+            "nickname": "Ally",
+            "group_only_greetings": ["Hi everyone"],
+            "creation_date": 1700000000,
+            "assets": [{"type": "icon", "uri": "ccdefault:", "name": "main", "ext": "png"}]
+        }
+    }"#;
+
+    #[test]
+    fn compact_image_shrinks_oversized_background() {
+        let image = image::RgbaImage::from_fn(1024, 768, |x, y| {
+            image::Rgba([(x % 256) as u8, (y % 256) as u8, ((x + y) % 256) as u8, 255])
+        });
+        let mut original = Vec::new();
+        DynamicImage::ImageRgba8(image)
+            .write_with_encoder(PngEncoder::new_with_quality(
+                &mut original,
+                CompressionType::Fast,
+                PngFilterType::NoFilter,
+            ))
+            .unwrap();
+
+        let compacted = compact_image_bytes(&original, 256, 90).unwrap();
+        let decoded = image::load_from_memory(&compacted).unwrap();
+
+        assert!(compacted.len() < original.len());
+        assert_eq!((decoded.width(), decoded.height()), (256, 192));
+    }
+
+    #[test]
+    fn compact_image_keeps_jpeg_format_at_requested_quality() {
+        let image = image::RgbImage::from_fn(512, 512, |x, y| {
+            image::Rgb([(x % 256) as u8, (y % 256) as u8, ((x * y) % 256) as u8])
+        });
+        let mut original = Vec::new();
+        DynamicImage::ImageRgb8(image)
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut original, 100))
+            .unwrap();
+
+        let high = compact_image_bytes(&original, 512, 90).unwrap();
+        let low = compact_image_bytes(&original, 512, 30).unwrap();
+
+        assert_eq!(image::guess_format(&low).unwrap(), ImageFormat::Jpeg);
+        assert!(low.len() < high.len());
+        assert!(high.len() < original.len());
+    }
+
+    #[test]
+    fn default_template_carries_embedded_card() {
+        let card = parse_tavern_card(V3_CARD).unwrap();
+        let card_json = serialize_tavern_card(&card).unwrap();
+
+        let png_bytes = PngMetadataUtils::write_character_data_to_bytes(
+            &default_card_template().unwrap(),
+            &serialize_tavern_card_as_v2(&card).unwrap(),
+            &card_json,
+        )
+        .unwrap();
+
+        let restored = parse_tavern_card(
+            &PngMetadataUtils::read_character_data_from_bytes(&png_bytes).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(restored.spec, SPEC_V3);
+        assert_eq!(restored.data.name, "Alice");
+        assert_eq!(
+            file_extension(Path::new("Alice.PNG")).as_deref(),
+            Some("png")
+        );
+    }
+
+    #[test]
+    fn v3_only_fields_survive_save_load_cycle() {
+        let mut character = sample_character("v3-test");
+        character.card = parse_tavern_card(V3_CARD).unwrap();
+        assert_eq!(character.card.data.name, "Alice");
+
+        let saved = serde_json::to_string(&character).unwrap();
+        let loaded: CharacterData = serde_json::from_str(&saved).unwrap();
+        let exported: serde_json::Value =
+            serde_json::from_str(&serialize_tavern_card(&loaded.card).unwrap()).unwrap();
+
+        assert_eq!(exported["spec"], "chara_card_v3");
+        assert_eq!(exported["spec_version"], "3.0");
+        assert_eq!(exported["data"]["nickname"], "Ally");
+        assert_eq!(exported["data"]["group_only_greetings"][0], "Hi everyone");
+        assert_eq!(exported["data"]["creation_date"], 1700000000);
+        assert_eq!(exported["data"]["assets"][0]["type"], "icon");
+        assert!(exported["data"].get("v3_fields").is_none());
+    }
+
+    #[test]
+    fn v2_cards_export_without_v3_fields() {
+        let mut value: serde_json::Value = serde_json::from_str(V3_CARD).unwrap();
+        value["spec"] = "chara_card_v2".into();
+        value["spec_version"] = "2.0".into();
+
+        let card = parse_tavern_card(&value.to_string()).unwrap();
+        assert!(card.data.v3_fields.is_none());
+
+        let exported: serde_json::Value =
+            serde_json::from_str(&serialize_tavern_card(&card).unwrap()).unwrap();
+        assert_eq!(exported["spec"], "chara_card_v2");
+        assert!(exported["data"].get("nickname").is_none());
+    }
+
+    #[test]
+    fn favorites_sort_ahead_of_recently_updated_characters() {
+        let character = |uuid: &str, updated_at: &str, favorite: bool| {
+            let mut character = sample_character(uuid);
+            character.meta.updated_at = updated_at.to_string();
+            character.meta.favorite = favorite;
+            character
+        };
+        let mut characters = vec![
+            character("recent", "2025-03-01T00:00:00+00:00", false),
+            character("old-favorite", "2024-01-01T00:00:00+00:00", true),
+            character("older", "2025-01-01T00:00:00+00:00", false),
+        ];
+
+        sort_characters(&mut characters);
+
+        let order: Vec<&str> = characters.iter().map(|c| c.uuid.as_str()).collect();
+        assert_eq!(order, vec!["old-favorite", "recent", "older"]);
+    }
+
+    #[test]
+    fn tags_are_counted_and_filtered_case_insensitively() {
+        let tagged = |uuid: &str, tags: &[&str]| {
+            let mut character = sample_character(uuid);
+            character.card.data.tags = tags.iter().map(|tag| tag.to_string()).collect();
+            character
+        };
+        let characters = vec![
+            tagged("a", &["Fantasy", "elf"]),
+            tagged("b", &["fantasy", "FANTASY", "sci-fi"]),
+            tagged("c", &["Elf", "fantasy"]),
+        ];
+
+        assert_eq!(
+            tag_counts(&characters),
+            vec![
+                ("Fantasy".to_string(), 3),
+                ("elf".to_string(), 2),
+                ("sci-fi".to_string(), 1),
+            ]
+        );
+        let elves: Vec<&str> = characters
+            .iter()
+            .filter(|character| has_tag(character, "ELF"))
+            .map(|character| character.uuid.as_str())
+            .collect();
+        assert_eq!(elves, vec!["a", "c"]);
+    }
+
+    #[test]
+    fn duplicate_gets_new_uuid_and_independent_world_book() {
+        let mut source = sample_character("original");
+        source.meta.favorite = true;
+        source.card.data.character_book = Some(
+            serde_json::from_value(serde_json::json!({
+                "entries": [{
+                    "keys": ["forest"],
+                    "content": "An old forest",
+                    "enabled": true,
+                    "insertion_order": 0
+                }]
+            }))
+            .unwrap(),
+        );
+
+        let now = "2025-06-01T00:00:00+00:00";
+        let mut copy = duplicate_character_data(&source, "copy", now);
+
+        assert_eq!(copy.uuid, "copy");
+        assert_eq!(copy.meta.uuid, "copy");
+        assert_ne!(copy.uuid, source.uuid);
+        assert_eq!(copy.card.data.name, "Alice (Copy)");
+        assert_eq!(copy.meta.created_at, now);
+        assert_eq!(copy.meta.updated_at, now);
+        assert!(!copy.meta.favorite);
+
+        let copied_book = copy.card.data.character_book.as_mut().unwrap();
+        copied_book.entries[0].content = "A burned forest".to_string();
+
+        let source_book = source.card.data.character_book.as_ref().unwrap();
+        assert_eq!(source_book.entries[0].content, "An old forest");
+    }
+
+    #[test]
+    fn history_survives_png_round_trip_alongside_card() {
+        let card = parse_tavern_card(V3_CARD).unwrap();
+        let card_json = serialize_tavern_card(&card).unwrap();
+        let history = vec![
+            crate::chat_history::ChatMessage {
+                role: "user".to_string(),
+                content: "你好".to_string(),
+                name: None,
+                reasoning_content: None,
+                tool_calls: None,
+                tool_call_id: None,
+                images: None,
+                timestamp: Some(1_700_000_000),
+                swipes: Vec::new(),
+                active_swipe: 0,
+            },
+            crate::chat_history::ChatMessage {
+                role: "assistant".to_string(),
+                content: "Hello, traveler".to_string(),
+                name: None,
+                reasoning_content: None,
+                tool_calls: None,
+                tool_call_id: None,
+                images: None,
+                timestamp: Some(1_700_000_001),
+                swipes: Vec::new(),
+                active_swipe: 0,
+            },
+        ];
+
+        let card_png = PngMetadataUtils::write_character_data_to_bytes(
+            &default_card_template().unwrap(),
+            &serialize_tavern_card_as_v2(&card).unwrap(),
+            &card_json,
+        )
+        .unwrap();
+        assert!(PngMetadataUtils::read_history_from_bytes(&card_png)
+            .unwrap()
+            .is_none());
+
+        let exported =
+            PngMetadataUtils::write_history_to_bytes(&card_png, &history_to_jsonl(&history))
+                .unwrap();
+        let restored_history = parse_history_jsonl(
+            &PngMetadataUtils::read_history_from_bytes(&exported)
+                .unwrap()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(restored_history, history);
+
+        let restored_card = parse_tavern_card(
+            &PngMetadataUtils::read_character_data_from_bytes(&exported).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(restored_card.data.name, "Alice");
+
+        let stripped = PngMetadataUtils::strip_history_from_bytes(&exported).unwrap();
+        assert_eq!(stripped, card_png);
+    }
+
+    #[test]
+    fn reembedding_card_keeps_pixels_and_history() {
+        let source = default_card_template().unwrap();
+        let original = parse_tavern_card(V3_CARD).unwrap();
+        let exported = PngMetadataUtils::write_history_to_bytes(
+            &embed_card_into_png(&source, &original).unwrap(),
+            "{}\n",
+        )
+        .unwrap();
+
+        let mut edited = original.clone();
+        edited.data.name = "Alicia".to_string();
+        let updated = embed_card_into_png(&exported, &edited).unwrap();
+
+        let restored =
+            parse_tavern_card(&PngMetadataUtils::read_character_data_from_bytes(&updated).unwrap())
+                .unwrap();
+        assert_eq!(restored.data.name, "Alicia");
+        assert_eq!(
+            PngMetadataUtils::extract_image_bytes(&updated).unwrap(),
+            PngMetadataUtils::extract_image_bytes(&source).unwrap()
+        );
+        assert_eq!(
+            PngMetadataUtils::read_history_from_bytes(&updated)
+                .unwrap()
+                .as_deref(),
+            Some("{}\n")
+        );
+        assert!(embed_card_into_png(b"not a png", &edited).is_err());
+    }
+
+    #[test]
+    fn linked_characters_are_collected_without_cycles() {
+        let character = |uuid: &str, name: &str, links: &[&str]| -> CharacterData {
+            let mut data = sample_character(uuid);
+            data.card.data.name = name.to_string();
+            data.card.data.extensions =
+                serde_json::json!({ LINKED_CHARACTERS_EXTENSION_KEY: links });
+            data
+        };
+        let alice = character("a", "Alice", &["b"]);
+        let library = [
+            character("b", "Bob", &["c", "a"]),
+            character("c", "Carol", &["b"]),
+        ];
+
+        let linked = collect_linked_characters(&alice, |uuid| {
+            library.iter().find(|item| item.uuid == uuid).cloned()
+        });
+
+        let names: Vec<&str> = linked.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, vec!["Bob", "Carol"]);
+        assert_eq!(linked_character_ids(&alice.card), vec!["b"]);
+    }
+
+    #[test]
+    fn editing_a_field_refreshes_cached_token_count() {
+        let mut character = sample_character("tokens");
+        assert_eq!(character.meta.token_count, None);
+
+        let mut card = character.card.clone();
+        apply_card_update(&mut character, &card, "2025-01-01T00:00:00+00:00");
+        let initial_count = character.meta.token_count.unwrap();
+        assert_eq!(initial_count, card_token_count(&card));
+
+        card.data
+            .description
+            .push_str(" She keeps a journal of every town she has visited.");
+        apply_card_update(&mut character, &card, "2025-01-02T00:00:00+00:00");
+
+        assert!(character.meta.token_count.unwrap() > initial_count);
+        assert_eq!(character.meta.updated_at, "2025-01-02T00:00:00+00:00");
+        assert_eq!(character.card.data.description, card.data.description);
+    }
+
+    fn scratch_dir(label: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}-{}", label, FileUtils::generate_uuid()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn archive_round_trip_restores_every_file() {
+        let source = scratch_dir("archive-source");
+        fs::write(source.join(CHARACTER_FILE_NAME), b"{}").unwrap();
+        fs::write(source.join(CARD_FILE_NAME), [0x89, b'P', b'N', b'G']).unwrap();
+        fs::write(source.join("chat_history.jsonl"), "{\"role\":\"user\"}\n").unwrap();
+        fs::write(source.join("usage_stats.json"), "{}").unwrap();
+        fs::create_dir_all(source.join("branches")).unwrap();
+        fs::write(source.join("branches").join("a.jsonl"), "").unwrap();
+
+        let workspace = scratch_dir("archive-output");
+        let archive = workspace.join("backup.zip");
+        assert_eq!(write_directory_archive(&source, &archive).unwrap(), 5);
+
+        let restored = workspace.join("restored");
+        assert_eq!(extract_archive(&archive, &restored).unwrap(), 5);
+        for name in [
+            CHARACTER_FILE_NAME,
+            CARD_FILE_NAME,
+            "chat_history.jsonl",
+            "usage_stats.json",
+            "branches/a.jsonl",
+        ] {
+            assert_eq!(
+                fs::read(restored.join(name)).unwrap(),
+                fs::read(source.join(name)).unwrap(),
+                "{name} should be restored"
+            );
+        }
+
+        fs::remove_dir_all(source).unwrap();
+        fs::remove_dir_all(workspace).unwrap();
+    }
+
+    #[test]
+    fn extracting_stops_at_the_size_limit() {
+        let source = scratch_dir("archive-limit-source");
+        fs::write(source.join(CHARACTER_FILE_NAME), vec![b'a'; 64]).unwrap();
+        fs::write(source.join("chat_history.jsonl"), vec![b'b'; 64]).unwrap();
+        let workspace = scratch_dir("archive-limit-output");
+        let archive = workspace.join("backup.zip");
+        write_directory_archive(&source, &archive).unwrap();
+
+        assert!(extract_archive_with_limit(&archive, &workspace.join("small"), 100).is_err());
+        assert_eq!(
+            extract_archive_with_limit(&archive, &workspace.join("large"), 128).unwrap(),
+            2
+        );
+
+        fs::remove_dir_all(source).unwrap();
+        fs::remove_dir_all(workspace).unwrap();
+    }
+
+    #[test]
+    fn restoring_an_archive_remaps_a_taken_uuid() {
+        let uuid = FileUtils::generate_uuid();
+        let source = scratch_dir("archive-character");
+        let character = sample_character(&uuid);
+        FileUtils::write_json_file(&source.join(CHARACTER_FILE_NAME), &character).unwrap();
+        let workspace = scratch_dir("archive-library");
+        let archive = workspace.join("backup.zip");
+        write_directory_archive(&source, &archive).unwrap();
+
+        let characters_dir = workspace.join("character-cards");
+        fs::create_dir_all(characters_dir.join(&uuid)).unwrap();
+        let restored_uuid = CharacterStorage::restore_archive(
+            &characters_dir,
+            &workspace.join("staging"),
+            &archive,
+        )
+        .unwrap();
+
+        assert_ne!(restored_uuid, uuid);
+        let restored: CharacterData = FileUtils::read_json_file(
+            &characters_dir
+                .join(&restored_uuid)
+                .join(CHARACTER_FILE_NAME),
+        )
+        .unwrap();
+        assert_eq!(restored.uuid, restored_uuid);
+        assert_eq!(restored.meta.uuid, restored_uuid);
+        assert!(!workspace.join("staging").exists());
+
+        fs::remove_dir_all(source).unwrap();
+        fs::remove_dir_all(workspace).unwrap();
+    }
+
+    #[test]
+    fn library_round_trip_reproduces_character_count() {
+        let source = scratch_dir("library-source");
+        for _ in 0..3 {
+            let uuid = FileUtils::generate_uuid();
+            let character_dir = source.join(CHARACTERS_DIR_NAME).join(&uuid);
+            fs::create_dir_all(&character_dir).unwrap();
+            fs::write(character_dir.join(CHARACTER_FILE_NAME), b"{}").unwrap();
+            fs::write(character_dir.join("chat_history.jsonl"), b"").unwrap();
+        }
+        fs::write(source.join(AI_CONFIG_FILE_NAME), "default_role: test\n").unwrap();
+        let api_configs: Vec<ApiConfig> = serde_json::from_value(serde_json::json!([{
+            "profile": "Primary",
+            "base_url": "https://api.openai.com/v1",
+            "api_key": "",
+            "model": "gpt-4.1",
+            "default": true,
+            "enabled": true
+        }]))
+        .unwrap();
+        let archive = source.join("library.zip");
+        write_library_archive(&source, &api_configs, &archive).unwrap();
+
+        let fresh = scratch_dir("library-fresh");
+        let (summary, restored_configs) =
+            restore_library_archive(&fresh, &archive, LibraryImportMode::Merge).unwrap();
+        let count_characters = |dir: &Path| {
+            fs::read_dir(dir.join(CHARACTERS_DIR_NAME))
+                .unwrap()
+                .filter(|entry| entry.as_ref().unwrap().path().is_dir())
+                .count()
+        };
+
+        assert_eq!(summary.imported_characters, 3);
+        assert_eq!(count_characters(&fresh), count_characters(&source));
+        assert!(summary.ai_config_restored);
+        assert!(fresh.join(AI_CONFIG_FILE_NAME).is_file());
+        assert_eq!(restored_configs.len(), 1);
+        assert_eq!(restored_configs[0].profile, "Primary");
+
+        let (again, _) =
+            restore_library_archive(&fresh, &archive, LibraryImportMode::Merge).unwrap();
+        assert_eq!(
+            (again.imported_characters, again.skipped_characters),
+            (0, 3)
+        );
+        assert!(!again.ai_config_restored);
+        let (replaced, _) =
+            restore_library_archive(&fresh, &archive, LibraryImportMode::Replace).unwrap();
+        assert_eq!(replaced.imported_characters, 3);
+        assert_eq!(count_characters(&fresh), 3);
+
+        fs::remove_dir_all(source).unwrap();
+        fs::remove_dir_all(fresh).unwrap();
+    }
+}
@@ -1,10 +1,11 @@
+use crate::character_storage::CharacterStorage;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io::{BufRead, BufReader, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tauri::{AppHandle, Manager};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ChatMessage {
     pub role: String,
     pub content: String,
@@ -16,11 +17,72 @@ pub struct ChatMessage {
     pub tool_calls: Option<Vec<ToolCall>>,
     #[serde(default)]
     pub tool_call_id: Option<String>,
+    /// 用户消息附带的图片（data URL 或 http(s) URL）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub images: Option<Vec<String>>,
     #[serde(default)]
     pub timestamp: Option<i64>,
+    /// 备选回复（swipe）；非空时 content 始终等于 swipes[active_swipe]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub swipes: Vec<String>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub active_swipe: usize,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+fn is_zero(value: &usize) -> bool {
+    *value == 0
+}
+
+impl ChatMessage {
+    /// 修改消息内容，存在备选回复时同步更新当前备选
+    pub fn set_content(&mut self, content: String) {
+        if let Some(swipe) = self.swipes.get_mut(self.active_swipe) {
+            *swipe = content.clone();
+        }
+        self.content = content;
+    }
+
+    /// 追加一条备选回复并设为当前回复，首次追加时原内容作为第一条备选
+    pub fn push_swipe(&mut self, content: String) {
+        if self.swipes.is_empty() {
+            self.swipes.push(self.content.clone());
+        }
+        self.swipes.push(content.clone());
+        self.active_swipe = self.swipes.len() - 1;
+        self.content = content;
+    }
+
+    /// 切换当前备选回复，content 随之更新
+    pub fn set_active_swipe(&mut self, index: usize) -> Result<(), String> {
+        let swipe_count = self.swipes.len().max(1);
+        if index >= swipe_count {
+            return Err(format!(
+                "备选回复索引 {} 超出范围（共 {} 条备选回复）",
+                index, swipe_count
+            ));
+        }
+
+        if let Some(swipe) = self.swipes.get(index) {
+            self.content = swipe.clone();
+            self.active_swipe = index;
+        }
+        Ok(())
+    }
+}
+
+/// 把新生成的回复作为备选合并到原回复中，推理内容与时间戳使用新回复
+///
+/// 备选回复只保存文本，工具调用不随某一条备选保留，合并后清空 tool_calls
+pub fn merge_swipe(previous: ChatMessage, new_reply: ChatMessage) -> ChatMessage {
+    let mut merged = previous;
+    merged.push_swipe(new_reply.content);
+    merged.reasoning_content = new_reply.reasoning_content;
+    merged.tool_calls = None;
+    merged.timestamp = new_reply.timestamp;
+    merged
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ToolCall {
     pub id: String,
     pub r#type: String,
@@ -29,7 +91,7 @@ pub struct ToolCall {
     pub thought_signatures: Option<Vec<String>>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ToolFunction {
     pub name: String,
     pub arguments: String,
@@ -46,9 +108,699 @@ fn parse_history_line(line: &str) -> Result<Option<ChatMessage>, String> {
         .map_err(|e| format!("解析聊天记录行失败: {} - {}", trimmed, e))
 }
 
+/// 分页加载的聊天历史，total 为历史中的消息总数
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatHistoryPage {
+    pub messages: Vec<ChatMessage>,
+    pub total: usize,
+}
+
+/// 逐行解析 JSONL 历史，跳过空行与无法解析的行（记录错误）
+fn parse_history_lines<S: AsRef<str>>(
+    lines: impl Iterator<Item = S>,
+) -> impl Iterator<Item = ChatMessage> {
+    lines.filter_map(|line| match parse_history_line(line.as_ref()) {
+        Ok(message) => message,
+        Err(error) => {
+            eprintln!("{}", error);
+            None
+        }
+    })
+}
+
+/// 从 JSONL 行中取出 [offset, offset + limit) 范围内的消息并统计总数；
+/// 与 load_history 一样跳过空行和无法解析的行，因此两者的消息索引一致
+pub fn history_page_from_lines<S: AsRef<str>>(
+    lines: impl Iterator<Item = S>,
+    offset: usize,
+    limit: usize,
+) -> (Vec<ChatMessage>, usize) {
+    let mut messages = Vec::new();
+    let mut total = 0;
+
+    for message in parse_history_lines(lines) {
+        if total >= offset && total - offset < limit {
+            messages.push(message);
+        }
+        total += 1;
+    }
+
+    (messages, total)
+}
+
+/// 序列化为 JSONL（每行一条消息）
+pub fn history_to_jsonl(history: &[ChatMessage]) -> String {
+    history
+        .iter()
+        .map(|msg| serde_json::to_string(msg).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n")
+        + "\n"
+}
+
+/// 解析 JSONL 聊天记录，任何一行无效都返回错误
+pub fn parse_history_jsonl(jsonl: &str) -> Result<Vec<ChatMessage>, String> {
+    jsonl
+        .lines()
+        .filter_map(|line| parse_history_line(line).transpose())
+        .collect()
+}
+
+/// 内存历史与磁盘历史的差异摘要
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatHistoryDiff {
+    pub in_memory_count: usize,
+    pub on_disk_count: usize,
+    /// 第一条不一致消息的索引；两者完全一致时为 None
+    pub first_divergent_index: Option<usize>,
+}
+
+pub fn diff_histories(in_memory: &[ChatMessage], on_disk: &[ChatMessage]) -> ChatHistoryDiff {
+    let first_divergent_index = in_memory
+        .iter()
+        .zip(on_disk.iter())
+        .position(|(memory_message, disk_message)| memory_message != disk_message)
+        .or_else(|| (in_memory.len() != on_disk.len()).then(|| in_memory.len().min(on_disk.len())));
+
+    ChatHistoryDiff {
+        in_memory_count: in_memory.len(),
+        on_disk_count: on_disk.len(),
+        first_divergent_index,
+    }
+}
+
+/// 完整性问题的严重程度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegritySeverity {
+    Error,
+    Warning,
+}
+
+/// 单个完整性问题，line 为 JSONL 中的行号（从 1 开始）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryIntegrityIssue {
+    pub line: usize,
+    pub severity: IntegritySeverity,
+    pub message: String,
+}
+
+/// 聊天历史完整性报告
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryIntegrityReport {
+    pub message_count: usize,
+    pub issues: Vec<HistoryIntegrityIssue>,
+    /// 与校验和文件的比对结果；没有校验和文件时为 None
+    pub checksum_matches: Option<bool>,
+}
+
+/// 检查 JSONL 聊天历史：每行可解析、工具调用成对、时间戳单调（时间戳倒退只作为警告）
+pub fn check_history_integrity(raw: &str) -> HistoryIntegrityReport {
+    let mut issues = Vec::new();
+    let mut message_count = 0;
+    let mut last_timestamp: Option<i64> = None;
+    // 尚未收到结果的工具调用：id -> 发起调用的行号
+    let mut pending_calls: Vec<(String, usize)> = Vec::new();
+
+    for (index, line) in raw.lines().enumerate() {
+        let line_number = index + 1;
+        let message = match parse_history_line(line) {
+            Ok(Some(message)) => message,
+            Ok(None) => continue,
+            Err(error) => {
+                issues.push(HistoryIntegrityIssue {
+                    line: line_number,
+                    severity: IntegritySeverity::Error,
+                    message: error,
+                });
+                continue;
+            }
+        };
+        message_count += 1;
+
+        if let Some(timestamp) = message.timestamp {
+            if let Some(previous) = last_timestamp.filter(|previous| timestamp < *previous) {
+                issues.push(HistoryIntegrityIssue {
+                    line: line_number,
+                    severity: IntegritySeverity::Warning,
+                    message: format!("时间戳 {} 早于上一条消息的 {}", timestamp, previous),
+                });
+            }
+            last_timestamp = Some(last_timestamp.map_or(timestamp, |last| last.max(timestamp)));
+        }
+
+        if message.role == "tool" {
+            let call_id = message.tool_call_id.clone().unwrap_or_default();
+            match pending_calls.iter().position(|(id, _)| *id == call_id) {
+                Some(position) => {
+                    pending_calls.remove(position);
+                }
+                None => issues.push(HistoryIntegrityIssue {
+                    line: line_number,
+                    severity: IntegritySeverity::Error,
+                    message: format!("工具结果 {} 没有对应的工具调用", call_id),
+                }),
+            }
+            continue;
+        }
+
+        for (id, call_line) in pending_calls.drain(..) {
+            issues.push(HistoryIntegrityIssue {
+                line: call_line,
+                severity: IntegritySeverity::Error,
+                message: format!("工具调用 {} 缺少工具结果", id),
+            });
+        }
+        if let Some(tool_calls) = &message.tool_calls {
+            pending_calls.extend(tool_calls.iter().map(|call| (call.id.clone(), line_number)));
+        }
+    }
+
+    for (id, call_line) in pending_calls {
+        issues.push(HistoryIntegrityIssue {
+            line: call_line,
+            severity: IntegritySeverity::Error,
+            message: format!("工具调用 {} 缺少工具结果", id),
+        });
+    }
+    issues.sort_by_key(|issue| issue.line);
+
+    HistoryIntegrityReport {
+        message_count,
+        issues,
+        checksum_matches: None,
+    }
+}
+
+/// 历史文件内容的校验和（CRC32，十六进制）
+pub fn history_checksum(bytes: &[u8]) -> String {
+    format!("{:08x}", crc32fast::hash(bytes))
+}
+
+/// 在已有校验和的基础上追加 bytes，得到追加后整个文件的校验和；
+/// previous 无法解析时返回 None
+pub fn append_history_checksum(previous: &str, appended: &[u8]) -> Option<String> {
+    let previous = u32::from_str_radix(previous.trim(), 16).ok()?;
+    let mut hasher = crc32fast::Hasher::new_with_initial(previous);
+    hasher.update(appended);
+    Some(format!("{:08x}", hasher.finalize()))
+}
+
+/// 合并连续的同角色消息（tool 消息保持独立），仅用于展示
+pub fn merge_consecutive_messages(messages: &[ChatMessage]) -> Vec<ChatMessage> {
+    let mut merged: Vec<ChatMessage> = Vec::new();
+
+    for message in messages {
+        let Some(last) = merged.last_mut() else {
+            merged.push(message.clone());
+            continue;
+        };
+
+        if message.role == "tool" || last.role == "tool" || last.role != message.role {
+            merged.push(message.clone());
+            continue;
+        }
+
+        if !message.content.is_empty() {
+            if !last.content.is_empty() {
+                last.content.push_str("\n\n");
+            }
+            last.content.push_str(&message.content);
+        }
+        if let Some(reasoning) = &message.reasoning_content {
+            match &mut last.reasoning_content {
+                Some(existing) => {
+                    existing.push_str("\n\n");
+                    existing.push_str(reasoning);
+                }
+                None => last.reasoning_content = Some(reasoning.clone()),
+            }
+        }
+        if let Some(tool_calls) = &message.tool_calls {
+            last.tool_calls
+                .get_or_insert_with(Vec::new)
+                .extend(tool_calls.iter().cloned());
+        }
+        last.timestamp = message.timestamp.or(last.timestamp);
+    }
+
+    merged
+}
+
+/// 摘要记忆消息的内容前缀
+pub const MEMORY_NOTE_PREFIX: &str = "[对话记忆摘要]";
+
+/// 保留最近 keep_recent 条消息时需要被摘要的前缀长度；
+/// 不拆开 assistant 工具调用与其 tool 结果，没有可摘要的消息时返回 None
+pub fn summary_split_index(history: &[ChatMessage], keep_recent: usize) -> Option<usize> {
+    let mut split = history.len().checked_sub(keep_recent)?;
+    // keep_recent 为 0 时没有保留区可以定位边界，视为无需摘要
+    if split >= history.len() {
+        return None;
+    }
+    while split > 0 && history[split].role == "tool" {
+        split -= 1;
+    }
+    (split > 0).then_some(split)
+}
+
+/// 把待摘要的消息渲染为 "role: content" 形式的文本，跳过没有正文的消息
+pub fn history_transcript(messages: &[ChatMessage]) -> String {
+    messages
+        .iter()
+        .filter(|message| !message.content.trim().is_empty())
+        .map(|message| format!("{}: {}", message.role, message.content.trim()))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// 用一条 system 记忆消息替换 history[..split]，其余消息原样保留
+pub fn splice_summary(
+    history: &[ChatMessage],
+    split: usize,
+    summary: &str,
+    timestamp: i64,
+) -> Vec<ChatMessage> {
+    let memory_note = ChatMessage {
+        role: "system".to_string(),
+        content: format!("{}\n{}", MEMORY_NOTE_PREFIX, summary.trim()),
+        name: None,
+        reasoning_content: None,
+        tool_calls: None,
+        tool_call_id: None,
+        images: None,
+        timestamp: Some(timestamp),
+        swipes: Vec::new(),
+        active_swipe: 0,
+    };
+
+    std::iter::once(memory_note)
+        .chain(history[split.min(history.len())..].iter().cloned())
+        .collect()
+}
+
+/// 微调数据导出方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FinetuneExportMode {
+    /// 整段对话写成一行
+    #[default]
+    Conversation,
+    /// 每组 user/assistant 对话写成一行
+    PerTurn,
+}
+
+/// 将聊天历史转换为 OpenAI 微调格式（每个元素对应 JSONL 中的一行）。
+/// include_tools 为 false 时只保留有正文的 user / assistant 消息，工具调用过程不会导出；
+/// 为 true 时按 OpenAI 的工具消息格式保留 assistant.tool_calls 与 tool 结果
+pub fn build_finetune_lines(
+    system_prompt: &str,
+    messages: &[ChatMessage],
+    mode: FinetuneExportMode,
+    include_tools: bool,
+) -> Vec<serde_json::Value> {
+    let dialogue: Vec<ChatMessage> = messages
+        .iter()
+        .filter(|message| match message.role.as_str() {
+            "user" => !message.content.trim().is_empty(),
+            "assistant" => {
+                !message.content.trim().is_empty()
+                    || (include_tools
+                        && message
+                            .tool_calls
+                            .as_ref()
+                            .is_some_and(|calls| !calls.is_empty()))
+            }
+            "tool" => include_tools,
+            _ => false,
+        })
+        .cloned()
+        .collect();
+    // 保留工具调用时不合并消息，以免打乱 tool_calls 与工具结果的对应关系
+    let dialogue = if include_tools {
+        dialogue
+    } else {
+        merge_consecutive_messages(&dialogue)
+    };
+
+    let to_message = |message: &ChatMessage| {
+        if include_tools {
+            to_openai_message(message)
+        } else {
+            serde_json::json!({ "role": message.role, "content": message.content })
+        }
+    };
+    let system_message = (!system_prompt.trim().is_empty())
+        .then(|| serde_json::json!({ "role": "system", "content": system_prompt }));
+    let to_line = |group: &[ChatMessage]| {
+        let line_messages: Vec<serde_json::Value> = system_message
+            .iter()
+            .cloned()
+            .chain(group.iter().map(to_message))
+            .collect();
+        serde_json::json!({ "messages": line_messages })
+    };
+    let has_reply = |group: &[ChatMessage]| group.iter().any(|message| message.role == "assistant");
+
+    match mode {
+        FinetuneExportMode::Conversation => {
+            if has_reply(&dialogue) {
+                vec![to_line(&dialogue)]
+            } else {
+                Vec::new()
+            }
+        }
+        // 每条 user 消息与其后的回复（及工具消息）组成一行，没有回复的轮次会被丢弃
+        FinetuneExportMode::PerTurn => {
+            let mut groups: Vec<&[ChatMessage]> = Vec::new();
+            let mut start = None;
+            for (index, message) in dialogue.iter().enumerate() {
+                if message.role == "user" {
+                    if let Some(start) = start {
+                        groups.push(&dialogue[start..index]);
+                    }
+                    start = Some(index);
+                }
+            }
+            if let Some(start) = start {
+                groups.push(&dialogue[start..]);
+            }
+
+            groups
+                .into_iter()
+                .filter(|group| has_reply(group))
+                .map(to_line)
+                .collect()
+        }
+    }
+}
+
+/// 角色聊天历史文件路径：每个角色目录下独立的 chat_history.jsonl
+pub fn history_file_path(app_dir: &Path, character_id: &str) -> PathBuf {
+    app_dir
+        .join("character-cards")
+        .join(character_id)
+        .join("chat_history.jsonl")
+}
+
+/// 分支对话使用的历史：复制到（含）index 处的消息
+pub fn branch_history(history: &[ChatMessage], index: usize) -> Result<Vec<ChatMessage>, String> {
+    if index >= history.len() {
+        return Err(format!(
+            "消息索引 {} 超出范围（共 {} 条消息）",
+            index,
+            history.len()
+        ));
+    }
+
+    Ok(history[..=index].to_vec())
+}
+
+/// 转换为 OpenAI 消息格式，保留工具调用（assistant.tool_calls）与工具结果（tool_call_id）
+fn to_openai_message(message: &ChatMessage) -> serde_json::Value {
+    let mut value = serde_json::json!({ "role": message.role, "content": message.content });
+    if let Some(calls) = message
+        .tool_calls
+        .as_ref()
+        .filter(|calls| !calls.is_empty())
+    {
+        if message.content.trim().is_empty() {
+            value["content"] = serde_json::Value::Null;
+        }
+        value["tool_calls"] = calls
+            .iter()
+            .map(|call| {
+                serde_json::json!({
+                    "id": call.id,
+                    "type": "function",
+                    "function": { "name": call.function.name, "arguments": call.function.arguments },
+                })
+            })
+            .collect();
+    }
+    if let Some(tool_call_id) = &message.tool_call_id {
+        value["tool_call_id"] = serde_json::json!(tool_call_id);
+    }
+    value
+}
+
+/// 超过该值的时间戳视为毫秒（秒级时间戳要到公元 5138 年才会达到）
+const MILLISECOND_TIMESTAMP_THRESHOLD: i64 = 100_000_000_000;
+
+/// 把 epoch 秒（或毫秒）格式化为 UTC 时间文本，超出范围时原样输出数字
+fn format_timestamp(timestamp: i64) -> String {
+    let time = if timestamp.abs() > MILLISECOND_TIMESTAMP_THRESHOLD {
+        chrono::DateTime::from_timestamp_millis(timestamp)
+    } else {
+        chrono::DateTime::from_timestamp(timestamp, 0)
+    };
+    time.map(|time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+/// 将聊天历史渲染为 Markdown：每条消息一个二级标题，assistant 使用角色名，
+/// 工具调用以带参数的代码块呈现；没有正文和工具调用的消息（如空 system 消息）会被跳过
+pub fn history_to_markdown(character_name: &str, messages: &[ChatMessage]) -> String {
+    let mut sections = vec![format!("# 与 {} 的对话", character_name)];
+
+    for message in messages {
+        let has_tool_calls = message
+            .tool_calls
+            .as_ref()
+            .is_some_and(|calls| !calls.is_empty());
+        if message.content.trim().is_empty() && !has_tool_calls {
+            continue;
+        }
+
+        let label = match message.role.as_str() {
+            "user" => "用户".to_string(),
+            "assistant" => character_name.to_string(),
+            "system" => "系统".to_string(),
+            "tool" => "工具结果".to_string(),
+            other => other.to_string(),
+        };
+        let mut section = format!("## {}", label);
+        if let Some(timestamp) = message.timestamp {
+            section.push_str(&format!("\n\n*{}*", format_timestamp(timestamp)));
+        }
+        if !message.content.trim().is_empty() {
+            section.push_str(&format!("\n\n{}", message.content.trim()));
+        }
+
+        for call in message.tool_calls.iter().flatten() {
+            let arguments = serde_json::from_str::<serde_json::Value>(&call.function.arguments)
+                .ok()
+                .and_then(|value| serde_json::to_string_pretty(&value).ok())
+                .unwrap_or_else(|| call.function.arguments.clone());
+            section.push_str(&format!(
+                "\n\n工具调用 `{}`：\n\n```json\n{}\n```",
+                call.function.name, arguments
+            ));
+        }
+
+        sections.push(section);
+    }
+
+    sections.join("\n\n") + "\n"
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{parse_history_line, ChatMessage, ToolCall, ToolFunction};
+    use super::{
+        append_history_checksum, branch_history, build_finetune_lines, check_history_integrity,
+        diff_histories, history_checksum, history_file_path, history_page_from_lines,
+        history_to_jsonl, history_to_markdown, history_transcript, merge_consecutive_messages,
+        merge_swipe, parse_history_jsonl, parse_history_line, parse_history_lines, splice_summary,
+        summary_split_index, ChatMessage, FinetuneExportMode, IntegritySeverity, ToolCall,
+        ToolFunction, MEMORY_NOTE_PREFIX,
+    };
+    use std::fs;
+
+    fn text_message(role: &str, content: &str, timestamp: i64) -> ChatMessage {
+        ChatMessage {
+            role: role.to_string(),
+            content: content.to_string(),
+            name: None,
+            reasoning_content: None,
+            tool_calls: None,
+            tool_call_id: None,
+            images: None,
+            timestamp: Some(timestamp),
+            swipes: Vec::new(),
+            active_swipe: 0,
+        }
+    }
+
+    #[test]
+    fn finetune_lines_include_system_prompt_and_valid_roles() {
+        let mut tool_message = text_message("tool", "tool output", 4);
+        tool_message.tool_call_id = Some("call_1".to_string());
+        let history = vec![
+            text_message("user", "hi", 1),
+            text_message("assistant", "hello", 2),
+            text_message("user", "who are you?", 3),
+            tool_message,
+            text_message("assistant", "I am Alice", 5),
+        ];
+
+        let conversation = build_finetune_lines(
+            "You are Alice.",
+            &history,
+            FinetuneExportMode::Conversation,
+            false,
+        );
+        let per_turn = build_finetune_lines(
+            "You are Alice.",
+            &history,
+            FinetuneExportMode::PerTurn,
+            false,
+        );
+
+        assert_eq!(conversation.len(), 1);
+        assert_eq!(per_turn.len(), 2);
+        for line in conversation.iter().chain(per_turn.iter()) {
+            let messages = line["messages"].as_array().unwrap();
+            assert_eq!(messages[0]["role"], "system");
+            assert_eq!(messages[0]["content"], "You are Alice.");
+            assert!(messages.iter().all(|message| matches!(
+                message["role"].as_str(),
+                Some("system" | "user" | "assistant")
+            )));
+        }
+        assert_eq!(conversation[0]["messages"].as_array().unwrap().len(), 5);
+        assert_eq!(per_turn[1]["messages"][2]["content"], "I am Alice");
+    }
+
+    #[test]
+    fn branch_copies_history_up_to_index_into_its_own_file() {
+        let source = vec![
+            text_message("user", "hi", 1),
+            text_message("assistant", "hello", 2),
+            text_message("user", "go north", 3),
+            text_message("assistant", "You walk north.", 4),
+        ];
+
+        let app_dir = std::env::temp_dir().join(format!(
+            "branch-history-{}-{}",
+            std::process::id(),
+            chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        ));
+        let source_path = history_file_path(&app_dir, "source-uuid");
+        let branch_path = history_file_path(&app_dir, "branch-uuid");
+        for path in [&source_path, &branch_path] {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+        }
+        fs::write(&source_path, history_to_jsonl(&source)).unwrap();
+        let source_before = fs::read(&source_path).unwrap();
+
+        let mut branch = branch_history(&source, 1).unwrap();
+        fs::write(&branch_path, history_to_jsonl(&branch)).unwrap();
+        branch.push(text_message("user", "go south", 5));
+        fs::write(&branch_path, history_to_jsonl(&branch)).unwrap();
+
+        assert_eq!(fs::read(&source_path).unwrap(), source_before);
+        let branch_saved = parse_history_jsonl(&fs::read_to_string(&branch_path).unwrap()).unwrap();
+        assert_eq!(branch_saved.len(), 3);
+        assert_eq!(branch_saved[2].content, "go south");
+        assert!(branch_history(&source, 4).is_err());
+
+        fs::remove_dir_all(&app_dir).unwrap();
+    }
+
+    #[test]
+    fn training_lines_group_turns_and_keep_tool_messages() {
+        let mut tool_request = text_message("assistant", "", 3);
+        tool_request.tool_calls = Some(vec![ToolCall {
+            id: "call_1".to_string(),
+            r#type: "function".to_string(),
+            function: ToolFunction {
+                name: "read_character".to_string(),
+                arguments: "{}".to_string(),
+            },
+            thought_signatures: None,
+        }]);
+        let mut tool_result = text_message("tool", "{\"name\":\"Alice\"}", 4);
+        tool_result.tool_call_id = Some("call_1".to_string());
+        let history = vec![
+            text_message("system", "memory", 0),
+            text_message("user", "hi", 1),
+            text_message("assistant", "hello", 2),
+            text_message("user", "what is your name?", 2),
+            tool_request,
+            tool_result,
+            text_message("assistant", "I am Alice", 5),
+            text_message("user", "unanswered", 6),
+        ];
+
+        let jsonl = build_finetune_lines(
+            "You are Alice.",
+            &history,
+            FinetuneExportMode::PerTurn,
+            true,
+        )
+        .iter()
+        .map(|line| serde_json::to_string(line).unwrap())
+        .collect::<Vec<_>>()
+        .join("\n");
+        let parsed: Vec<serde_json::Value> = jsonl
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(parsed.len(), 2);
+        let counts: Vec<usize> = parsed
+            .iter()
+            .map(|line| line["messages"].as_array().unwrap().len())
+            .collect();
+        assert_eq!(counts, vec![3, 5]);
+        let second = &parsed[1]["messages"];
+        assert_eq!(second[0]["role"], "system");
+        assert!(second[2]["content"].is_null());
+        assert_eq!(
+            second[2]["tool_calls"][0]["function"]["name"],
+            "read_character"
+        );
+        assert_eq!(second[3]["tool_call_id"], "call_1");
+        assert_eq!(
+            build_finetune_lines("", &history, FinetuneExportMode::PerTurn, true)[0]["messages"][0]
+                ["role"],
+            "user"
+        );
+    }
+
+    #[test]
+    fn markdown_export_labels_user_and_character_turns() {
+        let mut reply = text_message("assistant", "Hello, traveler.", 1_700_000_060);
+        reply.tool_calls = Some(vec![ToolCall {
+            id: "call_1".to_string(),
+            r#type: "function".to_string(),
+            function: ToolFunction {
+                name: "edit_character".to_string(),
+                arguments: r#"{"field":"name"}"#.to_string(),
+            },
+            thought_signatures: None,
+        }]);
+        let history = vec![
+            text_message("system", "  ", 1_699_999_999),
+            text_message("user", "Hi there", 1_700_000_000),
+            reply,
+        ];
+
+        let markdown = history_to_markdown("Alice", &history);
+        let headings: Vec<&str> = markdown
+            .lines()
+            .filter(|line| line.starts_with('#'))
+            .collect();
+
+        assert_eq!(headings, vec!["# 与 Alice 的对话", "## 用户", "## Alice"]);
+        assert!(markdown.contains("*2023-11-14 22:13:20 UTC*"));
+        assert_eq!(
+            super::format_timestamp(1_700_000_000_000),
+            "2023-11-14 22:13:20 UTC"
+        );
+        assert!(markdown.contains("工具调用 `edit_character`"));
+        assert!(markdown.contains("```json\n{\n  \"field\": \"name\"\n}\n```"));
+    }
 
     #[test]
     fn legacy_history_line_defaults_new_reasoning_fields() {
@@ -98,7 +850,10 @@ mod tests {
                 thought_signatures: Some(vec!["sig_1".to_string(), "sig_2".to_string()]),
             }]),
             tool_call_id: None,
+            images: None,
             timestamp: Some(1710000001),
+            swipes: Vec::new(),
+            active_swipe: 0,
         };
 
         let serialized = serde_json::to_string(&message)
@@ -119,6 +874,56 @@ mod tests {
         );
     }
 
+    #[test]
+    fn integrity_check_warns_on_non_monotonic_timestamp() {
+        let raw = [
+            text_message("user", "first", 200),
+            text_message("assistant", "second", 100),
+            text_message("user", "third", 300),
+        ]
+        .iter()
+        .map(|message| serde_json::to_string(message).unwrap())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+        let report = check_history_integrity(&raw);
+
+        assert_eq!(report.message_count, 3);
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(report.issues[0].line, 2);
+        assert_eq!(report.issues[0].severity, IntegritySeverity::Warning);
+    }
+
+    #[test]
+    fn integrity_check_reports_truncated_lines_and_unpaired_tools() {
+        let mut call = text_message("assistant", "", 1);
+        call.tool_calls = Some(vec![ToolCall {
+            id: "call-1".to_string(),
+            r#type: "function".to_string(),
+            function: ToolFunction {
+                name: "edit_character".to_string(),
+                arguments: "{}".to_string(),
+            },
+            thought_signatures: None,
+        }]);
+        let raw = format!(
+            "{}\n{}\n{{\"role\":\"user\",\"cont",
+            serde_json::to_string(&call).unwrap(),
+            serde_json::to_string(&text_message("user", "next", 2)).unwrap()
+        );
+
+        let report = check_history_integrity(&raw);
+
+        assert_eq!(report.message_count, 2);
+        let errors: Vec<usize> = report
+            .issues
+            .iter()
+            .filter(|issue| issue.severity == IntegritySeverity::Error)
+            .map(|issue| issue.line)
+            .collect();
+        assert_eq!(errors, vec![1, 3]);
+    }
+
     #[test]
     fn blank_history_line_is_ignored() {
         assert!(parse_history_line("   ")
@@ -131,6 +936,215 @@ mod tests {
         let error = parse_history_line("{bad json").expect_err("bad line should return error");
         assert!(error.contains("解析聊天记录行失败"));
     }
+
+    #[test]
+    fn diff_reports_unsaved_edit_as_divergence() {
+        let on_disk = vec![
+            text_message("user", "hello", 1),
+            text_message("assistant", "hi", 2),
+        ];
+        let mut in_memory = on_disk.clone();
+        in_memory[1].content = "edited but not saved".to_string();
+
+        let diff = diff_histories(&in_memory, &on_disk);
+        assert_eq!(diff.in_memory_count, 2);
+        assert_eq!(diff.on_disk_count, 2);
+        assert_eq!(diff.first_divergent_index, Some(1));
+    }
+
+    #[test]
+    fn diff_reports_unsaved_tail_and_identical_histories() {
+        let on_disk = vec![text_message("user", "hello", 1)];
+        let mut in_memory = on_disk.clone();
+        assert_eq!(
+            diff_histories(&in_memory, &on_disk).first_divergent_index,
+            None
+        );
+
+        in_memory.push(text_message("assistant", "pending", 2));
+        let diff = diff_histories(&in_memory, &on_disk);
+        assert_eq!(diff.in_memory_count, 2);
+        assert_eq!(diff.on_disk_count, 1);
+        assert_eq!(diff.first_divergent_index, Some(1));
+    }
+
+    #[test]
+    fn summary_replaces_older_messages_and_keeps_recent_tail() {
+        let mut history = vec![
+            text_message("user", "起个名字", 1),
+            text_message("assistant", "叫 Alice 吧", 2),
+            text_message("user", "补充背景", 3),
+            text_message("assistant", "", 4),
+            text_message("tool", "ok", 5),
+            text_message("assistant", "已更新背景", 6),
+        ];
+        history[3].tool_calls = Some(vec![ToolCall {
+            id: "call-1".to_string(),
+            r#type: "function".to_string(),
+            function: ToolFunction {
+                name: "edit_character".to_string(),
+                arguments: "{}".to_string(),
+            },
+            thought_signatures: None,
+        }]);
+
+        // 保留 2 条会落在 tool 结果上，分割点回退到发起调用的 assistant 消息
+        let split = summary_split_index(&history, 2).unwrap();
+        assert_eq!(split, 3);
+        assert_eq!(
+            history_transcript(&history[..split]),
+            "user: 起个名字\n\nassistant: 叫 Alice 吧\n\nuser: 补充背景"
+        );
+
+        let spliced = splice_summary(&history, split, "用户把角色命名为 Alice。", 99);
+        assert_eq!(spliced.len(), 4);
+        assert_eq!(spliced[0].role, "system");
+        assert_eq!(
+            spliced[0].content,
+            format!("{}\n用户把角色命名为 Alice。", MEMORY_NOTE_PREFIX)
+        );
+        assert_eq!(spliced[0].timestamp, Some(99));
+        assert_eq!(&spliced[1..], &history[3..]);
+
+        assert_eq!(summary_split_index(&history, 6), None);
+        assert_eq!(summary_split_index(&history, 10), None);
+        assert_eq!(summary_split_index(&history, 0), None);
+    }
+
+    #[test]
+    fn merge_joins_consecutive_assistant_messages() {
+        let mut tool_message = text_message("tool", "{\"success\":true}", 3);
+        tool_message.tool_call_id = Some("call-1".to_string());
+        let history = vec![
+            text_message("user", "hi", 1),
+            text_message("assistant", "first part", 2),
+            text_message("assistant", "second part", 3),
+            tool_message,
+            text_message("assistant", "after tool", 4),
+        ];
+
+        let merged = merge_consecutive_messages(&history);
+
+        assert_eq!(merged.len(), 4);
+        assert_eq!(merged[1].content, "first part\n\nsecond part");
+        assert_eq!(merged[1].timestamp, Some(3));
+        assert_eq!(merged[2].role, "tool");
+        assert_eq!(merged[3].content, "after tool");
+        assert_eq!(history.len(), 5);
+    }
+
+    #[test]
+    fn appended_checksum_matches_checksum_of_whole_file() {
+        let first = b"{\"role\":\"user\"}\n";
+        let second = b"{\"role\":\"assistant\"}\n";
+        let whole = [first.as_slice(), second.as_slice()].concat();
+
+        assert_eq!(
+            append_history_checksum(&history_checksum(first), second).as_deref(),
+            Some(history_checksum(&whole).as_str())
+        );
+        assert_eq!(
+            append_history_checksum(&history_checksum(b""), first),
+            Some(history_checksum(first))
+        );
+        assert!(append_history_checksum("not hex", second).is_none());
+    }
+
+    #[test]
+    fn pushing_swipes_keeps_original_reply_and_activates_new_one() {
+        let mut message = text_message("assistant", "first", 1);
+
+        message.push_swipe("second".to_string());
+        message.push_swipe("third".to_string());
+
+        assert_eq!(message.swipes, vec!["first", "second", "third"]);
+        assert_eq!(message.active_swipe, 2);
+        assert_eq!(message.content, "third");
+
+        message.set_content("third, edited".to_string());
+        assert_eq!(message.swipes[2], "third, edited");
+
+        let mut new_reply = text_message("assistant", "new", 1);
+        new_reply.tool_calls = Some(vec![ToolCall {
+            id: "call_1".to_string(),
+            r#type: "function".to_string(),
+            function: ToolFunction {
+                name: "edit_character".to_string(),
+                arguments: "{}".to_string(),
+            },
+            thought_signatures: None,
+        }]);
+        let merged = merge_swipe(text_message("assistant", "old", 1), new_reply);
+        assert_eq!(merged.swipes, vec!["old", "new"]);
+        assert_eq!(merged.content, "new");
+        assert!(merged.tool_calls.is_none());
+    }
+
+    #[test]
+    fn active_swipe_index_is_bounds_checked() {
+        let mut message = text_message("assistant", "first", 1);
+        assert!(message.set_active_swipe(0).is_ok());
+        assert!(message.set_active_swipe(1).is_err());
+
+        message.push_swipe("second".to_string());
+        message.set_active_swipe(0).unwrap();
+        assert_eq!(message.content, "first");
+        assert_eq!(message.active_swipe, 0);
+
+        assert!(message.set_active_swipe(2).is_err());
+        assert_eq!(message.content, "first");
+        assert_eq!(message.active_swipe, 0);
+
+        let serialized = serde_json::to_value(text_message("assistant", "plain", 1)).unwrap();
+        assert!(serialized.get("swipes").is_none());
+        assert!(serialized.get("active_swipe").is_none());
+    }
+
+    #[test]
+    fn paging_through_history_reconstructs_it_in_order() {
+        let history: Vec<ChatMessage> = (0..7)
+            .map(|index| {
+                let role = if index % 2 == 0 { "user" } else { "assistant" };
+                text_message(role, &format!("message {}", index), index)
+            })
+            .collect();
+        let jsonl = history_to_jsonl(&history) + "\n";
+
+        let mut paged = Vec::new();
+        let mut offset = 0;
+        loop {
+            let (page, total) = history_page_from_lines(jsonl.lines(), offset, 3);
+            assert_eq!(total, history.len());
+            if page.is_empty() {
+                break;
+            }
+            offset += page.len();
+            paged.extend(page);
+        }
+
+        assert_eq!(paged, history);
+        assert_eq!(history_page_from_lines(jsonl.lines(), 6, 3).0.len(), 1);
+        assert!(history_page_from_lines(jsonl.lines(), 10, 3).0.is_empty());
+    }
+
+    #[test]
+    fn corrupt_line_is_skipped_by_both_full_and_paged_loads() {
+        let history: Vec<ChatMessage> = (0..4)
+            .map(|index| text_message("user", &format!("message {}", index), index))
+            .collect();
+        let mut lines: Vec<String> = history_to_jsonl(&history)
+            .lines()
+            .map(str::to_string)
+            .collect();
+        lines.insert(2, "{\"role\": \"user\", \"content\": ".to_string());
+
+        let loaded: Vec<ChatMessage> = parse_history_lines(lines.iter()).collect();
+        assert_eq!(loaded, history);
+
+        let (page, total) = history_page_from_lines(lines.iter(), 2, 2);
+        assert_eq!(total, loaded.len());
+        assert_eq!(page, loaded[2..4].to_vec());
+    }
 }
 
 pub struct ChatHistoryManager {
@@ -153,12 +1167,72 @@ impl ChatHistoryManager {
             .app_data_dir()
             .map_err(|e| format!("获取应用数据目录失败: {}", e))?;
 
-        let character_dir = app_dir.join("character-cards").join(&self.character_id);
+        let file_path = history_file_path(&app_dir, &self.character_id);
 
         // 确保目录存在
-        fs::create_dir_all(&character_dir).map_err(|e| format!("创建角色目录失败: {}", e))?;
+        if let Some(character_dir) = file_path.parent() {
+            fs::create_dir_all(character_dir).map_err(|e| format!("创建角色目录失败: {}", e))?;
+        }
+
+        Ok(file_path)
+    }
+
+    fn get_checksum_file_path(&self) -> Result<PathBuf, String> {
+        Ok(self
+            .get_history_file_path()?
+            .with_file_name("chat_history.checksum"))
+    }
+
+    /// 整个文件重写后写入校验和文件，失败只记录日志
+    fn write_checksum(&self, bytes: &[u8]) {
+        let result = self.get_checksum_file_path().and_then(|checksum_path| {
+            fs::write(checksum_path, history_checksum(bytes))
+                .map_err(|e| format!("写入校验和文件失败: {}", e))
+        });
+        if let Err(error) = result {
+            eprintln!("更新聊天记录校验和失败: {}", error);
+        }
+    }
+
+    /// 追加写入后增量更新校验和，没有可用的旧校验和时回退为重新计算整个文件
+    fn append_checksum(&self, appended: &[u8]) {
+        let result = self.get_checksum_file_path().and_then(|checksum_path| {
+            let checksum = match fs::read_to_string(&checksum_path)
+                .ok()
+                .and_then(|previous| append_history_checksum(&previous, appended))
+            {
+                Some(checksum) => checksum,
+                None => {
+                    let bytes = fs::read(self.get_history_file_path()?)
+                        .map_err(|e| format!("读取历史文件失败: {}", e))?;
+                    history_checksum(&bytes)
+                }
+            };
+            fs::write(checksum_path, checksum).map_err(|e| format!("写入校验和文件失败: {}", e))
+        });
+        if let Err(error) = result {
+            eprintln!("更新聊天记录校验和失败: {}", error);
+        }
+    }
+
+    /// 检查历史文件完整性，并与校验和文件比对（只读）
+    pub fn verify_integrity(&self) -> Result<HistoryIntegrityReport, String> {
+        let file_path = self.get_history_file_path()?;
+        let bytes = if file_path.exists() {
+            fs::read(&file_path).map_err(|e| format!("读取历史文件失败: {}", e))?
+        } else {
+            Vec::new()
+        };
+
+        let mut report = check_history_integrity(&String::from_utf8_lossy(&bytes));
+        let checksum_path = self.get_checksum_file_path()?;
+        if checksum_path.exists() {
+            let expected = fs::read_to_string(&checksum_path)
+                .map_err(|e| format!("读取校验和文件失败: {}", e))?;
+            report.checksum_matches = Some(expected.trim() == history_checksum(&bytes));
+        }
 
-        Ok(character_dir.join("chat_history.jsonl"))
+        Ok(report)
     }
 
     pub fn save_message(&self, message: &ChatMessage) -> Result<(), String> {
@@ -178,13 +1252,15 @@ impl ChatHistoryManager {
             .map_err(|e| format!("序列化消息失败: {}", e))?;
 
         // 追加写入文件
+        let line = line + "\n";
         fs::OpenOptions::new()
             .create(true)
             .append(true)
             .open(&file_path)
             .map_err(|e| format!("打开历史文件失败: {}", e))?
-            .write_all((line + "\n").as_bytes())
+            .write_all(line.as_bytes())
             .map_err(|e| format!("写入历史文件失败: {}", e))?;
+        self.append_checksum(line.as_bytes());
 
         Ok(())
     }
@@ -200,19 +1276,36 @@ impl ChatHistoryManager {
         }
 
         let file = fs::File::open(&file_path).map_err(|e| format!("读取历史文件失败: {}", e))?;
-        let reader = BufReader::new(file);
-        let mut messages = Vec::new();
-
-        for line in reader.lines() {
-            let line = line.map_err(|e| format!("读取历史文件失败: {}", e))?;
-            match parse_history_line(&line) {
-                Ok(Some(message)) => messages.push(message),
-                Ok(None) => {}
-                Err(error) => eprintln!("{}", error),
-            }
+        let lines = BufReader::new(file)
+            .lines()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("读取历史文件失败: {}", e))?;
+
+        Ok(parse_history_lines(lines.iter()).collect())
+    }
+
+    /// 分页读取历史，offset 从最早的消息开始计数，返回该页消息与消息总数
+    pub fn load_history_page(
+        &self,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<ChatMessage>, usize), String> {
+        let file_path = self.get_history_file_path();
+
+        let Ok(file_path) = file_path else {
+            return Ok((Vec::new(), 0));
+        };
+        if !file_path.exists() {
+            return Ok((Vec::new(), 0));
         }
 
-        Ok(messages)
+        let file = fs::File::open(&file_path).map_err(|e| format!("读取历史文件失败: {}", e))?;
+        let lines = BufReader::new(file)
+            .lines()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("读取历史文件失败: {}", e))?;
+
+        Ok(history_page_from_lines(lines.iter(), offset, limit))
     }
 
     pub fn clear_history(&self) -> Result<(), String> {
@@ -220,6 +1313,7 @@ impl ChatHistoryManager {
 
         if file_path.exists() {
             fs::write(&file_path, "").map_err(|e| format!("清空历史文件失败: {}", e))?;
+            self.write_checksum(b"");
         }
 
         Ok(())
@@ -228,18 +1322,44 @@ impl ChatHistoryManager {
     pub fn save_history(&self, history: &[ChatMessage]) -> Result<(), String> {
         let file_path = self.get_history_file_path()?;
 
-        let content = history
-            .iter()
-            .map(|msg| serde_json::to_string(msg).unwrap_or_default())
-            .collect::<Vec<_>>()
-            .join("\n")
-            + "\n";
-
-        fs::write(&file_path, content).map_err(|e| format!("保存历史文件失败: {}", e))?;
+        let content = history_to_jsonl(history);
+        fs::write(&file_path, &content).map_err(|e| format!("保存历史文件失败: {}", e))?;
+        self.write_checksum(content.as_bytes());
 
         Ok(())
     }
 
+    /// 按轮次导出 OpenAI 微调格式的 JSONL，保留工具调用消息；
+    /// system_prompt 为 Some 时作为每行首条消息
+    pub fn export_training_jsonl(&self, system_prompt: Option<&str>) -> Result<String, String> {
+        let lines = build_finetune_lines(
+            system_prompt.unwrap_or_default(),
+            &self.load_history()?,
+            FinetuneExportMode::PerTurn,
+            true,
+        );
+
+        let mut content = String::new();
+        for line in lines {
+            content.push_str(
+                &serde_json::to_string(&line).map_err(|e| format!("序列化训练数据失败: {}", e))?,
+            );
+            content.push('\n');
+        }
+        Ok(content)
+    }
+
+    /// 将聊天历史渲染为 Markdown，assistant 标题使用角色名
+    pub fn export_markdown(&self) -> Result<String, String> {
+        let character_name =
+            CharacterStorage::get_character_by_uuid(&self.app_handle, &self.character_id)?
+                .map(|character| character.card.data.name)
+                .filter(|name| !name.trim().is_empty())
+                .unwrap_or_else(|| "助手".to_string());
+
+        Ok(history_to_markdown(&character_name, &self.load_history()?))
+    }
+
     pub fn get_last_message(&self) -> Result<Option<ChatMessage>, String> {
         let history = self.load_history()?;
         Ok(history.last().cloned())
@@ -7,10 +7,14 @@ use super::command::CommandExecutor;
 mod clear_command;
 mod continue_command;
 mod regenerate_command;
+mod summarize_command;
+mod undo_command;
 
 pub use clear_command::ClearCommand;
 pub use continue_command::ContinueCommand;
 pub use regenerate_command::RegenerateCommand;
+pub use summarize_command::SummarizeCommand;
+pub use undo_command::UndoCommand;
 
 pub type CommandBuilder = fn() -> Arc<dyn CommandExecutor>;
 
@@ -32,6 +36,18 @@ fn build_continue_command() -> Arc<dyn CommandExecutor> {
     Arc::new(ContinueCommand::new())
 }
 
+fn build_retry_command() -> Arc<dyn CommandExecutor> {
+    Arc::new(RegenerateCommand::alias("retry"))
+}
+
+fn build_summarize_command() -> Arc<dyn CommandExecutor> {
+    Arc::new(SummarizeCommand::new())
+}
+
+fn build_undo_command() -> Arc<dyn CommandExecutor> {
+    Arc::new(UndoCommand::new())
+}
+
 pub fn builtin_manifest() -> Vec<BuiltinCommandDescriptor> {
     vec![
         BuiltinCommandDescriptor {
@@ -49,6 +65,21 @@ pub fn builtin_manifest() -> Vec<BuiltinCommandDescriptor> {
             description: "基于最后一条用户消息继续对话",
             builder: build_continue_command,
         },
+        BuiltinCommandDescriptor {
+            id: "retry",
+            description: "重试最后一条 AI 回复（regenerate 的别名）",
+            builder: build_retry_command,
+        },
+        BuiltinCommandDescriptor {
+            id: "summarize",
+            description: "把较早的聊天记录压缩为一条记忆摘要",
+            builder: build_summarize_command,
+        },
+        BuiltinCommandDescriptor {
+            id: "undo",
+            description: "撤销最后一轮用户消息与 AI 回复",
+            builder: build_undo_command,
+        },
     ]
 }
 
@@ -64,3 +95,32 @@ static DISABLED_COMMANDS: Lazy<HashSet<String>> = Lazy::new(|| {
 pub fn is_enabled(id: &str) -> bool {
     !DISABLED_COMMANDS.contains(&id.to_lowercase())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{builtin_manifest, RegenerateCommand};
+    use crate::command_system::command::CommandExecutor;
+
+    #[test]
+    fn retry_resolves_to_the_regenerate_command() {
+        let manifest = builtin_manifest();
+        let retry = manifest
+            .iter()
+            .find(|descriptor| descriptor.id == "retry")
+            .expect("retry should be registered");
+
+        let command = (retry.builder)();
+        let metadata = command.metadata();
+        assert_eq!(metadata.id, "retry");
+        assert_eq!(metadata.name, "/retry");
+        let regenerate = RegenerateCommand::new();
+        assert_eq!(
+            metadata.description,
+            format!(
+                "{}（/regenerate 的别名）",
+                regenerate.metadata().description
+            )
+        );
+        assert_eq!(metadata.priority, regenerate.metadata().priority);
+    }
+}
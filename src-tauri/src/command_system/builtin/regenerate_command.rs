@@ -3,6 +3,7 @@ use async_trait::async_trait;
 use crate::backend::application::session_service::SessionService;
 use crate::backend::domain::{CommandCategory, CommandMetadata, CommandResult};
 use crate::character_session::SESSION_MANAGER;
+use crate::chat_history::ChatMessage;
 use crate::command_system::command::{CommandContext, CommandExecutor};
 
 pub struct RegenerateCommand {
@@ -24,6 +25,21 @@ impl RegenerateCommand {
             },
         }
     }
+
+    /// 以另一个命令 ID 注册的同一命令（如 /retry）
+    pub fn alias(id: &str) -> Self {
+        let mut command = Self::new();
+        command.metadata.id = id.to_string();
+        command.metadata.name = format!("/{}", id);
+        command.metadata.description =
+            format!("{}（/regenerate 的别名）", command.metadata.description);
+        command
+    }
+}
+
+/// 末尾是 AI 回复时才有可重新生成的内容
+fn can_regenerate(chat_history: &[ChatMessage]) -> bool {
+    matches!(chat_history.last(), Some(message) if message.role == "assistant")
 }
 
 #[async_trait]
@@ -41,7 +57,7 @@ impl CommandExecutor for RegenerateCommand {
             return false;
         };
 
-        matches!(session.chat_history.last(), Some(message) if message.role == "assistant")
+        can_regenerate(&session.chat_history)
     }
 
     async fn execute(&self, context: CommandContext) -> Result<CommandResult, String> {
@@ -55,3 +71,28 @@ impl CommandExecutor for RegenerateCommand {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::can_regenerate;
+    use crate::chat_history::ChatMessage;
+
+    fn message(role: &str) -> ChatMessage {
+        serde_json::from_value(serde_json::json!({ "role": role, "content": "..." })).unwrap()
+    }
+
+    #[test]
+    fn unavailable_on_empty_history() {
+        assert!(!can_regenerate(&[]));
+    }
+
+    #[test]
+    fn unavailable_after_a_trailing_user_message() {
+        assert!(!can_regenerate(&[message("assistant"), message("user")]));
+    }
+
+    #[test]
+    fn available_after_a_trailing_assistant_message() {
+        assert!(can_regenerate(&[message("user"), message("assistant")]));
+    }
+}
@@ -0,0 +1,134 @@
+use async_trait::async_trait;
+
+use crate::ai_chat::{AIChatService, ChatCompletionRequest, ChatMessage, MessageRole};
+use crate::api_config::ApiConfigService;
+use crate::app_settings::AppSettingsService;
+use crate::backend::domain::{CommandCategory, CommandMetadata, CommandResult};
+use crate::character_session::SESSION_MANAGER;
+use crate::chat_history::{history_transcript, splice_summary, summary_split_index};
+use crate::command_system::command::{CommandContext, CommandExecutor};
+use crate::events::EventEmitter;
+
+const SUMMARIZE_SYSTEM_PROMPT: &str = "你是对话记录整理助手。请把用户提供的角色卡创作对话压缩为简洁的记忆摘要，保留已确定的角色设定、世界观事实、用户偏好和尚未完成的事项，省略寒暄与重复内容。只输出摘要正文。";
+
+/// /summarize 命令 - 把较早的聊天记录压缩为一条 system 记忆消息
+pub struct SummarizeCommand {
+    metadata: CommandMetadata,
+}
+
+impl SummarizeCommand {
+    pub fn new() -> Self {
+        Self {
+            metadata: CommandMetadata {
+                id: "summarize".to_string(),
+                name: "/summarize".to_string(),
+                description: "把较早的对话压缩为一条记忆摘要，节省上下文".to_string(),
+                icon: None,
+                category: Some(CommandCategory::History),
+                priority: 3,
+                requires_confirmation: true,
+                confirmation_message: Some(
+                    "较早的对话将被替换为 AI 生成的摘要，此操作不可撤销。确定继续吗？".to_string(),
+                ),
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl CommandExecutor for SummarizeCommand {
+    fn metadata(&self) -> &CommandMetadata {
+        &self.metadata
+    }
+
+    async fn is_available(&self, context: &CommandContext) -> bool {
+        // 检查是否有活跃会话且有超出保留数量的聊天记录
+        let Some(uuid) = &context.session_uuid else {
+            return false;
+        };
+        let Some(session) = SESSION_MANAGER.get_session(uuid) else {
+            return false;
+        };
+        let Ok(keep_recent) = AppSettingsService::get_summarize_keep_recent(&context.app_handle)
+        else {
+            return false;
+        };
+
+        summary_split_index(&session.chat_history, keep_recent).is_some()
+    }
+
+    async fn execute(&self, context: CommandContext) -> Result<CommandResult, String> {
+        let uuid = context.session_uuid.ok_or("没有活跃的会话")?;
+        let session = SESSION_MANAGER.get_session(&uuid).ok_or("会话不存在")?;
+        let keep_recent = AppSettingsService::get_summarize_keep_recent(&context.app_handle)?;
+        let split = summary_split_index(&session.chat_history, keep_recent)
+            .ok_or("聊天记录不足，无需摘要")?;
+        let summarized = session.chat_history[..split].to_vec();
+
+        let api_config = ApiConfigService::get_default_api_config(&context.app_handle)?
+            .ok_or("没有可用的API配置")?;
+        let text_message = |role: MessageRole, content: String| ChatMessage {
+            role,
+            content,
+            name: None,
+            reasoning_content: None,
+            tool_calls: None,
+            tool_call_id: None,
+            images: None,
+        };
+        let request = ChatCompletionRequest {
+            model: api_config.model.clone(),
+            messages: vec![
+                text_message(MessageRole::System, SUMMARIZE_SYSTEM_PROMPT.to_string()),
+                text_message(MessageRole::User, history_transcript(&summarized)),
+            ],
+            temperature: Some(0.3),
+            max_tokens: None,
+            top_p: None,
+            frequency_penalty: None,
+            presence_penalty: None,
+            stop: None,
+            stream: None,
+            tools: None,
+            tool_choice: None,
+            max_tool_iterations: None,
+            response_format: None,
+            seed: None,
+        };
+
+        let response = AIChatService::create_chat_completion(&api_config, &request, None, None)
+            .await
+            .map_err(|e| format!("生成摘要失败: {}", e))?;
+        let summary = response
+            .choices
+            .first()
+            .map(|choice| choice.message.content.trim().to_string())
+            .filter(|summary| !summary.is_empty())
+            .ok_or("AI 未返回摘要")?;
+
+        // 生成摘要期间会话可能已有新消息，以最新会话为准，仅替换被摘要的前缀
+        let chat_history =
+            SESSION_MANAGER.with_session(&context.app_handle, uuid.clone(), |session| {
+                if !session.chat_history.starts_with(&summarized) {
+                    return Err("生成摘要期间聊天记录已被修改，请重试".to_string());
+                }
+                session.chat_history = splice_summary(
+                    &session.chat_history,
+                    split,
+                    &summary,
+                    chrono::Utc::now().timestamp(),
+                );
+                session.rewrite_all_history_now(&context.app_handle)?;
+                Ok(session.chat_history.clone())
+            })?;
+
+        EventEmitter::send_chat_history_loaded(&context.app_handle, &uuid, &chat_history)?;
+
+        Ok(CommandResult {
+            success: true,
+            message: Some(format!("已将 {} 条较早的消息压缩为记忆摘要", split)),
+            error: None,
+            data: None,
+        })
+    }
+}
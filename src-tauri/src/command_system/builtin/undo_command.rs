@@ -0,0 +1,120 @@
+use async_trait::async_trait;
+
+use crate::backend::domain::{CommandCategory, CommandMetadata, CommandResult};
+use crate::character_session::SESSION_MANAGER;
+use crate::chat_history::ChatMessage;
+use crate::command_system::command::{CommandContext, CommandExecutor};
+use crate::events::EventEmitter;
+
+/// /undo 命令 - 撤销最后一轮用户消息与 AI 回复
+pub struct UndoCommand {
+    metadata: CommandMetadata,
+}
+
+impl UndoCommand {
+    pub fn new() -> Self {
+        Self {
+            metadata: CommandMetadata {
+                id: "undo".to_string(),
+                name: "/undo".to_string(),
+                description: "撤销：删除最后一条用户消息及其 AI 回复".to_string(),
+                icon: None,
+                category: Some(CommandCategory::History),
+                priority: 4,
+                requires_confirmation: false,
+                confirmation_message: None,
+            },
+        }
+    }
+}
+
+/// 计算撤销后应保留的消息数量
+///
+/// 末尾为 AI 回复时连同其工具调用消息和之前的用户消息一起删除；
+/// 末尾为等待回复的用户消息时只删除这一条。
+fn undo_start_index(chat_history: &[ChatMessage]) -> Option<usize> {
+    let last = chat_history.last()?;
+    match last.role.as_str() {
+        "user" => Some(chat_history.len() - 1),
+        "assistant" => chat_history
+            .iter()
+            .rposition(|message| message.role != "assistant" && message.role != "tool")
+            .filter(|&index| chat_history[index].role == "user"),
+        _ => None,
+    }
+}
+
+#[async_trait]
+impl CommandExecutor for UndoCommand {
+    fn metadata(&self) -> &CommandMetadata {
+        &self.metadata
+    }
+
+    async fn is_available(&self, context: &CommandContext) -> bool {
+        // 检查是否有活跃会话且末尾有可撤销的对话
+        if let Some(uuid) = &context.session_uuid {
+            if let Some(session) = SESSION_MANAGER.get_session(uuid) {
+                return undo_start_index(&session.chat_history).is_some();
+            }
+        }
+        false
+    }
+
+    async fn execute(&self, context: CommandContext) -> Result<CommandResult, String> {
+        let uuid = context.session_uuid.ok_or("没有活跃的会话")?;
+
+        let (removed, chat_history) =
+            SESSION_MANAGER.with_session(&context.app_handle, uuid.clone(), |session| {
+                let start = undo_start_index(&session.chat_history).ok_or("没有可撤销的对话")?;
+                let removed = session.chat_history.len() - start;
+                session.chat_history.truncate(start);
+                session.rewrite_all_history_now(&context.app_handle)?;
+                Ok((removed, session.chat_history.clone()))
+            })?;
+
+        EventEmitter::send_chat_history_loaded(&context.app_handle, &uuid, &chat_history)?;
+
+        Ok(CommandResult {
+            success: true,
+            message: Some(format!("已撤销 {} 条消息", removed)),
+            error: None,
+            data: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::undo_start_index;
+    use crate::chat_history::ChatMessage;
+
+    fn message(role: &str) -> ChatMessage {
+        serde_json::from_value(serde_json::json!({ "role": role, "content": "..." })).unwrap()
+    }
+
+    #[test]
+    fn undo_removes_trailing_user_and_assistant_pair() {
+        let history = [
+            message("user"),
+            message("assistant"),
+            message("user"),
+            message("assistant"),
+            message("tool"),
+            message("assistant"),
+        ];
+        assert_eq!(undo_start_index(&history), Some(2));
+
+        assert_eq!(undo_start_index(&[]), None);
+        assert_eq!(undo_start_index(&[message("assistant")]), None);
+        assert_eq!(
+            undo_start_index(&[message("system"), message("assistant")]),
+            None
+        );
+    }
+
+    #[test]
+    fn undo_removes_only_pending_user_message() {
+        let history = [message("user"), message("assistant"), message("user")];
+        assert_eq!(undo_start_index(&history), Some(2));
+    }
+}
@@ -1,11 +1,97 @@
 use crate::ai_config::AIConfigService;
+use crate::api_config::ApiConfigService;
 use crate::backend::domain::{ContextBuilderOptions, TokenBudget};
 use crate::character_session::SESSION_MANAGER;
-use crate::character_storage::{CharacterBook, CharacterData, CharacterStorage};
+use crate::character_storage::{CharacterBook, CharacterData, CharacterStorage, WorldBookEntry};
 use crate::chat_history::ChatHistoryManager;
 use crate::chat_history::ChatMessage;
-use crate::token_counter::get_token_counter;
+use crate::token_counter::{get_token_counter_for_model, TokenCounter};
+use crate::tools::world_book_shared::entry_activated_by_text;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::sync::OnceLock;
+
+/// 关联角色摘要可占用的总预算比例
+const LINKED_CHARACTERS_BUDGET_RATIO: f64 = 0.10;
+/// 每个关联角色描述保留的最大字符数
+const LINKED_CHARACTER_DESCRIPTION_MAX_CHARS: usize = 500;
+/// 世界书未设置 scan_depth 时扫描的最近消息数
+const DEFAULT_SCAN_DEPTH: usize = 2;
+/// 递归扫描的最大轮数，避免条目互相引用时无限循环
+const MAX_RECURSION_DEPTH: usize = 5;
+/// at_depth 条目未设置 depth 时的默认插入深度（与 SillyTavern 一致）
+const DEFAULT_ENTRY_DEPTH: usize = 4;
+/// SillyTavern 在 extensions.position 中表示 at_depth 的取值
+const EXTENSION_POSITION_AT_DEPTH: i64 = 4;
+
+fn extension_flag(entry: &WorldBookEntry, key: &str) -> bool {
+    entry
+        .extensions
+        .get(key)
+        .and_then(serde_json::Value::as_bool)
+        .unwrap_or(false)
+}
+
+/// 世界书条目的插入位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WorldBookPosition {
+    BeforeChar,
+    AfterChar,
+    /// 插入到距聊天历史末尾 N 条消息处
+    AtDepth(usize),
+}
+
+impl WorldBookPosition {
+    /// position 为 at_depth（或未设置 position 但 extensions.position 为 4）时按 extensions.depth 插入，
+    /// before_char 放在角色信息前，其余放在角色信息后
+    fn of(entry: &WorldBookEntry) -> Self {
+        let extension_position = entry
+            .extensions
+            .get("position")
+            .and_then(serde_json::Value::as_i64);
+        let at_depth = match entry.position.as_deref() {
+            Some(position) => position == "at_depth",
+            None => extension_position == Some(EXTENSION_POSITION_AT_DEPTH),
+        };
+
+        if at_depth {
+            let depth = entry
+                .extensions
+                .get("depth")
+                .and_then(serde_json::Value::as_u64)
+                .map(|depth| depth as usize)
+                .unwrap_or(DEFAULT_ENTRY_DEPTH);
+            Self::AtDepth(depth)
+        } else if entry.position.as_deref() == Some("before_char") {
+            Self::BeforeChar
+        } else {
+            Self::AfterChar
+        }
+    }
+}
+
+/// 按插入位置分组后的世界书内容
+#[derive(Debug, Default)]
+struct WorldBookSections {
+    /// 实际纳入上下文的条目（未超出预算且满足最低重要性）
+    included: Vec<ProcessedWorldBookEntry>,
+    /// 放在角色信息前的条目
+    before_char: String,
+    /// 世界书概要及放在角色信息后的条目
+    after_char: String,
+    /// (插入深度, 条目内容)
+    at_depth: Vec<(usize, String)>,
+}
+
+/// 角色信息与世界书构成的 Assistant 消息，以及需要按深度插入历史的世界书条目
+struct AssistantMessages {
+    messages: Vec<OpenAIMessage>,
+    character_tokens: usize,
+    worldbook_tokens: usize,
+    /// (插入深度, 条目内容)
+    depth_entries: Vec<(usize, String)>,
+}
 
 /// OpenAI 消息结构
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,6 +102,9 @@ pub struct OpenAIMessage {
     pub reasoning_content: Option<String>,
     pub tool_calls: Option<Vec<crate::chat_history::ToolCall>>,
     pub tool_call_id: Option<String>,
+    /// 用户消息附带的图片，不计入文本 token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub images: Option<Vec<String>>,
 }
 
 /// 处理后的世界书条目
@@ -61,6 +150,21 @@ pub struct BuiltContextResult {
     pub was_truncated: bool,
 }
 
+/// 角色上下文的 token 预算明细
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenBudgetBreakdown {
+    /// 各部分 token 分配
+    pub token_allocation: TokenAllocation,
+    /// 总 Token 数量
+    pub total_tokens: usize,
+    /// 当前的 token 上限
+    pub token_limit: usize,
+    /// 纳入上下文的世界书条目，按 token 数从大到小排序
+    pub worldbook_entries: Vec<ProcessedWorldBookEntry>,
+    /// 世界书中不属于单个条目的部分（概要信息、消息包装等）
+    pub worldbook_overhead_tokens: usize,
+}
+
 /// 上下文构建器 - 负责构建完整的 AI 对话上下文
 pub struct ContextBuilder {
     token_budget: TokenBudget,
@@ -91,6 +195,31 @@ const TOOL_DECLARATIONS: &str = r#"tools:
     parameters: {"type": "object", "properties": {"entry_id": {"type": "string"}, "name": {"type": "string"}, "key": {"type": "string"}, "keys": {"type": "string"}, "content": {"type": "string"}, "comment": {"type": "string"}, "enabled": {"type": "boolean"}, "priority": {"type": "integer"}, "position": {"type": "string"}, "depth": {"type": "integer"}, "probability": {"type": "integer"}}}
 "#;
 
+fn character_macro_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(r"(?i)\{\{\s*(char|user)\s*\}\}").expect("macro pattern should compile")
+    })
+}
+
+/// 把 {{char}} / {{user}}（宏名不区分大小写）替换为角色名和用户名
+pub fn substitute_character_macros(text: &str, char_name: &str, user_name: &str) -> String {
+    character_macro_pattern()
+        .replace_all(text, |captures: &regex::Captures| {
+            if captures[1].eq_ignore_ascii_case("char") {
+                char_name.to_string()
+            } else {
+                user_name.to_string()
+            }
+        })
+        .into_owned()
+}
+
+/// 统计文本中 {{char}} / {{user}} 宏的数量
+pub fn count_character_macros(text: &str) -> usize {
+    character_macro_pattern().find_iter(text).count()
+}
+
 impl ContextBuilder {
     /// 创建新的上下文构建器
     pub fn new(options: ContextBuilderOptions) -> Self {
@@ -113,13 +242,25 @@ impl ContextBuilder {
         let system_tokens = self.count_messages_tokens(&system_messages);
 
         // 2. 构建 Assistant 消息（角色信息 + 世界书）
-        let (assistant_messages, character_tokens, worldbook_tokens) =
-            self.build_assistant_messages(character_data)?;
-
-        // 3. 处理聊天历史
-        let history_messages =
-            self.build_history_messages(chat_history, self.token_budget.history_reserved)?;
+        let AssistantMessages {
+            messages: assistant_messages,
+            character_tokens,
+            worldbook_tokens,
+            depth_entries,
+        } = self.build_assistant_messages(character_data, chat_history)?;
+
+        // 3. 处理聊天历史，并按深度插入 at_depth 世界书条目
+        let (mut history_messages, history_truncated) = self.build_history_messages(
+            chat_history,
+            &character_data.card.data.name,
+            self.token_budget.history_reserved,
+        )?;
         let history_tokens = self.count_messages_tokens(&history_messages);
+        let worldbook_tokens = worldbook_tokens
+            + Self::insert_depth_entries(&mut history_messages, depth_entries)
+                .iter()
+                .map(|message| self.count_message_tokens(message))
+                .sum::<usize>();
 
         // 4. 处理当前用户消息
         let current_message = current_user_message.map(|content| OpenAIMessage {
@@ -129,6 +270,7 @@ impl ContextBuilder {
             reasoning_content: None,
             tool_calls: None,
             tool_call_id: None,
+            images: None,
         });
 
         let current_tokens = current_message
@@ -146,7 +288,7 @@ impl ContextBuilder {
 
         let total_tokens =
             system_tokens + character_tokens + worldbook_tokens + history_tokens + current_tokens;
-        let was_truncated = total_tokens > self.options.token_limit;
+        let was_truncated = history_truncated || total_tokens > self.options.token_limit;
 
         Ok(BuiltContextResult {
             system_messages,
@@ -159,7 +301,39 @@ impl ContextBuilder {
         })
     }
 
-    /// 构建 System 消息（包含 role、task、tools、instructions）
+    /// 构建上下文并统计各部分与每个世界书条目占用的 token
+    pub fn analyze_token_budget(
+        &self,
+        character_data: &CharacterData,
+        chat_history: &[ChatMessage],
+    ) -> Result<TokenBudgetBreakdown, String> {
+        let context = self.build_full_context(character_data, chat_history, None)?;
+        let mut worldbook_entries = match &character_data.card.data.character_book {
+            Some(character_book) => {
+                self.build_worldbook_content(character_book, chat_history)?
+                    .included
+            }
+            None => Vec::new(),
+        };
+        worldbook_entries.sort_by_key(|entry| Reverse(entry.token_count));
+
+        let entry_tokens = worldbook_entries
+            .iter()
+            .map(|entry| entry.token_count)
+            .sum::<usize>();
+        Ok(TokenBudgetBreakdown {
+            worldbook_overhead_tokens: context
+                .token_allocation
+                .worldbook
+                .saturating_sub(entry_tokens),
+            token_allocation: context.token_allocation,
+            total_tokens: context.total_tokens,
+            token_limit: self.options.token_limit,
+            worldbook_entries,
+        })
+    }
+
+    /// 构建 System 消息（包含 role、task、tools、instructions，配置人设时附加 persona）
     fn build_system_messages(
         &self,
         character_data: &CharacterData,
@@ -179,7 +353,7 @@ impl ContextBuilder {
         }
 
         // 添加指令
-        let instructions = self.process_placeholders(&self.options.ai_instructions, character_data);
+        let instructions = self.process_placeholders(&self.options.instructions, character_data);
         content.push_str("instructions: |\n");
         for line in instructions.lines() {
             content.push_str("  ");
@@ -187,6 +361,24 @@ impl ContextBuilder {
             content.push('\n');
         }
 
+        if let Some(persona) = self
+            .options
+            .persona
+            .as_ref()
+            .filter(|persona| persona.is_configured())
+        {
+            content.push_str("persona:\n");
+            content.push_str(&format!("  name: \"{}\"\n", persona.name));
+            if !persona.description.trim().is_empty() {
+                content.push_str("  description: |\n");
+                for line in persona.description.lines() {
+                    content.push_str("    ");
+                    content.push_str(line);
+                    content.push('\n');
+                }
+            }
+        }
+
         Ok(vec![OpenAIMessage {
             role: "system".to_string(),
             content,
@@ -194,17 +386,44 @@ impl ContextBuilder {
             reasoning_content: None,
             tool_calls: None,
             tool_call_id: None,
+            images: None,
         }])
     }
 
-    /// 构建 Assistant 消息（角色信息 + 世界书）
+    /// 构建 Assistant 消息（角色信息 + 世界书），同时返回需要按深度插入历史的世界书条目
     fn build_assistant_messages(
         &self,
         character_data: &CharacterData,
-    ) -> Result<(Vec<OpenAIMessage>, usize, usize), String> {
+        chat_history: &[ChatMessage],
+    ) -> Result<AssistantMessages, String> {
         let mut messages = Vec::new();
+        let sections = match &character_data.card.data.character_book {
+            Some(character_book) => {
+                Some(self.build_worldbook_content(character_book, chat_history)?)
+            }
+            None => None,
+        };
+        let mut worldbook_tokens = 0;
 
-        // 1. 构建角色信息消息
+        // 1. before_char 世界书条目放在角色信息前
+        if let Some(before_char) = sections
+            .as_ref()
+            .map(|sections| &sections.before_char)
+            .filter(|content| !content.is_empty())
+        {
+            worldbook_tokens += self.count_tokens(before_char);
+            messages.push(OpenAIMessage {
+                role: "assistant".to_string(),
+                content: format!("worldbook_before_char:\n{}", before_char),
+                name: None,
+                reasoning_content: None,
+                tool_calls: None,
+                tool_call_id: None,
+                images: None,
+            });
+        }
+
+        // 2. 构建角色信息消息
         let character_content = self.build_character_content(character_data)?;
         let character_tokens = self.count_tokens(&character_content);
 
@@ -215,29 +434,115 @@ impl ContextBuilder {
             reasoning_content: None,
             tool_calls: None,
             tool_call_id: None,
+            images: None,
         });
 
-        // 2. 构建世界书消息（如果存在）
-        let worldbook_tokens =
-            if let Some(character_book) = &character_data.card.data.character_book {
-                let worldbook_content = self.build_worldbook_content(character_book)?;
-                let worldbook_tokens = self.count_tokens(&worldbook_content);
-
-                messages.push(OpenAIMessage {
-                    role: "assistant".to_string(),
-                    content: format!("worldbook:\n{}", worldbook_content),
-                    name: None,
-                    reasoning_content: None,
-                    tool_calls: None,
-                    tool_call_id: None,
-                });
-
-                worldbook_tokens
-            } else {
-                0
+        // 3. 构建世界书消息（如果存在）
+        let mut depth_entries = Vec::new();
+        if let Some(sections) = sections {
+            worldbook_tokens += self.count_tokens(&sections.after_char);
+            messages.push(OpenAIMessage {
+                role: "assistant".to_string(),
+                content: format!("worldbook:\n{}", sections.after_char),
+                name: None,
+                reasoning_content: None,
+                tool_calls: None,
+                tool_call_id: None,
+                images: None,
+            });
+            depth_entries = sections.at_depth;
+        }
+
+        // 4. 关联角色摘要（计入角色信息的 token）
+        let linked_content = self.build_linked_characters_content();
+        let character_tokens = if linked_content.is_empty() {
+            character_tokens
+        } else {
+            let linked_tokens = self.count_tokens(&linked_content);
+            messages.push(OpenAIMessage {
+                role: "assistant".to_string(),
+                content: format!("linked_characters:\n{}", linked_content),
+                name: None,
+                reasoning_content: None,
+                tool_calls: None,
+                tool_call_id: None,
+                images: None,
+            });
+            character_tokens + linked_tokens
+        };
+
+        Ok(AssistantMessages {
+            messages,
+            character_tokens,
+            worldbook_tokens,
+            depth_entries,
+        })
+    }
+
+    /// 把 at_depth 世界书条目插入到距历史末尾 depth 条消息处（不会拆开工具调用与其结果），
+    /// 返回插入的消息用于统计 token。
+    /// 条目以 user 消息发送：发送时 system 消息会被合并进顶部的系统提示，无法保留插入位置
+    fn insert_depth_entries(
+        history_messages: &mut Vec<OpenAIMessage>,
+        depth_entries: Vec<(usize, String)>,
+    ) -> Vec<OpenAIMessage> {
+        let mut inserted = Vec::new();
+        // 深度大的先插入，避免影响后续条目的位置计算
+        let mut depth_entries = depth_entries;
+        depth_entries.sort_by_key(|entry| Reverse(entry.0));
+
+        for (depth, content) in depth_entries {
+            let mut index = history_messages.len().saturating_sub(depth);
+            while index > 0
+                && index < history_messages.len()
+                && history_messages[index].role == "tool"
+            {
+                index -= 1;
+            }
+
+            let message = OpenAIMessage {
+                role: "user".to_string(),
+                content: format!("worldbook_entry:\n{}", content),
+                name: None,
+                reasoning_content: None,
+                tool_calls: None,
+                tool_call_id: None,
+                images: None,
             };
+            history_messages.insert(index, message.clone());
+            inserted.push(message);
+        }
 
-        Ok((messages, character_tokens, worldbook_tokens))
+        inserted
+    }
+
+    /// 构建关联角色摘要，超出子预算的角色不再加入
+    fn build_linked_characters_content(&self) -> String {
+        let budget =
+            (self.token_budget.total_limit as f64 * LINKED_CHARACTERS_BUDGET_RATIO) as usize;
+        let mut content = String::new();
+        let mut used_tokens = 0;
+
+        for linked in &self.options.linked_characters {
+            let description: String = linked
+                .description
+                .chars()
+                .take(LINKED_CHARACTER_DESCRIPTION_MAX_CHARS)
+                .collect();
+            let mut entry = format!("  - name: \"{}\"\n", linked.name);
+            if !description.trim().is_empty() {
+                entry.push_str(&format!("    description: \"{}\"\n", description.trim()));
+            }
+
+            let entry_tokens = self.count_tokens(&entry);
+            if used_tokens + entry_tokens > budget {
+                break;
+            }
+            used_tokens += entry_tokens;
+            content.push_str(&entry);
+        }
+
+        content
     }
 
     /// 构建角色内容
@@ -307,12 +612,90 @@ impl ContextBuilder {
             ));
         }
 
-        Ok(content)
+        Ok(substitute_character_macros(
+            &content,
+            &card_data.name,
+            &self.options.user_name,
+        ))
     }
 
-    /// 构建世界书内容
-    fn build_worldbook_content(&self, character_book: &CharacterBook) -> Result<String, String> {
-        let mut content = String::new();
+    /// 按最近 scan_depth 条消息激活世界书条目（常驻条目始终激活）。
+    /// 开启递归扫描时，已激活条目的内容会继续用于激活其他条目。
+    pub fn activate_entries<'a>(
+        entries: &'a [WorldBookEntry],
+        chat_history: &[ChatMessage],
+        scan_depth: usize,
+        recursive_scanning: bool,
+    ) -> Vec<&'a WorldBookEntry> {
+        let scan_start = chat_history.len().saturating_sub(scan_depth);
+        let scan_text = chat_history[scan_start..]
+            .iter()
+            .map(|message| message.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let mut activated = entries
+            .iter()
+            .map(|entry| {
+                entry.enabled
+                    && (entry.constant.unwrap_or(false)
+                        || entry_activated_by_text(entry, &scan_text))
+            })
+            .collect::<Vec<_>>();
+
+        if recursive_scanning {
+            let mut newly_activated = (0..entries.len())
+                .filter(|index| activated[*index])
+                .collect::<Vec<_>>();
+
+            for _ in 0..MAX_RECURSION_DEPTH {
+                let recursion_text = newly_activated
+                    .iter()
+                    .map(|index| &entries[*index])
+                    .filter(|entry| !extension_flag(entry, "prevent_recursion"))
+                    .map(|entry| entry.content.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                if recursion_text.is_empty() {
+                    break;
+                }
+
+                newly_activated = entries
+                    .iter()
+                    .enumerate()
+                    .filter(|(index, entry)| {
+                        !activated[*index]
+                            && entry.enabled
+                            && !extension_flag(entry, "exclude_recursion")
+                            && entry_activated_by_text(entry, &recursion_text)
+                    })
+                    .map(|(index, _)| index)
+                    .collect();
+                if newly_activated.is_empty() {
+                    break;
+                }
+                for index in &newly_activated {
+                    activated[*index] = true;
+                }
+            }
+        }
+
+        entries
+            .iter()
+            .zip(activated)
+            .filter(|(_, is_activated)| *is_activated)
+            .map(|(entry, _)| entry)
+            .collect()
+    }
+
+    /// 构建世界书内容，按条目的插入位置分组
+    fn build_worldbook_content(
+        &self,
+        character_book: &CharacterBook,
+        chat_history: &[ChatMessage],
+    ) -> Result<WorldBookSections, String> {
+        let mut sections = WorldBookSections::default();
+        let content = &mut sections.after_char;
 
         // 世界书基本信息
         if let Some(name) = &character_book.name {
@@ -337,43 +720,78 @@ impl ContextBuilder {
             character_book.entries.len()
         ));
 
+        let scan_depth = character_book
+            .scan_depth
+            .map(|depth| depth.max(0) as usize)
+            .unwrap_or(DEFAULT_SCAN_DEPTH);
+        let activated_entries = Self::activate_entries(
+            &character_book.entries,
+            chat_history,
+            scan_depth,
+            character_book.recursive_scanning.unwrap_or(false),
+        );
+        content.push_str(&format!(
+            "  activated_entries: {}\n",
+            activated_entries.len()
+        ));
+
         // 条目内容（按重要性排序）
         content.push_str("  entries:\n");
         let mut processed_entries = Vec::new();
 
-        for (index, entry) in character_book.entries.iter().enumerate() {
+        for (index, entry) in activated_entries.into_iter().enumerate() {
             let entry_json =
                 serde_json::to_value(entry).map_err(|e| format!("序列化条目失败: {}", e))?;
             let entry_obj = entry_json.as_object().ok_or("条目不是对象类型")?;
             let entry_content = self.serialize_worldbook_entry(entry_obj, index)?;
             let token_count = self.count_tokens(&entry_content);
             let importance_score = self.calculate_entry_importance(entry_obj);
+            if self
+                .options
+                .min_importance
+                .is_some_and(|min_importance| importance_score < min_importance)
+            {
+                continue;
+            }
 
-            processed_entries.push(ProcessedWorldBookEntry {
-                entry: entry_json,
-                token_count,
-                importance_score,
-            });
+            processed_entries.push((
+                WorldBookPosition::of(entry),
+                ProcessedWorldBookEntry {
+                    entry: entry_json,
+                    token_count,
+                    importance_score,
+                },
+            ));
         }
 
         // 按重要性排序
-        processed_entries
-            .sort_by(|a, b| b.importance_score.partial_cmp(&a.importance_score).unwrap());
+        processed_entries.sort_by(|a, b| {
+            b.1.importance_score
+                .partial_cmp(&a.1.importance_score)
+                .unwrap()
+        });
 
         // 输出条目（考虑 Token 限制）
         let mut used_tokens = 0;
-        for processed_entry in processed_entries {
+        for (position, processed_entry) in processed_entries {
             if used_tokens + processed_entry.token_count <= self.token_budget.worldbook_reserved {
                 let entry_content = self.serialize_worldbook_entry(
                     processed_entry.entry.as_object().unwrap(),
                     0, // index 在这里不重要
                 )?;
-                content.push_str(&entry_content);
                 used_tokens += processed_entry.token_count;
+                sections.included.push(processed_entry);
+                match position {
+                    WorldBookPosition::BeforeChar => sections.before_char.push_str(&entry_content),
+                    WorldBookPosition::AfterChar => sections.after_char.push_str(&entry_content),
+                    WorldBookPosition::AtDepth(depth) => {
+                        sections.at_depth.push((depth, entry_content))
+                    }
+                }
             }
         }
 
-        Ok(content)
+        Ok(sections)
     }
 
     /// 序列化世界书条目
@@ -455,31 +873,94 @@ impl ContextBuilder {
         score
     }
 
-    /// 构建历史消息（智能裁剪）
+    /// 构建历史消息（智能裁剪），返回保留的消息以及是否裁掉了较早的历史。
+    /// 启用智能裁剪时，在保留的历史前插入一条说明被省略内容的 system 消息；
+    /// 启用 preserve_first_message 时，开头的开场白先占用预算并始终保留
     fn build_history_messages(
         &self,
         chat_history: &[ChatMessage],
+        character_name: &str,
         token_limit: usize,
-    ) -> Result<Vec<OpenAIMessage>, String> {
-        let mut messages = Vec::new();
-        let mut used_tokens = 0;
-        let grouped_messages = Self::group_history_messages(chat_history);
+    ) -> Result<(Vec<OpenAIMessage>, bool), String> {
+        let mut grouped_messages = Self::group_history_messages(chat_history);
+        for message in grouped_messages.iter_mut().flatten() {
+            message.content = substitute_character_macros(
+                &message.content,
+                character_name,
+                &self.options.user_name,
+            );
+        }
 
-        for group in grouped_messages.iter().rev() {
-            let group_tokens = group
-                .iter()
-                .map(|message| self.count_message_tokens(message))
-                .sum::<usize>();
+        let group_tokens: Vec<usize> = grouped_messages
+            .iter()
+            .map(|group| self.count_messages_tokens(group))
+            .collect();
+
+        let preserve_first = self.options.preserve_first_message
+            && grouped_messages
+                .first()
+                .and_then(|group| group.first())
+                .is_some_and(|message| message.role == "assistant")
+            && group_tokens[0] <= token_limit;
+        let first_kept = usize::from(preserve_first);
+
+        // 从最新的消息组往前保留，first_kept..kept_from 之间的组都被裁掉
+        let mut kept_from = grouped_messages.len();
+        let mut used_tokens = if preserve_first { group_tokens[0] } else { 0 };
+        while kept_from > first_kept && used_tokens + group_tokens[kept_from - 1] <= token_limit {
+            kept_from -= 1;
+            used_tokens += group_tokens[kept_from];
+        }
 
-            if used_tokens + group_tokens > token_limit {
-                break;
-            }
+        if kept_from == first_kept {
+            return Ok((grouped_messages.into_iter().flatten().collect(), false));
+        }
 
-            messages.splice(0..0, group.iter().cloned());
-            used_tokens += group_tokens;
+        let mut summary_note = None;
+        if self.options.enable_smart_truncation {
+            // 说明消息本身也占预算，放不下时继续裁掉最旧的保留组
+            loop {
+                let note = Self::truncation_note(
+                    grouped_messages[first_kept..kept_from]
+                        .iter()
+                        .map(Vec::len)
+                        .sum(),
+                    group_tokens[first_kept..kept_from].iter().sum(),
+                );
+                let note_tokens = self.count_message_tokens(&note);
+                if used_tokens + note_tokens <= token_limit || kept_from == grouped_messages.len() {
+                    summary_note = Some(note);
+                    break;
+                }
+                used_tokens -= group_tokens[kept_from];
+                kept_from += 1;
+            }
         }
 
-        Ok(messages)
+        let kept = grouped_messages.split_off(kept_from);
+        let messages = grouped_messages
+            .into_iter()
+            .take(first_kept)
+            .flatten()
+            .chain(summary_note)
+            .chain(kept.into_iter().flatten())
+            .collect();
+        Ok((messages, true))
+    }
+
+    fn truncation_note(omitted_messages: usize, omitted_tokens: usize) -> OpenAIMessage {
+        OpenAIMessage {
+            role: "system".to_string(),
+            content: format!(
+                "[Earlier conversation summarized: {} messages omitted (~{} tokens)]",
+                omitted_messages, omitted_tokens
+            ),
+            name: None,
+            reasoning_content: None,
+            tool_calls: None,
+            tool_call_id: None,
+            images: None,
+        }
     }
 
     fn group_history_messages(chat_history: &[ChatMessage]) -> Vec<Vec<OpenAIMessage>> {
@@ -547,6 +1028,7 @@ impl ContextBuilder {
             reasoning_content: message.reasoning_content.clone(),
             tool_calls: message.tool_calls.clone(),
             tool_call_id: message.tool_call_id.clone(),
+            images: message.images.clone(),
         }
     }
 
@@ -561,26 +1043,39 @@ impl ContextBuilder {
         if let Some(task) = self.options.placeholders.get("{{TASK}}") {
             result = result.replace("{{TASK}}", task);
         }
-        if let Some(instructions) = self.options.placeholders.get("{{INSTRUCTIONS}}") {
-            result = result.replace("{{INSTRUCTIONS}}", instructions);
-        }
 
-        // 替换角色相关占位符
+        // 替换角色相关占位符，{{char}} / {{user}} 统一交给宏替换处理
         result = result.replace("{{CHARACTER_NAME}}", &character_data.card.data.name);
 
-        result
+        substitute_character_macros(
+            &result,
+            &character_data.card.data.name,
+            &self.options.user_name,
+        )
+    }
+
+    /// 与当前模型匹配的 Token 计数器
+    fn token_counter(&self) -> &'static TokenCounter {
+        get_token_counter_for_model(self.options.model.as_deref())
     }
 
     /// 计算 Token 数量
     fn count_tokens(&self, text: &str) -> usize {
-        let counter = get_token_counter();
-        counter.count_tokens(text).token_count
+        self.token_counter().count_tokens(text).token_count
     }
 
     /// 计算消息的 Token 数量
     fn count_message_tokens(&self, message: &OpenAIMessage) -> usize {
-        let counter = get_token_counter();
-        let content = serde_json::to_string(message).unwrap_or_default();
+        let counter = self.token_counter();
+        let content = if message.images.is_some() {
+            serde_json::to_string(&OpenAIMessage {
+                images: None,
+                ..message.clone()
+            })
+        } else {
+            serde_json::to_string(message)
+        }
+        .unwrap_or_default();
         counter.count_tokens(&content).token_count
     }
 
@@ -602,40 +1097,570 @@ pub fn create_context_builder(options: ContextBuilderOptions) -> ContextBuilder
 
 // ====================== Tauri命令 ======================
 
-/// 构建上下文（用于测试）
-#[tauri::command]
-pub async fn build_context(
-    app_handle: tauri::AppHandle,
-    character_uuid: String,
-    token_limit: Option<usize>,
+/// 读取构建上下文所需的角色、聊天历史（优先使用已加载的会话）与构建选项
+fn load_context_inputs(
+    app_handle: &tauri::AppHandle,
+    character_uuid: &str,
     role_id: Option<String>,
-) -> Result<BuiltContextResult, String> {
+) -> Result<(CharacterData, Vec<ChatMessage>, ContextBuilderOptions), String> {
     let (character_data, chat_history) = if let Some(session) =
-        SESSION_MANAGER.get_session(&character_uuid)
+        SESSION_MANAGER.get_session(character_uuid)
     {
         (session.character_data.clone(), session.chat_history.clone())
     } else {
-        let character_data = CharacterStorage::get_character_by_uuid(&app_handle, &character_uuid)?
+        let character_data = CharacterStorage::get_character_by_uuid(app_handle, character_uuid)?
             .ok_or_else(|| format!("角色 {} 不存在", character_uuid))?;
-        let history = ChatHistoryManager::new(&app_handle, &character_uuid).load_history()?;
+        let history = ChatHistoryManager::new(app_handle, character_uuid).load_history()?;
         (character_data, history)
     };
 
     let mut options = if let Some(requested_role_id) = role_id {
-        let (_, role) = AIConfigService::resolve_role(&app_handle, Some(&requested_role_id))?;
+        let (_, role) = AIConfigService::resolve_role(app_handle, Some(&requested_role_id))?;
         let mut resolved_options = ContextBuilderOptions::default();
         resolved_options.ai_role = role.context_role_template;
         resolved_options.ai_task = role.context_task_template;
-        resolved_options.ai_instructions = role.context_instructions_template;
+        resolved_options.instructions = role.context_instructions_template;
         resolved_options.tools_enabled = role.tools_enabled;
         resolved_options
     } else {
         ContextBuilderOptions::default()
     };
 
+    AIConfigService::apply_context_settings(app_handle, &mut options)?;
+    options.model =
+        ApiConfigService::get_default_api_config(app_handle)?.map(|config| config.model);
+    options.linked_characters =
+        CharacterStorage::resolve_linked_characters(app_handle, &character_data);
+
+    Ok((character_data, chat_history, options))
+}
+
+/// 构建上下文（用于测试）
+#[tauri::command]
+pub async fn build_context(
+    app_handle: tauri::AppHandle,
+    character_uuid: String,
+    token_limit: Option<usize>,
+    role_id: Option<String>,
+) -> Result<BuiltContextResult, String> {
+    let (character_data, chat_history, mut options) =
+        load_context_inputs(&app_handle, &character_uuid, role_id)?;
     if let Some(limit) = token_limit {
         options.token_limit = limit;
     }
 
     ContextBuilder::new(options).build_full_context(&character_data, &chat_history, None)
 }
+
+/// 分析角色上下文的 token 预算：各部分占用及每个世界书条目的 token 数（从大到小）
+#[tauri::command]
+pub async fn analyze_token_budget(
+    app_handle: tauri::AppHandle,
+    uuid: String,
+) -> Result<TokenBudgetBreakdown, String> {
+    let (character_data, chat_history, options) = load_context_inputs(&app_handle, &uuid, None)?;
+    ContextBuilder::new(options).analyze_token_budget(&character_data, &chat_history)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::character_storage::test_support;
+    use crate::persona::Persona;
+    use serde_json::json;
+
+    fn sample_character(name: &str) -> CharacterData {
+        let mut character = test_support::sample_character("test-uuid");
+        character.card.data.name = name.to_string();
+        character
+    }
+
+    #[test]
+    fn custom_instructions_appear_in_system_message() {
+        let options = ContextBuilderOptions {
+            instructions: "Always answer in English as {{char}} talking to {{user}}.".to_string(),
+            ..ContextBuilderOptions::default()
+        };
+        let builder = ContextBuilder::new(options);
+
+        let messages = builder
+            .build_system_messages(&sample_character("Alice"))
+            .expect("system messages should build");
+
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0]
+            .content
+            .contains("Always answer in English as Alice talking to 用户."));
+    }
+
+    #[test]
+    fn persona_block_is_appended_when_configured() {
+        let builder = ContextBuilder::new(ContextBuilderOptions {
+            persona: Some(Persona {
+                name: "Bob".to_string(),
+                description: "A novelist who writes fantasy.".to_string(),
+            }),
+            ..ContextBuilderOptions::default()
+        });
+
+        let content = builder
+            .build_system_messages(&sample_character("Alice"))
+            .expect("system messages should build")
+            .remove(0)
+            .content;
+
+        assert!(content.contains("persona:\n  name: \"Bob\"\n"));
+        assert!(content.contains("    A novelist who writes fantasy.\n"));
+
+        let without_persona = ContextBuilder::new(ContextBuilderOptions::default())
+            .build_system_messages(&sample_character("Alice"))
+            .expect("system messages should build");
+        assert!(!without_persona[0].content.contains("persona:"));
+    }
+
+    #[test]
+    fn user_macro_in_instructions_uses_the_user_name() {
+        let builder = ContextBuilder::new(ContextBuilderOptions {
+            instructions: "Write for {{user}}, not for {{Char}}.".to_string(),
+            user_name: "Bob".to_string(),
+            ..ContextBuilderOptions::default()
+        });
+
+        let content = builder
+            .build_system_messages(&sample_character("Alice"))
+            .expect("system messages should build")
+            .into_iter()
+            .map(|message| message.content)
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        assert!(content.contains("Write for Bob, not for Alice."));
+    }
+
+    #[test]
+    fn character_macros_are_replaced_in_description() {
+        let mut character = sample_character("Alice");
+        character.card.data.description =
+            "{{char}} guards {{User}}; {{ CHAR }} never leaves {{user}} alone.".to_string();
+        let builder = ContextBuilder::new(ContextBuilderOptions {
+            user_name: "Bob".to_string(),
+            ..ContextBuilderOptions::default()
+        });
+
+        let content = builder
+            .build_character_content(&character)
+            .expect("character content should build");
+
+        assert!(content.contains("Alice guards Bob; Alice never leaves Bob alone."));
+        assert!(!content.to_lowercase().contains("{{"));
+    }
+
+    #[test]
+    fn full_context_reports_tokens_for_world_book_character() {
+        let mut character = sample_character("Alice");
+        character.card.data.description = "A wandering swordswoman.".to_string();
+        character.card.data.character_book = Some(
+            serde_json::from_value(json!({
+                "name": "Kingdom Lore",
+                "entries": [{
+                    "keys": ["kingdom"],
+                    "content": "The kingdom of Aster is ruled by an old king.",
+                    "enabled": true,
+                    "insertion_order": 0
+                }]
+            }))
+            .expect("sample world book should deserialize"),
+        );
+        let history = vec![ChatMessage {
+            role: "user".to_string(),
+            content: "Tell me about the kingdom.".to_string(),
+            images: None,
+            timestamp: None,
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+            reasoning_content: None,
+            swipes: Vec::new(),
+            active_swipe: 0,
+        }];
+
+        let result = ContextBuilder::new(ContextBuilderOptions::default())
+            .build_full_context(&character, &history, None)
+            .expect("context should build");
+
+        let allocation = &result.token_allocation;
+        assert!(allocation.system > 0);
+        assert!(allocation.character > 0);
+        assert!(allocation.worldbook > 0);
+        assert!(allocation.history > 0);
+        assert_eq!(
+            result.total_tokens,
+            allocation.system + allocation.character + allocation.worldbook + allocation.history
+        );
+        assert_eq!(result.assistant_messages.len(), 2);
+    }
+
+    fn keyword_entry(key: &str, extensions: serde_json::Value) -> WorldBookEntry {
+        serde_json::from_value(json!({
+            "keys": [key],
+            "content": format!("lore about {key}"),
+            "extensions": extensions,
+            "enabled": true,
+            "insertion_order": 0
+        }))
+        .expect("sample entry should deserialize")
+    }
+
+    fn user_message(content: &str) -> ChatMessage {
+        ChatMessage {
+            role: "user".to_string(),
+            content: content.to_string(),
+            name: None,
+            reasoning_content: None,
+            tool_calls: None,
+            tool_call_id: None,
+            images: None,
+            timestamp: None,
+            swipes: Vec::new(),
+            active_swipe: 0,
+        }
+    }
+
+    #[test]
+    fn truncated_history_starts_with_summary_note() {
+        let history: Vec<ChatMessage> = (0..20)
+            .map(|index| user_message(&format!("Message number {index} about the kingdom.")))
+            .collect();
+        let builder = ContextBuilder::new(ContextBuilderOptions::default());
+
+        let (messages, truncated) = builder
+            .build_history_messages(&history, "Alice", 200)
+            .expect("history should build");
+
+        assert!(truncated);
+        assert_eq!(messages[0].role, "system");
+        assert!(messages[0]
+            .content
+            .starts_with("[Earlier conversation summarized: "));
+        let kept = messages.len() - 1;
+        assert!(messages[0]
+            .content
+            .contains(&format!("{} messages omitted", history.len() - kept)));
+        assert_eq!(
+            messages.last().unwrap().content,
+            "Message number 19 about the kingdom."
+        );
+        assert!(builder.count_messages_tokens(&messages) <= 200);
+
+        let plain = ContextBuilder::new(ContextBuilderOptions {
+            enable_smart_truncation: false,
+            ..ContextBuilderOptions::default()
+        });
+        let (messages, truncated) = plain
+            .build_history_messages(&history, "Alice", 200)
+            .expect("history should build");
+        assert!(truncated);
+        assert!(messages.iter().all(|message| message.role == "user"));
+    }
+
+    #[test]
+    fn first_greeting_survives_truncation() {
+        let mut history = vec![ChatMessage {
+            role: "assistant".to_string(),
+            ..user_message("The gates of Aster close at dusk.")
+        }];
+        history.extend(
+            (0..20)
+                .map(|index| user_message(&format!("Message number {index} about the kingdom."))),
+        );
+        let builder = ContextBuilder::new(ContextBuilderOptions {
+            enable_smart_truncation: false,
+            ..ContextBuilderOptions::default()
+        });
+
+        let (messages, truncated) = builder
+            .build_history_messages(&history, "Alice", 150)
+            .expect("history should build");
+
+        assert!(truncated);
+        assert_eq!(messages[0].content, "The gates of Aster close at dusk.");
+        assert_ne!(messages[1].content, "Message number 0 about the kingdom.");
+        assert_eq!(
+            messages.last().unwrap().content,
+            "Message number 19 about the kingdom."
+        );
+        assert!(messages.len() < history.len());
+        assert!(builder.count_messages_tokens(&messages) <= 150);
+    }
+
+    #[test]
+    fn summary_note_follows_preserved_greeting() {
+        let mut history = vec![ChatMessage {
+            role: "assistant".to_string(),
+            ..user_message("The gates of Aster close at dusk.")
+        }];
+        history.extend(
+            (0..20)
+                .map(|index| user_message(&format!("Message number {index} about the kingdom."))),
+        );
+        let builder = ContextBuilder::new(ContextBuilderOptions {
+            enable_smart_truncation: true,
+            preserve_first_message: true,
+            ..ContextBuilderOptions::default()
+        });
+
+        let (messages, truncated) = builder
+            .build_history_messages(&history, "Alice", 200)
+            .expect("history should build");
+
+        assert!(truncated);
+        assert_eq!(messages[0].content, "The gates of Aster close at dusk.");
+        assert_eq!(messages[1].role, "system");
+        assert!(messages[1]
+            .content
+            .starts_with("[Earlier conversation summarized: "));
+        let kept = messages.len() - 2;
+        assert!(messages[1]
+            .content
+            .contains(&format!("{} messages omitted", history.len() - 1 - kept)));
+        assert!(messages[2..].iter().all(|message| message.role == "user"));
+        assert_eq!(
+            messages.last().unwrap().content,
+            "Message number 19 about the kingdom."
+        );
+        assert!(builder.count_messages_tokens(&messages) <= 200);
+    }
+
+    #[test]
+    fn linked_character_names_appear_in_built_context() {
+        let builder = ContextBuilder::new(ContextBuilderOptions {
+            linked_characters: vec![crate::backend::domain::LinkedCharacterSummary {
+                name: "Bob".to_string(),
+                description: "Alice's older brother, a blacksmith.".to_string(),
+            }],
+            ..ContextBuilderOptions::default()
+        });
+
+        let result = builder
+            .build_full_context(&sample_character("Alice"), &[], None)
+            .expect("context should build");
+
+        let linked_message = result
+            .assistant_messages
+            .iter()
+            .find(|message| message.content.starts_with("linked_characters:"))
+            .expect("linked characters should be injected");
+        assert!(linked_message.content.contains("name: \"Bob\""));
+        assert!(linked_message.content.contains("blacksmith"));
+
+        let without_links = ContextBuilder::new(ContextBuilderOptions::default())
+            .build_full_context(&sample_character("Alice"), &[], None)
+            .expect("context should build");
+        assert!(without_links
+            .assistant_messages
+            .iter()
+            .all(|message| !message.content.starts_with("linked_characters:")));
+    }
+
+    #[test]
+    fn activate_entries_respects_case_sensitivity() {
+        let mut sensitive = keyword_entry("Aster", json!({}));
+        sensitive.case_sensitive = Some(true);
+        let insensitive = keyword_entry("Kingdom", json!({}));
+        let entries = vec![sensitive, insensitive];
+        let history = vec![user_message("the kingdom of aster")];
+
+        let activated = ContextBuilder::activate_entries(&entries, &history, 2, false);
+
+        assert_eq!(activated.len(), 1);
+        assert_eq!(activated[0].keys, vec!["Kingdom".to_string()]);
+    }
+
+    #[test]
+    fn activate_entries_respects_whole_word_matching() {
+        let whole_word = keyword_entry("king", json!({ "match_whole_words": true }));
+        let partial = keyword_entry("dom", json!({}));
+        let mut constant = keyword_entry("never", json!({}));
+        constant.constant = Some(true);
+        let entries = vec![whole_word, partial, constant];
+
+        let history = vec![user_message("The kingdom stands.")];
+        let activated = ContextBuilder::activate_entries(&entries, &history, 2, false);
+        let keys = activated
+            .iter()
+            .map(|entry| entry.keys[0].as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec!["dom", "never"]);
+
+        let history = vec![user_message("The king stands."), user_message("Hello")];
+        assert_eq!(
+            ContextBuilder::activate_entries(&entries, &history, 2, false).len(),
+            2
+        );
+        assert_eq!(
+            ContextBuilder::activate_entries(&entries, &history, 1, false).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn recursive_scanning_honors_prevent_recursion() {
+        let mut kingdom = keyword_entry("Aster", json!({}));
+        kingdom.content = "The kingdom is ruled by Rowan.".to_string();
+        let mut ruler = keyword_entry("Rowan", json!({}));
+        ruler.content = "Rowan rules over Aster.".to_string();
+        let history = vec![user_message("Tell me about Aster.")];
+
+        let entries = vec![kingdom.clone(), ruler.clone()];
+        assert_eq!(
+            ContextBuilder::activate_entries(&entries, &history, 2, false).len(),
+            1
+        );
+        assert_eq!(
+            ContextBuilder::activate_entries(&entries, &history, 2, true).len(),
+            2
+        );
+
+        kingdom.extensions = json!({ "prevent_recursion": true });
+        let entries = vec![kingdom, ruler];
+        let activated = ContextBuilder::activate_entries(&entries, &history, 2, true);
+        assert_eq!(activated.len(), 1);
+        assert_eq!(activated[0].keys, vec!["Aster".to_string()]);
+    }
+
+    #[test]
+    fn min_importance_drops_low_score_entries() {
+        let mut important = keyword_entry("Aster", json!({}));
+        important.priority = Some(10);
+        let minor = keyword_entry("Rowan", json!({}));
+        let book: CharacterBook = serde_json::from_value(json!({
+            "entries": [important, minor]
+        }))
+        .unwrap();
+        let history = vec![user_message("Aster and Rowan")];
+
+        let builder = ContextBuilder::new(ContextBuilderOptions::default());
+        let content = builder
+            .build_worldbook_content(&book, &history)
+            .unwrap()
+            .after_char;
+        assert!(content.contains("lore about Aster"));
+        assert!(content.contains("lore about Rowan"));
+
+        let builder = ContextBuilder::new(ContextBuilderOptions {
+            min_importance: Some(5.0),
+            ..ContextBuilderOptions::default()
+        });
+        let content = builder
+            .build_worldbook_content(&book, &history)
+            .unwrap()
+            .after_char;
+        assert!(content.contains("lore about Aster"));
+        assert!(!content.contains("lore about Rowan"));
+    }
+
+    #[test]
+    fn token_budget_breakdown_sums_to_reported_totals() {
+        let mut character = sample_character("Alice");
+        character.card.data.description = "A wandering swordswoman.".to_string();
+        let mut bloated = keyword_entry("Aster", json!({}));
+        bloated.content = "The kingdom of Aster keeps long records. ".repeat(20);
+        bloated.constant = Some(true);
+        let mut small = keyword_entry("Rowan", json!({}));
+        small.constant = Some(true);
+        character.card.data.character_book = Some(
+            serde_json::from_value(json!({ "name": "Lore", "entries": [small, bloated] }))
+                .expect("sample world book should deserialize"),
+        );
+        let builder = ContextBuilder::new(ContextBuilderOptions::default());
+
+        let breakdown = builder
+            .analyze_token_budget(&character, &[])
+            .expect("breakdown should build");
+        let context = builder
+            .build_full_context(&character, &[], None)
+            .expect("context should build");
+
+        assert_eq!(breakdown.worldbook_entries.len(), 2);
+        assert!(
+            breakdown.worldbook_entries[0].token_count > breakdown.worldbook_entries[1].token_count
+        );
+        assert!(breakdown.worldbook_entries[0].entry["content"]
+            .as_str()
+            .unwrap()
+            .starts_with("The kingdom of Aster"));
+        let entry_tokens: usize = breakdown
+            .worldbook_entries
+            .iter()
+            .map(|entry| entry.token_count)
+            .sum();
+        assert!(entry_tokens <= context.token_allocation.worldbook);
+        assert_eq!(
+            breakdown.token_allocation.worldbook,
+            context.token_allocation.worldbook
+        );
+        assert_eq!(breakdown.total_tokens, context.total_tokens);
+
+        // 单独取出世界书消息中 Rowan 条目的文本计数，与明细中的数值对照
+        let worldbook_message = context
+            .assistant_messages
+            .iter()
+            .find(|message| message.content.starts_with("worldbook:"))
+            .expect("world book message should be built");
+        let rowan_block = worldbook_message
+            .content
+            .split_inclusive("    }\n")
+            .find(|block| block.contains("lore about Rowan"))
+            .expect("Rowan entry should be serialized");
+        let rowan_block = &rowan_block[rowan_block.find("    - {").unwrap()..];
+        assert_eq!(
+            breakdown.worldbook_entries[1].token_count,
+            get_token_counter_for_model(None)
+                .count_tokens(rowan_block)
+                .token_count
+        );
+    }
+
+    #[test]
+    fn at_depth_entries_are_inserted_into_history() {
+        let mut character = sample_character("Alice");
+        let mut timely = keyword_entry("Aster", json!({ "depth": 2 }));
+        timely.position = Some("at_depth".to_string());
+        let mut prologue = keyword_entry("Rowan", json!({}));
+        prologue.position = Some("before_char".to_string());
+        character.card.data.character_book = Some(
+            serde_json::from_value(json!({ "entries": [timely, prologue] }))
+                .expect("sample world book should deserialize"),
+        );
+        let history = vec![
+            user_message("Hello"),
+            user_message("Where is Rowan?"),
+            user_message("Tell me about Aster."),
+            user_message("Anything else about Rowan?"),
+        ];
+
+        let result = ContextBuilder::new(ContextBuilderOptions::default())
+            .build_full_context(&character, &history, None)
+            .expect("context should build");
+
+        let messages = &result.history_messages;
+        assert_eq!(messages.len(), history.len() + 1);
+        let inserted = &messages[messages.len() - 3];
+        assert_eq!(inserted.role, "user");
+        assert!(inserted.content.starts_with("worldbook_entry:"));
+        assert!(inserted.content.contains("lore about Aster"));
+        assert_eq!(messages[messages.len() - 2].content, "Tell me about Aster.");
+
+        assert!(result.assistant_messages[0]
+            .content
+            .starts_with("worldbook_before_char:"));
+        assert!(result.assistant_messages[0]
+            .content
+            .contains("lore about Rowan"));
+        assert!(result
+            .assistant_messages
+            .iter()
+            .skip(1)
+            .all(|message| !message.content.contains("lore about")));
+    }
+}
@@ -1,10 +1,11 @@
-use crate::ai_chat::{MessageRole, ToolCallData};
+use crate::ai_chat::{MessageRole, StreamTestChunk, ToolCallData};
 use crate::backend::domain::{
-    CharacterLoadedPayload, CharacterUpdateType, CharacterUpdatedPayload, ChatHistoryLoadedPayload,
-    ContextBuiltPayload, MessageReasoningDeltaPayload, MessageReceivedPayload, MessageSentPayload,
-    MessageStreamDeltaPayload, ReasoningDeltaKind, SessionInfo, SessionUnloadReason,
-    SessionUnloadedPayload, TokenStatsPayload, TokenUsageStats, ToolExecutedPayload,
-    ToolExecutionPhase, ToolExecutionStatusPayload,
+    CharacterLoadedPayload, CharacterUpdateType, CharacterUpdatedPayload, ChatErrorPayload,
+    ChatHistoryLoadedPayload, ContextBuiltPayload, MessageReasoningDeltaPayload,
+    MessageReceivedPayload, MessageSentPayload, MessageStreamDeltaPayload,
+    MessageStreamDonePayload, MessageStreamErrorPayload, ReasoningDeltaKind, SessionInfo,
+    SessionUnloadReason, SessionUnloadedPayload, TokenStatsPayload, TokenUsageStats,
+    ToolExecutedPayload, ToolExecutionPhase, ToolExecutionStatusPayload,
 };
 use crate::character_storage::CharacterData;
 use crate::chat_history::ChatMessage;
@@ -77,6 +78,7 @@ impl EventEmitter {
         message: &ChatMessage,
         target_message_id: Option<&str>,
         intermediate_messages: Option<Vec<ChatMessage>>,
+        system_fingerprint: Option<String>,
     ) -> Result<(), String> {
         let payload = MessageReceivedPayload {
             uuid: uuid.to_string(),
@@ -84,6 +86,7 @@ impl EventEmitter {
             target_message_id: target_message_id.map(|value| value.to_string()),
             timestamp: chrono::Utc::now().timestamp(),
             intermediate_messages,
+            system_fingerprint,
         };
 
         app.emit("message-received", &payload)
@@ -117,6 +120,68 @@ impl EventEmitter {
         Ok(())
     }
 
+    /// 发送流式生成结束事件（携带 finish_reason 与完整内容）
+    pub fn send_message_stream_done(
+        app: &AppHandle,
+        uuid: &str,
+        target_message_id: &str,
+        finish_reason: &str,
+        content: &str,
+    ) -> Result<(), String> {
+        let payload = MessageStreamDonePayload {
+            uuid: uuid.to_string(),
+            target_message_id: target_message_id.to_string(),
+            finish_reason: finish_reason.to_string(),
+            content: content.to_string(),
+            timestamp: chrono::Utc::now().timestamp(),
+        };
+
+        app.emit("message-stream-done", &payload)
+            .map_err(|e| format!("发送流式结束事件失败: {}", e))?;
+
+        Ok(())
+    }
+
+    /// 发送流式生成失败事件
+    pub fn send_message_stream_error(
+        app: &AppHandle,
+        uuid: &str,
+        target_message_id: &str,
+        error: &str,
+    ) -> Result<(), String> {
+        let payload = MessageStreamErrorPayload {
+            uuid: uuid.to_string(),
+            target_message_id: target_message_id.to_string(),
+            error: error.to_string(),
+            timestamp: chrono::Utc::now().timestamp(),
+        };
+
+        app.emit("message-stream-error", &payload)
+            .map_err(|e| format!("发送流式错误事件失败: {}", e))?;
+
+        Ok(())
+    }
+
+    /// 发送 AI 请求失败事件
+    pub fn send_chat_error(
+        app: &AppHandle,
+        uuid: &str,
+        status: Option<u16>,
+        error: &str,
+    ) -> Result<(), String> {
+        let payload = ChatErrorPayload {
+            uuid: uuid.to_string(),
+            status,
+            error: error.to_string(),
+            timestamp: chrono::Utc::now().timestamp(),
+        };
+
+        app.emit("chat-error", &payload)
+            .map_err(|e| format!("发送聊天错误事件失败: {}", e))?;
+
+        Ok(())
+    }
+
     pub fn send_message_reasoning_delta(
         app: &AppHandle,
         uuid: &str,
@@ -273,6 +338,27 @@ impl EventEmitter {
         Ok(())
     }
 
+    /// 发送流式测试的内容块事件
+    pub fn send_stream_test_chunk(app: &AppHandle, chunk: &StreamTestChunk) -> Result<(), String> {
+        app.emit("stream-test-chunk", chunk)
+            .map_err(|e| format!("发送流式测试事件失败: {}", e))?;
+
+        Ok(())
+    }
+
+    /// 发送流式测试失败事件
+    pub fn send_stream_test_error(app: &AppHandle, error: &str) -> Result<(), String> {
+        let payload = serde_json::json!({
+            "error": error,
+            "timestamp": chrono::Utc::now().timestamp()
+        });
+
+        app.emit("stream-test-error", &payload)
+            .map_err(|e| format!("发送流式测试错误事件失败: {}", e))?;
+
+        Ok(())
+    }
+
     /// 发送通用进度事件（用于长时间操作）
     pub fn send_progress(
         app: &AppHandle,
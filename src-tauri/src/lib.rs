@@ -6,7 +6,9 @@ mod ai_chat;
 mod ai_config;
 mod ai_tools;
 mod api_config;
+mod app_settings;
 mod backend;
+mod card_validation;
 mod character_session;
 mod character_state;
 mod character_storage;
@@ -16,30 +18,58 @@ mod context_builder;
 mod debug_log;
 mod events;
 mod file_utils;
+mod lorebook;
+mod model_cache;
+mod persona;
 mod png_utils;
+mod provider_probe;
+mod request_limiter;
 mod token_counter;
+mod tooling_config;
 mod tools;
+mod usage_stats;
 
 use backend::infrastructure::tauri::{
-    add_ai_role, check_token_limit, cleanup_expired_sessions, clear_chat_history, continue_chat,
-    count_tokens, count_tokens_batch, create_api_config, create_character, create_chat_completion,
-    delete_ai_role, delete_api_config, delete_character, delete_chat_message, edit_chat_message,
-    execute_tool_call, export_character_card, fetch_models, generate_uuid, get_ai_config,
-    get_ai_role, get_all_ai_roles, get_all_api_configs, get_all_characters, get_all_sessions,
-    get_api_config_by_profile, get_available_tools, get_character_by_uuid, get_default_api_config,
-    get_last_chat_message, get_recent_chat_messages, get_session_info, get_tool_categories,
-    get_tools_by_category, import_character_card, import_character_card_from_bytes,
-    interrupt_ai_response, load_character_session, load_chat_history, regenerate_last_message,
-    save_all_sessions, save_chat_message, send_chat_message, set_default_ai_role,
-    set_default_api_config, test_api_connection, toggle_api_config, truncate_to_token_limit,
-    unload_character_session, update_ai_role, update_api_config, update_character,
-    update_character_background_path, update_character_field, upload_background_image,
+    add_ai_role, bake_placeholders, branch_conversation, cancel_generation, character_completeness,
+    check_token_limit, clean_world_book_keys, cleanup_expired_sessions, clear_chat_history,
+    continue_chat, count_tokens, count_tokens_batch, count_tokens_with_encoding, create_api_config,
+    create_character, create_chat_completion, delete_ai_role, delete_api_config, delete_character,
+    delete_chat_message, diff_session_history, duplicate_character, edit_chat_message,
+    estimate_generation_cost, execute_tool_call, export_all_characters, export_character_archive,
+    export_character_card, export_character_with_history, export_chat_markdown,
+    export_finetune_jsonl, export_lorebook, export_tooling_config, export_training_jsonl,
+    extract_card_avatar, extract_persona_from_card, fetch_models, find_dead_world_book_entries,
+    generate_swipe, generate_uuid, get_active_state, get_ai_config, get_ai_role, get_all_ai_roles,
+    get_all_api_configs, get_all_characters, get_all_sessions, get_all_tags,
+    get_api_config_by_profile, get_auto_cleanup_config, get_available_tools, get_character_by_uuid,
+    get_character_usage_stats, get_characters_by_tag, get_context_instructions,
+    get_default_api_config, get_greeting_count, get_last_chat_message, get_last_offered_tools,
+    get_max_concurrent_requests, get_max_reply_chars, get_max_sessions, get_max_tool_iterations,
+    get_merged_history, get_min_importance, get_model_cache_ttl, get_next_reply_prefix,
+    get_persona, get_provider_default_model, get_recent_chat_messages, get_session_info,
+    get_session_role, get_summarize_keep_recent, get_tool_categories, get_tools_by_category,
+    get_used_macros, import_all_characters, import_character_archive, import_character_card,
+    import_character_card_from_bytes, import_character_with_history, import_lorebook,
+    import_tooling_config, interrupt_ai_response, lint_greetings, load_character_session,
+    load_chat_history, load_chat_history_page, merge_world_books, optimize_background,
+    preview_prompt, probe_provider, regenerate_from_index, regenerate_last_message,
+    reorder_world_book, repair_default_api_config, reset_character_usage_stats,
+    rotate_encryption_key, save_all_sessions, save_chat_message, search_tools, select_greeting,
+    send_chat_message, set_active_swipe, set_auto_cleanup_config, set_context_instructions,
+    set_default_ai_role, set_default_api_config, set_linked_characters,
+    set_max_concurrent_requests, set_max_reply_chars, set_max_sessions, set_max_tool_iterations,
+    set_min_importance, set_model_cache_ttl, set_next_reply_prefix, set_persona, set_session_role,
+    set_summarize_keep_recent, stream_test, test_api_connection, toggle_api_config,
+    toggle_favorite, truncate_to_token_limit, unload_all_sessions, unload_character_session,
+    update_ai_role, update_api_config, update_character, update_character_background_path,
+    update_character_field, update_png_character_data, upload_background_image, validate_card_spec,
+    validate_character, verify_history_integrity,
 };
 use character_state::{
     clear_active_character, get_active_character, has_active_character, set_active_character,
 };
 use command_system::tauri_commands::{execute_command, get_available_commands, search_commands};
-use context_builder::build_context;
+use context_builder::{analyze_token_budget, build_context};
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -47,7 +77,39 @@ pub fn run() {
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
-        .setup(|_app| {
+        .setup(|app| {
+            // 按设置调整会话上限，再恢复上次退出时的活跃会话
+            match app_settings::AppSettingsService::get_max_sessions(app.handle()) {
+                Ok(max_sessions) => {
+                    let _ = character_session::SESSION_MANAGER.set_max_sessions(max_sessions);
+                }
+                Err(error) => eprintln!("读取应用设置失败: {}", error),
+            }
+            match app_settings::AppSettingsService::get_max_concurrent_requests(app.handle()) {
+                Ok(max_requests) => {
+                    let _ = request_limiter::REQUEST_LIMITER.set_max_permits(max_requests);
+                }
+                Err(error) => eprintln!("读取应用设置失败: {}", error),
+            }
+            match app_settings::AppSettingsService::get_model_cache_ttl_secs(app.handle()) {
+                Ok(ttl_secs) => {
+                    let _ = model_cache::MODEL_LIST_CACHE
+                        .set_ttl(std::time::Duration::from_secs(ttl_secs));
+                }
+                Err(error) => eprintln!("读取应用设置失败: {}", error),
+            }
+            match character_session::SESSION_MANAGER.restore_state(app.handle()) {
+                Ok(count) => crate::debug_log!("已恢复 {} 个会话", count),
+                Err(error) => eprintln!("恢复会话状态失败: {}", error),
+            }
+
+            // 定期清理过期会话
+            tauri::async_runtime::spawn(
+                backend::application::session_service::SessionService::run_auto_cleanup(
+                    app.handle().clone(),
+                ),
+            );
+
             // 初始化命令系统
             tauri::async_runtime::spawn(async {
                 command_system::tauri_commands::initialize_command_system().await;
@@ -58,15 +120,43 @@ pub fn run() {
             // 角色卡命令
             get_all_characters,
             get_character_by_uuid,
+            get_all_tags,
+            get_characters_by_tag,
             create_character,
+            duplicate_character,
+            branch_conversation,
+            toggle_favorite,
+            set_linked_characters,
             update_character,
             update_character_field,
             delete_character,
             upload_background_image,
             update_character_background_path,
+            optimize_background,
             export_character_card,
+            export_character_with_history,
+            update_png_character_data,
             import_character_card,
             import_character_card_from_bytes,
+            validate_card_spec,
+            validate_character,
+            import_character_with_history,
+            export_character_archive,
+            import_character_archive,
+            export_all_characters,
+            import_all_characters,
+            extract_card_avatar,
+            find_dead_world_book_entries,
+            clean_world_book_keys,
+            reorder_world_book,
+            merge_world_books,
+            import_lorebook,
+            export_lorebook,
+            bake_placeholders,
+            get_greeting_count,
+            lint_greetings,
+            get_used_macros,
+            character_completeness,
             // API配置命令
             get_all_api_configs,
             get_api_config_by_profile,
@@ -75,9 +165,18 @@ pub fn run() {
             update_api_config,
             delete_api_config,
             set_default_api_config,
+            repair_default_api_config,
             toggle_api_config,
+            rotate_encryption_key,
             test_api_connection,
             fetch_models,
+            get_provider_default_model,
+            get_max_concurrent_requests,
+            set_max_concurrent_requests,
+            get_model_cache_ttl,
+            set_model_cache_ttl,
+            probe_provider,
+            stream_test,
             // AI配置命令
             get_ai_config,
             get_ai_role,
@@ -85,17 +184,37 @@ pub fn run() {
             add_ai_role,
             delete_ai_role,
             set_default_ai_role,
+            get_max_tool_iterations,
+            set_max_tool_iterations,
+            get_context_instructions,
+            set_context_instructions,
+            get_persona,
+            set_persona,
+            extract_persona_from_card,
+            get_min_importance,
+            set_min_importance,
+            get_max_reply_chars,
+            set_max_reply_chars,
             get_all_ai_roles,
             // AI工具命令
             get_available_tools,
             get_tools_by_category,
+            search_tools,
             execute_tool_call,
             get_tool_categories,
+            export_tooling_config,
+            import_tooling_config,
             // AI聊天命令
             create_chat_completion,
             // 聊天历史命令
             save_chat_message,
             load_chat_history,
+            load_chat_history_page,
+            get_merged_history,
+            export_finetune_jsonl,
+            export_training_jsonl,
+            export_chat_markdown,
+            verify_history_integrity,
             clear_chat_history,
             get_last_chat_message,
             get_recent_chat_messages,
@@ -106,24 +225,49 @@ pub fn run() {
             has_active_character,
             // 角色会话管理命令
             load_character_session,
+            select_greeting,
             send_chat_message,
             unload_character_session,
+            unload_all_sessions,
             get_session_info,
+            get_active_state,
+            get_last_offered_tools,
+            set_session_role,
+            get_session_role,
+            set_next_reply_prefix,
+            get_next_reply_prefix,
+            diff_session_history,
+            estimate_generation_cost,
+            preview_prompt,
             get_all_sessions,
             save_all_sessions,
             cleanup_expired_sessions,
+            get_auto_cleanup_config,
+            set_auto_cleanup_config,
             delete_chat_message,
             edit_chat_message,
             regenerate_last_message,
+            regenerate_from_index,
+            generate_swipe,
+            set_active_swipe,
             continue_chat,
             interrupt_ai_response,
+            cancel_generation,
+            get_max_sessions,
+            set_max_sessions,
+            get_summarize_keep_recent,
+            set_summarize_keep_recent,
             // 上下文构建命令
             build_context,
+            analyze_token_budget,
             // Token 计数命令
             count_tokens,
             count_tokens_batch,
+            count_tokens_with_encoding,
             check_token_limit,
             truncate_to_token_limit,
+            get_character_usage_stats,
+            reset_character_usage_stats,
             // 命令系统
             get_available_commands,
             search_commands,
@@ -131,6 +275,14 @@ pub fn run() {
             // 通用命令
             generate_uuid
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app_handle, event| {
+            if let tauri::RunEvent::Exit = event {
+                // 退出时保存会话状态快照
+                if let Err(error) = character_session::SESSION_MANAGER.persist_state(app_handle) {
+                    eprintln!("保存会话状态失败: {}", error);
+                }
+            }
+        });
 }
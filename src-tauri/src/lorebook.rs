@@ -0,0 +1,307 @@
+use crate::character_storage::WorldBookEntry;
+use serde_json::{json, Map, Value};
+
+/// 直接映射到 WorldBookEntry 字段的 lorebook 条目字段，其余字段保存在 extensions 中
+const MAPPED_FIELDS: [&str; 11] = [
+    "uid",
+    "key",
+    "keysecondary",
+    "comment",
+    "content",
+    "constant",
+    "selective",
+    "order",
+    "disable",
+    "caseSensitive",
+    "position",
+];
+
+/// SillyTavern 在 character_book extensions 中保留驼峰写法的字段
+const CAMEL_CASE_EXTENSIONS: [&str; 2] = ["useProbability", "selectiveLogic"];
+
+fn camel_to_snake(key: &str) -> String {
+    if CAMEL_CASE_EXTENSIONS.contains(&key) {
+        return key.to_string();
+    }
+
+    let mut snake = String::with_capacity(key.len() + 4);
+    for ch in key.chars() {
+        if ch.is_ascii_uppercase() {
+            snake.push('_');
+            snake.push(ch.to_ascii_lowercase());
+        } else {
+            snake.push(ch);
+        }
+    }
+    snake
+}
+
+fn snake_to_camel(key: &str) -> String {
+    let mut camel = String::with_capacity(key.len());
+    let mut uppercase_next = false;
+    for ch in key.chars() {
+        if ch == '_' {
+            uppercase_next = true;
+        } else if uppercase_next {
+            camel.push(ch.to_ascii_uppercase());
+            uppercase_next = false;
+        } else {
+            camel.push(ch);
+        }
+    }
+    camel
+}
+
+fn string_list(value: Option<&Value>) -> Vec<String> {
+    match value {
+        Some(Value::Array(items)) => items
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect(),
+        Some(Value::String(text)) => text
+            .split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn as_i32(value: Option<&Value>) -> Option<i32> {
+    value
+        .and_then(Value::as_i64)
+        .and_then(|number| i32::try_from(number).ok())
+}
+
+/// lorebook 的数字 position 中只有 0 / 1 对应角色卡规范的 before_char / after_char
+fn position_name(position: Option<i64>) -> Option<String> {
+    match position {
+        Some(0) => Some("before_char".to_string()),
+        Some(1) => Some("after_char".to_string()),
+        _ => None,
+    }
+}
+
+fn entry_from_lorebook(index: usize, entry: &Map<String, Value>) -> WorldBookEntry {
+    let extensions: Map<String, Value> = entry
+        .iter()
+        .filter(|(key, _)| !MAPPED_FIELDS.contains(&key.as_str()) || key.as_str() == "position")
+        .map(|(key, value)| (camel_to_snake(key), value.clone()))
+        .collect();
+    let secondary_keys = string_list(entry.get("keysecondary"));
+
+    WorldBookEntry {
+        keys: string_list(entry.get("key")),
+        content: entry
+            .get("content")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        extensions: Value::Object(extensions),
+        enabled: !entry
+            .get("disable")
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+        insertion_order: as_i32(entry.get("order")).unwrap_or(100),
+        case_sensitive: entry.get("caseSensitive").and_then(Value::as_bool),
+        name: None,
+        priority: None,
+        id: as_i32(entry.get("uid")).or_else(|| i32::try_from(index).ok()),
+        comment: entry
+            .get("comment")
+            .and_then(Value::as_str)
+            .filter(|comment| !comment.is_empty())
+            .map(str::to_string),
+        selective: entry.get("selective").and_then(Value::as_bool),
+        secondary_keys: (!secondary_keys.is_empty()).then_some(secondary_keys),
+        constant: entry.get("constant").and_then(Value::as_bool),
+        position: position_name(entry.get("position").and_then(Value::as_i64)),
+    }
+}
+
+/// 解析 SillyTavern lorebook JSON，entries 可以是以序号为键的对象，也可以是数组
+pub fn parse_lorebook(json: &str) -> Result<Vec<WorldBookEntry>, String> {
+    let root: Value =
+        serde_json::from_str(json).map_err(|e| format!("解析世界书 JSON 失败: {}", e))?;
+
+    let raw_entries: Vec<&Value> = match root.get("entries") {
+        Some(Value::Array(entries)) => entries.iter().collect(),
+        Some(Value::Object(entries)) => {
+            let mut keyed: Vec<(&String, &Value)> = entries.iter().collect();
+            keyed.sort_by(|(left, _), (right, _)| {
+                let numeric = |key: &str| key.parse::<u64>().unwrap_or(u64::MAX);
+                numeric(left)
+                    .cmp(&numeric(right))
+                    .then_with(|| left.cmp(right))
+            });
+            keyed.into_iter().map(|(_, entry)| entry).collect()
+        }
+        Some(_) => return Err("世界书 entries 必须是对象或数组".to_string()),
+        None => return Err("世界书缺少 entries 字段".to_string()),
+    };
+
+    raw_entries
+        .into_iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            entry
+                .as_object()
+                .map(|entry| entry_from_lorebook(index, entry))
+                .ok_or_else(|| format!("世界书条目 {} 必须是对象", index))
+        })
+        .collect()
+}
+
+/// 导出为 SillyTavern lorebook JSON（entries 以 uid 为键）
+pub fn lorebook_to_json(entries: &[WorldBookEntry]) -> Value {
+    let mut lorebook_entries = Map::new();
+
+    for (index, entry) in entries.iter().enumerate() {
+        let uid = entry
+            .id
+            .and_then(|id| u32::try_from(id).ok())
+            .filter(|id| !lorebook_entries.contains_key(&id.to_string()))
+            .unwrap_or(index as u32);
+
+        let mut fields: Map<String, Value> = entry
+            .extensions
+            .as_object()
+            .map(|extensions| {
+                extensions
+                    .iter()
+                    .map(|(key, value)| (snake_to_camel(key), value.clone()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let position = fields.get("position").and_then(Value::as_i64).unwrap_or(
+            match entry.position.as_deref() {
+                Some("after_char") => 1,
+                Some("at_depth") => 4,
+                _ => 0,
+            },
+        );
+        let comment = entry.comment.as_ref().or(entry.name.as_ref());
+
+        fields.extend([
+            ("uid".to_string(), json!(uid)),
+            ("key".to_string(), json!(entry.keys)),
+            (
+                "keysecondary".to_string(),
+                json!(entry.secondary_keys.clone().unwrap_or_default()),
+            ),
+            (
+                "comment".to_string(),
+                json!(comment.cloned().unwrap_or_default()),
+            ),
+            ("content".to_string(), json!(entry.content)),
+            (
+                "constant".to_string(),
+                json!(entry.constant.unwrap_or(false)),
+            ),
+            (
+                "selective".to_string(),
+                json!(entry.selective.unwrap_or(false)),
+            ),
+            ("order".to_string(), json!(entry.insertion_order)),
+            ("disable".to_string(), json!(!entry.enabled)),
+            ("caseSensitive".to_string(), json!(entry.case_sensitive)),
+            ("position".to_string(), json!(position)),
+        ]);
+
+        lorebook_entries.insert(uid.to_string(), Value::Object(fields));
+    }
+
+    json!({ "entries": lorebook_entries })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lorebook_to_json, parse_lorebook};
+
+    const LOREBOOK: &str = r#"{
+        "entries": {
+            "10": {
+                "uid": 10,
+                "key": ["tavern"],
+                "keysecondary": [],
+                "comment": "Tavern",
+                "content": "A busy tavern.",
+                "constant": false,
+                "selective": true,
+                "order": 50,
+                "position": 1,
+                "disable": true,
+                "depth": 4,
+                "excludeRecursion": true,
+                "useProbability": true
+            },
+            "2": {
+                "uid": 2,
+                "key": ["castle", "keep"],
+                "keysecondary": ["king"],
+                "comment": "Castle",
+                "content": "An old castle.",
+                "constant": true,
+                "selective": false,
+                "order": 100,
+                "position": 4,
+                "disable": false,
+                "caseSensitive": true
+            }
+        }
+    }"#;
+
+    #[test]
+    fn lorebook_entries_are_ordered_and_mapped() {
+        let entries = parse_lorebook(LOREBOOK).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].id, Some(2));
+        assert_eq!(entries[0].keys, vec!["castle", "keep"]);
+        assert_eq!(entries[0].secondary_keys, Some(vec!["king".to_string()]));
+        assert_eq!(entries[0].position, None);
+        assert_eq!(entries[0].extensions["position"], 4);
+        assert_eq!(entries[1].position.as_deref(), Some("after_char"));
+        assert!(!entries[1].enabled);
+        assert_eq!(entries[1].insertion_order, 50);
+        assert_eq!(entries[1].extensions["exclude_recursion"], true);
+        assert_eq!(entries[1].extensions["useProbability"], true);
+    }
+
+    #[test]
+    fn lorebook_round_trips_through_export() {
+        let entries = parse_lorebook(LOREBOOK).unwrap();
+        let exported = lorebook_to_json(&entries);
+        let reimported = parse_lorebook(&exported.to_string()).unwrap();
+
+        assert_eq!(exported["entries"]["10"]["excludeRecursion"], true);
+        assert_eq!(
+            serde_json::to_value(&reimported).unwrap(),
+            serde_json::to_value(&entries).unwrap()
+        );
+    }
+
+    #[test]
+    fn array_entries_parse_like_keyed_entries() {
+        let keyed = parse_lorebook(LOREBOOK).unwrap();
+        let exported = lorebook_to_json(&keyed);
+        let array = serde_json::json!({
+            "entries": exported["entries"]
+                .as_object()
+                .unwrap()
+                .values()
+                .cloned()
+                .collect::<Vec<_>>()
+        });
+        let mut from_array = parse_lorebook(&array.to_string()).unwrap();
+        from_array.sort_by_key(|entry| entry.id);
+
+        assert_eq!(
+            serde_json::to_value(&from_array).unwrap(),
+            serde_json::to_value(&keyed).unwrap()
+        );
+    }
+}
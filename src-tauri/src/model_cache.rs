@@ -0,0 +1,206 @@
+use crate::api_config::ModelInfo;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_MODEL_CACHE_TTL_SECS: u64 = 300;
+
+struct CachedModels {
+    fetched_at: Instant,
+    models: Vec<ModelInfo>,
+}
+
+/// 模型列表缓存：按 profile + endpoint 区分，过期后重新拉取
+pub struct ModelListCache {
+    ttl: Mutex<Duration>,
+    entries: Mutex<HashMap<(String, String), CachedModels>>,
+}
+
+fn cache_key(profile: &str, endpoint: &str) -> (String, String) {
+    (
+        profile.to_string(),
+        endpoint.trim().trim_end_matches('/').to_string(),
+    )
+}
+
+impl ModelListCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl: Mutex::new(ttl),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+            .lock()
+            .map(|ttl| *ttl)
+            .unwrap_or(Duration::from_secs(DEFAULT_MODEL_CACHE_TTL_SECS))
+    }
+
+    pub fn set_ttl(&self, ttl: Duration) -> Result<(), String> {
+        let mut current = self
+            .ttl
+            .lock()
+            .map_err(|error| format!("锁定模型列表缓存失败: {error}"))?;
+        *current = ttl;
+        Ok(())
+    }
+
+    fn fresh(&self, key: &(String, String)) -> Option<Vec<ModelInfo>> {
+        let ttl = self.ttl();
+        let entries = self.entries.lock().ok()?;
+        entries
+            .get(key)
+            .filter(|cached| cached.fetched_at.elapsed() < ttl)
+            .map(|cached| cached.models.clone())
+    }
+
+    /// 缓存未过期时直接返回，否则（或 force 为 true 时）调用 fetch 拉取并写入缓存
+    pub async fn get_or_fetch<F, Fut>(
+        &self,
+        profile: &str,
+        endpoint: &str,
+        force: bool,
+        fetch: F,
+    ) -> Result<Vec<ModelInfo>, String>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Vec<ModelInfo>, String>>,
+    {
+        let key = cache_key(profile, endpoint);
+        if !force {
+            if let Some(models) = self.fresh(&key) {
+                return Ok(models);
+            }
+        }
+
+        let models = fetch().await?;
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.insert(
+                key,
+                CachedModels {
+                    fetched_at: Instant::now(),
+                    models: models.clone(),
+                },
+            );
+        }
+        Ok(models)
+    }
+
+    /// 丢弃某个配置的全部缓存（端点或密钥变更、配置删除时调用）
+    pub fn invalidate_profile(&self, profile: &str) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.retain(|(cached_profile, _), _| cached_profile != profile);
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref MODEL_LIST_CACHE: ModelListCache =
+        ModelListCache::new(Duration::from_secs(DEFAULT_MODEL_CACHE_TTL_SECS));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ModelListCache;
+    use crate::api_config::ModelInfo;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    fn model(id: &str) -> ModelInfo {
+        ModelInfo {
+            id: id.to_string(),
+            object: "model".to_string(),
+            owned_by: None,
+            max_tokens: None,
+            context_window: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn second_fetch_within_ttl_uses_cache() {
+        let cache = ModelListCache::new(Duration::from_secs(300));
+        let calls = &AtomicUsize::new(0);
+        let fetch = move || async move {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![model("gpt-4.1")])
+        };
+
+        let first = cache
+            .get_or_fetch("Primary", "https://api.openai.com/v1", false, fetch)
+            .await
+            .unwrap();
+        let second = cache
+            .get_or_fetch("Primary", "https://api.openai.com/v1/", false, fetch)
+            .await
+            .unwrap();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(first[0].id, second[0].id);
+
+        cache
+            .get_or_fetch("Primary", "https://api.openai.com/v1", true, fetch)
+            .await
+            .unwrap();
+        cache
+            .get_or_fetch("Primary", "https://proxy.example.com/v1", false, fetch)
+            .await
+            .unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn stale_or_invalidated_entries_are_refetched() {
+        let cache = ModelListCache::new(Duration::ZERO);
+        let calls = &AtomicUsize::new(0);
+        let fetch = move || async move {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![model("claude-sonnet")])
+        };
+
+        cache
+            .get_or_fetch("Claude", "https://api.anthropic.com", false, fetch)
+            .await
+            .unwrap();
+        cache
+            .get_or_fetch("Claude", "https://api.anthropic.com", false, fetch)
+            .await
+            .unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        cache.set_ttl(Duration::from_secs(300)).unwrap();
+        cache
+            .get_or_fetch("Claude", "https://api.anthropic.com", false, fetch)
+            .await
+            .unwrap();
+        cache.invalidate_profile("Claude");
+        cache
+            .get_or_fetch("Claude", "https://api.anthropic.com", false, fetch)
+            .await
+            .unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn failed_fetch_is_not_cached() {
+        let cache = ModelListCache::new(Duration::from_secs(300));
+
+        let error = cache
+            .get_or_fetch("Primary", "https://api.openai.com/v1", false, || async {
+                Err("获取模型列表失败: 429 Too Many Requests".to_string())
+            })
+            .await
+            .unwrap_err();
+        assert!(error.contains("429"));
+
+        let models = cache
+            .get_or_fetch("Primary", "https://api.openai.com/v1", false, || async {
+                Ok(vec![model("gpt-4.1")])
+            })
+            .await
+            .unwrap();
+        assert_eq!(models.len(), 1);
+    }
+}
@@ -0,0 +1,139 @@
+use crate::backend::domain::sessions::config::DEFAULT_USER_NAME;
+use crate::character_storage::TavernCardV2;
+use crate::file_utils::FileUtils;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// 用户人设：用于在上下文中介绍用户，并作为 {{user}} 的替换名称
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Persona {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+impl Persona {
+    /// 名称为空时视为未配置
+    pub fn is_configured(&self) -> bool {
+        !self.name.trim().is_empty()
+    }
+}
+
+/// 角色卡中可能携带用户人设提示的字段名（extensions 或 V3 附加字段）
+const PERSONA_HINT_KEYS: [&str; 3] = ["persona", "user_persona", "user_description"];
+
+fn persona_from_hint(value: &serde_json::Value) -> Option<Persona> {
+    let persona = match value {
+        serde_json::Value::String(description) => Persona {
+            name: DEFAULT_USER_NAME.to_string(),
+            description: description.trim().to_string(),
+        },
+        serde_json::Value::Object(fields) => {
+            let text = |key: &str| {
+                fields
+                    .get(key)
+                    .and_then(|value| value.as_str())
+                    .map(|value| value.trim().to_string())
+                    .unwrap_or_default()
+            };
+            let name = text("name");
+            Persona {
+                name: if name.is_empty() {
+                    DEFAULT_USER_NAME.to_string()
+                } else {
+                    name
+                },
+                description: text("description"),
+            }
+        }
+        _ => return None,
+    };
+
+    (!persona.description.is_empty()).then_some(persona)
+}
+
+/// 从角色卡的 extensions / V3 字段中提取作者预设的用户人设，没有提示时返回 None（不会自动应用）
+pub fn extract_persona_hint(card: &TavernCardV2) -> Option<Persona> {
+    let extensions = card.data.extensions.as_object();
+    let v3_fields = card.data.v3_fields.as_ref();
+
+    PERSONA_HINT_KEYS.iter().find_map(|key| {
+        extensions
+            .and_then(|fields| fields.get(*key))
+            .or_else(|| v3_fields.and_then(|fields| fields.get(*key)))
+            .and_then(persona_from_hint)
+    })
+}
+
+/// 人设服务（app_data/persona.json）
+pub struct PersonaService;
+
+impl PersonaService {
+    fn get_persona_path(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
+        let app_data_dir = FileUtils::get_app_data_dir(app_handle)?;
+        Ok(app_data_dir.join("persona.json"))
+    }
+
+    /// 读取人设，未配置时返回 None
+    pub fn load_persona(app_handle: &tauri::AppHandle) -> Result<Option<Persona>, String> {
+        let persona_path = Self::get_persona_path(app_handle)?;
+        if !persona_path.exists() {
+            return Ok(None);
+        }
+
+        let persona: Persona = FileUtils::read_json_file(&persona_path)?;
+        Ok(persona.is_configured().then_some(persona))
+    }
+
+    /// 保存人设，传入 None 或空名称时清除人设
+    pub fn save_persona(
+        app_handle: &tauri::AppHandle,
+        persona: Option<Persona>,
+    ) -> Result<(), String> {
+        let persona_path = Self::get_persona_path(app_handle)?;
+        match persona.filter(Persona::is_configured) {
+            Some(persona) => {
+                let persona = Persona {
+                    name: persona.name.trim().to_string(),
+                    description: persona.description.trim().to_string(),
+                };
+                FileUtils::write_json_file(&persona_path, &persona)
+            }
+            None if persona_path.exists() => FileUtils::delete_path(&persona_path),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract_persona_hint, Persona};
+    use crate::character_storage::test_support::sample_card;
+    use crate::character_storage::TavernCardV2;
+    use serde_json::json;
+
+    fn card_with_extensions(extensions: serde_json::Value) -> TavernCardV2 {
+        let mut card = sample_card();
+        card.data.extensions = extensions;
+        card
+    }
+
+    #[test]
+    fn extracts_persona_from_card_extension() {
+        let card = card_with_extensions(json!({
+            "persona": { "name": " Bob ", "description": "A wandering knight." }
+        }));
+        assert_eq!(
+            extract_persona_hint(&card),
+            Some(Persona {
+                name: "Bob".to_string(),
+                description: "A wandering knight.".to_string(),
+            })
+        );
+
+        let card = card_with_extensions(json!({ "user_persona": "A curious student." }));
+        assert_eq!(extract_persona_hint(&card).unwrap().name, "User");
+
+        assert_eq!(extract_persona_hint(&card_with_extensions(json!({}))), None);
+    }
+}
@@ -1,5 +1,6 @@
 use base64::{engine::general_purpose::STANDARD, Engine as _};
-use png::{Decoder, Encoder};
+use flate2::read::ZlibDecoder;
+use std::io::Read;
 
 /// PNG 元数据处理错误
 #[derive(Debug)]
@@ -55,7 +56,7 @@ impl From<base64::DecodeError> for PngMetadataError {
 pub struct PngMetadataUtils;
 
 impl PngMetadataUtils {
-    /// 从字节数组中读取角色卡数据
+    /// 从字节数组中读取角色卡数据，同时存在 ccv3 与 chara 块时优先返回 ccv3
     ///
     /// # 参数
     /// * `png_bytes` - PNG 文件字节数组
@@ -63,7 +64,7 @@ impl PngMetadataUtils {
     /// # 返回
     /// * `Ok(String)` - Base64 解码后的 JSON 字符串
     pub fn read_character_data_from_bytes(png_bytes: &[u8]) -> Result<String, PngMetadataError> {
-        // 手动解析 PNG chunks 来查找 tEXt 块
+        // 手动解析 PNG chunks 来查找 tEXt / zTXt / iTXt 块
         // PNG 格式: 8字节签名 + chunks
         // Chunk 格式: 4字节长度 + 4字节类型 + 数据 + 4字节CRC
 
@@ -72,6 +73,7 @@ impl PngMetadataUtils {
         }
 
         let mut pos = 8; // 跳过 PNG 签名
+        let mut chara_json: Option<String> = None;
 
         while pos + 12 <= png_bytes.len() {
             // 读取 chunk 长度 (大端序)
@@ -88,30 +90,42 @@ impl PngMetadataUtils {
 
             crate::debug_warn!("[DEBUG] 发现 chunk: {} (长度: {})", chunk_type_str, length);
 
-            // 检查是否是 tEXt chunk
-            if chunk_type == b"tEXt" && pos + 8 + length <= png_bytes.len() {
-                // tEXt chunk 数据: keyword\0text
+            // 检查是否是 tEXt / zTXt / iTXt chunk
+            if pos + 8 + length <= png_bytes.len() {
                 let data = &png_bytes[pos + 8..pos + 8 + length];
 
-                // 查找 null 终止符
-                if let Some(null_pos) = data.iter().position(|&b| b == 0) {
-                    let keyword = String::from_utf8_lossy(&data[..null_pos]);
-                    let text = &data[null_pos + 1..];
+                let decoded = match decode_text_chunk(chunk_type, data, &["chara", "ccv3"]) {
+                    Ok(decoded) => decoded,
+                    Err(e) => {
+                        // 损坏或过大的文本块直接跳过，不影响其它块
+                        crate::debug_warn!(
+                            "[DEBUG] 跳过无法解析的 {} chunk: {}",
+                            chunk_type_str,
+                            e
+                        );
+                        None
+                    }
+                };
 
+                if let Some((keyword, text)) = decoded {
                     crate::debug_warn!(
-                        "[DEBUG] tEXt keyword: '{}', text length: {}",
+                        "[DEBUG] {} keyword: '{}', text length: {}",
+                        chunk_type_str,
                         keyword,
                         text.len()
                     );
 
                     if keyword == "chara" || keyword == "ccv3" {
-                        crate::debug_warn!("[DEBUG] 找到角色卡 tEXt chunk!");
+                        crate::debug_warn!("[DEBUG] 找到角色卡 {} chunk!", chunk_type_str);
                         // text 应该是 Base64 编码的 JSON
-                        let text_str = String::from_utf8_lossy(text);
-                        let json_bytes = STANDARD.decode(text_str.as_bytes())?;
+                        let text_str = String::from_utf8_lossy(&text);
+                        let json_bytes = STANDARD.decode(text_str.trim().as_bytes())?;
                         let json_str = String::from_utf8(json_bytes)
                             .map_err(|_| PngMetadataError::InvalidImageFormat)?;
-                        return Ok(json_str);
+                        if keyword == "ccv3" {
+                            return Ok(json_str);
+                        }
+                        chara_json.get_or_insert(json_str);
                     }
                 }
             }
@@ -120,56 +134,334 @@ impl PngMetadataUtils {
             pos += 4 + 4 + length + 4;
         }
 
+        if let Some(json_str) = chara_json {
+            return Ok(json_str);
+        }
+
         crate::debug_warn!("[DEBUG] 遍历完所有 chunks，未找到角色卡数据");
         Err(PngMetadataError::CharaDataNotFound)
     }
 
     /// 将角色卡数据写入 PNG 字节数组
     ///
+    /// 直接拼接源文件的 chunk：保留除角色卡块以外的全部 chunk（包括其他元数据），
+    /// 并在 IEND 之前写入新的 chara 与 ccv3 块。
+    ///
     /// # 参数
     /// * `source_png_bytes` - 源 PNG 文件字节数组
-    /// * `character_json` - 角色卡 JSON 字符串
+    /// * `chara_json` - 写入 chara 块的 V2 兼容 JSON
+    /// * `ccv3_json` - 写入 ccv3 块的 JSON
     ///
     /// # 返回
     /// * `Ok(Vec<u8>)` - 包含角色卡数据的 PNG 字节数组
     pub fn write_character_data_to_bytes(
         source_png_bytes: &[u8],
-        character_json: &str,
+        chara_json: &str,
+        ccv3_json: &str,
     ) -> Result<Vec<u8>, PngMetadataError> {
-        // 读取源 PNG 数据
-        let decoder = Decoder::new(source_png_bytes);
-        let mut reader = decoder.read_info()?;
+        if !source_png_bytes.starts_with(&PNG_SIGNATURE) {
+            return Err(PngMetadataError::InvalidImageFormat);
+        }
 
-        let info = reader.info().clone();
-        let width = info.width;
-        let height = info.height;
-        let color_type = info.color_type;
-        let bit_depth = info.bit_depth;
+        let mut output_buf =
+            Vec::with_capacity(source_png_bytes.len() + (chara_json.len() + ccv3_json.len()) * 2);
+        output_buf.extend_from_slice(&PNG_SIGNATURE);
 
-        // 读取图像数据
-        let mut buf = vec![0; reader.output_buffer_size()];
-        let _info = reader.next_frame(&mut buf)?;
+        let mut found_end = false;
+        for chunk in split_chunks(source_png_bytes)? {
+            if is_character_chunk(&chunk) {
+                continue;
+            }
 
-        // 创建输出缓冲区
-        let mut output_buf = Vec::new();
-        {
-            let mut encoder = Encoder::new(&mut output_buf, width, height);
-            encoder.set_color(color_type);
-            encoder.set_depth(bit_depth);
+            if chunk.chunk_type == *b"IEND" {
+                // 将 JSON 转为 Base64 并添加 tEXt 块
+                output_buf.extend(encode_text_chunk("chara", &STANDARD.encode(chara_json)));
+                output_buf.extend(encode_text_chunk("ccv3", &STANDARD.encode(ccv3_json)));
+                found_end = true;
+            }
 
-            // 将 JSON 转为 Base64
-            let base64_data = STANDARD.encode(character_json.as_bytes());
+            output_buf.extend_from_slice(chunk.raw);
 
-            // 添加 tEXt 块
-            encoder.add_text_chunk("chara".to_string(), base64_data.clone())?;
-            encoder.add_text_chunk("ccv3".to_string(), base64_data.clone())?;
+            if found_end {
+                break;
+            }
+        }
 
-            let mut writer = encoder.write_header()?;
-            writer.write_image_data(&buf)?;
+        if !found_end {
+            return Err(PngMetadataError::InvalidImageFormat);
         }
 
         Ok(output_buf)
     }
+
+    /// 写入聊天记录块（Base64 编码的 JSONL），替换已有的聊天记录块
+    pub fn write_history_to_bytes(
+        png_bytes: &[u8],
+        history_jsonl: &str,
+    ) -> Result<Vec<u8>, PngMetadataError> {
+        replace_history_chunk(png_bytes, Some(history_jsonl))
+    }
+
+    /// 去掉聊天记录块，其余 chunk 原样保留
+    pub fn strip_history_from_bytes(png_bytes: &[u8]) -> Result<Vec<u8>, PngMetadataError> {
+        replace_history_chunk(png_bytes, None)
+    }
+
+    /// 读取聊天记录块中的 JSONL，不存在时返回 None
+    pub fn read_history_from_bytes(png_bytes: &[u8]) -> Result<Option<String>, PngMetadataError> {
+        if !png_bytes.starts_with(&PNG_SIGNATURE) {
+            return Err(PngMetadataError::InvalidImageFormat);
+        }
+
+        for chunk in split_chunks(png_bytes)? {
+            // 损坏或过大的文本块直接跳过
+            let Ok(Some((keyword, text))) =
+                decode_text_chunk(&chunk.chunk_type, chunk.data, &[HISTORY_CHUNK_KEYWORD])
+            else {
+                continue;
+            };
+            if keyword == HISTORY_CHUNK_KEYWORD {
+                let text_str = String::from_utf8_lossy(&text);
+                let jsonl_bytes = STANDARD.decode(text_str.trim().as_bytes())?;
+                let jsonl = String::from_utf8(jsonl_bytes)
+                    .map_err(|_| PngMetadataError::InvalidImageFormat)?;
+                return Ok(Some(jsonl));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// 提取角色卡中的图片本身（去掉 chara / ccv3 块的 PNG），用于头像预览
+    ///
+    /// # 参数
+    /// * `png_bytes` - PNG 文件字节数组
+    ///
+    /// # 返回
+    /// * `Ok(Vec<u8>)` - 不含角色卡数据的 PNG 字节数组
+    pub fn extract_image_bytes(png_bytes: &[u8]) -> Result<Vec<u8>, PngMetadataError> {
+        if !png_bytes.starts_with(&PNG_SIGNATURE) {
+            return Err(PngMetadataError::InvalidImageFormat);
+        }
+
+        let mut output_buf = Vec::with_capacity(png_bytes.len());
+        output_buf.extend_from_slice(&PNG_SIGNATURE);
+
+        let mut found_end = false;
+        for chunk in split_chunks(png_bytes)? {
+            if is_character_chunk(&chunk) {
+                continue;
+            }
+
+            output_buf.extend_from_slice(chunk.raw);
+            if chunk.chunk_type == *b"IEND" {
+                found_end = true;
+                break;
+            }
+        }
+
+        if !found_end {
+            return Err(PngMetadataError::InvalidImageFormat);
+        }
+
+        Ok(output_buf)
+    }
+}
+
+pub const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+/// 随角色卡一起导出的聊天记录块关键字
+pub const HISTORY_CHUNK_KEYWORD: &str = "ccc_history";
+
+/// 压缩文本块解压后的最大字节数，防止解压炸弹
+const MAX_INFLATED_TEXT_BYTES: u64 = 32 * 1024 * 1024;
+
+/// PNG chunk 视图
+struct PngChunk<'a> {
+    chunk_type: [u8; 4],
+    data: &'a [u8],
+    /// 包含长度、类型、数据与 CRC 的完整字节
+    raw: &'a [u8],
+}
+
+/// 按顺序切分 PNG chunk（不校验 CRC）
+fn split_chunks(png_bytes: &[u8]) -> Result<Vec<PngChunk<'_>>, PngMetadataError> {
+    let mut chunks = Vec::new();
+    let mut pos = PNG_SIGNATURE.len();
+
+    while pos + 12 <= png_bytes.len() {
+        let length = u32::from_be_bytes([
+            png_bytes[pos],
+            png_bytes[pos + 1],
+            png_bytes[pos + 2],
+            png_bytes[pos + 3],
+        ]) as usize;
+        let end = pos + 12 + length;
+        if end > png_bytes.len() {
+            return Err(PngMetadataError::InvalidImageFormat);
+        }
+
+        chunks.push(PngChunk {
+            chunk_type: [
+                png_bytes[pos + 4],
+                png_bytes[pos + 5],
+                png_bytes[pos + 6],
+                png_bytes[pos + 7],
+            ],
+            data: &png_bytes[pos + 8..end - 4],
+            raw: &png_bytes[pos..end],
+        });
+        pos = end;
+    }
+
+    Ok(chunks)
+}
+
+/// 解析文本类 chunk，返回关键字与（解压后的）文本
+///
+/// 只有关键字在 `keywords` 中的块才会被解压；非文本 chunk 或其它关键字返回 None
+fn decode_text_chunk(
+    chunk_type: &[u8],
+    data: &[u8],
+    keywords: &[&str],
+) -> Result<Option<(String, Vec<u8>)>, PngMetadataError> {
+    if !matches!(chunk_type, b"tEXt" | b"zTXt" | b"iTXt") {
+        return Ok(None);
+    }
+
+    // 所有文本块都以 keyword\0 开头
+    let Some(null_pos) = data.iter().position(|&b| b == 0) else {
+        return Ok(None);
+    };
+    let keyword = String::from_utf8_lossy(&data[..null_pos]).into_owned();
+    if !keywords.contains(&keyword.as_str()) {
+        return Ok(None);
+    }
+    let rest = &data[null_pos + 1..];
+
+    let text = match chunk_type {
+        // tEXt: keyword\0text
+        b"tEXt" => rest.to_vec(),
+        // zTXt: keyword\0 压缩方法(1字节) zlib 数据
+        b"zTXt" => {
+            let Some((_method, compressed)) = rest.split_first() else {
+                return Err(PngMetadataError::InvalidImageFormat);
+            };
+            inflate_zlib(compressed)?
+        }
+        // iTXt: keyword\0 压缩标志(1) 压缩方法(1) language\0 translated_keyword\0 text
+        _ => {
+            if rest.len() < 2 {
+                return Err(PngMetadataError::InvalidImageFormat);
+            }
+            let compressed = rest[0] == 1;
+            let mut fields = rest[2..].splitn(3, |&b| b == 0);
+            let (Some(_language), Some(_translated_keyword), Some(text)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(PngMetadataError::InvalidImageFormat);
+            };
+
+            if compressed {
+                inflate_zlib(text)?
+            } else {
+                text.to_vec()
+            }
+        }
+    };
+
+    Ok(Some((keyword, text)))
+}
+
+fn inflate_zlib(compressed: &[u8]) -> Result<Vec<u8>, PngMetadataError> {
+    let mut decoded = Vec::new();
+    ZlibDecoder::new(compressed)
+        .take(MAX_INFLATED_TEXT_BYTES + 1)
+        .read_to_end(&mut decoded)?;
+    if decoded.len() as u64 > MAX_INFLATED_TEXT_BYTES {
+        return Err(PngMetadataError::InvalidImageFormat);
+    }
+    Ok(decoded)
+}
+
+/// 文本类 chunk 的关键字；非文本 chunk 返回 None
+fn text_chunk_keyword<'a>(chunk: &PngChunk<'a>) -> Option<&'a [u8]> {
+    if !matches!(&chunk.chunk_type, b"tEXt" | b"zTXt" | b"iTXt") {
+        return None;
+    }
+
+    let keyword_end = chunk
+        .data
+        .iter()
+        .position(|&b| b == 0)
+        .unwrap_or(chunk.data.len());
+    Some(&chunk.data[..keyword_end])
+}
+
+/// 是否为携带角色卡数据的文本块（chara / ccv3 / 聊天记录）
+fn is_character_chunk(chunk: &PngChunk<'_>) -> bool {
+    text_chunk_keyword(chunk).is_some_and(|keyword| {
+        matches!(keyword, b"chara" | b"ccv3") || keyword == HISTORY_CHUNK_KEYWORD.as_bytes()
+    })
+}
+
+/// 去掉已有聊天记录块，传入 JSONL 时在 IEND 之前写入新的聊天记录块
+fn replace_history_chunk(
+    png_bytes: &[u8],
+    history_jsonl: Option<&str>,
+) -> Result<Vec<u8>, PngMetadataError> {
+    if !png_bytes.starts_with(&PNG_SIGNATURE) {
+        return Err(PngMetadataError::InvalidImageFormat);
+    }
+
+    let mut output_buf = Vec::with_capacity(
+        png_bytes.len() + history_jsonl.map(|jsonl| jsonl.len() * 2).unwrap_or(0),
+    );
+    output_buf.extend_from_slice(&PNG_SIGNATURE);
+
+    let mut found_end = false;
+    for chunk in split_chunks(png_bytes)? {
+        if text_chunk_keyword(&chunk) == Some(HISTORY_CHUNK_KEYWORD.as_bytes()) {
+            continue;
+        }
+
+        if chunk.chunk_type == *b"IEND" {
+            if let Some(jsonl) = history_jsonl {
+                output_buf.extend(encode_text_chunk(
+                    HISTORY_CHUNK_KEYWORD,
+                    &STANDARD.encode(jsonl),
+                ));
+            }
+            found_end = true;
+        }
+
+        output_buf.extend_from_slice(chunk.raw);
+
+        if found_end {
+            break;
+        }
+    }
+
+    if !found_end {
+        return Err(PngMetadataError::InvalidImageFormat);
+    }
+
+    Ok(output_buf)
+}
+
+/// 编码一个完整的 tEXt chunk
+fn encode_text_chunk(keyword: &str, text: &str) -> Vec<u8> {
+    let mut body = Vec::with_capacity(4 + keyword.len() + 1 + text.len());
+    body.extend_from_slice(b"tEXt");
+    body.extend_from_slice(keyword.as_bytes());
+    body.push(0);
+    body.extend_from_slice(text.as_bytes());
+
+    let mut chunk = Vec::with_capacity(body.len() + 8);
+    chunk.extend_from_slice(&((body.len() - 4) as u32).to_be_bytes());
+    chunk.extend_from_slice(&body);
+    chunk.extend_from_slice(&crc32fast::hash(&body).to_be_bytes());
+    chunk
 }
 
 #[cfg(test)]
@@ -184,4 +476,150 @@ mod tests {
         let decoded_str = String::from_utf8(decoded).unwrap();
         assert_eq!(test_json, decoded_str);
     }
+
+    fn png_with_text_chunk(keyword: &str, text: &str) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, 2, 2);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder
+                .add_text_chunk(keyword.to_string(), text.to_string())
+                .unwrap();
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&[255; 16]).unwrap();
+        }
+        bytes
+    }
+
+    fn text_chunks(png_bytes: &[u8]) -> Vec<(String, String)> {
+        let mut reader = png::Decoder::new(png_bytes).read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut buf).unwrap();
+        // 读取 IDAT 之后的 chunk
+        reader.finish().unwrap();
+        reader
+            .info()
+            .uncompressed_latin1_text
+            .iter()
+            .map(|chunk| (chunk.keyword.clone(), chunk.text.clone()))
+            .collect()
+    }
+
+    fn png_with_compressed_chunk(chunk_type: &[u8; 4], keyword: &str, text: &str) -> Vec<u8> {
+        use flate2::write::ZlibEncoder;
+        use std::io::Write;
+
+        let mut compressed = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        compressed.write_all(text.as_bytes()).unwrap();
+        let compressed = compressed.finish().unwrap();
+
+        let mut data = keyword.as_bytes().to_vec();
+        data.push(0);
+        if chunk_type == b"iTXt" {
+            // 压缩标志、压缩方法、空 language 与 translated keyword
+            data.extend_from_slice(&[1, 0, 0, 0]);
+        } else {
+            data.push(0);
+        }
+        data.extend_from_slice(&compressed);
+
+        let mut body = chunk_type.to_vec();
+        body.extend_from_slice(&data);
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(&body);
+        chunk.extend_from_slice(&crc32fast::hash(&body).to_be_bytes());
+
+        let mut png_bytes = png_with_text_chunk("Software", "OtherTool 1.0");
+        let iend = png_bytes.len() - 12;
+        png_bytes.splice(iend..iend, chunk);
+        png_bytes
+    }
+
+    #[test]
+    fn reads_character_data_from_ztxt_chunk() {
+        let card_json = r#"{"spec":"chara_card_v2","data":{"name":"压缩角色"}}"#;
+        let png_bytes = png_with_compressed_chunk(b"zTXt", "chara", &STANDARD.encode(card_json));
+
+        assert_eq!(
+            PngMetadataUtils::read_character_data_from_bytes(&png_bytes).unwrap(),
+            card_json
+        );
+    }
+
+    #[test]
+    fn skips_corrupt_and_oversized_unrelated_compressed_chunks() {
+        let card_json = r#"{"spec":"chara_card_v2","data":{"name":"压缩角色"}}"#;
+        let mut png_bytes =
+            png_with_compressed_chunk(b"zTXt", "chara", &STANDARD.encode(card_json));
+
+        // 插入一个关键字无关且数据损坏的 zTXt 块
+        let data = b"Comment\0\0not zlib".to_vec();
+        let mut body = b"zTXt".to_vec();
+        body.extend_from_slice(&data);
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(&body);
+        chunk.extend_from_slice(&crc32fast::hash(&body).to_be_bytes());
+        png_bytes.splice(8 + 25..8 + 25, chunk);
+
+        assert_eq!(
+            PngMetadataUtils::read_character_data_from_bytes(&png_bytes).unwrap(),
+            card_json
+        );
+
+        let oversized = "A".repeat(MAX_INFLATED_TEXT_BYTES as usize + 1);
+        let png_bytes = png_with_compressed_chunk(b"zTXt", "chara", &oversized);
+        assert!(matches!(
+            PngMetadataUtils::read_character_data_from_bytes(&png_bytes),
+            Err(PngMetadataError::CharaDataNotFound)
+        ));
+    }
+
+    #[test]
+    fn reads_character_data_from_compressed_itxt_chunk() {
+        let card_json = r#"{"spec":"chara_card_v3","data":{"name":"iTXt"}}"#;
+        let png_bytes = png_with_compressed_chunk(b"iTXt", "ccv3", &STANDARD.encode(card_json));
+
+        assert_eq!(
+            PngMetadataUtils::read_character_data_from_bytes(&png_bytes).unwrap(),
+            card_json
+        );
+    }
+
+    #[test]
+    fn extract_image_bytes_strips_character_chunks() {
+        let source = png_with_text_chunk("Software", "OtherTool 1.0");
+        let card = PngMetadataUtils::write_character_data_to_bytes(&source, "{}", "{}").unwrap();
+
+        let image_bytes = PngMetadataUtils::extract_image_bytes(&card).unwrap();
+
+        assert_eq!(image_bytes, source);
+        assert!(matches!(
+            PngMetadataUtils::read_character_data_from_bytes(&image_bytes),
+            Err(PngMetadataError::CharaDataNotFound)
+        ));
+        assert!(matches!(
+            PngMetadataUtils::extract_image_bytes(b"{\"spec\":\"chara_card_v2\"}"),
+            Err(PngMetadataError::InvalidImageFormat)
+        ));
+    }
+
+    #[test]
+    fn writing_character_data_preserves_other_text_chunks() {
+        let source = png_with_text_chunk("Software", "OtherTool 1.0");
+
+        let first = PngMetadataUtils::write_character_data_to_bytes(&source, "{}", "{}").unwrap();
+        let output =
+            PngMetadataUtils::write_character_data_to_bytes(&first, r#"{"v":2}"#, r#"{"v":3}"#)
+                .unwrap();
+
+        let chunks = text_chunks(&output);
+        assert!(chunks.contains(&("Software".to_string(), "OtherTool 1.0".to_string())));
+        assert_eq!(chunks.iter().filter(|(k, _)| k == "chara").count(), 1);
+        assert_eq!(chunks.iter().filter(|(k, _)| k == "ccv3").count(), 1);
+        assert_eq!(
+            PngMetadataUtils::read_character_data_from_bytes(&output).unwrap(),
+            r#"{"v":3}"#
+        );
+    }
 }
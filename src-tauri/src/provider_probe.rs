@@ -0,0 +1,167 @@
+use crate::ai_chat::AIChatService;
+use crate::api_config::{ApiConfig, ApiConfigService};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// 单个探测请求的超时时间
+pub const PROBE_TIMEOUT_SECS: u64 = 20;
+
+/// 提供商能力探测结果
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProviderCapabilities {
+    pub supports_streaming: bool,
+    pub supports_tools: bool,
+    pub supports_models_endpoint: bool,
+    /// 旧版探测结果没有该字段，默认视为支持以免误删图片
+    #[serde(default = "default_supports_vision")]
+    pub supports_vision: bool,
+}
+
+fn default_supports_vision() -> bool {
+    true
+}
+
+/// 提供商能力探测接口，每个方法发送一次轻量请求
+#[async_trait]
+pub trait ProviderProbe: Send + Sync {
+    async fn probe_streaming(&self) -> Result<(), String>;
+    async fn probe_tools(&self) -> Result<(), String>;
+    async fn probe_models(&self) -> Result<(), String>;
+    async fn probe_vision(&self) -> Result<(), String>;
+}
+
+/// 基于真实 API 配置的探测实现
+pub struct LiveProviderProbe<'a> {
+    pub app_handle: &'a tauri::AppHandle,
+    pub config: &'a ApiConfig,
+}
+
+#[async_trait]
+impl ProviderProbe for LiveProviderProbe<'_> {
+    async fn probe_streaming(&self) -> Result<(), String> {
+        AIChatService::probe_streaming(self.config).await
+    }
+
+    async fn probe_tools(&self) -> Result<(), String> {
+        AIChatService::probe_tool_support(self.config).await
+    }
+
+    async fn probe_models(&self) -> Result<(), String> {
+        let models = ApiConfigService::fetch_models(self.app_handle, self.config).await?;
+        if models.is_empty() {
+            return Err("模型列表为空".to_string());
+        }
+        Ok(())
+    }
+
+    async fn probe_vision(&self) -> Result<(), String> {
+        AIChatService::probe_vision_support(self.config).await
+    }
+}
+
+async fn run_probe<F>(label: &str, timeout: Duration, probe: F) -> bool
+where
+    F: std::future::Future<Output = Result<(), String>>,
+{
+    match tokio::time::timeout(timeout, probe).await {
+        Ok(Ok(())) => true,
+        Ok(Err(error)) => {
+            crate::debug_log!("能力探测 {} 失败: {}", label, error);
+            false
+        }
+        Err(_) => {
+            crate::debug_log!("能力探测 {} 超时", label);
+            false
+        }
+    }
+}
+
+/// 并发执行所有探测。视觉探测会额外发送一次带图片的付费请求，仅在 include_vision 为 true 时执行，
+/// 否则沿用 known_vision（没有时视为支持）
+pub async fn probe_capabilities(
+    probe: &dyn ProviderProbe,
+    timeout: Duration,
+    include_vision: bool,
+    known_vision: Option<bool>,
+) -> ProviderCapabilities {
+    let vision = async {
+        if include_vision {
+            run_probe("vision", timeout, probe.probe_vision()).await
+        } else {
+            known_vision.unwrap_or_else(default_supports_vision)
+        }
+    };
+    let (supports_streaming, supports_tools, supports_models_endpoint, supports_vision) = tokio::join!(
+        run_probe("streaming", timeout, probe.probe_streaming()),
+        run_probe("tools", timeout, probe.probe_tools()),
+        run_probe("models", timeout, probe.probe_models()),
+        vision,
+    );
+
+    ProviderCapabilities {
+        supports_streaming,
+        supports_tools,
+        supports_models_endpoint,
+        supports_vision,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct ToolsOnlyProvider;
+
+    #[async_trait]
+    impl ProviderProbe for ToolsOnlyProvider {
+        async fn probe_streaming(&self) -> Result<(), String> {
+            Err("stream not supported".to_string())
+        }
+
+        async fn probe_tools(&self) -> Result<(), String> {
+            Ok(())
+        }
+
+        async fn probe_models(&self) -> Result<(), String> {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(())
+        }
+
+        async fn probe_vision(&self) -> Result<(), String> {
+            Err("image input not supported".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn probe_reports_tools_without_streaming() {
+        let capabilities =
+            probe_capabilities(&ToolsOnlyProvider, Duration::from_millis(50), true, None).await;
+
+        assert_eq!(
+            capabilities,
+            ProviderCapabilities {
+                supports_streaming: false,
+                supports_tools: true,
+                supports_models_endpoint: false,
+                supports_vision: false,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn vision_is_not_probed_unless_requested() {
+        let capabilities =
+            probe_capabilities(&ToolsOnlyProvider, Duration::from_millis(50), false, None).await;
+        assert!(capabilities.supports_vision);
+
+        let capabilities = probe_capabilities(
+            &ToolsOnlyProvider,
+            Duration::from_millis(50),
+            false,
+            Some(false),
+        )
+        .await;
+        assert!(!capabilities.supports_vision);
+    }
+}
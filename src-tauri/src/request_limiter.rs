@@ -0,0 +1,163 @@
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 4;
+
+struct LimiterState {
+    max_permits: usize,
+    /// 调低上限时还在使用中的多余名额，归还时直接回收
+    pending_forget: usize,
+}
+
+/// 全局 API 请求并发限制：超出上限的请求排队等待，而不是直接失败
+pub struct RequestLimiter {
+    semaphore: Arc<Semaphore>,
+    state: Arc<Mutex<LimiterState>>,
+}
+
+/// 一个请求名额，释放时归还给限制器（上限已调低时被回收）
+pub struct RequestPermit {
+    permit: Option<OwnedSemaphorePermit>,
+    state: Arc<Mutex<LimiterState>>,
+}
+
+impl Drop for RequestPermit {
+    fn drop(&mut self) {
+        let Some(permit) = self.permit.take() else {
+            return;
+        };
+        if let Ok(mut state) = self.state.lock() {
+            if state.pending_forget > 0 {
+                state.pending_forget -= 1;
+                permit.forget();
+            }
+        }
+    }
+}
+
+impl RequestLimiter {
+    pub fn new(max_permits: usize) -> Self {
+        let max_permits = max_permits.max(1);
+        Self {
+            semaphore: Arc::new(Semaphore::new(max_permits)),
+            state: Arc::new(Mutex::new(LimiterState {
+                max_permits,
+                pending_forget: 0,
+            })),
+        }
+    }
+
+    /// 获取一个请求名额，持有期间占用并发数，释放后由下一个排队请求获取
+    pub async fn acquire(&self) -> Result<RequestPermit, String> {
+        let permit = self
+            .semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|error| format!("获取请求名额失败: {error}"))?;
+        Ok(RequestPermit {
+            permit: Some(permit),
+            state: self.state.clone(),
+        })
+    }
+
+    pub fn max_permits(&self) -> usize {
+        self.state
+            .lock()
+            .map(|state| state.max_permits)
+            .unwrap_or(DEFAULT_MAX_CONCURRENT_REQUESTS)
+    }
+
+    /// 调整并发上限；调低时正在进行的请求不受影响，完成后名额不再归还
+    pub fn set_max_permits(&self, max_permits: usize) -> Result<(), String> {
+        if max_permits == 0 {
+            return Err("最大并发请求数必须大于 0".to_string());
+        }
+
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|error| format!("锁定请求并发限制失败: {error}"))?;
+
+        if max_permits > state.max_permits {
+            // 先抵消尚未回收的名额，再补充新的名额
+            let increase = max_permits - state.max_permits;
+            let cancelled = increase.min(state.pending_forget);
+            state.pending_forget -= cancelled;
+            self.semaphore.add_permits(increase - cancelled);
+        } else if max_permits < state.max_permits {
+            let excess = state.max_permits - max_permits;
+            let forgotten = self.semaphore.forget_permits(excess);
+            state.pending_forget += excess - forgotten;
+        }
+
+        state.max_permits = max_permits;
+        Ok(())
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref REQUEST_LIMITER: RequestLimiter =
+        RequestLimiter::new(DEFAULT_MAX_CONCURRENT_REQUESTS);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RequestLimiter;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn limiter_never_exceeds_max_permits() {
+        let limiter = Arc::new(RequestLimiter::new(2));
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let limiter = limiter.clone();
+                let in_flight = in_flight.clone();
+                let peak = peak.clone();
+                tokio::spawn(async move {
+                    let _permit = limiter.acquire().await.unwrap();
+                    let running = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(running, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert!(limiter.set_max_permits(0).is_err());
+        limiter.set_max_permits(3).unwrap();
+        assert_eq!(limiter.max_permits(), 3);
+    }
+
+    #[tokio::test]
+    async fn lowering_limit_reclaims_permits_in_use() {
+        let limiter = RequestLimiter::new(3);
+        let first = limiter.acquire().await.unwrap();
+        let second = limiter.acquire().await.unwrap();
+
+        limiter.set_max_permits(1).unwrap();
+        assert_eq!(limiter.semaphore.available_permits(), 0);
+
+        drop(first);
+        assert_eq!(limiter.semaphore.available_permits(), 0);
+        drop(second);
+        assert_eq!(limiter.semaphore.available_permits(), 1);
+
+        let held = limiter.acquire().await.unwrap();
+        limiter.set_max_permits(0).unwrap_err();
+        limiter.set_max_permits(1).unwrap();
+        limiter.set_max_permits(2).unwrap();
+        drop(held);
+        assert_eq!(limiter.semaphore.available_permits(), 2);
+    }
+}
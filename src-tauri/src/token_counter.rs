@@ -1,28 +1,119 @@
 use once_cell::sync::Lazy;
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
-use tiktoken_rs::{cl100k_base, CoreBPE};
+use tiktoken_rs::{cl100k_base, o200k_base, p50k_base, p50k_edit, r50k_base, CoreBPE};
+
+/// 批量计数时少于该数量的文本直接顺序处理，避免线程调度开销
+const PARALLEL_BATCH_THRESHOLD: usize = 8;
 
 /// Token 计数结果
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct TokenCountResult {
     pub text: String,
     pub token_count: usize,
     pub char_count: usize,
 }
 
+/// 分词编码
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenEncoding {
+    Cl100kBase,
+    O200kBase,
+    P50kBase,
+    P50kEdit,
+    R50kBase,
+}
+
+/// 使用 o200k_base 的模型名前缀（GPT-4o / GPT-4.1 / GPT-5 / o 系列推理模型）
+const O200K_MODEL_PREFIXES: [&str; 8] = [
+    "gpt-4o",
+    "chatgpt-4o",
+    "gpt-4.1",
+    "gpt-4.5",
+    "gpt-5",
+    "o1",
+    "o3",
+    "o4",
+];
+
+impl TokenEncoding {
+    /// 编码名称（与 tiktoken 命名一致）
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Cl100kBase => "cl100k_base",
+            Self::O200kBase => "o200k_base",
+            Self::P50kBase => "p50k_base",
+            Self::P50kEdit => "p50k_edit",
+            Self::R50kBase => "r50k_base",
+        }
+    }
+
+    /// 按名称解析编码，未知名称返回错误
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        let name = name.trim().to_ascii_lowercase();
+        [
+            Self::Cl100kBase,
+            Self::O200kBase,
+            Self::P50kBase,
+            Self::P50kEdit,
+            Self::R50kBase,
+        ]
+        .into_iter()
+        .find(|encoding| encoding.name() == name)
+        .ok_or_else(|| {
+            format!(
+                "未知的分词编码: {}（可选: cl100k_base, o200k_base, p50k_base, p50k_edit, r50k_base）",
+                name
+            )
+        })
+    }
+
+    /// 根据模型名选择编码，忽略 "openai/" 等提供商前缀；未知模型回退 cl100k_base
+    pub fn for_model(model: &str) -> Self {
+        let model = model.trim().to_ascii_lowercase();
+        let model = model.rsplit('/').next().unwrap_or_default();
+        if O200K_MODEL_PREFIXES
+            .iter()
+            .any(|prefix| model.starts_with(prefix))
+        {
+            Self::O200kBase
+        } else {
+            Self::Cl100kBase
+        }
+    }
+}
+
 /// Token 计数服务
 pub struct TokenCounter {
     encoding: CoreBPE,
 }
 
 impl TokenCounter {
-    /// 创建新的 Token 计数器实例
+    /// 创建新的 Token 计数器实例（cl100k_base）
     pub fn new() -> Result<Self, String> {
-        let encoding = cl100k_base().map_err(|e| format!("Failed to load tokenizer: {}", e))?;
+        Self::with_encoding(TokenEncoding::Cl100kBase)
+    }
+
+    /// 使用指定编码创建 Token 计数器实例
+    pub fn with_encoding(encoding: TokenEncoding) -> Result<Self, String> {
+        let encoding = match encoding {
+            TokenEncoding::Cl100kBase => cl100k_base(),
+            TokenEncoding::O200kBase => o200k_base(),
+            TokenEncoding::P50kBase => p50k_base(),
+            TokenEncoding::P50kEdit => p50k_edit(),
+            TokenEncoding::R50kBase => r50k_base(),
+        }
+        .map_err(|e| format!("Failed to load tokenizer: {}", e))?;
         Ok(Self { encoding })
     }
 
+    /// 获取与模型匹配的全局计数器（每种编码只加载一次）
+    pub fn for_model(model: &str) -> &'static TokenCounter {
+        get_token_counter_for_encoding(TokenEncoding::for_model(model))
+    }
+
     /// 计算单个文本的 Token 数量
     pub fn count_tokens(&self, text: &str) -> TokenCountResult {
         let allowed_special = HashSet::new(); // 不允许任何特殊token
@@ -34,8 +125,18 @@ impl TokenCounter {
         }
     }
 
-    /// 批量计算多个文本的 Token 数量
+    /// 批量计算多个文本的 Token 数量，文本较多时并行处理，结果与输入顺序一致
     pub fn count_tokens_batch(&self, texts: &[String]) -> Vec<TokenCountResult> {
+        if texts.len() < PARALLEL_BATCH_THRESHOLD {
+            return self.count_tokens_sequential(texts);
+        }
+        texts
+            .par_iter()
+            .map(|text| self.count_tokens(text))
+            .collect()
+    }
+
+    fn count_tokens_sequential(&self, texts: &[String]) -> Vec<TokenCountResult> {
         texts.iter().map(|text| self.count_tokens(text)).collect()
     }
 
@@ -73,7 +174,153 @@ impl Default for TokenCounter {
 static TOKEN_COUNTER: Lazy<TokenCounter> =
     Lazy::new(|| TokenCounter::new().expect("Failed to initialize TokenCounter"));
 
+/// 全局 o200k_base Token 计数器实例
+static O200K_TOKEN_COUNTER: Lazy<TokenCounter> = Lazy::new(|| {
+    TokenCounter::with_encoding(TokenEncoding::O200kBase)
+        .expect("Failed to initialize o200k TokenCounter")
+});
+
+/// 全局 p50k_base Token 计数器实例
+static P50K_TOKEN_COUNTER: Lazy<TokenCounter> = Lazy::new(|| {
+    TokenCounter::with_encoding(TokenEncoding::P50kBase)
+        .expect("Failed to initialize p50k TokenCounter")
+});
+
+/// 全局 p50k_edit Token 计数器实例
+static P50K_EDIT_TOKEN_COUNTER: Lazy<TokenCounter> = Lazy::new(|| {
+    TokenCounter::with_encoding(TokenEncoding::P50kEdit)
+        .expect("Failed to initialize p50k_edit TokenCounter")
+});
+
+/// 全局 r50k_base Token 计数器实例
+static R50K_TOKEN_COUNTER: Lazy<TokenCounter> = Lazy::new(|| {
+    TokenCounter::with_encoding(TokenEncoding::R50kBase)
+        .expect("Failed to initialize r50k TokenCounter")
+});
+
 /// 获取全局 Token 计数器实例
 pub fn get_token_counter() -> &'static TokenCounter {
     &TOKEN_COUNTER
 }
+
+/// 获取指定编码的全局 Token 计数器实例
+pub fn get_token_counter_for_encoding(encoding: TokenEncoding) -> &'static TokenCounter {
+    match encoding {
+        TokenEncoding::Cl100kBase => &TOKEN_COUNTER,
+        TokenEncoding::O200kBase => &O200K_TOKEN_COUNTER,
+        TokenEncoding::P50kBase => &P50K_TOKEN_COUNTER,
+        TokenEncoding::P50kEdit => &P50K_EDIT_TOKEN_COUNTER,
+        TokenEncoding::R50kBase => &R50K_TOKEN_COUNTER,
+    }
+}
+
+/// 获取与模型匹配的全局 Token 计数器实例，未指定模型时使用 cl100k_base
+pub fn get_token_counter_for_model(model: Option<&str>) -> &'static TokenCounter {
+    model.map_or_else(get_token_counter, TokenCounter::for_model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_token_counter, get_token_counter_for_encoding, TokenCounter, TokenEncoding};
+
+    #[test]
+    fn model_names_select_matching_encoding() {
+        assert_eq!(
+            TokenEncoding::for_model("gpt-4o-mini"),
+            TokenEncoding::O200kBase
+        );
+        assert_eq!(
+            TokenEncoding::for_model("openai/o1-preview"),
+            TokenEncoding::O200kBase
+        );
+        assert_eq!(
+            TokenEncoding::for_model("gpt-4-turbo"),
+            TokenEncoding::Cl100kBase
+        );
+        assert_eq!(
+            TokenEncoding::for_model("deepseek-chat"),
+            TokenEncoding::Cl100kBase
+        );
+    }
+
+    #[test]
+    fn same_text_counts_differently_under_each_encoding() {
+        let text = "角色卡编写助手正在帮助用户完善世界书条目与开场白。";
+        let cl100k = get_token_counter().count_tokens(text).token_count;
+        let o200k = TokenCounter::for_model("gpt-4o")
+            .count_tokens(text)
+            .token_count;
+
+        assert!(cl100k > 0 && o200k > 0);
+        assert_ne!(cl100k, o200k);
+        assert_eq!(
+            TokenCounter::for_model("gpt-4")
+                .count_tokens(text)
+                .token_count,
+            cl100k
+        );
+    }
+
+    #[test]
+    fn global_counter_is_shared_across_threads() {
+        let text = "角色卡编写助手正在帮助用户完善世界书条目与开场白。";
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                std::thread::spawn(move || {
+                    let counter = get_token_counter();
+                    (
+                        counter as *const TokenCounter as usize,
+                        counter.count_tokens(text).token_count,
+                    )
+                })
+            })
+            .collect();
+
+        let results: Vec<(usize, usize)> = handles
+            .into_iter()
+            .map(|handle| handle.join().expect("counter thread should not panic"))
+            .collect();
+
+        let expected = (
+            get_token_counter() as *const TokenCounter as usize,
+            get_token_counter().count_tokens(text).token_count,
+        );
+        assert!(results.iter().all(|result| *result == expected));
+    }
+
+    #[test]
+    fn parallel_batch_matches_sequential_order_and_counts() {
+        let texts: Vec<String> = (0..64)
+            .map(|index| {
+                format!(
+                    "世界书条目 {index}: {}",
+                    "the kingdom of Aster ".repeat(index % 7)
+                )
+            })
+            .collect();
+        let counter = get_token_counter();
+
+        let parallel = counter.count_tokens_batch(&texts);
+
+        assert_eq!(parallel, counter.count_tokens_sequential(&texts));
+        assert_eq!(
+            counter.count_tokens_batch(&texts[..3]),
+            counter.count_tokens_sequential(&texts[..3])
+        );
+    }
+
+    #[test]
+    fn named_encodings_count_independently_of_model_mapping() {
+        let text = "角色卡编写助手正在帮助用户完善世界书条目与开场白。";
+        let count = |name: &str| {
+            get_token_counter_for_encoding(TokenEncoding::from_name(name).unwrap())
+                .count_tokens(text)
+                .token_count
+        };
+
+        assert_ne!(count("cl100k_base"), count("o200k_base"));
+        assert_eq!(count("O200K_BASE"), count("o200k_base"));
+        assert!(count("p50k_base") > 0);
+        assert!(TokenEncoding::from_name("gpt2").is_err());
+    }
+}
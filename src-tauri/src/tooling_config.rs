@@ -0,0 +1,205 @@
+use crate::ai_config::{
+    AIConfig, AIConfigService, MAX_MAX_TOOL_ITERATIONS, MIN_MAX_TOOL_ITERATIONS,
+};
+use crate::file_utils::FileUtils;
+use crate::tools::ToolRegistry;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// 工具配置包格式版本
+pub const TOOLING_CONFIG_VERSION: u32 = 1;
+
+/// 单个 AI 角色的工具设置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoleToolingConfig {
+    pub tools_enabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_tools: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tool_iterations: Option<u32>,
+}
+
+/// 可在机器之间迁移的工具配置包
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolingConfig {
+    pub version: u32,
+    pub max_tool_iterations: u32,
+    /// 角色 ID → 工具设置
+    #[serde(default)]
+    pub roles: BTreeMap<String, RoleToolingConfig>,
+}
+
+/// 导入结果：已应用的角色、本机不存在而跳过的角色、未知而被移除的工具名
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ToolingImportReport {
+    pub applied_roles: Vec<String>,
+    pub skipped_roles: Vec<String>,
+    pub skipped_tools: Vec<String>,
+}
+
+fn validate_iterations(max_tool_iterations: u32, field: &str) -> Result<(), String> {
+    if (MIN_MAX_TOOL_ITERATIONS..=MAX_MAX_TOOL_ITERATIONS).contains(&max_tool_iterations) {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} 必须在 {} 到 {} 之间",
+            field, MIN_MAX_TOOL_ITERATIONS, MAX_MAX_TOOL_ITERATIONS
+        ))
+    }
+}
+
+/// 从 AI 配置中提取工具配置
+pub fn tooling_config_from(config: &AIConfig) -> ToolingConfig {
+    ToolingConfig {
+        version: TOOLING_CONFIG_VERSION,
+        max_tool_iterations: config.max_tool_iterations,
+        roles: config
+            .roles
+            .iter()
+            .map(|(role_id, role)| {
+                (
+                    role_id.clone(),
+                    RoleToolingConfig {
+                        tools_enabled: role.tools_enabled,
+                        allowed_tools: role.allowed_tools.clone(),
+                        max_tool_iterations: role.max_tool_iterations,
+                    },
+                )
+            })
+            .collect(),
+    }
+}
+
+/// 校验并把工具配置应用到 AI 配置；本机不存在的角色和未知工具会被跳过
+pub fn apply_tooling_config(
+    config: &mut AIConfig,
+    bundle: &ToolingConfig,
+    known_tools: &[String],
+) -> Result<ToolingImportReport, String> {
+    if bundle.version > TOOLING_CONFIG_VERSION {
+        return Err(format!("不支持的工具配置版本: {}", bundle.version));
+    }
+    validate_iterations(bundle.max_tool_iterations, "max_tool_iterations")?;
+    for (role_id, role) in &bundle.roles {
+        if let Some(max_tool_iterations) = role.max_tool_iterations {
+            validate_iterations(
+                max_tool_iterations,
+                &format!("角色 {} 的 max_tool_iterations", role_id),
+            )?;
+        }
+    }
+
+    let mut report = ToolingImportReport::default();
+    config.max_tool_iterations = bundle.max_tool_iterations;
+
+    for (role_id, role_tooling) in &bundle.roles {
+        let Some(role) = config.roles.get_mut(role_id) else {
+            report.skipped_roles.push(role_id.clone());
+            continue;
+        };
+
+        role.tools_enabled = role_tooling.tools_enabled;
+        role.max_tool_iterations = role_tooling.max_tool_iterations;
+        role.allowed_tools = role_tooling.allowed_tools.as_ref().map(|allowed_tools| {
+            allowed_tools
+                .iter()
+                .filter(|tool| {
+                    let known = known_tools.contains(tool);
+                    if !known && !report.skipped_tools.contains(tool) {
+                        report.skipped_tools.push((*tool).clone());
+                    }
+                    known
+                })
+                .cloned()
+                .collect()
+        });
+        report.applied_roles.push(role_id.clone());
+    }
+
+    Ok(report)
+}
+
+/// 工具配置导入导出服务
+pub struct ToolingConfigService;
+
+impl ToolingConfigService {
+    /// 导出工具配置到 JSON 文件
+    pub fn export_tooling_config(
+        app_handle: &tauri::AppHandle,
+        output_path: &str,
+    ) -> Result<ToolingConfig, String> {
+        let bundle = tooling_config_from(&AIConfigService::load_config(app_handle)?);
+        FileUtils::write_json_file(Path::new(output_path), &bundle)?;
+        Ok(bundle)
+    }
+
+    /// 从 JSON 文件导入工具配置
+    pub fn import_tooling_config(
+        app_handle: &tauri::AppHandle,
+        file_path: &str,
+    ) -> Result<ToolingImportReport, String> {
+        let bundle: ToolingConfig = FileUtils::read_json_file(Path::new(file_path))?;
+        let known_tools: Vec<String> = ToolRegistry::get_available_tools_global()
+            .into_iter()
+            .map(|tool| tool.function.name)
+            .collect();
+
+        let mut config = AIConfigService::load_config(app_handle)?;
+        let report = apply_tooling_config(&mut config, &bundle, &known_tools)?;
+        AIConfigService::save_config(app_handle, &config)?;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_tooling_config, tooling_config_from};
+    use crate::ai_config::AIConfig;
+
+    fn config_with_role(role_id: &str) -> AIConfig {
+        serde_json::from_value(serde_json::json!({
+            "default_role": role_id,
+            "roles": { role_id: { "name": "助手" } }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn tooling_config_round_trips_and_skips_unknown_ids() {
+        let mut source = config_with_role("writer");
+        source.max_tool_iterations = 8;
+        let role = source.roles.get_mut("writer").unwrap();
+        role.tools_enabled = false;
+        role.allowed_tools = Some(vec![
+            "edit_character".to_string(),
+            "send_webhook".to_string(),
+        ]);
+        role.max_tool_iterations = Some(3);
+
+        let mut bundle = tooling_config_from(&source);
+        bundle
+            .roles
+            .insert("missing".to_string(), bundle.roles["writer"].clone());
+        let json = serde_json::to_string(&bundle).unwrap();
+        let bundle = serde_json::from_str(&json).unwrap();
+
+        let mut target = config_with_role("writer");
+        let report =
+            apply_tooling_config(&mut target, &bundle, &["edit_character".to_string()]).unwrap();
+
+        assert_eq!(report.applied_roles, vec!["writer"]);
+        assert_eq!(report.skipped_roles, vec!["missing"]);
+        assert_eq!(report.skipped_tools, vec!["send_webhook"]);
+
+        let role = &target.roles["writer"];
+        assert_eq!(target.max_tool_iterations, 8);
+        assert!(!role.tools_enabled);
+        assert_eq!(role.allowed_tools, Some(vec!["edit_character".to_string()]));
+        assert_eq!(role.max_tool_iterations, Some(3));
+
+        let mut invalid = tooling_config_from(&source);
+        invalid.max_tool_iterations = 0;
+        assert!(apply_tooling_config(&mut target, &invalid, &[]).is_err());
+    }
+}
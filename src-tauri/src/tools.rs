@@ -1,3 +1,4 @@
+pub mod alternate_greeting_editor;
 pub mod character_editor;
 pub mod character_field_patcher;
 pub mod character_fields;
@@ -0,0 +1,325 @@
+use super::{failure_result, AIToolTrait};
+use crate::ai_tools::{
+    ToolCallRequest, ToolDefinition, ToolFunction, ToolParameter as ChatToolParameter,
+    ToolParameters, ToolResult,
+};
+use crate::backend::domain::CharacterUpdateType;
+use crate::character_storage::{CharacterStorage, TavernCardV2};
+use crate::events::EventEmitter;
+use crate::tools::world_book_shared::{get_string_parameter, get_usize_parameter};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tauri::AppHandle;
+
+/// 追加单条备选开场白
+pub struct AddAlternateGreetingTool;
+
+/// 按索引修改或删除单条备选开场白
+pub struct EditAlternateGreetingTool;
+
+#[derive(Debug, Clone, PartialEq)]
+enum GreetingEdit {
+    Update(String),
+    Delete,
+}
+
+/// 追加一条备选开场白，返回其索引
+fn append_greeting(greetings: &mut Vec<String>, content: &str) -> Result<usize, String> {
+    let content = content.trim();
+    if content.is_empty() {
+        return Err("开场白内容不能为空".to_string());
+    }
+
+    greetings.push(content.to_string());
+    Ok(greetings.len() - 1)
+}
+
+/// 修改或删除指定索引的备选开场白，返回原内容
+fn edit_greeting(
+    greetings: &mut Vec<String>,
+    index: usize,
+    edit: GreetingEdit,
+) -> Result<String, String> {
+    if index >= greetings.len() {
+        return Err(format!(
+            "备选开场白索引 {} 超出范围（共 {} 条，索引从 0 开始）",
+            index,
+            greetings.len()
+        ));
+    }
+
+    match edit {
+        GreetingEdit::Update(content) => {
+            let content = content.trim();
+            if content.is_empty() {
+                return Err("开场白内容不能为空，删除请使用 action=delete".to_string());
+            }
+            Ok(std::mem::replace(
+                &mut greetings[index],
+                content.to_string(),
+            ))
+        }
+        GreetingEdit::Delete => Ok(greetings.remove(index)),
+    }
+}
+
+fn parse_edit(parameters: &HashMap<String, Value>) -> Result<GreetingEdit, String> {
+    match get_string_parameter(parameters, "action").unwrap_or("update") {
+        "update" => get_string_parameter(parameters, "content")
+            .map(|content| GreetingEdit::Update(content.to_string()))
+            .ok_or_else(|| "action=update 时必须提供 content".to_string()),
+        "delete" => Ok(GreetingEdit::Delete),
+        other => Err(format!("不支持的操作 '{}'，仅支持 update、delete", other)),
+    }
+}
+
+/// 读取当前角色卡，交给 apply 修改后保存并发送角色更新事件
+fn update_greetings<F>(
+    app_handle: &AppHandle,
+    request: &ToolCallRequest,
+    start_time: std::time::Instant,
+    apply: F,
+) -> ToolResult
+where
+    F: FnOnce(&mut TavernCardV2) -> Result<Value, String>,
+{
+    let character_uuid = match &request.character_uuid {
+        Some(uuid) => uuid.clone(),
+        None => return failure_result(start_time, "missing_character_uuid", "缺少角色UUID", None),
+    };
+
+    let mut character_data =
+        match CharacterStorage::get_character_by_uuid(app_handle, &character_uuid) {
+            Ok(Some(data)) => data,
+            Ok(None) => {
+                return failure_result(start_time, "character_not_found", "角色不存在", None)
+            }
+            Err(error) => {
+                return failure_result(
+                    start_time,
+                    "load_character_failed",
+                    format!("获取角色数据失败: {}", error),
+                    None,
+                )
+            }
+        };
+
+    let result_data = match apply(&mut character_data.card) {
+        Ok(data) => data,
+        Err(error) => return failure_result(start_time, "invalid_greeting_edit", error, None),
+    };
+
+    if let Err(error) =
+        CharacterStorage::update_character(app_handle, &character_uuid, &character_data.card)
+    {
+        return failure_result(
+            start_time,
+            "save_character_failed",
+            format!("保存角色数据失败: {}", error),
+            None,
+        );
+    }
+
+    if let Err(error) = EventEmitter::send_character_updated(
+        app_handle,
+        &character_uuid,
+        &character_data,
+        CharacterUpdateType::Fields {
+            fields: vec!["alternate_greetings".to_string()],
+        },
+    ) {
+        eprintln!("发送角色更新事件失败: {}", error);
+    }
+
+    ToolResult {
+        success: true,
+        data: Some(result_data),
+        error: None,
+        execution_time_ms: start_time.elapsed().as_millis() as u64,
+    }
+}
+
+fn string_parameter(description: &str, enum_values: Option<Vec<String>>) -> ChatToolParameter {
+    ChatToolParameter {
+        param_type: "string".to_string(),
+        description: Some(description.to_string()),
+        enum_values,
+        items: None,
+        properties: None,
+        required: None,
+    }
+}
+
+#[async_trait]
+impl AIToolTrait for AddAlternateGreetingTool {
+    fn name(&self) -> &'static str {
+        "add_alternate_greeting"
+    }
+
+    fn description(&self) -> &'static str {
+        "为当前角色追加一条备选开场白（alternate_greetings），无需重发已有的开场白。"
+    }
+
+    fn category(&self) -> &'static str {
+        "character"
+    }
+
+    async fn execute(&self, app_handle: &AppHandle, request: &ToolCallRequest) -> ToolResult {
+        let start_time = std::time::Instant::now();
+        let content = get_string_parameter(&request.parameters, "content")
+            .unwrap_or_default()
+            .to_string();
+
+        update_greetings(app_handle, request, start_time, |card| {
+            let index = append_greeting(&mut card.data.alternate_greetings, &content)?;
+            Ok(json!({
+                "message": "备选开场白已添加",
+                "index": index,
+                "total": card.data.alternate_greetings.len(),
+            }))
+        })
+    }
+
+    fn to_tool_definition(&self) -> ToolDefinition {
+        let mut properties = HashMap::new();
+        properties.insert(
+            "content".to_string(),
+            string_parameter("新开场白的完整内容", None),
+        );
+
+        ToolDefinition {
+            tool_type: "function".to_string(),
+            function: ToolFunction {
+                name: self.name().to_string(),
+                description: Some(self.description().to_string()),
+                parameters: Some(ToolParameters {
+                    param_type: "object".to_string(),
+                    properties,
+                    required: Some(vec!["content".to_string()]),
+                }),
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl AIToolTrait for EditAlternateGreetingTool {
+    fn name(&self) -> &'static str {
+        "edit_alternate_greeting"
+    }
+
+    fn description(&self) -> &'static str {
+        "按索引修改或删除当前角色的单条备选开场白。index 从 0 开始，对应 alternate_greetings 中的位置；action 为 update（需提供 content）或 delete。"
+    }
+
+    fn category(&self) -> &'static str {
+        "character"
+    }
+
+    async fn execute(&self, app_handle: &AppHandle, request: &ToolCallRequest) -> ToolResult {
+        let start_time = std::time::Instant::now();
+
+        let index = match get_usize_parameter(&request.parameters, "index") {
+            Some(index) => index,
+            None => {
+                return failure_result(start_time, "invalid_index", "index 必须是非负整数", None)
+            }
+        };
+
+        let edit = match parse_edit(&request.parameters) {
+            Ok(edit) => edit,
+            Err(error) => return failure_result(start_time, "invalid_action", error, None),
+        };
+        let deleted = edit == GreetingEdit::Delete;
+
+        update_greetings(app_handle, request, start_time, |card| {
+            let previous = edit_greeting(&mut card.data.alternate_greetings, index, edit)?;
+            Ok(json!({
+                "message": if deleted { "备选开场白已删除" } else { "备选开场白已修改" },
+                "index": index,
+                "previous_content": previous,
+                "total": card.data.alternate_greetings.len(),
+            }))
+        })
+    }
+
+    fn to_tool_definition(&self) -> ToolDefinition {
+        let mut properties = HashMap::new();
+        properties.insert(
+            "index".to_string(),
+            ChatToolParameter {
+                param_type: "integer".to_string(),
+                description: Some("备选开场白索引，从 0 开始".to_string()),
+                enum_values: None,
+                items: None,
+                properties: None,
+                required: None,
+            },
+        );
+        properties.insert(
+            "action".to_string(),
+            string_parameter(
+                "操作类型：update 修改（默认），delete 删除",
+                Some(vec!["update".to_string(), "delete".to_string()]),
+            ),
+        );
+        properties.insert(
+            "content".to_string(),
+            string_parameter("action=update 时的新内容", None),
+        );
+
+        ToolDefinition {
+            tool_type: "function".to_string(),
+            function: ToolFunction {
+                name: self.name().to_string(),
+                description: Some(self.description().to_string()),
+                parameters: Some(ToolParameters {
+                    param_type: "object".to_string(),
+                    properties,
+                    required: Some(vec!["index".to_string()]),
+                }),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{append_greeting, edit_greeting, GreetingEdit};
+
+    #[test]
+    fn append_adds_trimmed_greeting_at_the_end() {
+        let mut greetings = vec!["Hi there.".to_string()];
+
+        let index = append_greeting(&mut greetings, "  Good evening.\n").unwrap();
+
+        assert_eq!(index, 1);
+        assert_eq!(greetings, vec!["Hi there.", "Good evening."]);
+        assert!(append_greeting(&mut greetings, "   ").is_err());
+        assert_eq!(greetings.len(), 2);
+    }
+
+    #[test]
+    fn edit_and_delete_target_a_single_greeting() {
+        let mut greetings = vec!["A".to_string(), "B".to_string(), "C".to_string()];
+
+        let previous =
+            edit_greeting(&mut greetings, 1, GreetingEdit::Update("B2".to_string())).unwrap();
+        assert_eq!(previous, "B");
+
+        let removed = edit_greeting(&mut greetings, 0, GreetingEdit::Delete).unwrap();
+        assert_eq!(removed, "A");
+        assert_eq!(greetings, vec!["B2", "C"]);
+    }
+
+    #[test]
+    fn deleting_out_of_range_index_fails() {
+        let mut greetings = vec!["A".to_string()];
+
+        let error = edit_greeting(&mut greetings, 3, GreetingEdit::Delete).unwrap_err();
+
+        assert!(error.contains("超出范围"));
+        assert_eq!(greetings, vec!["A"]);
+    }
+}
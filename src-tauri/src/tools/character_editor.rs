@@ -336,7 +336,10 @@ impl AIToolTrait for EditCharacterTool {
             "alternate_greetings".to_string(),
             ChatToolParameter {
                 param_type: "string".to_string(),
-                description: Some("备用问候语，使用 <START_ALT> 标记每段开头".to_string()),
+                description: Some(
+                    "备用问候语，使用 <START_ALT> 标记每段开头；只增改删单条时请使用 add_alternate_greeting / edit_alternate_greeting"
+                        .to_string(),
+                ),
                 enum_values: None,
                 items: None,
                 properties: None,
@@ -1,4 +1,9 @@
 use crate::character_storage::TavernCardV2;
+use crate::context_builder::{count_character_macros, substitute_character_macros};
+use regex::Regex;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
 
 pub const LONG_TEXT_FIELDS: &[(&str, &str)] = &[
     ("description", "角色描述"),
@@ -77,9 +82,243 @@ pub fn parse_tags(value: &str) -> Vec<String> {
         .collect()
 }
 
+/// 开场白总数（first_mes + alternate_greetings），有效索引为 0..count
+pub fn greeting_count(card: &TavernCardV2) -> usize {
+    1 + card.data.alternate_greetings.len()
+}
+
+/// 按索引取开场白：-1 为 first_mes，0..n 对应 alternate_greetings，小于 -1 的索引无效
+pub fn select_greeting(card: &TavernCardV2, index: i32) -> Result<&str, String> {
+    if index == -1 {
+        return Ok(&card.data.first_mes);
+    }
+    if index < -1 {
+        return Err(format!(
+            "开场白索引 {} 无效（-1 为 first_mes，0 起为备选开场白）",
+            index
+        ));
+    }
+
+    card.data
+        .alternate_greetings
+        .get(index as usize)
+        .map(String::as_str)
+        .ok_or_else(|| {
+            format!(
+                "开场白索引 {} 超出范围（-1 为 first_mes，备选开场白共 {} 条）",
+                index,
+                card.data.alternate_greetings.len()
+            )
+        })
+}
+
+/// 开场白中可以被正确替换的占位符（不区分大小写）
+pub const KNOWN_GREETING_MACROS: &[&str] = &["char", "user"];
+
+/// 单条开场白中无法识别的占位符
+#[derive(Debug, Clone, Serialize)]
+pub struct GreetingMacroIssue {
+    /// 开场白索引：0 为 first_mes，之后依次为 alternate_greetings
+    pub index: usize,
+    pub macros: Vec<String>,
+}
+
+fn macro_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"\{\{([^{}]*)\}\}").expect("macro pattern should compile"))
+}
+
+/// 找出文本中不在 KNOWN_GREETING_MACROS 里的 {{...}} 占位符（按出现顺序去重）
+pub fn unknown_macros(text: &str) -> Vec<String> {
+    let mut macros: Vec<String> = Vec::new();
+    for captures in macro_pattern().captures_iter(text) {
+        let name = captures[1].trim();
+        let known = KNOWN_GREETING_MACROS
+            .iter()
+            .any(|known| known.eq_ignore_ascii_case(name));
+        let full = captures[0].to_string();
+        if !known && !macros.contains(&full) {
+            macros.push(full);
+        }
+    }
+    macros
+}
+
+/// 检查 first_mes 与 alternate_greetings，只返回包含未知占位符的开场白
+pub fn lint_greeting_macros(card: &TavernCardV2) -> Vec<GreetingMacroIssue> {
+    std::iter::once(&card.data.first_mes)
+        .chain(card.data.alternate_greetings.iter())
+        .enumerate()
+        .filter_map(|(index, greeting)| {
+            let macros = unknown_macros(greeting);
+            (!macros.is_empty()).then_some(GreetingMacroIssue { index, macros })
+        })
+        .collect()
+}
+
+/// 角色卡中出现的占位符及出现次数
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MacroUsage {
+    /// 原样的占位符文本，如 {{char}}
+    pub name: String,
+    pub count: usize,
+}
+
+/// 统计角色卡文本字段、备选开场白和世界书内容中的 {{...}} 占位符（按次数降序）
+pub fn collect_used_macros(card: &TavernCardV2) -> Vec<MacroUsage> {
+    let data = &card.data;
+    let world_book_contents = data
+        .character_book
+        .iter()
+        .flat_map(|book| book.entries.iter().map(|entry| &entry.content));
+    let texts = [
+        &data.description,
+        &data.personality,
+        &data.scenario,
+        &data.first_mes,
+        &data.mes_example,
+        &data.creator_notes,
+        &data.system_prompt,
+        &data.post_history_instructions,
+    ]
+    .into_iter()
+    .chain(data.alternate_greetings.iter())
+    .chain(world_book_contents);
+
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for text in texts {
+        for found in macro_pattern().find_iter(text) {
+            *counts.entry(found.as_str().to_string()).or_default() += 1;
+        }
+    }
+
+    let mut usages: Vec<MacroUsage> = counts
+        .into_iter()
+        .map(|(name, count)| MacroUsage { name, count })
+        .collect();
+    usages.sort_by_key(|usage| std::cmp::Reverse(usage.count));
+    usages
+}
+
+/// 把角色卡文本字段、备选开场白和世界书内容中的 {{char}} / {{user}} 固化为实际名称，返回替换次数
+pub fn bake_card_placeholders(card: &mut TavernCardV2, user_name: &str) -> usize {
+    let char_name = card.data.name.clone();
+    let data = &mut card.data;
+    let world_book_contents = data
+        .character_book
+        .iter_mut()
+        .flat_map(|book| book.entries.iter_mut().map(|entry| &mut entry.content));
+    let texts = [
+        &mut data.description,
+        &mut data.personality,
+        &mut data.scenario,
+        &mut data.first_mes,
+        &mut data.mes_example,
+        &mut data.creator_notes,
+        &mut data.system_prompt,
+        &mut data.post_history_instructions,
+    ]
+    .into_iter()
+    .chain(data.alternate_greetings.iter_mut())
+    .chain(world_book_contents);
+
+    let mut substitutions = 0;
+    for text in texts {
+        let count = count_character_macros(text);
+        if count > 0 {
+            *text = substitute_character_macros(text, &char_name, user_name);
+            substitutions += count;
+        }
+    }
+    substitutions
+}
+
+/// 单个字段的完整度检查结果
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldCompleteness {
+    pub field: String,
+    /// 实际长度：文本为字符数，标签与世界书为条目数
+    pub length: usize,
+    /// 达标所需的最小长度
+    pub threshold: usize,
+    pub complete: bool,
+}
+
+/// 角色卡完整度报告：score 为达标字段占比（0–100）
+#[derive(Debug, Clone, Serialize)]
+pub struct CompletenessReport {
+    pub score: u8,
+    pub fields: Vec<FieldCompleteness>,
+}
+
+/// 按关键字段的长度阈值给角色卡完整度打分（只读）
+pub fn score_card_completeness(card: &TavernCardV2) -> CompletenessReport {
+    let data = &card.data;
+    let world_book_entries = data
+        .character_book
+        .as_ref()
+        .map(|book| {
+            book.entries
+                .iter()
+                .filter(|entry| !entry.content.trim().is_empty())
+                .count()
+        })
+        .unwrap_or(0);
+    let text_len = |text: &str| text.trim().chars().count();
+
+    let fields: Vec<FieldCompleteness> = [
+        ("description", text_len(&data.description), 200),
+        ("personality", text_len(&data.personality), 50),
+        ("scenario", text_len(&data.scenario), 50),
+        ("first_mes", text_len(&data.first_mes), 50),
+        ("mes_example", text_len(&data.mes_example), 100),
+        (
+            "tags",
+            data.tags
+                .iter()
+                .filter(|tag| !tag.trim().is_empty())
+                .count(),
+            1,
+        ),
+        ("world_book", world_book_entries, 1),
+    ]
+    .into_iter()
+    .map(|(field, length, threshold)| FieldCompleteness {
+        field: field.to_string(),
+        length,
+        threshold,
+        complete: length >= threshold,
+    })
+    .collect();
+
+    let completed = fields.iter().filter(|field| field.complete).count();
+    let score = (completed * 100 / fields.len()) as u8;
+
+    CompletenessReport { score, fields }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{long_text_field_names, parse_alternate_greetings, parse_tags, slice_by_chars};
+    use super::{
+        bake_card_placeholders, collect_used_macros, greeting_count, lint_greeting_macros,
+        long_text_field_names, parse_alternate_greetings, parse_tags, score_card_completeness,
+        slice_by_chars, MacroUsage,
+    };
+    use crate::character_storage::test_support::sample_card;
+    use crate::character_storage::CharacterBook;
+    use serde_json::json;
+
+    fn single_entry_book(keys: &[&str], content: &str) -> CharacterBook {
+        serde_json::from_value(json!({
+            "entries": [{
+                "keys": keys,
+                "content": content,
+                "enabled": true,
+                "insertion_order": 0
+            }]
+        }))
+        .unwrap()
+    }
 
     #[test]
     fn long_text_fields_are_shared() {
@@ -100,4 +339,99 @@ mod tests {
         );
         assert_eq!(parse_tags("a, b\nc"), vec!["a", "b", "c"]);
     }
+
+    #[test]
+    fn greeting_count_includes_first_message_and_alternates() {
+        let mut card = sample_card();
+        card.data.first_mes = "Hello!".to_string();
+        card.data.alternate_greetings = vec!["Hi there.".to_string(), "Good evening.".to_string()];
+
+        assert_eq!(greeting_count(&card), 3);
+    }
+
+    #[test]
+    fn lint_greeting_macros_reports_unknown_placeholders() {
+        let mut card = sample_card();
+        card.data.first_mes = "Hello {{user}}, I am {{Char}}.".to_string();
+        card.data.alternate_greetings = vec![
+            "Roll: {{random:1,2,3}} and {{random}} {{random}}".to_string(),
+            "Plain greeting.".to_string(),
+        ];
+
+        let issues = lint_greeting_macros(&card);
+
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].index, 1);
+        assert_eq!(issues[0].macros, vec!["{{random:1,2,3}}", "{{random}}"]);
+    }
+
+    #[test]
+    fn bake_card_placeholders_replaces_macros_everywhere() {
+        let mut card = sample_card();
+        card.data.description = "{{char}} protects {{user}}.".to_string();
+        card.data.first_mes = "Hi {{USER}}!".to_string();
+        card.data.alternate_greetings = vec!["{{char}} waves.".to_string()];
+        card.data.character_book = Some(single_entry_book(
+            &["{{char}}"],
+            "{{char}} was born in Aster.",
+        ));
+
+        assert_eq!(bake_card_placeholders(&mut card, "Bob"), 5);
+        assert_eq!(card.data.description, "Alice protects Bob.");
+        assert_eq!(card.data.first_mes, "Hi Bob!");
+        assert_eq!(card.data.alternate_greetings, vec!["Alice waves."]);
+        let entry = &card.data.character_book.as_ref().unwrap().entries[0];
+        assert_eq!(entry.content, "Alice was born in Aster.");
+        assert_eq!(entry.keys, vec!["{{char}}"]);
+
+        assert_eq!(bake_card_placeholders(&mut card, "Bob"), 0);
+    }
+
+    #[test]
+    fn completeness_scores_empty_card_low_and_full_card_high() {
+        let empty = sample_card();
+
+        let report = score_card_completeness(&empty);
+        assert_eq!(report.score, 0);
+        assert!(report.fields.iter().all(|field| !field.complete));
+
+        let mut full = sample_card();
+        full.data.description = "A".repeat(200);
+        full.data.personality = "B".repeat(50);
+        full.data.scenario = "C".repeat(50);
+        full.data.first_mes = "D".repeat(50);
+        full.data.mes_example = "E".repeat(100);
+        full.data.tags = vec!["fantasy".to_string()];
+        full.data.character_book = Some(single_entry_book(&["Aster"], "Alice was born in Aster."));
+
+        let report = score_card_completeness(&full);
+        assert_eq!(report.score, 100);
+        assert_eq!(report.fields.len(), 7);
+        assert!(report.fields.iter().all(|field| field.complete));
+    }
+
+    #[test]
+    fn used_macros_are_counted_across_fields_and_world_book() {
+        let mut card = sample_card();
+        card.data.description = "{{char}} guards the gate.".to_string();
+        card.data.first_mes = "Welcome, {{user}}.".to_string();
+        card.data.character_book = Some(single_entry_book(
+            &["gate"],
+            "{{char}} never leaves the gate.",
+        ));
+
+        assert_eq!(
+            collect_used_macros(&card),
+            vec![
+                MacroUsage {
+                    name: "{{char}}".to_string(),
+                    count: 2,
+                },
+                MacroUsage {
+                    name: "{{user}}".to_string(),
+                    count: 1,
+                },
+            ]
+        );
+    }
 }
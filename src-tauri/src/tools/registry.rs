@@ -82,6 +82,29 @@ impl ToolRegistry {
             .collect()
     }
 
+    /// 按名称、描述和分类搜索工具（不区分大小写，忽略开头的 /），结果按名称排序
+    pub fn search_tools(&self, query: &str) -> Vec<ToolDefinition> {
+        let normalized_query = query.trim().trim_start_matches('/').to_lowercase();
+
+        let mut tools: Vec<_> = self
+            .tools
+            .values()
+            .filter(|tool| tool.enabled())
+            .filter(|tool| {
+                normalized_query.is_empty()
+                    || tool.name().to_lowercase().contains(&normalized_query)
+                    || tool
+                        .description()
+                        .to_lowercase()
+                        .contains(&normalized_query)
+                    || tool.category().to_lowercase().contains(&normalized_query)
+            })
+            .map(|tool| tool.to_tool_definition())
+            .collect();
+        tools.sort_by(|a, b| a.function.name.cmp(&b.function.name));
+        tools
+    }
+
     // ========== 便捷的静态方法 ==========
 
     /// 获取所有可用工具（静态方法）
@@ -101,6 +124,12 @@ impl ToolRegistry {
         let registry = TOOL_REGISTRY.read().unwrap();
         registry.get_tools_by_category(category)
     }
+
+    /// 搜索工具（静态方法）
+    pub fn search_tools_global(query: &str) -> Vec<ToolDefinition> {
+        let registry = TOOL_REGISTRY.read().unwrap();
+        registry.search_tools(query)
+    }
 }
 
 // 全局工具注册中心实例
@@ -111,6 +140,8 @@ pub static ref TOOL_REGISTRY: std::sync::RwLock<ToolRegistry> = {
           registry.register_tool(super::character_editor::EditCharacterTool);
           registry.register_tool(super::character_field_patcher::PatchCharacterFieldTool);
           registry.register_tool(super::read_character_field::ReadCharacterFieldTool);
+          registry.register_tool(super::alternate_greeting_editor::AddAlternateGreetingTool);
+          registry.register_tool(super::alternate_greeting_editor::EditAlternateGreetingTool);
           registry.register_tool(super::world_book_creator::CreateWorldBookEntryTool);
           registry.register_tool(super::world_book_lister::ListWorldBookEntriesTool);
           registry.register_tool(super::world_book_reader::ReadWorldBookEntryTool);
@@ -119,3 +150,28 @@ pub static ref TOOL_REGISTRY: std::sync::RwLock<ToolRegistry> = {
           std::sync::RwLock::new(registry)
       };
   }
+
+#[cfg(test)]
+mod tests {
+    use super::ToolRegistry;
+
+    #[test]
+    fn search_matches_world_book_tools_case_insensitively() {
+        let names: Vec<String> = ToolRegistry::search_tools_global("/World")
+            .into_iter()
+            .map(|tool| tool.function.name)
+            .collect();
+
+        assert_eq!(
+            names,
+            vec![
+                "create_world_book_entry",
+                "delete_world_book_entry",
+                "list_world_book_entries",
+                "read_world_book_entry",
+                "update_world_book_entry",
+            ]
+        );
+        assert!(ToolRegistry::search_tools_global("no-such-capability").is_empty());
+    }
+}
@@ -4,7 +4,7 @@ use crate::ai_tools::{
     ToolParameters, ToolResult,
 };
 use crate::character_storage::{CharacterBook, CharacterStorage, WorldBookEntry};
-use crate::tools::world_book_shared::build_content_preview;
+use crate::tools::world_book_shared::{build_content_preview, validate_entry_range_parameters};
 use async_trait::async_trait;
 use std::collections::HashMap;
 use tauri::{AppHandle, Emitter};
@@ -117,7 +117,7 @@ fn validate_required_parameters(
         return Err(error_result(start_time, format!("缺少必填参数: {}", field)));
     }
 
-    Ok(())
+    validate_entry_range_parameters(parameters).map_err(|error| error_result(start_time, error))
 }
 
 fn load_character_data(
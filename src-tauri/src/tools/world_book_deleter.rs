@@ -4,7 +4,7 @@ use crate::ai_tools::{
     ToolParameters, ToolResult,
 };
 use crate::character_storage::CharacterStorage;
-use crate::tools::world_book_shared::{locate_entry, summarize_entry};
+use crate::tools::world_book_shared::{remove_entry, summarize_entry};
 use async_trait::async_trait;
 use serde_json::json;
 use std::collections::HashMap;
@@ -75,22 +75,22 @@ impl AIToolTrait for DeleteWorldBookEntryTool {
             }
         };
 
-        let selection = match locate_entry(&world_book.entries, &request.parameters, "删除") {
-            Ok(selection) => selection,
-            Err(error) => {
-                return ToolResult {
-                    success: false,
-                    data: Some(json!({
-                        "error_code": error.code,
-                        "details": error.details,
-                    })),
-                    error: Some(error.message),
-                    execution_time_ms: start_time.elapsed().as_millis() as u64,
-                };
-            }
-        };
+        let (removed_entry, selection) =
+            match remove_entry(&mut world_book.entries, &request.parameters) {
+                Ok(removed) => removed,
+                Err(error) => {
+                    return ToolResult {
+                        success: false,
+                        data: Some(json!({
+                            "error_code": error.code,
+                            "details": error.details,
+                        })),
+                        error: Some(error.message),
+                        execution_time_ms: start_time.elapsed().as_millis() as u64,
+                    };
+                }
+            };
 
-        let removed_entry = world_book.entries.remove(selection.index);
         let removed_entry_id = removed_entry.id.unwrap_or_default();
         let removed_entry_name = removed_entry.name.clone();
         let removed_entry_keys = removed_entry.keys.clone();
@@ -1,8 +1,14 @@
 use crate::character_storage::WorldBookEntry;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::collections::{HashMap, HashSet};
 
 const CONTENT_PREVIEW_CHAR_LIMIT: usize = 50;
+pub const MAX_ENTRY_DEPTH: i32 = 1000;
+
+/// 需要校验取值范围的条目参数
+const RANGED_ENTRY_PARAMETERS: &[(&str, i32, i32)] =
+    &[("probability", 0, 100), ("depth", 0, MAX_ENTRY_DEPTH)];
 
 #[derive(Debug, Clone)]
 pub struct EntrySelection {
@@ -65,6 +71,28 @@ pub fn get_usize_parameter(parameters: &HashMap<String, Value>, key: &str) -> Op
         .and_then(|text| text.trim().parse::<usize>().ok())
 }
 
+/// 校验 probability/depth 参数：提供时必须是范围内的整数
+pub fn validate_entry_range_parameters(parameters: &HashMap<String, Value>) -> Result<(), String> {
+    for (key, min, max) in RANGED_ENTRY_PARAMETERS {
+        if !parameters.contains_key(*key) {
+            continue;
+        }
+
+        match get_i32_parameter(parameters, key) {
+            Some(value) if (*min..=*max).contains(&value) => {}
+            Some(value) => {
+                return Err(format!(
+                    "{} 超出范围：{}（允许 {}-{}）",
+                    key, value, min, max
+                ))
+            }
+            None => return Err(format!("{} 必须是整数", key)),
+        }
+    }
+
+    Ok(())
+}
+
 pub fn build_content_preview(content: &str) -> String {
     if content.chars().count() > CONTENT_PREVIEW_CHAR_LIMIT {
         let truncated: String = content.chars().take(CONTENT_PREVIEW_CHAR_LIMIT).collect();
@@ -264,6 +292,242 @@ pub fn entry_matches_query(entry: &WorldBookEntry, query: &str) -> bool {
         .any(|value| value.to_ascii_lowercase().contains(&query_lower))
 }
 
+/// 判断单个关键词是否出现在文本中，遵循条目的大小写与整词匹配设置
+pub fn key_matches_text(entry: &WorldBookEntry, key: &str, text: &str) -> bool {
+    let key = key.trim();
+    if key.is_empty() {
+        return false;
+    }
+
+    let case_sensitive = entry.case_sensitive.unwrap_or(false);
+    let whole_words = entry
+        .extensions
+        .get("match_whole_words")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+
+    let (haystack, needle) = if case_sensitive {
+        (text.to_string(), key.to_string())
+    } else {
+        (text.to_lowercase(), key.to_lowercase())
+    };
+
+    if !whole_words {
+        return haystack.contains(&needle);
+    }
+
+    let is_word_char = |ch: char| ch.is_alphanumeric() || ch == '_';
+    haystack.match_indices(&needle).any(|(start, matched)| {
+        let before = haystack[..start].chars().next_back();
+        let after = haystack[start + matched.len()..].chars().next();
+        !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+    })
+}
+
+/// 判断条目是否会被文本激活（主关键词命中，选择性条目还需命中次关键词）
+pub fn entry_activated_by_text(entry: &WorldBookEntry, text: &str) -> bool {
+    if !entry
+        .keys
+        .iter()
+        .any(|key| key_matches_text(entry, key, text))
+    {
+        return false;
+    }
+
+    if !entry.selective.unwrap_or(false) {
+        return true;
+    }
+
+    match entry.secondary_keys.as_deref() {
+        Some(secondary_keys) if !secondary_keys.is_empty() => secondary_keys
+            .iter()
+            .any(|key| key_matches_text(entry, key, text)),
+        _ => true,
+    }
+}
+
+/// 找出在所有样本消息下都不会被激活的启用条目（常驻条目除外）
+pub fn find_dead_entries(
+    entries: &[WorldBookEntry],
+    sample_messages: &[String],
+) -> Vec<WorldBookEntry> {
+    entries
+        .iter()
+        .filter(|entry| entry.enabled && !entry.constant.unwrap_or(false))
+        .filter(|entry| {
+            !sample_messages
+                .iter()
+                .any(|message| entry_activated_by_text(entry, message))
+        })
+        .cloned()
+        .collect()
+}
+
+/// 关键词清理结果
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct KeyCleanup {
+    /// 删除的关键词数量
+    pub removed: usize,
+    /// 是否有关键词发生变化（包括只去除了首尾空白）
+    pub changed: bool,
+}
+
+impl KeyCleanup {
+    fn merge(&mut self, other: KeyCleanup) {
+        self.removed += other.removed;
+        self.changed |= other.changed;
+    }
+}
+
+/// 清理关键词：去除首尾空白、删除空关键词并去重。
+/// case_sensitive 为 None 时使用各条目自身的 case_sensitive 设置。
+pub fn clean_entry_keys(
+    entries: &mut [WorldBookEntry],
+    case_sensitive: Option<bool>,
+) -> KeyCleanup {
+    fn clean_keys(keys: &mut Vec<String>, case_sensitive: bool) -> KeyCleanup {
+        let mut seen = HashSet::new();
+
+        let cleaned: Vec<String> = keys
+            .iter()
+            .map(|key| key.trim().to_string())
+            .filter(|key| !key.is_empty())
+            .filter(|key| {
+                if case_sensitive {
+                    seen.insert(key.clone())
+                } else {
+                    seen.insert(key.to_lowercase())
+                }
+            })
+            .collect();
+
+        let cleanup = KeyCleanup {
+            removed: keys.len() - cleaned.len(),
+            changed: *keys != cleaned,
+        };
+        *keys = cleaned;
+        cleanup
+    }
+
+    let mut total = KeyCleanup::default();
+    for entry in entries.iter_mut() {
+        let case_sensitive =
+            case_sensitive.unwrap_or_else(|| entry.case_sensitive.unwrap_or(false));
+        total.merge(clean_keys(&mut entry.keys, case_sensitive));
+        if let Some(secondary_keys) = entry.secondary_keys.as_mut() {
+            total.merge(clean_keys(secondary_keys, case_sensitive));
+        }
+    }
+    total
+}
+
+/// 按 ordered_ids 的顺序重排条目，并把 insertion_order 依次改写为 0..n。
+/// ordered_ids 必须与现有条目 id 完全一致（不能缺少、多余或重复）
+pub fn reorder_entries(entries: &mut [WorldBookEntry], ordered_ids: &[i32]) -> Result<(), String> {
+    let mut positions = HashMap::new();
+    for (position, id) in ordered_ids.iter().enumerate() {
+        if positions.insert(*id, position).is_some() {
+            return Err(format!("条目 id {} 重复", id));
+        }
+    }
+
+    let mut existing_ids = HashSet::new();
+    for entry in entries.iter() {
+        let id = entry
+            .id
+            .ok_or_else(|| "存在没有 id 的世界书条目，无法按 id 排序".to_string())?;
+        if !existing_ids.insert(id) {
+            return Err(format!("世界书中存在重复的条目 id {}", id));
+        }
+    }
+
+    let mut missing: Vec<i32> = existing_ids
+        .iter()
+        .filter(|id| !positions.contains_key(id))
+        .copied()
+        .collect();
+    let mut unknown: Vec<i32> = ordered_ids
+        .iter()
+        .filter(|id| !existing_ids.contains(id))
+        .copied()
+        .collect();
+    if !missing.is_empty() || !unknown.is_empty() {
+        missing.sort_unstable();
+        unknown.sort_unstable();
+        return Err(format!(
+            "条目 id 与现有世界书不一致，缺少: {:?}，多余: {:?}",
+            missing, unknown
+        ));
+    }
+
+    entries.sort_by_key(|entry| entry.id.map(|id| positions[&id]));
+    for (order, entry) in entries.iter_mut().enumerate() {
+        entry.insertion_order = order as i32;
+    }
+
+    Ok(())
+}
+
+/// 合并世界书的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorldBookMergeStrategy {
+    /// 全部追加到末尾
+    Append,
+    /// 名称相同的条目覆盖目标条目，其余追加
+    ReplaceByName,
+}
+
+/// 把 source 的条目合并进 target，返回追加与覆盖的条目数。
+/// 追加的条目会在目标现有最大值之后重新分配 id 与 insertion_order；
+/// 覆盖时保留目标条目原有的 id 与 insertion_order。
+pub fn merge_world_book_entries(
+    target: &mut Vec<WorldBookEntry>,
+    source: &[WorldBookEntry],
+    strategy: WorldBookMergeStrategy,
+) -> usize {
+    let mut next_id = target
+        .iter()
+        .filter_map(|entry| entry.id)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    let mut next_order = target
+        .iter()
+        .map(|entry| entry.insertion_order)
+        .max()
+        .unwrap_or(0)
+        + 1;
+
+    for entry in source {
+        if strategy == WorldBookMergeStrategy::ReplaceByName {
+            let name = entry.name.as_deref().map(str::trim).unwrap_or_default();
+            let existing = target.iter_mut().find(|candidate| {
+                !name.is_empty() && candidate.name.as_deref().map(str::trim) == Some(name)
+            });
+            if let Some(existing) = existing {
+                let (id, insertion_order) = (existing.id, existing.insertion_order);
+                *existing = WorldBookEntry {
+                    id,
+                    insertion_order,
+                    ..entry.clone()
+                };
+                continue;
+            }
+        }
+
+        target.push(WorldBookEntry {
+            id: Some(next_id),
+            insertion_order: next_order,
+            ..entry.clone()
+        });
+        next_id += 1;
+        next_order += 1;
+    }
+
+    source.len()
+}
+
 pub fn unique_fragments_from_text(text: &str, min_chars: usize) -> Vec<String> {
     let mut seen = HashSet::new();
     let mut fragments = Vec::new();
@@ -315,6 +579,16 @@ fn ambiguous_lookup_error(
     }
 }
 
+/// 按 entry_id / name / key 定位并移除单个条目
+pub fn remove_entry(
+    entries: &mut Vec<WorldBookEntry>,
+    parameters: &HashMap<String, Value>,
+) -> Result<(WorldBookEntry, EntrySelection), EntryLookupError> {
+    let selection = locate_entry(entries, parameters, "删除")?;
+    let removed_entry = entries.remove(selection.index);
+    Ok((removed_entry, selection))
+}
+
 fn value_to_i32(value: &Value) -> Option<i32> {
     if let Some(number) = value.as_i64() {
         return i32::try_from(number).ok();
@@ -327,7 +601,11 @@ fn value_to_i32(value: &Value) -> Option<i32> {
 
 #[cfg(test)]
 mod tests {
-    use super::{locate_entry, summarize_entry};
+    use super::{
+        clean_entry_keys, find_dead_entries, locate_entry, merge_world_book_entries, remove_entry,
+        reorder_entries, summarize_entry, validate_entry_range_parameters, WorldBookMergeStrategy,
+        MAX_ENTRY_DEPTH,
+    };
     use crate::character_storage::WorldBookEntry;
     use serde_json::json;
     use std::collections::HashMap;
@@ -351,6 +629,82 @@ mod tests {
         }
     }
 
+    #[test]
+    fn append_merge_renumbers_ids_and_insertion_orders() {
+        let mut target = vec![
+            sample_entry(1, "castle", "castle"),
+            sample_entry(4, "town", "town"),
+        ];
+        let source = vec![
+            sample_entry(1, "forest", "forest"),
+            sample_entry(2, "town", "inn"),
+        ];
+
+        let merged = merge_world_book_entries(&mut target, &source, WorldBookMergeStrategy::Append);
+
+        assert_eq!(merged, 2);
+        let numbering: Vec<(Option<i32>, i32)> = target
+            .iter()
+            .map(|entry| (entry.id, entry.insertion_order))
+            .collect();
+        assert_eq!(
+            numbering,
+            vec![(Some(1), 1), (Some(4), 4), (Some(5), 5), (Some(6), 6)]
+        );
+        assert_eq!(target[2].content, "content-1");
+    }
+
+    #[test]
+    fn replace_merge_overwrites_entries_with_matching_name() {
+        let mut target = vec![
+            sample_entry(1, "castle", "castle"),
+            sample_entry(2, "town", "town"),
+        ];
+        let source = vec![
+            sample_entry(9, "town", "inn"),
+            sample_entry(3, "forest", "forest"),
+        ];
+
+        merge_world_book_entries(&mut target, &source, WorldBookMergeStrategy::ReplaceByName);
+
+        assert_eq!(target.len(), 3);
+        assert_eq!(target[1].keys, vec!["inn".to_string()]);
+        assert_eq!(target[1].content, "content-9");
+        assert_eq!((target[1].id, target[1].insertion_order), (Some(2), 2));
+        assert_eq!(target[2].name.as_deref(), Some("forest"));
+        assert_eq!(target[2].id, Some(3));
+    }
+
+    #[test]
+    fn clean_entry_keys_trims_and_dedupes() {
+        let mut entry = sample_entry(1, "火焰", "Fire");
+        entry.keys = vec![
+            "Fire".to_string(),
+            "fire".to_string(),
+            " fire ".to_string(),
+            "  ".to_string(),
+        ];
+        let mut entries = vec![entry];
+
+        let mut case_sensitive_entries = entries.clone();
+        assert_eq!(
+            clean_entry_keys(&mut case_sensitive_entries, Some(true)).removed,
+            2
+        );
+        assert_eq!(case_sensitive_entries[0].keys, vec!["Fire", "fire"]);
+
+        assert_eq!(clean_entry_keys(&mut entries, None).removed, 3);
+        assert_eq!(entries[0].keys, vec!["Fire"]);
+
+        let mut trimmed_only = vec![sample_entry(2, "水", "Water")];
+        trimmed_only[0].keys = vec![" Water ".to_string()];
+        let cleanup = clean_entry_keys(&mut trimmed_only, None);
+        assert_eq!(cleanup.removed, 0);
+        assert!(cleanup.changed);
+        assert_eq!(trimmed_only[0].keys, vec!["Water"]);
+        assert!(!clean_entry_keys(&mut trimmed_only, None).changed);
+    }
+
     #[test]
     fn locate_entry_by_id() {
         let entries = vec![
@@ -365,6 +719,26 @@ mod tests {
         assert_eq!(selection.matched_by, "entry_id");
     }
 
+    #[test]
+    fn remove_entry_by_id_keeps_other_entries() {
+        let mut entries = vec![
+            sample_entry(1, "Alpha", "alpha"),
+            sample_entry(2, "Beta", "beta"),
+        ];
+        let mut params = HashMap::new();
+        params.insert("entry_id".to_string(), json!(1));
+
+        let (removed, selection) = remove_entry(&mut entries, &params).expect("should remove");
+        assert_eq!(removed.id, Some(1));
+        assert_eq!(selection.matched_by, "entry_id");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].id, Some(2));
+
+        let error = remove_entry(&mut entries, &params).expect_err("entry already removed");
+        assert_eq!(error.code, "entry_not_found");
+        assert_eq!(entries.len(), 1);
+    }
+
     #[test]
     fn locate_entry_reports_candidates() {
         let entries = vec![
@@ -385,4 +759,90 @@ mod tests {
         assert_eq!(summary["id"], json!(1));
         assert!(summary["content_preview"].as_str().is_some());
     }
+
+    #[test]
+    fn find_dead_entries_reports_unmatched_keys() {
+        let mut constant_entry = sample_entry(3, "Gamma", "never-mentioned");
+        constant_entry.constant = Some(true);
+        let entries = vec![
+            sample_entry(1, "Alpha", "dragon"),
+            sample_entry(2, "Beta", "unicorn"),
+            constant_entry,
+        ];
+        let samples = vec![
+            "The Dragon attacks the village".to_string(),
+            "Everyone runs away".to_string(),
+        ];
+
+        let dead = find_dead_entries(&entries, &samples);
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].id, Some(2));
+    }
+
+    #[test]
+    fn validate_entry_ranges_accepts_boundaries() {
+        let mut params = HashMap::new();
+        params.insert("probability".to_string(), json!(0));
+        params.insert("depth".to_string(), json!("0"));
+        assert!(validate_entry_range_parameters(&params).is_ok());
+
+        params.insert("probability".to_string(), json!("100"));
+        params.insert("depth".to_string(), json!(MAX_ENTRY_DEPTH));
+        assert!(validate_entry_range_parameters(&params).is_ok());
+
+        assert!(validate_entry_range_parameters(&HashMap::new()).is_ok());
+    }
+
+    #[test]
+    fn validate_entry_ranges_rejects_invalid_values() {
+        for (key, value) in [
+            ("probability", json!(101)),
+            ("probability", json!("500")),
+            ("probability", json!(-1)),
+            ("probability", json!("often")),
+            ("depth", json!(-3)),
+            ("depth", json!(MAX_ENTRY_DEPTH + 1)),
+        ] {
+            let mut params = HashMap::new();
+            params.insert(key.to_string(), value.clone());
+            assert!(
+                validate_entry_range_parameters(&params).is_err(),
+                "{key}={value} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn reorder_entries_rewrites_insertion_order_sequentially() {
+        let mut entries = vec![
+            sample_entry(1, "Aster", "aster"),
+            sample_entry(2, "Rowan", "rowan"),
+            sample_entry(3, "Vale", "vale"),
+        ];
+        for entry in &mut entries {
+            entry.insertion_order = 40;
+        }
+
+        reorder_entries(&mut entries, &[3, 1, 2]).unwrap();
+
+        let order: Vec<(Option<i32>, i32)> = entries
+            .iter()
+            .map(|entry| (entry.id, entry.insertion_order))
+            .collect();
+        assert_eq!(order, vec![(Some(3), 0), (Some(1), 1), (Some(2), 2)]);
+    }
+
+    #[test]
+    fn reorder_entries_rejects_mismatched_ids() {
+        let mut entries = vec![
+            sample_entry(1, "Aster", "aster"),
+            sample_entry(2, "Rowan", "rowan"),
+        ];
+
+        for ordered_ids in [vec![1], vec![1, 2, 3], vec![1, 1], vec![2, 4]] {
+            assert!(reorder_entries(&mut entries, &ordered_ids).is_err());
+        }
+        assert_eq!(entries[0].id, Some(1));
+        assert_eq!(entries[0].insertion_order, 1);
+    }
 }
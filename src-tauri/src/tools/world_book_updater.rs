@@ -6,7 +6,7 @@ use crate::ai_tools::{
 use crate::character_storage::{CharacterStorage, WorldBookEntry};
 use crate::tools::world_book_shared::{
     detailed_entry, get_bool_parameter, get_i32_parameter, get_string_parameter, locate_entry,
-    set_extension_i32,
+    set_extension_i32, validate_entry_range_parameters,
 };
 use async_trait::async_trait;
 use serde_json::json;
@@ -191,7 +191,11 @@ impl AIToolTrait for UpdateWorldBookEntryTool {
             ("comment", "备注", "string"),
             ("enabled", "是否启用（true/false）", "boolean"),
             ("priority", "优先级（数字）", "integer"),
-            ("position", "位置（before_char/after_char）", "string"),
+            (
+                "position",
+                "位置（before_char/after_char/at_depth）",
+                "string",
+            ),
             ("depth", "插入深度（数字）", "integer"),
             ("probability", "触发概率（数字0-100）", "integer"),
         ] {
@@ -227,6 +231,8 @@ fn apply_entry_updates(
     entry: &mut WorldBookEntry,
     request: &ToolCallRequest,
 ) -> Result<Vec<&'static str>, String> {
+    validate_entry_range_parameters(&request.parameters)?;
+
     let mut updated_fields = Vec::new();
 
     if let Some(keys) = get_string_parameter(&request.parameters, "keys") {
@@ -299,3 +305,68 @@ fn should_update_name(request: &ToolCallRequest) -> bool {
         || request.parameters.contains_key("depth")
         || request.parameters.contains_key("probability")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::apply_entry_updates;
+    use crate::ai_tools::ToolCallRequest;
+    use crate::character_storage::WorldBookEntry;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn sample_entry() -> WorldBookEntry {
+        serde_json::from_value(json!({
+            "id": 7,
+            "name": "Forest",
+            "keys": ["forest", "woods"],
+            "content": "An old forest",
+            "extensions": { "depth": 4, "probability": 100 },
+            "enabled": true,
+            "insertion_order": 0
+        }))
+        .unwrap()
+    }
+
+    fn update_request(parameters: HashMap<String, serde_json::Value>) -> ToolCallRequest {
+        ToolCallRequest {
+            tool_name: "update_world_book_entry".to_string(),
+            parameters,
+            character_uuid: Some("character".to_string()),
+            context: None,
+        }
+    }
+
+    #[test]
+    fn content_only_update_leaves_keys_intact() {
+        let mut entry = sample_entry();
+        let request = update_request(HashMap::from([
+            ("entry_id".to_string(), json!(7)),
+            ("content".to_string(), json!("A burned forest")),
+        ]));
+
+        let updated_fields = apply_entry_updates(&mut entry, &request).unwrap();
+
+        assert_eq!(updated_fields, vec!["content"]);
+        assert_eq!(entry.content, "A burned forest");
+        assert_eq!(entry.keys, vec!["forest", "woods"]);
+        assert_eq!(entry.name.as_deref(), Some("Forest"));
+        assert_eq!(entry.extensions["depth"], 4);
+    }
+
+    #[test]
+    fn depth_and_probability_update_nested_extensions() {
+        let mut entry = sample_entry();
+        let request = update_request(HashMap::from([
+            ("entry_id".to_string(), json!(7)),
+            ("depth".to_string(), json!(2)),
+            ("probability".to_string(), json!(50)),
+        ]));
+
+        let updated_fields = apply_entry_updates(&mut entry, &request).unwrap();
+
+        assert_eq!(updated_fields, vec!["depth", "probability"]);
+        assert_eq!(entry.extensions["depth"], 2);
+        assert_eq!(entry.extensions["probability"], 50);
+        assert_eq!(entry.content, "An old forest");
+    }
+}
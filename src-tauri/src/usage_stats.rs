@@ -0,0 +1,96 @@
+use crate::file_utils::FileUtils;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// 角色累计 token 用量（跨所有会话）
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CharacterUsageStats {
+    #[serde(default)]
+    pub prompt_tokens: u64,
+    #[serde(default)]
+    pub completion_tokens: u64,
+    #[serde(default)]
+    pub request_count: u64,
+}
+
+impl CharacterUsageStats {
+    /// 累加一次成功响应的用量
+    pub fn record(&mut self, prompt_tokens: u64, completion_tokens: u64) {
+        self.prompt_tokens = self.prompt_tokens.saturating_add(prompt_tokens);
+        self.completion_tokens = self.completion_tokens.saturating_add(completion_tokens);
+        self.request_count = self.request_count.saturating_add(1);
+    }
+}
+
+/// 角色用量统计服务（character-cards/{uuid}/usage_stats.json，首次记录时创建）
+pub struct UsageStatsService;
+
+impl UsageStatsService {
+    fn get_stats_path(app_handle: &tauri::AppHandle, uuid: &str) -> Result<PathBuf, String> {
+        let app_data_dir = FileUtils::get_app_data_dir(app_handle)?;
+        Ok(app_data_dir
+            .join("character-cards")
+            .join(uuid)
+            .join("usage_stats.json"))
+    }
+
+    /// 读取累计用量，文件不存在时返回全零
+    pub fn load_stats(
+        app_handle: &tauri::AppHandle,
+        uuid: &str,
+    ) -> Result<CharacterUsageStats, String> {
+        let stats_path = Self::get_stats_path(app_handle, uuid)?;
+        if !stats_path.exists() {
+            return Ok(CharacterUsageStats::default());
+        }
+
+        FileUtils::read_json_file(&stats_path)
+    }
+
+    /// 记录一次成功响应并返回更新后的累计用量
+    pub fn record_usage(
+        app_handle: &tauri::AppHandle,
+        uuid: &str,
+        prompt_tokens: u64,
+        completion_tokens: u64,
+    ) -> Result<CharacterUsageStats, String> {
+        let stats_path = Self::get_stats_path(app_handle, uuid)?;
+        let mut stats = Self::load_stats(app_handle, uuid)?;
+        stats.record(prompt_tokens, completion_tokens);
+        FileUtils::write_json_file(&stats_path, &stats)?;
+        Ok(stats)
+    }
+
+    /// 清零累计用量（删除统计文件）
+    pub fn reset_stats(
+        app_handle: &tauri::AppHandle,
+        uuid: &str,
+    ) -> Result<CharacterUsageStats, String> {
+        let stats_path = Self::get_stats_path(app_handle, uuid)?;
+        if stats_path.exists() {
+            FileUtils::delete_path(&stats_path)?;
+        }
+        Ok(CharacterUsageStats::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CharacterUsageStats;
+
+    #[test]
+    fn recorded_responses_accumulate() {
+        let mut stats = CharacterUsageStats::default();
+        stats.record(120, 30);
+        stats.record(80, 45);
+
+        assert_eq!(
+            stats,
+            CharacterUsageStats {
+                prompt_tokens: 200,
+                completion_tokens: 75,
+                request_count: 2,
+            }
+        );
+    }
+}
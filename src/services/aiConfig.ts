@@ -7,6 +7,9 @@ export interface AIRole {
   temperature: number
   max_tokens: number
   tools_enabled: boolean
+  json_mode?: boolean
+  seed?: number | null
+  max_tool_iterations?: number | null
   context_role_template: string
   context_task_template: string
   context_instructions_template: string
@@ -22,6 +25,11 @@ export interface AIConfig {
   roles: Record<string, AIRole>
 }
 
+export interface Persona {
+  name: string
+  description: string
+}
+
 export class AIConfigService {
   static async getConfig(): Promise<AIConfig> {
     return await invoke<AIConfig>('get_ai_config')
@@ -50,4 +58,16 @@ export class AIConfigService {
   static async getAllRoles(): Promise<AIRoleEntry[]> {
     return await invoke<AIRoleEntry[]>('get_all_ai_roles')
   }
+
+  static async getPersona(): Promise<Persona | null> {
+    return await invoke<Persona | null>('get_persona')
+  }
+
+  static async setPersona(persona: Persona | null): Promise<void> {
+    await invoke<void>('set_persona', { persona })
+  }
+
+  static async extractPersonaFromCard(uuid: string): Promise<Persona | null> {
+    return await invoke<Persona | null>('extract_persona_from_card', { uuid })
+  }
 }
@@ -113,6 +113,19 @@ export async function toggleApiConfig(profile: string, enabled: boolean): Promis
   }
 }
 
+/**
+ * 轮换API密钥的本机加密密钥
+ * @returns 重新加密的密钥数量
+ */
+export async function rotateEncryptionKey(): Promise<number> {
+  try {
+    return await invoke<number>('rotate_encryption_key');
+  } catch (error) {
+    console.error('轮换加密密钥失败:', error);
+    throw new Error(error as string);
+  }
+}
+
 /**
  * 测试API连接
  * @param config API配置
@@ -140,16 +153,17 @@ export async function testApiConnection(config: ApiConfig): Promise<ApiTestResul
 }
 
 /**
- * 获取可用模型列表
+ * 获取可用模型列表，默认优先使用未过期的缓存
  * @param config API配置
+ * @param force 为 true 时跳过缓存重新拉取
  */
-export async function fetchModels(config: ApiConfig): Promise<ModelInfo[]> {
+export async function fetchModels(config: ApiConfig, force = false): Promise<ModelInfo[]> {
   if (!config.base_url || !config.api_key) {
     throw new Error('API Base URL 和密钥不能为空');
   }
 
   try {
-    const models = await invoke<ModelInfo[]>('fetch_models', { config });
+    const models = await invoke<ModelInfo[]>('fetch_models', { config, force });
     return models;
   } catch (error) {
     console.error('获取模型列表失败:', error);
@@ -157,6 +171,69 @@ export async function fetchModels(config: ApiConfig): Promise<ModelInfo[]> {
   }
 }
 
+/**
+ * 获取端点最近一次成功生成所用的模型
+ * @param endpoint API Base URL
+ */
+export async function getProviderDefaultModel(endpoint: string): Promise<string | null> {
+  try {
+    return await invoke<string | null>('get_provider_default_model', { endpoint });
+  } catch (error) {
+    console.error('获取端点默认模型失败:', error);
+    return null;
+  }
+}
+
+/**
+ * 获取同时进行的 API 请求上限
+ */
+export async function getMaxConcurrentRequests(): Promise<number> {
+  try {
+    return await invoke<number>('get_max_concurrent_requests');
+  } catch (error) {
+    console.error('获取最大并发请求数失败:', error);
+    throw new Error(error as string);
+  }
+}
+
+/**
+ * 获取模型列表缓存有效期（秒）
+ */
+export async function getModelCacheTtl(): Promise<number> {
+  try {
+    return await invoke<number>('get_model_cache_ttl');
+  } catch (error) {
+    console.error('获取模型列表缓存有效期失败:', error);
+    throw new Error(error as string);
+  }
+}
+
+/**
+ * 设置模型列表缓存有效期（秒），0 表示不缓存
+ * @param ttlSecs 有效期秒数
+ */
+export async function setModelCacheTtl(ttlSecs: number): Promise<void> {
+  try {
+    await invoke('set_model_cache_ttl', { ttlSecs });
+  } catch (error) {
+    console.error('设置模型列表缓存有效期失败:', error);
+    throw new Error(error as string);
+  }
+}
+
+/**
+ * 设置同时进行的 API 请求上限，超出的请求会排队等待
+ * @param maxConcurrentRequests 最大并发请求数
+ */
+export async function setMaxConcurrentRequests(maxConcurrentRequests: number): Promise<void> {
+  try {
+    await invoke('set_max_concurrent_requests', { maxConcurrentRequests });
+  } catch (error) {
+    console.error('设置最大并发请求数失败:', error);
+    throw new Error(error as string);
+  }
+}
+
 /**
  * 复制API配置
  * @param api 要复制的API配置
@@ -64,6 +64,33 @@ export async function createCharacter(name: string): Promise<CharacterData> {
   }
 }
 
+/**
+ * 复制角色卡（不包含聊天记录）
+ * @param uuid 源角色UUID
+ * @returns 新复制的角色数据
+ */
+export async function duplicateCharacter(uuid: string): Promise<CharacterData> {
+  try {
+    const character = await invoke<CharacterData>('duplicate_character', { uuid });
+    return character;
+  } catch (error) {
+    console.error('复制角色卡失败:', error);
+    throw new Error(error as string);
+  }
+}
+
+/**
+ * 设置关联角色，其名称与描述摘要会注入到上下文中
+ */
+export async function setLinkedCharacters(uuid: string, linkedUuids: string[]): Promise<string[]> {
+  try {
+    return await invoke<string[]>('set_linked_characters', { uuid, linkedUuids });
+  } catch (error) {
+    console.error('设置关联角色失败:', error);
+    throw new Error(error as string);
+  }
+}
+
 /**
  * 更新角色卡
  * @param uuid 角色UUID
@@ -151,18 +178,135 @@ export async function updateCharacterBackgroundPath(uuid: string, backgroundPath
  * 导出角色卡
  * @param uuid 角色UUID
  * @param outputPath 输出文件路径
- * @returns 导出的文件类型（"json" 或 "png"）
+ * @returns 实际写入的文件路径
  */
 export async function exportCharacterCard(uuid: string, outputPath: string): Promise<string> {
   try {
-    const fileType = await invoke<string>('export_character_card', { uuid, outputPath });
-    return fileType;
+    const exportedPath = await invoke<string>('export_character_card', { uuid, outputPath });
+    return exportedPath;
   } catch (error) {
     console.error('导出角色卡失败:', error);
     throw new Error(error as string);
   }
 }
 
+/**
+ * 用当前角色卡数据原地更新已导出的 PNG 角色卡（图像数据不变）
+ * @param pngPath 目标 PNG 文件路径
+ * @param uuid 角色UUID
+ * @returns 更新的文件路径
+ */
+export async function updatePngCharacterData(pngPath: string, uuid: string): Promise<string> {
+  try {
+    const updatedPath = await invoke<string>('update_png_character_data', { pngPath, uuid });
+    return updatedPath;
+  } catch (error) {
+    console.error('更新 PNG 角色卡失败:', error);
+    throw new Error(error as string);
+  }
+}
+
+/**
+ * 导出携带聊天记录的 PNG 角色卡
+ * @param uuid 角色UUID
+ * @param outputPath 输出文件路径
+ * @returns 实际写入的文件路径
+ */
+export async function exportCharacterWithHistory(uuid: string, outputPath: string): Promise<string> {
+  try {
+    const exportedPath = await invoke<string>('export_character_with_history', { uuid, outputPath });
+    return exportedPath;
+  } catch (error) {
+    console.error('导出角色卡及聊天记录失败:', error);
+    throw new Error(error as string);
+  }
+}
+
+/**
+ * 导入角色卡并恢复其中携带的聊天记录
+ * @param filePath 文件路径
+ * @returns 导入的角色数据
+ */
+export async function importCharacterWithHistory(filePath: string): Promise<CharacterData> {
+  try {
+    const character = await invoke<CharacterData>('import_character_with_history', { filePath });
+    return character;
+  } catch (error) {
+    console.error('导入角色卡及聊天记录失败:', error);
+    throw new Error(error as string);
+  }
+}
+
+/**
+ * 把角色（角色数据、背景图、聊天记录、使用统计）打包为 zip 备份
+ * @param uuid 角色UUID
+ * @param outputPath 输出文件路径
+ * @returns 实际写入的备份文件路径
+ */
+export async function exportCharacterArchive(uuid: string, outputPath: string): Promise<string> {
+  try {
+    return await invoke<string>('export_character_archive', { uuid, outputPath });
+  } catch (error) {
+    console.error('备份角色失败:', error);
+    throw new Error(error as string);
+  }
+}
+
+/**
+ * 从 zip 备份恢复角色，uuid 已被占用时会分配新的 uuid
+ * @param filePath 备份文件路径
+ * @returns 恢复的角色数据
+ */
+export async function importCharacterArchive(filePath: string): Promise<CharacterData> {
+  try {
+    return await invoke<CharacterData>('import_character_archive', { filePath });
+  } catch (error) {
+    console.error('恢复角色备份失败:', error);
+    throw new Error(error as string);
+  }
+}
+
+export type LibraryImportMode = 'merge' | 'replace';
+
+export interface LibraryImportSummary {
+  imported_characters: number;
+  skipped_characters: number;
+  imported_api_configs: number;
+  ai_config_restored: boolean;
+}
+
+/**
+ * 备份整个角色库（全部角色、AI 配置与 API 配置）为 zip
+ * @param outputPath 输出文件路径
+ * @param includeApiKeys 是否导出 API 密钥（明文写入备份），默认不导出
+ * @returns 实际写入的备份文件路径
+ */
+export async function exportAllCharacters(outputPath: string, includeApiKeys = false): Promise<string> {
+  try {
+    return await invoke<string>('export_all_characters', { outputPath, includeApiKeys });
+  } catch (error) {
+    console.error('备份角色库失败:', error);
+    throw new Error(error as string);
+  }
+}
+
+/**
+ * 从整库备份恢复角色与配置
+ * @param filePath 备份文件路径
+ * @param mode merge 跳过已存在的角色，replace 覆盖
+ */
+export async function importAllCharacters(
+  filePath: string,
+  mode: LibraryImportMode,
+): Promise<LibraryImportSummary> {
+  try {
+    return await invoke<LibraryImportSummary>('import_all_characters', { filePath, mode });
+  } catch (error) {
+    console.error('恢复角色库失败:', error);
+    throw new Error(error as string);
+  }
+}
+
 /**
  * 从文件导入角色卡
  * @param filePath 文件路径
@@ -75,12 +75,12 @@ export const useAiStore = defineStore('ai', () => {
     throw new Error('后端会话加载超时')
   }
 
-  async function sendChatMessage(message: string) {
+  async function sendChatMessage(message: string, images?: string[]) {
     try {
       isLoading.value = true
       isStopping.value = false
       const roleId = getEffectiveRoleId() || null
-      await invoke('send_chat_message', { message, roleId })
+      await invoke('send_chat_message', { message, images: images?.length ? images : null, roleId })
       invalidateCommandAvailability()
     } catch (error) {
       if (isInterruptedError(error)) {
@@ -139,6 +139,34 @@ export const useAiStore = defineStore('ai', () => {
     }
   }
 
+  async function generateSwipe() {
+    try {
+      isLoading.value = true
+      isStopping.value = false
+      const roleId = getEffectiveRoleId() || null
+      await invoke('generate_swipe', { roleId })
+      invalidateCommandAvailability()
+    } catch (error) {
+      if (isInterruptedError(error)) {
+        return
+      }
+      console.error('生成备选回复失败:', error)
+      throw error
+    } finally {
+      isLoading.value = false
+      isStopping.value = false
+    }
+  }
+
+  async function setActiveSwipe(index: number) {
+    try {
+      return await invoke<ChatMessage>('set_active_swipe', { index })
+    } catch (error) {
+      console.error('切换备选回复失败:', error)
+      throw error
+    }
+  }
+
   async function continueChat() {
     try {
       isLoading.value = true
@@ -195,6 +223,23 @@ export const useAiStore = defineStore('ai', () => {
     }
   }
 
+  async function loadChatHistoryPage(
+    characterId: string,
+    offset: number,
+    limit: number,
+  ): Promise<{ messages: ChatMessage[]; total: number }> {
+    try {
+      return await invoke<{ messages: ChatMessage[]; total: number }>('load_chat_history_page', {
+        characterId,
+        offset,
+        limit,
+      })
+    } catch (error) {
+      console.error('分页加载聊天历史失败:', error)
+      throw error
+    }
+  }
+
   async function loadAIRoles(forceRefresh = false): Promise<AIRoleEntry[]> {
     if (!forceRefresh && rolesLoaded.value && aiRoles.value.length > 0) {
       return aiRoles.value
@@ -296,9 +341,12 @@ export const useAiStore = defineStore('ai', () => {
     editChatMessage,
     deleteChatMessage,
     regenerateLastMessage,
+    generateSwipe,
+    setActiveSwipe,
     continueChat,
     interruptResponse,
     loadChatHistory,
+    loadChatHistoryPage,
     loadAIRoles,
     selectRole,
 
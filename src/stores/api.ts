@@ -209,6 +209,8 @@ export const useApiStore = defineStore('api', () => {
         context_window: draft.value.context_window,
         default: draft.value.default,
         enabled: draft.value.enabled,
+        deployment: draft.value.deployment ?? '',
+        api_version: draft.value.api_version ?? '',
       }
 
       await updateApi(request)
@@ -21,6 +21,18 @@ export interface ApiConfig {
   default: boolean;
   /** 是否启用 */
   enabled: boolean;
+  /** 自定义请求头，冲突时覆盖默认请求头 */
+  headers?: Record<string, string>;
+  /** 请求超时（秒），默认 60 */
+  timeout_secs?: number;
+  /** 网络错误或 5xx 时的重试次数，默认 0 */
+  max_retries?: number;
+  /** Azure OpenAI 部署名，未填写时使用模型名 */
+  deployment?: string;
+  /** Azure OpenAI 的 api-version */
+  api_version?: string;
+  /** 出站请求代理，支持 http / https / socks5 */
+  proxy_url?: string;
 }
 
 export type ApiProvider =
@@ -28,6 +40,7 @@ export type ApiProvider =
   | 'open_ai_responses'
   | 'claude'
   | 'gemini_v1_beta'
+  | 'azure'
 
 export interface ApiListResponse {
   apis: ApiConfig[];
@@ -43,6 +56,12 @@ export interface CreateApiRequest {
   context_window?: number;
   default?: boolean;
   enabled?: boolean;
+  headers?: Record<string, string>;
+  timeout_secs?: number;
+  max_retries?: number;
+  deployment?: string;
+  api_version?: string;
+  proxy_url?: string;
 }
 
 export interface UpdateApiRequest extends Partial<ApiConfig> {
@@ -98,7 +117,10 @@ export interface ChatMessage {
   reasoning_content?: string;
   tool_calls?: ToolCall[];
   tool_call_id?: string;
+  images?: string[]; // 用户消息附带的图片（data URL 或 http(s) URL）
   timestamp?: number; // 消息时间戳（毫秒）
+  swipes?: string[]; // 备选回复，content 始终等于当前备选
+  active_swipe?: number;
 }
 
 /**
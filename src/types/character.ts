@@ -90,7 +90,7 @@ export interface WorldBookEntry {
   selective?: boolean;
   secondary_keys?: Array<string>;
   constant?: boolean;
-  position?: 'before_char' | 'after_char';
+  position?: 'before_char' | 'after_char' | 'at_depth';
 }
 
 /**
@@ -107,8 +107,8 @@ export interface CharacterBook {
 }
 
 export interface TavernCardV2 {
-  spec: 'chara_card_v2';
-  spec_version: '2.0';
+  spec: 'chara_card_v2' | 'chara_card_v3';
+  spec_version: string;
   data: {
     name: string;
     description: string;
@@ -125,6 +125,7 @@ export interface TavernCardV2 {
     creator: string;
     character_version: string;
     extensions: Record<string, unknown>;
+    v3_fields?: Record<string, unknown>;
   };
 }
 
@@ -136,6 +137,9 @@ export interface CharacterMeta {
   version: string;
   createdAt: string;
   updatedAt: string;
+  token_count?: number; // 保存时缓存的角色卡 token 数
+  favorite?: boolean; // 收藏的角色在列表中置顶
+  sort_order?: number; // 手动排序序号，越小越靠前
 }
 
 /**
@@ -164,6 +168,16 @@ export interface CharacterData {
   card: TavernCardV2;
   backgroundPath: string; // card.png 路径（绝对路径）
   thumbnailPath: string; // thumbnail.png 路径（绝对路径）
+  importWarnings?: CardValidationIssue[]; // 仅导入结果携带的规范检查问题
+}
+
+/**
+ * 角色卡规范检查问题
+ */
+export interface CardValidationIssue {
+  path: string;
+  severity: 'error' | 'warning';
+  message: string;
 }
 
 /**
@@ -196,7 +210,7 @@ export interface CreateWorldBookEntryParams {
   content: string;
   enabled?: boolean;
   priority?: number;
-  position?: 'before_char' | 'after_char';
+  position?: 'before_char' | 'after_char' | 'at_depth';
 }
 
 /**
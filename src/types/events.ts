@@ -31,6 +31,8 @@ export interface MessageReceivedPayload {
   timestamp: number
   /** 中间消息（包括 assistant with tool_calls 和 tool results） */
   intermediate_messages?: ChatMessage[]
+  /** 提供商返回的 system_fingerprint */
+  system_fingerprint?: string
 }
 
 export type ToolExecutionPhase = 'started' | 'succeeded' | 'failed'